@@ -1,17 +1,16 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use duplicate_destroyer;
 use std::fs::{DirBuilder, File};
 use std::io::{self, Write};
 use std::path::Path;
 use tempdir::TempDir;
 
-fn write_file(path: &Path, contents: &str) -> io::Result<()>{
+fn write_file(path: &Path, contents: &str) -> io::Result<()> {
     let mut file = File::create(path).expect("Could not create a file.");
     writeln!(file, "{}", contents)?;
     Ok(())
 }
 
-fn bench_compare_two_identical_dirs(c: &mut Criterion) -> io::Result<()>{
+fn bench_compare_two_identical_dirs(c: &mut Criterion) -> io::Result<()> {
     let tmp_dir = TempDir::new("add_directories_success_test").expect("Failed creating temp dir.");
     let tmp_dir_str = tmp_dir.path().to_owned().into_os_string();
     let tmp_dir_path = tmp_dir.path();
@@ -52,5 +51,9 @@ fn bench_compare_two_identical_dirs(c: &mut Criterion) -> io::Result<()>{
     Ok(())
 }
 
-criterion_group!(benches, bench_compare_two_identical_dirs);
+fn bench_compare(c: &mut Criterion) {
+    bench_compare_two_identical_dirs(c).expect("Could not set up benchmark.");
+}
+
+criterion_group!(benches, bench_compare);
 criterion_main!(benches);
@@ -0,0 +1,105 @@
+//! Asynchronous interface to duplicate search
+//!
+//! This module provides a stream of duplicate groups found on a background thread, so that DuDe
+//! can be used from async code without blocking the executor. Enable it with the `async` feature.
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use futures_core::Stream;
+
+use crate::config::Settings;
+use crate::{Config, DuplicateObject, Scanner};
+
+/// Find the largest duplicate directories or files on a background thread
+///
+/// Works like [`get_duplicates`](crate::get_duplicates), but returns immediately with a stream of
+/// the duplicate groups found. The groups are yielded from the largest once the search finishes.
+///
+/// The progress indicators in `config` are not used, since they can't be shared with the
+/// background thread.
+///
+/// # Arguments:
+/// * `directories` - paths that will be searched for duplicates
+/// * `config` - configuration of duplicate destroyer. See [`Config`](crate::Config) struct
+pub fn get_duplicates_async(
+    directories: impl IntoIterator<Item = impl Into<PathBuf>>,
+    config: &Config,
+) -> DuplicateStream {
+    let directories: Vec<PathBuf> = directories.into_iter().map(Into::into).collect();
+    let settings = Settings::from_config(config);
+    let state = Arc::new(Mutex::new(StreamState::default()));
+
+    let thread_state = Arc::clone(&state);
+    thread::spawn(move || {
+        // Make sure the stream ends even if the search panics
+        let _guard = FinishGuard(Arc::clone(&thread_state));
+
+        let config = settings.into_config();
+        let mut scanner = match Scanner::new(directories, &config) {
+            Ok(scanner) => scanner,
+            Err(e) => {
+                log::error!("Could not scan directories: {:?}", e);
+                return;
+            }
+        };
+
+        for group in scanner.topmost_duplicates(config.get_minimum_size()) {
+            let mut state = thread_state.lock().expect("Stream state lock poisoned.");
+            state.groups.push_back(group);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    });
+
+    DuplicateStream { state }
+}
+
+/// Stream of duplicate groups returned by [`get_duplicates_async`]
+pub struct DuplicateStream {
+    state: Arc<Mutex<StreamState>>,
+}
+
+impl Stream for DuplicateStream {
+    type Item = DuplicateObject;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<DuplicateObject>> {
+        let mut state = self.state.lock().expect("Stream state lock poisoned.");
+        if let Some(group) = state.groups.pop_front() {
+            Poll::Ready(Some(group))
+        } else if state.finished {
+            Poll::Ready(None)
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// State shared by the stream and the background thread
+#[derive(Default)]
+struct StreamState {
+    groups: VecDeque<DuplicateObject>,
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+/// Marks the stream as finished when dropped
+struct FinishGuard(Arc<Mutex<StreamState>>);
+
+impl Drop for FinishGuard {
+    fn drop(&mut self) {
+        let mut state = match self.0.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        state.finished = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Location of the audio stream in audio files
+//!
+//! Tags (ID3, APE, Vorbis comments or iTunes metadata) are stored next to the audio data, so
+//! changing them changes the bytes of a file but not the recording. The functions of this module
+//! find the parts of MP3, FLAC and M4A files that hold the audio data, so that only those can be
+//! compared:
+//! * MP3 - everything except the ID3v2 tags at the start and the APEv2 and ID3v1 tags at the end
+//! * FLAC - the audio frames following the metadata blocks (without trailing tags)
+//! * M4A - the content of the `mdat` boxes
+//!
+//! The format is chosen by the extension of the file (ignoring its case).
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::content::read_at;
+use crate::video::read_box_header;
+
+/// Extensions of the audio files whose audio stream can be found
+const AUDIO_EXTENSIONS: [&str; 3] = ["mp3", "flac", "m4a"];
+
+/// Returns true if the audio stream of the file at `path` can be found (by its extension)
+pub(crate) fn is_audio(path: &Path) -> bool {
+    match path.extension().and_then(|x| x.to_str()) {
+        Some(extension) => AUDIO_EXTENSIONS.iter().any(|x| x.eq_ignore_ascii_case(extension)),
+        None => false,
+    }
+}
+
+/// Get the ranges of bytes of the file at `path` holding its audio stream
+///
+/// Returns None if the file is not an MP3, FLAC or M4A file or if its structure is not recognised.
+///
+/// # Arguments
+/// * `path` - path to the file (only its extension is used)
+/// * `file` - the opened file
+pub(crate) fn audio_ranges(path: &Path, file: &mut File) -> io::Result<Option<Vec<Range<u64>>>> {
+    let extension = match path.extension().and_then(|x| x.to_str()) {
+        Some(extension) => extension.to_ascii_lowercase(),
+        None => return Ok(None),
+    };
+    let len = file.metadata()?.len();
+    match extension.as_str() {
+        "mp3" => mp3_ranges(file, len),
+        "flac" => flac_ranges(file, len),
+        "m4a" => mp4_ranges(file, len),
+        _ => Ok(None),
+    }
+}
+
+/// MPEG audio frames are everything between the leading and the trailing tags
+fn mp3_ranges(file: &mut File, len: u64) -> io::Result<Option<Vec<Range<u64>>>> {
+    let start = skip_id3v2(file, len)?;
+    let end = strip_trailing_tags(file, start, len)?;
+    Ok(Some(vec![Range { start, end }]))
+}
+
+/// FLAC frames follow the `fLaC` marker and the metadata blocks (including Vorbis comments and
+/// pictures)
+fn flac_ranges(file: &mut File, len: u64) -> io::Result<Option<Vec<Range<u64>>>> {
+    let mut position = skip_id3v2(file, len)?;
+    let mut marker = [0u8; 4];
+    if !read_at(file, position, &mut marker)? || &marker != b"fLaC" {
+        return Ok(None);
+    }
+    position += 4;
+
+    // Each block header has the last-block flag, block type and 24-bit length of the block
+    let mut header = [0u8; 4];
+    loop {
+        if !read_at(file, position, &mut header)? {
+            return Ok(None);
+        }
+        position += 4 + u64::from(u32::from_be_bytes([0, header[1], header[2], header[3]]));
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+    if position > len {
+        return Ok(None);
+    }
+    let end = strip_trailing_tags(file, position, len)?;
+    Ok(Some(vec![Range { start: position, end }]))
+}
+
+/// Audio samples of MP4 files are stored in the `mdat` boxes, the metadata in the `moov` box
+fn mp4_ranges(file: &mut File, len: u64) -> io::Result<Option<Vec<Range<u64>>>> {
+    let mut ranges = vec![];
+    let mut position = 0;
+    while position + 8 <= len {
+        let (kind, header_len, size) = match read_box_header(file, position, len)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        if position == 0 && &kind != b"ftyp" {
+            return Ok(None);
+        }
+        if &kind == b"mdat" {
+            ranges.push(position + header_len..position + size);
+        }
+        position += size;
+    }
+
+    if ranges.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(ranges))
+    }
+}
+
+/// Get the position after the ID3v2 tags at the start of the file
+fn skip_id3v2(file: &mut File, len: u64) -> io::Result<u64> {
+    let mut position = 0;
+    let mut header = [0u8; 10];
+    while read_at(file, position, &mut header)? && &header[..3] == b"ID3" {
+        // The size excludes the header and the footer and is stored in 7 bits of each byte
+        let size = header[6..].iter().fold(0u64, |size, x| size << 7 | u64::from(x & 0x7f));
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        position = (position + 10 + size + footer).min(len);
+    }
+    Ok(position)
+}
+
+/// Get the end of the file without the ID3v1 and APEv2 tags (but not before `start`)
+fn strip_trailing_tags(file: &mut File, start: u64, len: u64) -> io::Result<u64> {
+    let mut end = len;
+
+    let mut id3v1 = [0u8; 3];
+    if end - start >= 128 && read_at(file, end - 128, &mut id3v1)? && &id3v1 == b"TAG" {
+        end -= 128;
+    }
+
+    let mut footer = [0u8; 32];
+    if end - start >= 32 && read_at(file, end - 32, &mut footer)? && &footer[..8] == b"APETAGEX" {
+        // The size includes the footer but not the optional header
+        let size = u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]);
+        let flags = u32::from_le_bytes([footer[20], footer[21], footer[22], footer[23]]);
+        let header = if flags & 0x8000_0000 != 0 { 32 } else { 0 };
+        end = end.saturating_sub(u64::from(size) + header).max(start);
+    }
+
+    Ok(end)
+}
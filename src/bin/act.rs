@@ -0,0 +1,119 @@
+//! Duplicate groups streamed between a scan and the actions
+//!
+//! `dude scan --output groups` writes each group as one line of json, so the groups can be
+//! filtered line by line (e.g. with `grep` or `jq -c`) before `dude act` handles them. The groups
+//! are read back and each of them is verified against the disk, so that groups that changed since
+//! the scan (or were changed by the filter) are left out instead of acting on stale data.
+//!
+//! Each line also stores the scanned paths, so that `dude act` refuses to delete them as a whole
+//! even if only some of the lines are handled.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use duplicate_destroyer::{Config, DuplicateObject};
+use serde::{Deserialize, Serialize};
+
+use crate::report::InputRoot;
+use crate::verify::get_drift;
+use crate::Units;
+
+/// Path of the input read from stdin
+const STDIN: &str = "-";
+
+/// One line of the groups output
+#[derive(Serialize)]
+struct GroupLine<'a> {
+    #[serde(flatten)]
+    group: &'a DuplicateObject,
+    roots: &'a [InputRoot],
+}
+
+/// One line of the groups input, lines without the scanned paths are accepted
+#[derive(Deserialize)]
+struct StoredLine {
+    #[serde(flatten)]
+    group: DuplicateObject,
+    #[serde(default)]
+    roots: Vec<InputRoot>,
+}
+
+/// Write `duplicates` found by a scan of `roots` to `out`, one group per line
+pub fn write_groups<W: Write>(
+    duplicates: &[DuplicateObject],
+    roots: &[InputRoot],
+    out: &mut W,
+) -> io::Result<()> {
+    for group in duplicates {
+        writeln!(out, "{}", serde_json::to_string(&GroupLine { group, roots })?)?;
+    }
+    Ok(())
+}
+
+/// Read the groups written by [`write_groups`] from the file at `path` (or stdin for `-`)
+///
+/// Empty lines are skipped. Returns the groups and the scanned paths stored in any of the lines.
+pub fn read_groups(path: &Path) -> io::Result<(Vec<DuplicateObject>, Vec<OsString>)> {
+    let reader: Box<dyn BufRead> = if path == Path::new(STDIN) {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+
+    let mut duplicates = vec![];
+    let mut roots = vec![];
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line: StoredLine = serde_json::from_str(&line).map_err(|e| {
+            let message = format!("Invalid group on line {}: {}", index + 1, e);
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        })?;
+        for root in line.roots {
+            if !roots.contains(&root.0) {
+                roots.push(root.0);
+            }
+        }
+        duplicates.push(line.group);
+    }
+    Ok((duplicates, roots))
+}
+
+/// Get the groups of `duplicates` that did not change since the scan
+///
+/// The members that changed are printed to stderr. As for `dude verify`, the `config` should match
+/// the options of the scan.
+pub fn verified_groups(
+    duplicates: Vec<DuplicateObject>,
+    units: Units,
+    config: &mut Config,
+) -> Vec<DuplicateObject> {
+    config.set_hash_all_files(true);
+
+    let num_groups = duplicates.len();
+    let mut verified = Vec::with_capacity(num_groups);
+    for group in duplicates {
+        let drift = get_drift(&group, config);
+        if drift.is_empty() {
+            verified.push(group);
+            continue;
+        }
+        eprintln!("Skipping group that changed since the scan:");
+        for item in drift {
+            eprintln!("  {}", item.describe(units));
+        }
+    }
+
+    if verified.len() < num_groups {
+        eprintln!(
+            "{} of {} groups changed since the scan.",
+            num_groups - verified.len(),
+            num_groups
+        );
+    }
+    verified
+}
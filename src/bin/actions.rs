@@ -1,11 +1,15 @@
-/// Actions that can be performed on each group found by DuDe
+//! Actions that can be performed on each group found by DuDe
 
+use crate::audit::AuditLog;
+use crate::freed_space::get_freed_space;
 use crate::helper_functions::*;
+use crate::protection::ProtectedPaths;
+use crate::{get_human_readable_size, Units};
 
-use std::ffi::OsString;
-use std::fs::{remove_dir_all, remove_file};
-use std::io;
-use std::path::Path;
+use std::ffi::{OsStr, OsString};
+use std::fs::{read_dir, remove_dir_all, remove_file, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use copy_confirmer::*;
@@ -15,20 +19,44 @@ use regex::Regex;
 /// Retries for input of user actions
 const MAX_RETRIES: u32 = 4;
 
+/// Number of lines shown by the preview action if user does not choose another number
+pub const DEFAULT_PREVIEW_LINES: usize = 40;
+
+/// Number of bytes of a file read for its preview
+const PREVIEW_BYTES: u64 = 64 * 1024;
+
+/// Number of bytes shown on each line of a hexdump
+const HEXDUMP_WIDTH: usize = 16;
+
+/// Keys of the actions that can be chosen for a duplicate group
+pub const ACTION_KEYS: &str = "[O]pen, Open [F]older, Pre[V]iew, [C]ompare, Compare [P]arent folders, [D]elete, ReplaceWith[H]ardlink, ReplaceWith[S]oftlink, [I]gnore forever, [U]ndo last action, [N]othing, [Q]uit";
+
+/// Command comparing two files or directories used if user does not choose another one
+pub const DEFAULT_DIFF_TOOL: &str = "git diff --no-index";
 
 /// Actions possible for duplicate groups
 ///
 /// All actions except `Nothing` and `Quit` contain vector of paths the action should be taken on.
-/// Destructive actions (Delete, ReplaceWithHardlink and ReplaceWithSoftlink) also contain a path
-/// that will not be changed to ensure that at least one path stays intact.
-// TODO: Add Diff parent dir
+/// Destructive actions (Delete, ReplaceWithHardlink, ReplaceWithSoftlink and ReplaceWithReflink)
+/// also contain a path that will not be changed to ensure that at least one path stays intact.
+/// Compare actions contain the two paths that are compared. `Preview` contains the paths whose
+/// beginning is shown. `Filter` contains the pattern that
+/// the members of the remaining groups should match (empty to show all groups). `Ignore` adds the
+/// group to the ignore list. `Undo` reverses the last destructive action (see [`Actions::undo`]).
 #[derive(Debug)]
 pub enum Actions {
     Open(Vec<OsString>),
     OpenFolder(Vec<OsString>),
+    Preview(Vec<OsString>),
+    Compare(OsString, OsString),
+    CompareFolders(OsString, OsString),
     Delete(Vec<OsString>, OsString),
     ReplaceWithHardlink(Vec<OsString>, OsString),
     ReplaceWithSoftlink(Vec<OsString>, OsString),
+    ReplaceWithReflink(Vec<OsString>, OsString),
+    Filter(String),
+    Ignore,
+    Undo,
     Nothing,
     Quit,
 }
@@ -36,20 +64,306 @@ pub enum Actions {
 enum LinkType {
     HardLink,
     SoftLink,
+    Reflink,
+}
+
+/// Settings shared by all executed actions
+pub struct ActionContext {
+    /// Paths that must not be deleted or replaced
+    pub protected: ProtectedPaths,
+    /// Log of executed actions
+    pub audit_log: Option<AuditLog>,
+    /// Ask user for confirmation before destructive actions
+    pub confirm: bool,
+    /// Only print the destructive actions that would be executed
+    pub dry_run: bool,
+    /// Command comparing two paths given as its last two arguments (split on whitespace)
+    pub diff_tool: String,
+    /// Number of lines of text (or of a hexdump) shown by the preview action
+    pub preview_lines: usize,
+    /// Ignore list the groups ignored in interactive handling are added to
+    pub ignore_list: PathBuf,
+    /// Units used to print the space freed by destructive actions
+    pub units: Units,
+    /// Replace files with hard links or reflinks even if the original has a different owner,
+    /// group or mode
+    pub allow_access_change: bool,
+    /// Restore modification times of the directories changed by destructive actions
+    pub preserve_dir_mtimes: bool,
+    /// Delete files with hardlinks outside of the deleted paths
+    pub allow_multiply_linked: bool,
+    /// Counts of the destructive actions executed so far
+    pub summary: ActionSummary,
+    /// Files replaced with links by the last executed action, undo turns them back into copies
+    pub created_links: Vec<OsString>,
+}
+
+/// Counts of the executed destructive actions, logged once the duplicates are handled
+#[derive(Default)]
+pub struct ActionSummary {
+    /// Number of destructive actions executed (including the failed ones)
+    pub executed: usize,
+    /// Number of destructive actions that failed
+    pub failed: usize,
+    /// Number of paths deleted or replaced
+    pub changed: usize,
+}
+
+impl ActionSummary {
+    /// Log the summary (to syslog with `--syslog`)
+    pub fn log(&self) {
+        log::info!(
+            target: crate::syslog::SUMMARY_TARGET,
+            "Executed {} destructive actions ({} failed), {} paths deleted or replaced",
+            self.executed,
+            self.failed,
+            self.changed
+        );
+    }
 }
 
 impl Actions {
-    pub fn execute(&self) -> io::Result<()> {
-        use Actions::*; 
+    /// Execute the action
+    ///
+    /// Destructive actions are refused if any of the paths they would change is protected.
+    /// Deletion is refused if any of the deleted files has hardlinks outside of the deleted paths,
+    /// unless allowed in `context`. If there is an audit log in `context`, the action is recorded
+    /// in it. In a dry run, destructive actions are only checked and printed. The space freed by
+    /// destructive actions is printed before asking for confirmation and in a dry run.
+    ///
+    /// If set in `context`, the modification times of the directories changed by the action are
+    /// restored afterwards, so that backup and sync tools do not examine them again.
+    ///
+    /// Returns the number of paths deleted or replaced by the action (paths that user declined to
+    /// change are not counted).
+    ///
+    /// # Arguments
+    /// * `context` - settings shared by all actions
+    pub fn execute(&self, context: &mut ActionContext) -> io::Result<usize> {
+        let (paths, original) = self.paths();
+
+        if context.dry_run {
+            if let Some(original) = original {
+                self.check_protected(&context.protected)?;
+                self.check_links(context.allow_multiply_linked)?;
+                for path in paths {
+                    println!("Would {} {:?} (keeping {:?})", self.name(), path, original);
+                }
+                self.print_freed_space(context.units);
+                return Ok(0);
+            }
+        }
+
+        let hashes = match context.audit_log {
+            Some(_) if !paths.is_empty() => {
+                let mut hashed: Vec<_> = paths.iter().collect();
+                hashed.extend(original);
+                AuditLog::get_hashes(&hashed)
+            }
+            _ => vec![],
+        };
+
+        context.created_links.clear();
+        let result = self
+            .check_protected(&context.protected)
+            .and_then(|_| self.check_links(context.allow_multiply_linked))
+            .and_then(|_| {
+                if context.confirm {
+                    self.print_freed_space(context.units);
+                }
+                if !context.preserve_dir_mtimes {
+                    return self.run(context);
+                }
+                let mtimes = get_mtimes(self.changed_dirs());
+                let result = self.run(context);
+                for (dir, seconds, nanoseconds) in mtimes {
+                    if let Err(e) = set_mtime(&dir, seconds, nanoseconds) {
+                        println!("Could not restore modification time of {:?}: {}", dir, e);
+                    }
+                }
+                result
+            });
+
+        if original.is_some() {
+            context.summary.executed += 1;
+            match result {
+                Ok(changed) => context.summary.changed += changed,
+                Err(_) => context.summary.failed += 1,
+            }
+        }
+
+        if let Some(ref mut audit_log) = context.audit_log {
+            if !paths.is_empty() {
+                audit_log.record(self.name(), paths, original, hashes, &result)?;
+            }
+        }
+
+        result
+    }
+
+    /// Check that no path changed by destructive action is protected
+    fn check_protected(&self, protected: &ProtectedPaths) -> io::Result<()> {
+        use Actions::*;
+
+        if let Delete(files, original)
+        | ReplaceWithHardlink(files, original)
+        | ReplaceWithSoftlink(files, original)
+        | ReplaceWithReflink(files, original) = self
+        {
+            for file in files {
+                protected.check(file, original)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that no file deleted by the action has hardlinks outside of the deleted paths
+    ///
+    /// Such a file is the same data as the paths it is linked to, which may be outside of the
+    /// scanned directories, and only its inode was checked to differ from the kept original. With
+    /// `allow_multiply_linked` the files are only warned about.
+    fn check_links(&self, allow_multiply_linked: bool) -> io::Result<()> {
+        let files = match self {
+            Actions::Delete(files, _) => files,
+            _ => return Ok(()),
+        };
+        let linked = get_freed_space(files, false)?.linked_elsewhere;
+        if linked == 0 {
+            return Ok(());
+        }
+
+        let message = format!(
+            "{} of the deleted files have hardlinks outside of the deleted paths, they may be the \
+            same data as files outside of the scanned directories.",
+            linked
+        );
+        if allow_multiply_linked {
+            eprintln!("WARNING: {}", message);
+            return Ok(());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("{} Use --allow-multiply-linked to delete them anyway.", message),
+        ))
+    }
+
+    /// Get directories whose entries are changed by destructive action
+    ///
+    /// Deleting a path changes its parent directory, replacing a path with links changes also all
+    /// directories in it.
+    fn changed_dirs(&self) -> Vec<PathBuf> {
+        let (paths, original) = self.paths();
+        if original.is_none() {
+            return vec![];
+        }
+        let mut dirs: Vec<PathBuf> =
+            paths.iter().filter_map(|x| Path::new(x).parent()).map(|x| x.to_owned()).collect();
+        if !matches!(self, Actions::Delete(..)) {
+            for path in paths {
+                dirs.extend(
+                    walkdir::WalkDir::new(path)
+                        .into_iter()
+                        .filter_map(|x| x.ok())
+                        .filter(|x| x.file_type().is_dir())
+                        .map(|x| x.into_path()),
+                );
+            }
+        }
+        dirs.sort_unstable();
+        dirs.dedup();
+        dirs
+    }
+
+    /// Print the space freed on each device by destructive action
+    ///
+    /// Nothing is printed for other actions.
+    fn print_freed_space(&self, units: Units) {
+        let (paths, original) = self.paths();
+        if original.is_none() {
+            return;
+        }
+        let keep_dirs = !matches!(self, Actions::Delete(..));
+        let freed = match get_freed_space(paths, keep_dirs) {
+            Ok(freed) => freed,
+            Err(e) => {
+                println!("Could not get the space freed: {}", e);
+                return;
+            }
+        };
+
+        if freed.per_device.values().all(|x| *x == 0) {
+            println!("This frees no space.");
+        }
+        for (device, size) in freed.per_device.iter().filter(|(_, size)| **size > 0) {
+            println!("This frees {} on device {}.", get_human_readable_size(*size, units), device);
+        }
+        if freed.linked_elsewhere > 0 {
+            println!(
+                "{} files are not freed, because they have hardlinks outside of the selection.",
+                freed.linked_elsewhere
+            );
+        }
+    }
+
+    /// Get name of the action
+    pub fn name(&self) -> &'static str {
+        use Actions::*;
+
+        match self {
+            Open(_) => "Open",
+            OpenFolder(_) => "OpenFolder",
+            Preview(_) => "Preview",
+            Compare(..) => "Compare",
+            CompareFolders(..) => "CompareFolders",
+            Delete(..) => "Delete",
+            ReplaceWithHardlink(..) => "ReplaceWithHardlink",
+            ReplaceWithSoftlink(..) => "ReplaceWithSoftlink",
+            ReplaceWithReflink(..) => "ReplaceWithReflink",
+            Filter(_) => "Filter",
+            Ignore => "Ignore",
+            Undo => "Undo",
+            Nothing => "Nothing",
+            Quit => "Quit",
+        }
+    }
+
+    /// Get paths acted upon and path kept by the action
+    ///
+    /// Compare actions change no paths, so they return no paths.
+    pub fn paths(&self) -> (&[OsString], Option<&OsString>) {
+        use Actions::*;
+
+        match self {
+            Open(files) | OpenFolder(files) | Preview(files) => (files, None),
+            Compare(..) | CompareFolders(..) => (&[], None),
+            Delete(files, original)
+            | ReplaceWithHardlink(files, original)
+            | ReplaceWithSoftlink(files, original)
+            | ReplaceWithReflink(files, original) => (files, Some(original)),
+            Filter(_) | Ignore | Undo | Nothing | Quit => (&[], None),
+        }
+    }
+
+    /// Execute the action without any checks
+    ///
+    /// Returns the number of paths deleted or replaced.
+    ///
+    /// # Arguments
+    /// * `context` - settings shared by all actions
+    fn run(&self, context: &mut ActionContext) -> io::Result<usize> {
+        use Actions::*;
+
+        let confirm = context.confirm;
+        let mut changed = 0;
 
         match self {
             Delete(files, original) => {
                 for file in files {
-                    delete_dir(file, original)?;
+                    changed += delete_dir(file, original, confirm)? as usize;
                 }
             }
 
-            Nothing => {}
+            Filter(_) | Ignore | Undo | Nothing => {}
 
             Open(files) => {
                 for file in files {
@@ -63,122 +377,268 @@ impl Actions {
                 }
             }
 
+            Preview(files) => {
+                let mut text = String::new();
+                for file in files {
+                    text.push_str(&format!("==> {:?} <==\n", file));
+                    text.push_str(&get_preview(file, context.preview_lines)?);
+                    text.push('\n');
+                }
+                print_to_pager(text);
+            }
+
+            Compare(first, second) => compare(first, second, &context.diff_tool)?,
+
+            CompareFolders(first, second) => {
+                compare(get_parent(first), get_parent(second), &context.diff_tool)?
+            }
+
             ReplaceWithHardlink(files, original) => {
                 for file in files {
-                    replace_with_link(file, original, LinkType::HardLink)?;
+                    changed += replace_with_link(
+                        file,
+                        original,
+                        LinkType::HardLink,
+                        confirm,
+                        context.allow_access_change,
+                        &mut context.created_links,
+                    )? as usize;
                 }
             }
 
             ReplaceWithSoftlink(files, original) => {
                 for file in files {
-                    replace_with_link(file, original, LinkType::SoftLink)?;
+                    changed += replace_with_link(
+                        file,
+                        original,
+                        LinkType::SoftLink,
+                        confirm,
+                        context.allow_access_change,
+                        &mut context.created_links,
+                    )? as usize;
+                }
+            }
+
+            ReplaceWithReflink(files, original) => {
+                for file in files {
+                    changed += replace_with_link(
+                        file,
+                        original,
+                        LinkType::Reflink,
+                        confirm,
+                        context.allow_access_change,
+                        &mut context.created_links,
+                    )? as usize;
                 }
             }
 
             Quit => std::process::exit(0),
         }
 
-        Ok(())
+        Ok(changed)
+    }
+
+    /// Reverse the destructive action after it was executed
+    ///
+    /// Deleted paths are recreated as copies of the kept original. Links created by the action
+    /// (`links`, taken from `context.created_links` right after the action was executed) are
+    /// replaced with copies of the files they link to, so that they share no data with the
+    /// original. The copies take over the owner, mode and modification time of the original.
+    /// Deleted paths that exist again (e.g. because user declined the deletion) and links that
+    /// were removed since are left as they are. Other actions change nothing and can't be undone.
+    /// If there is an audit log in `context`, the undo is recorded in it.
+    ///
+    /// Returns the number of paths restored.
+    pub fn undo(&self, links: &[OsString], context: &mut ActionContext) -> io::Result<usize> {
+        use Actions::*;
+
+        let (restored_paths, original) = match self {
+            Delete(files, original) => (&files[..], Some(original)),
+            ReplaceWithHardlink(_, original)
+            | ReplaceWithSoftlink(_, original)
+            | ReplaceWithReflink(_, original) => (links, Some(original)),
+            _ => (&[][..], None),
+        };
+        let hashes = match context.audit_log {
+            Some(_) if !restored_paths.is_empty() => {
+                AuditLog::get_hashes(&restored_paths.iter().collect::<Vec<_>>())
+            }
+            _ => vec![],
+        };
 
+        let result = self.restore(links);
+
+        if let Some(ref mut audit_log) = context.audit_log {
+            if !restored_paths.is_empty() {
+                let action = format!("Undo{}", self.name());
+                audit_log.record(&action, restored_paths, original, hashes, &result)?;
+            }
+        }
+
+        result
+    }
+
+    /// Recreate paths deleted by the action and replace `links` created by it with copies
+    ///
+    /// Returns the number of paths restored.
+    fn restore(&self, links: &[OsString]) -> io::Result<usize> {
+        let mut restored = 0;
+        match self {
+            Actions::Delete(files, original) => {
+                for file in files.iter().filter(|x| Path::new(x).symlink_metadata().is_err()) {
+                    println!("Recreating {:?} from {:?}", file, original);
+                    copy_unshared(original, file)?;
+                    restored += 1;
+                }
+            }
+            _ => {
+                for link in links {
+                    restored += unlink(link)? as usize;
+                }
+            }
+        }
+        Ok(restored)
     }
 
     /// Returns true if action can be followed by another action
     pub fn should_get_another(&self) -> bool {
         use Actions::*;
 
-        matches!(self, Open(_) | OpenFolder(_))
+        matches!(self, Open(_) | OpenFolder(_) | Preview(_) | Compare(..) | CompareFolders(..))
     }
 
     /// Get action and files affected from user input
     ///
     /// # Arguments
     /// * `files` - Vector of duplicate files in a duplicate group
-    pub fn get_from_input(files: &[OsString]) -> io::Result<Actions> {
-        use Actions::*;
-
-        println!(
-            "[O]pen, Open [F]older, [D]elete, ReplaceWith[H]ardlink, ReplaceWith[S]oftlink, [N]othing, [Q]uit"
-        );
+    /// * `canonical` - file that should be kept by destructive actions if it is not acted upon
+    pub fn get_from_input(files: &[OsString], canonical: Option<&OsString>) -> io::Result<Actions> {
+        println!("{}", ACTION_KEYS);
+        println!("(Use e.g. \"K 0 H\" to keep file 0 and replace all other files with hardlinks.)");
+        println!("(Use e.g. \"/photos\" to show only groups with a path matching the pattern.)");
 
         for i in 0..MAX_RETRIES {
             // get user input
             let mut input = String::new();
             io::stdin().read_line(&mut input)?;
-            #[allow(unused_assignments)]
-            let mut file_nums = vec![];
-            #[allow(unused_assignments)]
-            let mut action_rep = String::new();
-
-            // parse user input into Actions enum member and numbers of files
-            match Self::parse_action_input(&input.trim().to_uppercase()) {
-                Ok((new_action, new_files)) => {
-                    action_rep = new_action;
-                    file_nums = new_files;
-                }
-
+            match Self::parse_input(&input, files, canonical) {
+                Ok(action) => return Ok(action),
                 // Could not parse input
-                Err(err) => {
-                    Self::print_action_input_err(i, &err);
-                    continue;
-                }
-            };
+                Err(err) => Self::print_action_input_err(i, &err),
+            }
+        }
+        // Did not get valid input, return default action
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "Failed to parse user input."))
+    }
 
-            // Check that user input files for actions that require them
-            if let "O" | "F" | "D" | "S" | "H" = action_rep.as_str() {
-                if file_nums.is_empty(){
-                    Self::print_action_input_err(i, "Select at least one file for this action.")
-                }
+    /// Get action from a line of user input
+    ///
+    /// # Arguments
+    /// * `input` - action and numbers of files, "K", numbers of kept files and a destructive
+    ///   action, or "/" and a pattern filtering the groups
+    /// * `files` - Vector of duplicate files in a duplicate group
+    /// * `canonical` - file that should be kept by destructive actions if it is not acted upon
+    fn parse_input(
+        input: &str,
+        files: &[OsString],
+        canonical: Option<&OsString>,
+    ) -> Result<Actions, String> {
+        if let Some(pattern) = input.trim().strip_prefix('/') {
+            return Ok(Actions::Filter(pattern.to_owned()));
+        }
+
+        // parse user input into Actions enum member and numbers of files
+        let input = input.trim().to_uppercase();
+        let parsed = match Self::parse_keep_input(&input) {
+            Some((action, kept)) => match kept.iter().find(|x| **x >= files.len()) {
+                Some(num) => Err(format!("There is no file with number {num}")),
+                None => Ok((action, (0..files.len()).filter(|x| !kept.contains(x)).collect())),
+            },
+            None => Self::parse_action_input(&input),
+        };
+        parsed.and_then(|(action_rep, file_nums)| {
+            Self::from_selection(&action_rep, &file_nums, files, canonical)
+        })
+    }
+
+    /// Create action from its letter and the numbers of the files selected
+    ///
+    /// Returns the reason if the files selected do not fit the action.
+    ///
+    /// # Arguments
+    /// * `action_rep` - letter of the action (one of [`ACTION_KEYS`])
+    /// * `file_nums` - numbers of the files selected
+    /// * `files` - Vector of duplicate files in a duplicate group
+    /// * `canonical` - file that should be kept by destructive actions if it is not acted upon
+    pub fn from_selection(
+        action_rep: &str,
+        file_nums: &[usize],
+        files: &[OsString],
+        canonical: Option<&OsString>,
+    ) -> Result<Actions, String> {
+        use Actions::*;
+
+        // Check that user input files for actions that require them
+        if let "O" | "F" | "V" | "D" | "S" | "H" = action_rep {
+            if file_nums.is_empty() {
+                return Err("Select at least one file for this action.".to_owned());
             }
+        }
 
-            // Check that file numbers entered are valid
-            let file_max = file_nums.iter().max().unwrap_or(&0);
-            if *file_max > files.len() {
-                Self::print_action_input_err(
-                    i,
-                    &format!("There is no file with number {file_max}"),
-                );
-                continue;
+        // Check that user input two files for comparison
+        if let "C" | "P" = action_rep {
+            if file_nums.len() != 2 {
+                return Err("Select two files to compare.".to_owned());
             }
+        }
 
-            // Get paths corresponding to file numbers
-            let acted_paths: Vec<_> = files
-                .iter()
-                .enumerate()
-                .filter(|(num, _path)| file_nums.contains(num))
-                .map(|(_num, path)| path.to_owned())
-                .collect();
+        // Check that file numbers entered are valid
+        let file_max = file_nums.iter().max().unwrap_or(&0);
+        if *file_max >= files.len() {
+            return Err(format!("There is no file with number {file_max}"));
+        }
 
-            // If we are deleting/replacing files, get a file that will not be modified
-            let mut original_path: Option<OsString> = None;
-            if let "D" | "S" | "H" = action_rep.as_str() {
-                if acted_paths.len() >= files.len() {
-                    Self::print_action_input_err(
-                        i,
-                        "Selected destructive action for all duplicates! Please repeat selection."
-                    );
-                    continue;
-                }
-                original_path =
-                    Some(files.iter().find(|x| !acted_paths.contains(x)).unwrap().to_owned());
-            }
+        // Get paths corresponding to file numbers
+        let acted_paths: Vec<_> = files
+            .iter()
+            .enumerate()
+            .filter(|(num, _path)| file_nums.contains(num))
+            .map(|(_num, path)| path.to_owned())
+            .collect();
 
-            // Create action
-            let action = match action_rep.as_str() {
-                "D" => Delete(acted_paths, original_path.unwrap()),
-                "S" => ReplaceWithSoftlink(acted_paths, original_path.unwrap()),
-                "H" => ReplaceWithHardlink(acted_paths, original_path.unwrap()),
-                "O" => Open(acted_paths),
-                "F" => OpenFolder(acted_paths),
-                "Q" => Quit,
-                "N" => Nothing, 
-                &_ => panic!("Error parsing user input.")
-                
+        // If we are deleting/replacing files, get a file that will not be modified
+        let mut original_path: Option<OsString> = None;
+        if let "D" | "S" | "H" = action_rep {
+            if acted_paths.len() >= files.len() {
+                return Err(
+                    "Selected destructive action for all duplicates! Please repeat selection."
+                        .to_owned(),
+                );
+            }
+            original_path = match canonical {
+                Some(path) if !acted_paths.contains(path) => Some(path.to_owned()),
+                _ => Some(files.iter().find(|x| !acted_paths.contains(x)).unwrap().to_owned()),
             };
-            
-            return Ok(action);
         }
-        // Did not get valid input, return default action
-        Err(io::Error::new(io::ErrorKind::InvalidInput, "Failed to parse user input."))
+
+        // Create action
+        let action = match action_rep {
+            "D" => Delete(acted_paths, original_path.unwrap()),
+            "S" => ReplaceWithSoftlink(acted_paths, original_path.unwrap()),
+            "H" => ReplaceWithHardlink(acted_paths, original_path.unwrap()),
+            "O" => Open(acted_paths),
+            "F" => OpenFolder(acted_paths),
+            "V" => Preview(acted_paths),
+            "C" => Compare(files[file_nums[0]].to_owned(), files[file_nums[1]].to_owned()),
+            "P" => CompareFolders(files[file_nums[0]].to_owned(), files[file_nums[1]].to_owned()),
+            "Q" => Quit,
+            "I" => Ignore,
+            "U" => Undo,
+            "N" => Nothing,
+            &_ => panic!("Error parsing user input."),
+        };
+
+        Ok(action)
     }
 
     // FIXME: Do this with some real parser...
@@ -187,7 +647,7 @@ impl Actions {
     /// Returns a tuple of Actions enum member and a vector of file numbers
     fn parse_action_input(input: &str) -> Result<(String, Vec<usize>), String> {
         log::trace!("Got action input {input}");
-        let re = Regex::new(r"(?P<action>[OFDHSNQ])(?P<files>(\s+\d+)*)$").unwrap();
+        let re = Regex::new(r"(?P<action>[OFVCPDHSIUNQ])(?P<files>(\s+\d+)*)$").unwrap();
         let captures = re.captures(input);
         if let Some(cap) = captures {
             let action_str = cap.name("action").unwrap().as_str().to_owned();
@@ -197,8 +657,8 @@ impl Actions {
                 files = files_rep
                     .as_str()
                     .split_whitespace()
-                    .map(|s| s.parse().expect("Parsing error"))
-                    .collect();
+                    .map(|s| s.parse().map_err(|_| format!("There is no file with number {s}")))
+                    .collect::<Result<_, _>>()?;
             }
             Ok((action_str, files))
         // Can not parse input
@@ -207,6 +667,24 @@ impl Actions {
         }
     }
 
+    /// Parse user input string in the form "K <file numbers> <destructive action>"
+    ///
+    /// Returns a tuple of Actions enum member and a vector of numbers of files that should be
+    /// kept, or None if the input is not in this form (or a number does not fit into usize).
+    fn parse_keep_input(input: &str) -> Option<(String, Vec<usize>)> {
+        let re = Regex::new(r"^K(?P<files>(\s+\d+)+)\s+(?P<action>[DHS])$").unwrap();
+        let cap = re.captures(input)?;
+        let action_str = cap.name("action").unwrap().as_str().to_owned();
+        let files = cap
+            .name("files")
+            .unwrap()
+            .as_str()
+            .split_whitespace()
+            .map(|s| s.parse().ok())
+            .collect::<Option<Vec<usize>>>()?;
+        Some((action_str, files))
+    }
+
     /// Print error if the user entered action in wrong format
     fn print_action_input_err(iteration: u32, message: &str) {
         println!("{}", message);
@@ -216,7 +694,6 @@ impl Actions {
             println!("Let's move to another group instead...");
         }
     }
-
 }
 
 /********************/
@@ -230,8 +707,7 @@ impl Actions {
 fn open_file(file: &OsString) -> io::Result<()> {
     log::trace!("Opening file {:?}", file);
 
-    let file_str: String = file.to_owned().into_string().unwrap();
-    let out = Command::new("xdg-open").arg(file_str).output()?;
+    let out = Command::new("xdg-open").arg(file).output()?;
 
     // If opening failed, print stderr
     if !out.status.success() {
@@ -252,12 +728,92 @@ fn open_file(file: &OsString) -> io::Result<()> {
 /// # Arguments
 /// `file` - file, whose parent dir should be opened
 fn open_containing_dir(file: &OsString) -> io::Result<()> {
-    let dir = Path::new(file)
-        .parent()
-        .expect("Could not get parent path of {data.path}")
-        .as_os_str()
-        .to_owned();
-    open_file(&dir)
+    open_file(&get_parent(file).as_os_str().to_owned())
+}
+
+/// Get the beginning of the file at `path` for a quick look at its content
+///
+/// Returns the first `lines` lines of text files, and a hexdump of the first `lines` * 16 bytes of
+/// other files. A file is considered text if its first [`PREVIEW_BYTES`] bytes are valid UTF-8
+/// without NUL bytes. For directories, the names of the first `lines` entries are returned.
+///
+/// # Arguments
+/// * `path` - previewed file or directory
+/// * `lines` - maximum number of lines returned
+fn get_preview(path: &OsString, lines: usize) -> io::Result<String> {
+    if Path::new(path).is_dir() {
+        let mut names = vec![];
+        for entry in read_dir(path)?.take(lines) {
+            names.push(format!("{:?}", entry?.file_name()));
+        }
+        names.sort();
+        return Ok(names.join("\n"));
+    }
+
+    let mut buffer = vec![];
+    File::open(path)?.take(PREVIEW_BYTES).read_to_end(&mut buffer)?;
+    // A character can be cut off at the end of the buffer
+    let text = match std::str::from_utf8(&buffer) {
+        Ok(text) => Some(text),
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&buffer[..e.valid_up_to()]).ok(),
+        Err(_) => None,
+    };
+    match text {
+        Some(text) if !text.contains('\0') => {
+            Ok(text.lines().take(lines).collect::<Vec<_>>().join("\n"))
+        }
+        _ => Ok(hexdump(&buffer, lines)),
+    }
+}
+
+/// Format the first `lines` lines of a hexdump of `data` like `hexdump -C`
+fn hexdump(data: &[u8], lines: usize) -> String {
+    data.chunks(HEXDUMP_WIDTH)
+        .take(lines)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let hex: Vec<_> = chunk.iter().map(|x| format!("{:02x}", x)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|x| if x.is_ascii_graphic() || *x == b' ' { *x as char } else { '.' })
+                .collect();
+            format!(
+                "{:08x}  {:<width$}  |{}|",
+                index * HEXDUMP_WIDTH,
+                hex.join(" "),
+                ascii,
+                width = HEXDUMP_WIDTH * 3 - 1
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Get directory containing `file`
+fn get_parent(file: &OsString) -> &Path {
+    Path::new(file).parent().expect("Could not get parent path of {data.path}")
+}
+
+/// Open two files or directories in the diff tool
+///
+/// The diff tool is run with the paths appended to its arguments and waited for. Its exit status is
+/// not checked, because tools like `diff` fail if the paths differ.
+///
+/// # Arguments
+/// * `first` - first compared path
+/// * `second` - second compared path
+/// * `diff_tool` - command with arguments separated by whitespace
+fn compare(first: impl AsRef<OsStr>, second: impl AsRef<OsStr>, diff_tool: &str) -> io::Result<()> {
+    let mut words = diff_tool.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "The diff tool is empty."))?;
+    log::trace!("Comparing {:?} and {:?} with {}", first.as_ref(), second.as_ref(), diff_tool);
+
+    Command::new(program).args(words).arg(first).arg(second).status().map_err(|e| {
+        io::Error::new(e.kind(), format!("Could not run diff tool \"{}\": {}", program, e))
+    })?;
+    Ok(())
 }
 
 /// Delete `deleted` dir
@@ -265,19 +821,23 @@ fn open_containing_dir(file: &OsString) -> io::Result<()> {
 /// First confirms that user truly wants to delete the directory, that all the files in
 /// `deleted` dir are present in another (`original`) dir and that the directories share no inodes.
 ///
+/// Returns false if user declined the deletion.
+///
 /// # Arguments
 /// * `deleted` - deleted directory
 /// * `original` - directory that should contain all the files of `deleted`
-fn delete_dir(deleted: &OsString, original: &OsString) -> io::Result<()> {
+/// * `confirm` - ask user for confirmation
+fn delete_dir(deleted: &OsString, original: &OsString, confirm: bool) -> io::Result<bool> {
     // Prompt user for confirmation
-    if !Confirm::new()
-        .with_prompt(format!("Do you want to delete {:?}", deleted))
-        .wait_for_newline(true)
-        .interact()
-        .expect("Could not show dialogue.")
+    if confirm
+        && !Confirm::new()
+            .with_prompt(format!("Do you want to delete {:?}", deleted))
+            .wait_for_newline(true)
+            .interact()
+            .expect("Could not show dialogue.")
     {
         println!("Abandoning deletion...");
-        return Ok(());
+        return Ok(false);
     }
 
     // Check that original contains all files of deleted and that they share no inodes
@@ -294,39 +854,52 @@ fn delete_dir(deleted: &OsString, original: &OsString) -> io::Result<()> {
     } else {
         remove_file(deleted)?;
     }
-    Ok(())
+    Ok(true)
 }
 
-/// Replace files in `replaced` with hard links to files in `original`
+/// Replace files in `replaced` with links to files in `original`
+///
+/// Confirms that user really wants to replace all files with links and that all files are in
+/// the `original` dir and then replaces all the files with links to their duplicates
+///
+/// Hard links and reflinks take over the owner, group and mode of the original, so unless
+/// `allow_access_change` is set, nothing is replaced if any of the files differs in them from its
+/// original.
 ///
-/// Confirms that user really wants to replace all files with hard links and that all files are in
-/// the `original` dir and then replaces all the files with hardlinks to their duplicates
+/// Returns false if user declined the replacement.
 ///
 /// # Arguments
-/// * `replaced` - folder whose content should be replaced with hardlinks
+/// * `replaced` - folder whose content should be replaced with links
 /// * `original` - folder whose contents should be kept
+/// * `link_type` - type of the links
+/// * `confirm` - ask user for confirmation
+/// * `allow_access_change` - replace files whose originals have different owner, group or mode
 // FIXME: Make this multiplatform?
 fn replace_with_link(
     replaced: &OsString,
     original: &OsString,
     link_type: LinkType,
-) -> io::Result<()> {
-    #[allow(unused_assignments)]
-    let mut prompt = String::new();
-    if let LinkType::HardLink = link_type {
-        prompt = format!("Do you want to replace all contents of {:?} with hard links?", replaced);
-    } else {
-        prompt = format!("Do you want to replace all contents of {:?} with soft links?", replaced);
-    }
+    confirm: bool,
+    allow_access_change: bool,
+    created_links: &mut Vec<OsString>,
+) -> io::Result<bool> {
+    let link_name = match link_type {
+        LinkType::HardLink => "hard links",
+        LinkType::SoftLink => "soft links",
+        LinkType::Reflink => "reflinks",
+    };
+    let prompt =
+        format!("Do you want to replace all contents of {:?} with {}?", replaced, link_name);
     // Prompt user for confirmation
-    if !Confirm::new()
-        .with_prompt(prompt)
-        .wait_for_newline(true)
-        .interact()
-        .expect("Could not show dialogue.")
+    if confirm
+        && !Confirm::new()
+            .with_prompt(prompt)
+            .wait_for_newline(true)
+            .interact()
+            .expect("Could not show dialogue.")
     {
         println!("Abandoning replacement...");
-        return Ok(());
+        return Ok(false);
     }
 
     // Check that original contains all files of replaced folder
@@ -356,20 +929,286 @@ fn replace_with_link(
             // src_paths are files in `replaced` directory, dest_paths are their duplicates in
             // `original` directory
             println!("Done.");
+
+            // Links take over the metadata of the original, check that no access would change
+            if !allow_access_change && !matches!(link_type, LinkType::SoftLink) {
+                let mut changed_access = vec![];
+                for FileFound { src_paths, dest_paths } in found_files.values() {
+                    for path in src_paths {
+                        if !has_same_access(path, &dest_paths[0])? {
+                            changed_access.push(path);
+                        }
+                    }
+                }
+                if let Some(first) = changed_access.first() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!(
+                            "Could not replace {:?} with {}, {} files (e.g. {:?}) have different owner, group or mode than their originals. Use --allow-access-change to replace them anyway",
+                            replaced,
+                            link_name,
+                            changed_access.len(),
+                            first
+                        ),
+                    ));
+                }
+            }
+
             println!("Replacing all files at {:?} with links.", replaced);
             for FileFound { src_paths, dest_paths } in found_files.values() {
                 for path in src_paths {
-                    remove_file(path)?;
-                    if let LinkType::HardLink = link_type {
-                        std::fs::hard_link(&dest_paths[0], path)?;
-                    } else {
-                        std::os::unix::fs::symlink(&dest_paths[0], path)?;
+                    match link_type {
+                        LinkType::HardLink => {
+                            remove_file(path)?;
+                            std::fs::hard_link(&dest_paths[0], path)?;
+                        }
+                        LinkType::SoftLink => {
+                            // Relative target would be resolved from the directory of the link
+                            let target = std::fs::canonicalize(&dest_paths[0])?;
+                            remove_file(path)?;
+                            std::os::unix::fs::symlink(target, path)?;
+                        }
+                        LinkType::Reflink => reflink(&dest_paths[0], path)?,
                     }
+                    created_links.push(path.clone());
                 }
             }
         }
     }
 
+    Ok(true)
+}
+
+/// Replace the link created at `path` with a copy of the file it links to
+///
+/// Returns false if there is no file or symbolic link at `path` anymore.
+fn unlink(path: &OsString) -> io::Result<bool> {
+    match Path::new(path).symlink_metadata() {
+        Ok(metadata) if metadata.is_file() || metadata.file_type().is_symlink() => {}
+        _ => return Ok(false),
+    }
+    println!("Replacing link {:?} with a copy", path);
+    let mut tmp_path = path.to_owned();
+    tmp_path.push(".dude-undo");
+    copy_unshared(path, &tmp_path)?;
+    std::fs::rename(tmp_path, path)?;
+    Ok(true)
+}
+
+/// Copy `source` (with all its content if it is a directory) to `destination`
+///
+/// The copy is made with `cp` and shares no data with `source` (it is not a reflink). Symbolic
+/// link at `source` is followed.
+fn copy_unshared(source: &OsStr, destination: &OsStr) -> io::Result<()> {
+    let out = Command::new("cp")
+        .arg("-R")
+        .arg("-H")
+        .arg("--reflink=never")
+        .arg("--preserve=all")
+        .arg("--")
+        .arg(source)
+        .arg(destination)
+        .output()?;
+    if !out.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "Could not copy {:?} to {:?}: {}",
+                source,
+                destination,
+                String::from_utf8_lossy(&out.stderr).trim()
+            ),
+        ));
+    }
     Ok(())
 }
 
+/// Replace `path` with a reflink (copy-on-write clone) of `target`
+///
+/// The clone is created next to `path` with `cp --reflink=always` and then moved over `path`, so
+/// `path` stays intact if the filesystem does not support reflinks.
+fn reflink(target: &OsString, path: &OsString) -> io::Result<()> {
+    let mut tmp_path = path.to_owned();
+    tmp_path.push(".dude-reflink");
+
+    let out = Command::new("cp")
+        .arg("--reflink=always")
+        .arg("--preserve=all")
+        .arg("--")
+        .arg(target)
+        .arg(&tmp_path)
+        .output()?;
+    if !out.status.success() {
+        let _ = remove_file(&tmp_path);
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "Could not create reflink of {:?}: {}",
+                target,
+                String::from_utf8_lossy(&out.stderr).trim()
+            ),
+        ));
+    }
+    std::fs::rename(tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use std::os::unix::fs::MetadataExt;
+    use tempdir::TempDir;
+
+    /// Get paths of a group with `num` members
+    fn get_files(num: usize) -> Vec<OsString> {
+        (0..num).map(|x| format!("file{x}").into()).collect()
+    }
+
+    /// Get context of actions run without confirmation that are recorded in `audit_log`
+    fn get_context(audit_log: &Path) -> io::Result<ActionContext> {
+        Ok(ActionContext {
+            protected: ProtectedPaths::new(),
+            audit_log: Some(AuditLog::open(audit_log)?),
+            confirm: false,
+            dry_run: false,
+            diff_tool: DEFAULT_DIFF_TOOL.to_owned(),
+            preview_lines: 0,
+            ignore_list: PathBuf::new(),
+            units: Units::Si,
+            allow_access_change: false,
+            preserve_dir_mtimes: false,
+            allow_multiply_linked: false,
+            summary: Default::default(),
+            created_links: vec![],
+        })
+    }
+
+    /// Create directories `names` in `dir`, each with the same two files
+    fn create_copies(dir: &Path, names: &[&str]) -> io::Result<Vec<OsString>> {
+        let mut paths = vec![];
+        for name in names {
+            let path = dir.join(name);
+            std::fs::create_dir(&path)?;
+            std::fs::write(path.join("file1"), "Duplicate content")?;
+            std::fs::write(path.join("file2"), "Other duplicate content")?;
+            paths.push(path.into_os_string());
+        }
+        Ok(paths)
+    }
+
+    #[test]
+    /// Check that undo of a deletion recreates the deleted directory and is recorded
+    ///
+    /// Create directory structure:
+    /// tmp_dir/
+    /// ├── a/
+    /// │   ├── file1
+    /// │   └── file2
+    /// └── b/
+    ///     ├── file1
+    ///     └── file2
+    ///
+    /// and delete `b` keeping `a`.
+    fn undo_delete_test() -> Result<(), Box<dyn Error>> {
+        let tmp_dir = TempDir::new("undo_delete")?;
+        let paths = create_copies(tmp_dir.path(), &["a", "b"])?;
+        let log = tmp_dir.path().join("audit.log");
+        let mut context = get_context(&log)?;
+
+        let action = Actions::Delete(vec![paths[1].clone()], paths[0].clone());
+        assert_eq!(action.execute(&mut context)?, 1);
+        assert!(!Path::new(&paths[1]).exists());
+
+        let links = std::mem::take(&mut context.created_links);
+        assert_eq!(action.undo(&links, &mut context)?, 1);
+        for name in ["file1", "file2"] {
+            let restored = std::fs::read(Path::new(&paths[1]).join(name))?;
+            assert_eq!(restored, std::fs::read(Path::new(&paths[0]).join(name))?);
+        }
+        // Nothing is restored twice
+        assert_eq!(action.undo(&links, &mut context)?, 0);
+
+        let records = std::fs::read_to_string(&log)?;
+        let records: Vec<serde_json::Value> =
+            records.lines().map(serde_json::from_str).collect::<Result<_, _>>()?;
+        let actions: Vec<_> = records.iter().map(|x| x["action"].as_str()).collect();
+        assert_eq!(actions, [Some("Delete"), Some("UndoDelete"), Some("UndoDelete")]);
+
+        tmp_dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    /// Check that undo of a replacement with hardlinks turns only the created links into copies
+    ///
+    /// Create directory structure:
+    /// tmp_dir/
+    /// ├── a/
+    /// │   ├── file1
+    /// │   └── file2
+    /// ├── b/
+    /// │   ├── file1
+    /// │   └── file2
+    /// └── linked -> hardlink of a/file2
+    ///
+    /// and replace `b` with hardlinks to `a`.
+    fn undo_hardlink_test() -> Result<(), Box<dyn Error>> {
+        let tmp_dir = TempDir::new("undo_hardlink")?;
+        let paths = create_copies(tmp_dir.path(), &["a", "b"])?;
+        let linked = tmp_dir.path().join("linked");
+        std::fs::hard_link(Path::new(&paths[0]).join("file2"), &linked)?;
+        let mut context = get_context(&tmp_dir.path().join("audit.log"))?;
+
+        let action = Actions::ReplaceWithHardlink(vec![paths[1].clone()], paths[0].clone());
+        assert_eq!(action.execute(&mut context)?, 1);
+        let mut links = std::mem::take(&mut context.created_links);
+        links.sort();
+        let b = Path::new(&paths[1]);
+        assert_eq!(links, [b.join("file1").into_os_string(), b.join("file2").into_os_string()]);
+        assert_eq!(b.join("file2").metadata()?.nlink(), 3);
+
+        assert_eq!(action.undo(&links, &mut context)?, 2);
+        for name in ["file1", "file2"] {
+            let original = Path::new(&paths[0]).join(name);
+            assert_eq!(b.join(name).metadata()?.nlink(), 1);
+            assert_ne!(b.join(name).metadata()?.ino(), original.metadata()?.ino());
+            assert_eq!(std::fs::read(b.join(name))?, std::fs::read(original)?);
+        }
+        // The hardlink that existed before the action is kept
+        assert_eq!(linked.metadata()?.ino(), Path::new(&paths[0]).join("file2").metadata()?.ino());
+
+        tmp_dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    /// Check that "K <n> D" acts on all files except the kept ones
+    fn keep_input_test() -> Result<(), String> {
+        let files = get_files(4);
+        let action = Actions::parse_input("K 2 D\n", &files, None)?;
+        assert_eq!(action.name(), "Delete");
+        let acted = [files[0].clone(), files[1].clone(), files[3].clone()];
+        assert_eq!(action.paths(), (&acted[..], Some(&files[2])));
+
+        let action = Actions::parse_input("k 0 3 h", &files, None)?;
+        assert_eq!(action.name(), "ReplaceWithHardlink");
+        assert_eq!(action.paths(), (&files[1..3], Some(&files[0])));
+
+        // The canonical member is kept if it is among the kept files
+        let action = Actions::parse_input("K 0 3 S", &files, Some(&files[3]))?;
+        assert_eq!(action.name(), "ReplaceWithSoftlink");
+        assert_eq!(action.paths(), (&files[1..3], Some(&files[3])));
+        Ok(())
+    }
+
+    #[test]
+    /// Check that "K" with missing files or actions that keep nothing is refused, as well as file
+    /// numbers too large to parse
+    fn keep_input_error_test() {
+        let files = get_files(3);
+        let too_large = ["K 99999999999999999999 D", "D 99999999999999999999"];
+        for input in ["K 3 D", "K D", "K 0 O", "K 0"].iter().chain(&too_large) {
+            assert!(Actions::parse_input(input, &files, None).is_err(), "{input:?} was accepted");
+        }
+    }
+}
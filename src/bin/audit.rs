@@ -0,0 +1,122 @@
+//! Audit log of executed actions
+//!
+//! Each executed action is appended to the audit log as one line of json, so that it is possible
+//! to find out later what exactly DuDe changed.
+
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, prelude::*, BufReader};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use blake2::{Blake2b512, Digest};
+use duplicate_destroyer::path_serde;
+use serde::{Serialize, Serializer};
+
+/// One line of the audit log
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    /// Seconds since unix epoch
+    timestamp: u64,
+    action: &'a str,
+    paths: Vec<RawPath<'a>>,
+    original: Option<RawPath<'a>>,
+    /// Blake2 checksums of the files (directories are omitted) before the action
+    hashes: Vec<(RawPath<'a>, &'a str)>,
+    user: String,
+    /// "ok" or the error message
+    result: String,
+}
+
+/// Path serialized as a string or as an array of bytes if it is not valid Unicode
+struct RawPath<'a>(&'a OsString);
+
+impl Serialize for RawPath<'_> {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        path_serde::serialize(self.0, s)
+    }
+}
+
+/// Append-only audit log in json lines format
+pub struct AuditLog {
+    file: File,
+}
+
+impl AuditLog {
+    /// Open audit log at `path`, creating it if it does not exist
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AuditLog { file })
+    }
+
+    /// Get checksums of `paths` that should be recorded with an action
+    ///
+    /// Has to be called before the action is executed, since the action can remove the files.
+    pub fn get_hashes(paths: &[&OsString]) -> Vec<(OsString, String)> {
+        paths
+            .iter()
+            .filter(|path| Path::new(path).is_file())
+            .filter_map(|path| Some(((*path).clone(), hash_file(path).ok()?)))
+            .collect()
+    }
+
+    /// Append executed action to the log
+    ///
+    /// # Arguments
+    /// * `action` - name of the action
+    /// * `paths` - paths the action was taken on
+    /// * `original` - path kept by destructive actions
+    /// * `hashes` - checksums of files obtained by `get_hashes` before executing the action
+    /// * `result` - result of the action
+    pub fn record(
+        &mut self,
+        action: &str,
+        paths: &[OsString],
+        original: Option<&OsString>,
+        hashes: Vec<(OsString, String)>,
+        result: &io::Result<usize>,
+    ) -> io::Result<()> {
+        let record = AuditRecord {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |x| x.as_secs()),
+            action,
+            paths: paths.iter().map(RawPath).collect(),
+            original: original.map(RawPath),
+            hashes: hashes.iter().map(|(path, hash)| (RawPath(path), hash.as_str())).collect(),
+            user: get_user(),
+            result: match result {
+                Ok(_) => "ok".to_owned(),
+                Err(e) => e.to_string(),
+            },
+        };
+        let line = serde_json::to_string(&record)?;
+        writeln!(self.file, "{}", line)?;
+        self.file.flush()
+    }
+}
+
+/// Get name of the user running DuDe
+fn get_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .unwrap_or_else(|_| "unknown".to_owned())
+}
+
+/// Calculate blake2 checksum of file at `path`
+fn hash_file(path: &OsString) -> io::Result<String> {
+    let mut hasher = Blake2b512::new();
+    let mut buffer = [0u8; 1024];
+    let mut buf_reader = BufReader::new(File::open(path)?);
+
+    loop {
+        let count = buf_reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,93 @@
+//! Non-interactive handling of all duplicate groups
+//!
+//! The member of each group chosen by the keep policy is kept and the action is executed on each of
+//! the other members separately, so that a protected or changed member does not stop the handling
+//! of the rest.
+
+use std::ffi::OsString;
+use std::io;
+
+use clap::ValueEnum;
+use duplicate_destroyer::DuplicateObject;
+
+use crate::actions::{ActionContext, Actions};
+use crate::keep_policy::KeepPolicy;
+
+/// Type of links replacing the duplicates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LinkMode {
+    Hard,
+    Soft,
+    /// Copy-on-write clone (needs filesystem support, e.g. btrfs or XFS)
+    Reflink,
+}
+
+/// Replace all members of `duplicates` except the kept ones with links
+///
+/// Returns an error if any of the members could not be replaced.
+///
+/// # Arguments
+/// * `duplicates` - duplicate groups to handle
+/// * `policy` - policy choosing the kept member of each group
+/// * `mode` - type of links
+/// * `context` - settings shared by all executed actions
+pub fn link_duplicates(
+    duplicates: &[DuplicateObject],
+    policy: KeepPolicy,
+    mode: LinkMode,
+    context: &mut ActionContext,
+) -> io::Result<()> {
+    run_for_all(duplicates, policy, context, |path, original| match mode {
+        LinkMode::Hard => Actions::ReplaceWithHardlink(vec![path], original),
+        LinkMode::Soft => Actions::ReplaceWithSoftlink(vec![path], original),
+        LinkMode::Reflink => Actions::ReplaceWithReflink(vec![path], original),
+    })
+}
+
+/// Delete all members of `duplicates` except the kept ones
+///
+/// Returns an error if any of the members could not be deleted.
+///
+/// # Arguments
+/// * `duplicates` - duplicate groups to handle
+/// * `policy` - policy choosing the kept member of each group
+/// * `context` - settings shared by all executed actions
+pub fn delete_duplicates(
+    duplicates: &[DuplicateObject],
+    policy: KeepPolicy,
+    context: &mut ActionContext,
+) -> io::Result<()> {
+    run_for_all(duplicates, policy, context, |path, original| Actions::Delete(vec![path], original))
+}
+
+/// Execute action created by `make_action` for each member of each group that is not kept
+fn run_for_all<F>(
+    duplicates: &[DuplicateObject],
+    policy: KeepPolicy,
+    context: &mut ActionContext,
+    make_action: F,
+) -> io::Result<()>
+where
+    F: Fn(OsString, OsString) -> Actions,
+{
+    let mut failed = 0;
+
+    for group in duplicates {
+        let original = policy.choose(group);
+        for path in group.duplicates.iter().filter(|x| *x != original) {
+            let action = make_action(path.into(), original.into());
+            if let Err(e) = action.execute(context) {
+                eprintln!("Skipping {:?}: {}", path, e);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("Could not handle {} duplicates.", failed),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! Benchmark of the walking and hashing phases
+//!
+//! The directories are scanned once for each combination of the number of jobs and the hash
+//! algorithm. All files are hashed (not only the possible duplicates), so the throughput shows how
+//! fast the hardware can be read and hashed with the given settings.
+
+use std::ffi::OsString;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use duplicate_destroyer::{Config, HashAlgorithm, Scanner};
+
+use crate::{get_human_readable_size, Units};
+
+/// Scan `paths` with each combination of `jobs` and `algorithms` and print the throughput
+///
+/// All algorithms are benchmarked if `algorithms` is empty.
+pub fn run_bench(paths: &[OsString], jobs: &[usize], algorithms: &[HashAlgorithm], units: Units) {
+    let algorithms =
+        if algorithms.is_empty() { HashAlgorithm::value_variants() } else { algorithms };
+
+    println!("The first run may read from disk and the later ones from the page cache.");
+    println!(
+        "{:<10} {:>4} {:>9} {:>9} {:>9} {:>11}",
+        "Algorithm", "Jobs", "Walking", "Hashing", "Hashed", "Throughput"
+    );
+    for algorithm in algorithms {
+        for num_jobs in jobs {
+            let mut config: Config = Default::default();
+            config.set_hash_algorithm(*algorithm);
+            config.set_num_threads(num_jobs.saturating_sub(1));
+            config.set_hash_all_files(true);
+
+            let timings = Scanner::new(paths.to_vec(), &config).unwrap().timings();
+            let total = timings.walking + timings.hashing;
+            println!(
+                "{:<10} {:>4} {:>8.2}s {:>8.2}s {:>9} {:>9}/s",
+                algorithm.to_possible_value().expect("No skipped algorithms.").get_name(),
+                num_jobs,
+                timings.walking.as_secs_f64(),
+                timings.hashing.as_secs_f64(),
+                get_human_readable_size(timings.bytes_hashed, units),
+                get_human_readable_size(get_throughput(timings.bytes_hashed, total), units)
+            );
+        }
+    }
+}
+
+/// Get number of bytes processed per second
+fn get_throughput(bytes: u64, duration: Duration) -> u64 {
+    let seconds = duration.as_secs_f64();
+    if seconds > 0.0 {
+        (bytes as f64 / seconds) as u64
+    } else {
+        0
+    }
+}
@@ -0,0 +1,138 @@
+//! Inspection and removal of the checksum journals in the cache directory
+//!
+//! A journal is left behind in the cache directory by each scan that did not finish (see
+//! [`get_journal_path`](crate::get_journal_path)). It is only reused by the same scan, so the
+//! journals of scans that are never run again have to be removed by `dude cache --clear`.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::{get_human_readable_size, Units};
+
+/// Prefix of the names of the journals in the cache directory
+pub const JOURNAL_PREFIX: &str = "journal-";
+
+/// Print the journals in `cache_dir` with their size, number of entries and age
+pub fn print_cache(cache_dir: &Path, units: Units) -> io::Result<()> {
+    println!("Cache directory: {}", cache_dir.display());
+    let journals = get_journals(cache_dir)?;
+    if journals.is_empty() {
+        println!("No journals of unfinished scans.");
+        return Ok(());
+    }
+
+    let mut total_size = 0;
+    for path in &journals {
+        let metadata = fs::symlink_metadata(path)?;
+        let entries = fs::read(path).map_or(0, |x| x.iter().filter(|x| **x == b'\n').count());
+        let age = metadata.modified().ok().and_then(|x| SystemTime::now().duration_since(x).ok());
+        total_size += metadata.len();
+        println!(
+            "{}\t{}\t{} entries\tmodified {} ago",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            get_human_readable_size(metadata.len(), units),
+            entries,
+            age.map_or_else(|| "?".to_owned(), format_age),
+        );
+    }
+    println!("{} journals taking {}", journals.len(), get_human_readable_size(total_size, units));
+    Ok(())
+}
+
+/// Remove the journals in `cache_dir`
+///
+/// The journals used by a running scan are skipped, so that the scan still removes its journal
+/// when it finishes.
+pub fn clear_cache(cache_dir: &Path) -> io::Result<()> {
+    let mut removed = 0;
+    for path in get_journals(cache_dir)? {
+        match remove_journal(&path) {
+            Ok(()) => removed += 1,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                println!("Skipping {}, it is used by a running scan.", path.display())
+            }
+            Err(e) => println!("Could not remove {}: {}", path.display(), e),
+        }
+    }
+    println!("Removed {removed} journals from {}.", cache_dir.display());
+    Ok(())
+}
+
+/// Get paths of the journals in `cache_dir` sorted by name
+fn get_journals(cache_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut journals = vec![];
+    for entry in fs::read_dir(cache_dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with(JOURNAL_PREFIX) {
+            journals.push(entry.path());
+        }
+    }
+    journals.sort();
+    Ok(journals)
+}
+
+/// Remove the journal at `path` unless it is locked by a running scan
+#[cfg(unix)]
+fn remove_journal(path: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if !fs::symlink_metadata(path)?.is_file() {
+        return fs::remove_file(path);
+    }
+    let file = File::open(path)?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    fs::remove_file(path)
+}
+
+/// The journals are not locked on platforms other than Unix
+#[cfg(not(unix))]
+fn remove_journal(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)
+}
+
+/// Format `age` in the largest unit in which it is at least 1 (e.g. 3h or 2d)
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}min", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Check that only the journals are removed from the cache directory and that a journal
+    /// locked by a running scan is kept
+    fn clear_cache_test() -> Result<(), Box<dyn Error>> {
+        use std::os::unix::io::AsRawFd;
+
+        let tmp_dir = TempDir::new("clear_cache")?;
+        let cache_dir = tmp_dir.path();
+        for name in ["journal-0001", "journal-0002", "journal-0003", "other"] {
+            fs::write(cache_dir.join(name), "1\t2\tabcd\tpath\n")?;
+        }
+        let used = File::open(cache_dir.join("journal-0003"))?;
+        assert_eq!(unsafe { libc::flock(used.as_raw_fd(), libc::LOCK_EX) }, 0);
+
+        clear_cache(cache_dir)?;
+
+        let remaining: Vec<_> = get_journals(cache_dir)?;
+        assert_eq!(remaining, vec![cache_dir.join("journal-0003")]);
+        assert!(cache_dir.join("other").exists());
+
+        drop(used);
+        tmp_dir.close()?;
+        Ok(())
+    }
+}
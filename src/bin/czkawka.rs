@@ -0,0 +1,81 @@
+//! Duplicates in the json layout of czkawka
+//!
+//! czkawka saves the duplicate files found by their hash as a map from the size of the files to
+//! the groups of files of that size, each file with its path, modification time, size and hash.
+//! Writing the same layout lets the scripts and GUIs parsing czkawka's results read the results of
+//! DuDe as well. czkawka only reports files, so the duplicate directories are written as the groups
+//! of the files they contain matched by their content.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use duplicate_destroyer::{DuplicateObject, Scanner};
+use serde::Serialize;
+
+/// File of a duplicate group
+#[derive(Serialize)]
+struct DuplicateEntry {
+    #[serde(serialize_with = "duplicate_destroyer::path_serde::serialize")]
+    path: PathBuf,
+    /// Seconds since the Unix epoch (0 if unknown)
+    modified_date: u64,
+    size: u64,
+    /// Checksum of the content (empty if the file was not hashed)
+    hash: String,
+}
+
+/// Write the files of `duplicates` grouped by their content and sizes to `out`
+///
+/// # Arguments
+/// * `duplicates` - duplicate groups found by `scanner`
+/// * `scanner` - scanner used to match the files of duplicate directories
+/// * `out` - destination of the json
+pub fn write_czkawka<W: Write>(
+    duplicates: &[DuplicateObject],
+    scanner: &Scanner,
+    out: &mut W,
+) -> io::Result<()> {
+    let mut groups: BTreeMap<u64, Vec<Vec<DuplicateEntry>>> = BTreeMap::new();
+    for group in duplicates {
+        match scanner.expand_group(group) {
+            Some(expansion) => {
+                for file_match in expansion.matches {
+                    let hash = file_match.digest.unwrap_or_default();
+                    let entries = file_match
+                        .paths
+                        .into_iter()
+                        .map(|x| get_entry(x, file_match.size, &hash))
+                        .collect();
+                    groups.entry(file_match.size).or_default().push(entries);
+                }
+            }
+            // The members were not scanned, so they can't be matched by files
+            None => {
+                let hash = group.digest.clone().unwrap_or_default();
+                let entries =
+                    group.duplicates.iter().map(|x| get_entry(x.clone(), group.size, &hash));
+                groups.entry(group.size).or_default().push(entries.collect());
+            }
+        }
+    }
+
+    serde_json::to_writer_pretty(&mut *out, &groups)?;
+    writeln!(out)
+}
+
+/// Get entry of the file at `path`
+fn get_entry(path: PathBuf, size: u64, hash: &str) -> DuplicateEntry {
+    DuplicateEntry { modified_date: get_modified(&path), path, size, hash: hash.to_owned() }
+}
+
+/// Get modification time of the file at `path` in seconds since the Unix epoch (0 if unknown)
+fn get_modified(path: &Path) -> u64 {
+    fs::symlink_metadata(path)
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |x| x.as_secs())
+}
@@ -0,0 +1,89 @@
+//! Comparison of two directories by content
+//!
+//! Both directories are scanned together and every file or directory that has no copy in the other
+//! directory is printed. Directories are printed whole if none of their contents has a copy,
+//! otherwise only their contents without a copy are printed.
+
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use duplicate_destroyer::{Config, NodeKind, Scanner, TreeNode};
+
+/// Print contents of `left` missing in `right` (prefixed by `-`) and contents of `right` missing
+/// in `left` (prefixed by `+`)
+pub fn print_diff(left: OsString, right: OsString, config: &Config) -> io::Result<()> {
+    let scanner = Scanner::new(vec![left, right], config)?;
+
+    let roots = scanner.roots();
+    if roots.len() != 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The directories are the same or one is nested in the other.",
+        ));
+    }
+
+    for (index, root) in roots.iter().enumerate() {
+        let other = roots[1 - index].path();
+        let sign = if index == 0 { '-' } else { '+' };
+        for path in get_missing(root, &other) {
+            println!("{} {:?}", sign, path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Get topmost paths under `node` that have no duplicate under `other`
+///
+/// The tree is walked with an explicit stack, so that deep trees do not overflow the stack. Each
+/// directory is finished after all of its children, so that it can be reported whole if none of
+/// its contents has a copy.
+fn get_missing(node: &TreeNode, other: &Path) -> Vec<PathBuf> {
+    // Directories entered and not finished yet, with their number of children, the missing paths
+    // found in the children so far and the number of children missing as a whole
+    let mut open: Vec<(PathBuf, usize, Vec<PathBuf>, usize)> = vec![];
+    // `None` finishes the last directory entered
+    let mut stack = vec![Some(node.clone())];
+    let mut result = vec![];
+    while let Some(item) = stack.pop() {
+        let (path, missing) = match item {
+            Some(node) if node.duplicates().iter().any(|x| x.path().starts_with(other)) => {
+                (node.path(), vec![])
+            }
+            Some(node) => {
+                let children = node.children();
+                if node.kind() != NodeKind::Dir || children.is_empty() {
+                    (node.path(), vec![node.path()])
+                } else {
+                    open.push((node.path(), children.len(), vec![], 0));
+                    stack.push(None);
+                    // The first child is popped first
+                    stack.extend(children.into_iter().rev().map(Some));
+                    continue;
+                }
+            }
+            None => {
+                let (path, num_children, missing, num_whole) =
+                    open.pop().expect("Directory was entered.");
+                if num_whole == num_children {
+                    // Nothing in the directory has a copy
+                    (path.clone(), vec![path])
+                } else {
+                    (path, missing)
+                }
+            }
+        };
+
+        match open.last_mut() {
+            Some((_, _, parent_missing, num_whole)) => {
+                if missing.len() == 1 && missing[0] == path {
+                    *num_whole += 1;
+                }
+                parent_missing.extend(missing);
+            }
+            None => result = missing,
+        }
+    }
+    result
+}
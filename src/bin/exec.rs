@@ -0,0 +1,109 @@
+//! User command run for each duplicate group
+//!
+//! The command is run by `sh -c` once for each group after the placeholders in it are replaced
+//! with the values of the group quoted for the shell:
+//! * `{paths}` - all paths in the group separated by spaces
+//! * `{keep}` - path chosen by `--keep`
+//! * `{size}` - size of one path in bytes
+//! * `{hash}` - digest of the content of the paths (empty if it is not known)
+//! * `{count}` - number of paths in the group
+
+use std::ffi::OsStr;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::process::Command;
+
+use duplicate_destroyer::DuplicateObject;
+
+use crate::keep_policy::KeepPolicy;
+use crate::script::quote;
+use crate::template;
+
+/// Run `command` for each of the `duplicates`
+///
+/// Returns an error if the command failed for any of the groups, the command is run for the
+/// remaining groups anyway.
+///
+/// # Arguments
+/// * `duplicates` - duplicate groups the command is run for
+/// * `command` - shell command with placeholders
+/// * `policy` - policy choosing the path substituted for `{keep}`
+pub fn run_for_groups(
+    duplicates: &[DuplicateObject],
+    command: &str,
+    policy: KeepPolicy,
+) -> io::Result<()> {
+    let mut failed = 0;
+
+    for (index, group) in duplicates.iter().enumerate() {
+        let expanded = expand(command, group, policy);
+        log::debug!("Running {:?}", OsStr::from_bytes(&expanded));
+        let status = Command::new("sh").arg("-c").arg(OsStr::from_bytes(&expanded)).status()?;
+        if !status.success() {
+            eprintln!(
+                "Command for group {}/{} failed with {}",
+                index + 1,
+                duplicates.len(),
+                status
+            );
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("Command failed for {} groups.", failed),
+        ));
+    }
+    Ok(())
+}
+
+/// Replace the placeholders in `command` with the quoted values of `group`
+fn expand(command: &str, group: &DuplicateObject, policy: KeepPolicy) -> Vec<u8> {
+    let paths: Vec<_> = group.duplicates.iter().map(|x| quote(x.as_os_str())).collect();
+    let digest = group.digest.clone().unwrap_or_default();
+    let values = [
+        ("{paths}", paths.join(&b' ')),
+        ("{keep}", quote(policy.choose(group).as_os_str())),
+        ("{size}", group.size.to_string().into_bytes()),
+        ("{hash}", quote(OsStr::new(&digest))),
+        ("{count}", group.len().to_string().into_bytes()),
+    ];
+    template::fill(command, &values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Check that the command gets the paths with special characters as separate arguments
+    ///
+    /// The group consists of paths with a quote, a newline and bytes that are not valid UTF-8 in
+    /// their names. The command prints each of its arguments followed by a null byte.
+    fn exec_special_names_test() -> Result<(), Box<dyn Error>> {
+        let tmp_dir = TempDir::new("exec_names")?;
+        let paths: Vec<OsString> =
+            vec!["it's".into(), "new\nline".into(), OsString::from_vec(b"caf\xe9".to_vec())];
+        let group = DuplicateObject::new(17, paths.iter().map(|x| tmp_dir.path().join(x)));
+        let out = tmp_dir.path().join("out");
+        let command = format!("printf '%s\\0' {{paths}} {{keep}} > {:?}", out);
+
+        run_for_groups(std::slice::from_ref(&group), &command, KeepPolicy::First)?;
+
+        let mut expected: Vec<_> = group.duplicates.iter().collect();
+        expected.push(KeepPolicy::First.choose(&group));
+        let printed = std::fs::read(&out)?;
+        let printed: Vec<_> = printed.split(|x| *x == 0).filter(|x| !x.is_empty()).collect();
+        let expected: Vec<_> = expected.iter().map(|x| x.as_os_str().as_bytes()).collect();
+        assert_eq!(printed, expected);
+
+        tmp_dir.close()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+//! Copies of given files in the searched directories
+//!
+//! Only the files of the same size as one of the given files are read, so finding the copies of a
+//! few files is much faster than a scan.
+
+use std::ffi::OsString;
+use std::io;
+
+use duplicate_destroyer::Config;
+
+/// Print each of `files` followed by its copies in `paths`
+pub fn print_copies(files: Vec<OsString>, paths: Vec<OsString>, config: &Config) -> io::Result<()> {
+    for file in duplicate_destroyer::find_copies(files, paths, config)? {
+        println!("{:?}", file.path);
+        if file.copies.is_empty() {
+            println!("  (no copies)");
+        }
+        for copy in file.copies {
+            println!("  = {:?}", copy);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,66 @@
+//! Preview of the space freed by destructive actions
+//!
+//! Deleting or replacing members of a group does not always free their nominal size. Files with
+//! hardlinks outside of the selection (e.g. to the kept original) keep their data, and the space
+//! allocated on disk differs from the size of sparse or small files. Replacing the files with links
+//! also keeps their directories, only deleting frees them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+
+use walkdir::WalkDir;
+
+/// Space freed by a destructive action
+#[derive(Debug, Default)]
+pub struct FreedSpace {
+    /// Bytes freed on each device
+    pub per_device: BTreeMap<u64, u64>,
+    /// Number of files that are not freed, because they have hardlinks outside of the selection
+    pub linked_elsewhere: usize,
+}
+
+/// Get the space freed by removing all files in `paths`
+///
+/// A file is freed only if all of its hardlinks are in `paths`. Extents shared by reflinks made
+/// before can't be detected, so such files are counted as freed.
+///
+/// # Arguments
+/// * `paths` - deleted or replaced paths
+/// * `keep_dirs` - the directories (and symlinks) are kept, only the files are replaced
+pub fn get_freed_space(paths: &[OsString], keep_dirs: bool) -> io::Result<FreedSpace> {
+    let mut freed = FreedSpace::default();
+    // Number of hardlinks, links found and allocated space of each file with multiple hardlinks
+    let mut links: HashMap<(u64, u64), (u64, u64, u64)> = HashMap::new();
+
+    for path in paths {
+        for entry in WalkDir::new(path) {
+            let metadata = entry?.metadata()?;
+            if keep_dirs && !metadata.is_file() {
+                continue;
+            }
+            let allocated = metadata.blocks() * 512;
+            if metadata.is_file() && metadata.nlink() > 1 {
+                let link = links.entry((metadata.dev(), metadata.ino())).or_insert((
+                    metadata.nlink(),
+                    0,
+                    allocated,
+                ));
+                link.1 += 1;
+            } else {
+                *freed.per_device.entry(metadata.dev()).or_default() += allocated;
+            }
+        }
+    }
+
+    for ((device, _), (nlink, found, allocated)) in links {
+        if found >= nlink {
+            *freed.per_device.entry(device).or_default() += allocated;
+        } else {
+            freed.linked_elsewhere += 1;
+        }
+    }
+
+    Ok(freed)
+}
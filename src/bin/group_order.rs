@@ -0,0 +1,75 @@
+//! Orders in which the duplicate groups are presented
+//!
+//! The groups found by DuDe are sorted by the size of their elements (largest first). Other orders
+//! can help to go through the groups that save the most space or that are in the same part of the
+//! directory tree together.
+
+use std::cmp::Reverse;
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+
+use clap::ValueEnum;
+use duplicate_destroyer::DuplicateObject;
+
+/// Order of duplicate groups
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupOrder {
+    /// Largest elements first
+    Size,
+    /// Largest space saved by keeping only one member first
+    Savings,
+    /// Most members first
+    Count,
+    /// Groups under the same top-level directory together (largest elements first within it)
+    Directory,
+}
+
+impl GroupOrder {
+    /// Sort `groups` in this order
+    ///
+    /// The sort is stable, so groups that are equal in this order keep their relative order.
+    ///
+    /// # Arguments
+    /// * `groups` - duplicate groups sorted by the size of their elements
+    /// * `input_paths` - input paths that were searched for duplicates
+    pub fn sort(&self, groups: &mut [DuplicateObject], input_paths: &[OsString]) {
+        match self {
+            GroupOrder::Size => groups.sort_by_key(|x| Reverse(x.size)),
+            GroupOrder::Savings => groups.sort_by_key(|x| Reverse(x.savings())),
+            GroupOrder::Count => groups.sort_by_key(|x| Reverse(x.len())),
+            GroupOrder::Directory => {
+                groups.sort_by_cached_key(|group| top_level_dir(group, input_paths))
+            }
+        }
+    }
+}
+
+/// Get the top-level directory containing the first member of `group`
+///
+/// The top-level directory is the child of the input path the member was found in. If the input
+/// path is not known, it is the first component of the member's path.
+fn top_level_dir(group: &DuplicateObject, input_paths: &[OsString]) -> PathBuf {
+    let path = match group.duplicates.iter().next() {
+        Some(path) => Path::new(path),
+        None => return PathBuf::new(),
+    };
+
+    let root = group.roots.get(path).and_then(|x| input_paths.get(*x));
+    match root.and_then(|root| Some((root, path.strip_prefix(root).ok()?))) {
+        Some((root, relative)) => {
+            let mut top = PathBuf::from(root);
+            top.extend(relative.components().next());
+            top
+        }
+        None => {
+            let mut top = PathBuf::new();
+            for component in path.components() {
+                top.push(component);
+                if let Component::Normal(_) = component {
+                    break;
+                }
+            }
+            top
+        }
+    }
+}
@@ -1,13 +1,15 @@
-/// 
+//! Helper functions for actions performed on duplicate groups
 
 use std::collections::HashSet;
+use std::ffi::{CString, OsString};
 use std::io;
-use std::ffi::OsString;
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
 
 use copy_confirmer::*;
-use walkdir::WalkDir;
 use minus::Pager;
+use walkdir::WalkDir;
 
 /// Print text to static pager
 pub fn print_to_pager(text: String) {
@@ -42,7 +44,6 @@ pub fn verify_copy(original: &OsString, copy: &OsString) -> io::Result<bool> {
 
         return Ok(false);
     }
-    
 
     // Verify that the copy shares no inodes with original dir
     let origin_inodes: HashSet<_> = WalkDir::new(original)
@@ -72,3 +73,42 @@ pub fn verify_copy(original: &OsString, copy: &OsString) -> io::Result<bool> {
     Ok(true)
 }
 
+/// Returns true if `path` has the same owner, group and mode as `original`
+///
+/// A hard link (or a reflink copying the metadata of the original) replacing `path` takes over the
+/// owner, group and mode of `original`, which would change who can access `path` otherwise.
+pub fn has_same_access(path: &OsString, original: &OsString) -> io::Result<bool> {
+    let path = std::fs::metadata(path)?;
+    let original = std::fs::metadata(original)?;
+    Ok(path.uid() == original.uid()
+        && path.gid() == original.gid()
+        && path.mode() == original.mode())
+}
+
+/// Get modification times (seconds and nanoseconds) of `dirs`
+///
+/// Directories whose metadata can't be read are left out.
+pub fn get_mtimes(dirs: Vec<PathBuf>) -> Vec<(PathBuf, i64, i64)> {
+    dirs.into_iter()
+        .filter_map(|dir| {
+            let metadata = std::fs::metadata(&dir).ok()?;
+            Some((dir, metadata.mtime(), metadata.mtime_nsec()))
+        })
+        .collect()
+}
+
+/// Set modification time of `path`, leaving its access time unchanged
+pub fn set_mtime(path: &Path, seconds: i64, nanoseconds: i64) -> io::Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let times = [
+        libc::timespec { tv_sec: 0, tv_nsec: libc::UTIME_OMIT },
+        libc::timespec { tv_sec: seconds as libc::time_t, tv_nsec: nanoseconds as _ },
+    ];
+    // SAFETY: the path is a valid nul-terminated string and times point to two timespecs
+    let result = unsafe { libc::utimensat(libc::AT_FDCWD, c_path.as_ptr(), times.as_ptr(), 0) };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
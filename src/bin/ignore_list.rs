@@ -0,0 +1,104 @@
+//! Persistent list of duplicate groups that user chose to keep
+//!
+//! Some duplicates are intentional (e.g. templates or seeded torrents). Groups ignored in the
+//! interactive handling are appended to the ignore list as one line of json with their digest and
+//! paths, and scans with `--respect-ignores` leave them out. A group is left out only while all of
+//! its paths are among the recorded ones and its content did not change, so a new copy of ignored
+//! content is reported again.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use duplicate_destroyer::DuplicateObject;
+use serde::{Deserialize, Serialize};
+
+/// Name of the ignore list in the data directory of DuDe
+const IGNORE_LIST_NAME: &str = "ignored.jsonl";
+
+/// One line of the ignore list
+#[derive(Serialize, Deserialize)]
+struct IgnoredGroup {
+    /// Digest of the content of the group
+    digest: String,
+    paths: Vec<IgnoredPath>,
+}
+
+/// Path serialized as a string or as an array of bytes if it is not valid Unicode
+#[derive(Serialize, Deserialize)]
+struct IgnoredPath(#[serde(with = "duplicate_destroyer::path_serde")] PathBuf);
+
+/// Groups recorded in the ignore list
+pub struct IgnoreList {
+    /// Paths of the ignored groups by their digest
+    groups: HashMap<String, Vec<BTreeSet<PathBuf>>>,
+}
+
+impl IgnoreList {
+    /// Read the ignore list at `path` (empty if it does not exist)
+    ///
+    /// Lines that can't be parsed are skipped.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut groups: HashMap<String, Vec<BTreeSet<PathBuf>>> = HashMap::new();
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(IgnoreList { groups }),
+            Err(e) => return Err(e),
+        };
+        for line in BufReader::new(file).lines() {
+            match serde_json::from_str::<IgnoredGroup>(&line?) {
+                Ok(group) => groups
+                    .entry(group.digest)
+                    .or_default()
+                    .push(group.paths.into_iter().map(|x| x.0).collect()),
+                Err(e) => log::warn!("Skipping invalid line of ignore list {:?}: {}", path, e),
+            }
+        }
+        Ok(IgnoreList { groups })
+    }
+
+    /// Returns true if `group` has the digest of an ignored group and all its paths were ignored
+    /// with it
+    pub fn ignores(&self, group: &DuplicateObject) -> bool {
+        let ignored = match group.digest.as_ref().and_then(|x| self.groups.get(x)) {
+            Some(ignored) => ignored,
+            None => return false,
+        };
+        ignored.iter().any(|paths| group.duplicates.is_subset(paths))
+    }
+}
+
+/// Append `group` to the ignore list at `path`, creating the list (and its directory) if needed
+///
+/// # Errors
+/// Fails if the digest of the group is not known.
+pub fn ignore_group(path: &Path, group: &DuplicateObject) -> io::Result<()> {
+    let digest = group.digest.clone().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "The content of the group was not hashed.")
+    })?;
+    let record = IgnoredGroup {
+        digest,
+        paths: group.duplicates.iter().map(|x| IgnoredPath(x.clone())).collect(),
+    };
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(&record)?)
+}
+
+/// Get the default path of the ignore list
+///
+/// The list is in `$XDG_DATA_HOME/dude` (or `~/.local/share/dude` if it is not set).
+pub fn default_path() -> PathBuf {
+    let data_home = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let home = std::env::var_os("HOME").unwrap_or_default();
+            PathBuf::from(home).join(".local/share")
+        }
+    };
+    data_home.join("dude").join(IGNORE_LIST_NAME)
+}
@@ -0,0 +1,54 @@
+//! Policies choosing which member of a duplicate group is kept
+//!
+//! The member in a path given by `--prefer` is always kept if there is one. Otherwise the member
+//! is chosen by the policy.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use clap::ValueEnum;
+use duplicate_destroyer::DuplicateObject;
+
+/// Which member of a duplicate group is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeepPolicy {
+    /// Keep the first path in alphabetical order
+    First,
+    /// Keep the last path in alphabetical order
+    Last,
+    /// Keep the path with the fewest characters
+    Shortest,
+    /// Keep the path with the most characters
+    Longest,
+    /// Keep the least recently modified path
+    Oldest,
+    /// Keep the most recently modified path
+    Newest,
+}
+
+impl KeepPolicy {
+    /// Choose the member of `group` that is kept
+    pub fn choose<'a>(&self, group: &'a DuplicateObject) -> &'a PathBuf {
+        if let Some(canonical) = group.canonical.as_ref().and_then(|x| group.duplicates.get(x)) {
+            return canonical;
+        }
+
+        // The paths are sorted, so ties resolve to the alphabetically first path
+        let paths: Vec<_> = group.duplicates.iter().collect();
+        let chosen = match self {
+            KeepPolicy::First => paths.first().copied(),
+            KeepPolicy::Last => paths.last().copied(),
+            KeepPolicy::Shortest => paths.iter().copied().min_by_key(|x| x.as_os_str().len()),
+            KeepPolicy::Longest => paths.iter().copied().rev().max_by_key(|x| x.as_os_str().len()),
+            KeepPolicy::Oldest => paths.iter().copied().min_by_key(|x| modified(x)),
+            KeepPolicy::Newest => paths.iter().copied().rev().max_by_key(|x| modified(x)),
+        };
+        chosen.expect("Duplicate group should not be empty.")
+    }
+}
+
+/// Get modification time of `path`, paths that can't be read are considered the oldest
+fn modified(path: &Path) -> SystemTime {
+    fs::symlink_metadata(path).and_then(|x| x.modified()).unwrap_or(SystemTime::UNIX_EPOCH)
+}
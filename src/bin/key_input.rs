@@ -0,0 +1,199 @@
+//! Single-key input of the actions on duplicate groups
+//!
+//! When both stdin and stdout are terminals, each action is chosen by a single keypress without
+//! pressing Enter. The arrow keys move the cursor to a file of the group and the actions are taken
+//! on the file under the cursor, or on the files marked with space. Otherwise the actions are read
+//! line by line by [`Actions::get_from_input`].
+
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::io;
+
+use dialoguer::console::{Key, Term};
+
+use crate::actions::{Actions, ACTION_KEYS};
+
+/// Returns true if the actions can be chosen by single keypresses
+pub fn is_available() -> bool {
+    Term::stdout().is_term() && unsafe { libc::isatty(libc::STDIN_FILENO) } == 1
+}
+
+/// Get action and files affected from single keypresses
+///
+/// # Arguments
+/// * `files` - Vector of duplicate files in a duplicate group
+/// * `canonical` - file that should be kept by destructive actions if it is not acted upon
+pub fn get_from_keys(files: &[OsString], canonical: Option<&OsString>) -> io::Result<Actions> {
+    println!("{}", ACTION_KEYS);
+    println!("(Use ↑/↓ to choose a file and Space to mark more files, Esc to unmark them.)");
+    println!("(Use K and then D, H or S to keep the chosen file and act on all other files.)");
+    println!("(Use / to show only groups with a path matching a pattern.)");
+
+    let term = Term::stdout();
+    let mut selection = Selection::default();
+    loop {
+        term.clear_line()?;
+        term.write_str(&get_status(files, &selection))?;
+        let key = match term.read_key() {
+            Ok(key) => key,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                term.write_line("")?;
+                return Ok(Actions::Quit);
+            }
+            Err(e) => return Err(e),
+        };
+
+        if key == Key::Char('/') {
+            term.clear_line()?;
+            term.write_str("/")?;
+            let pattern = term.read_line()?;
+            return Ok(Actions::Filter(pattern.trim().to_owned()));
+        }
+        let (action_rep, file_nums) = match selection.press(key, files.len()) {
+            Some(chosen) => chosen,
+            None => continue,
+        };
+
+        term.write_line("")?;
+        match Actions::from_selection(&action_rep.to_string(), &file_nums, files, canonical) {
+            Ok(action) => return Ok(action),
+            Err(message) => println!("{}", message),
+        }
+    }
+}
+
+/// File under the cursor and the files marked by the keys pressed so far
+#[derive(Default)]
+struct Selection {
+    cursor: usize,
+    marked: BTreeSet<usize>,
+    /// K was pressed, so the next destructive action keeps only the file under the cursor
+    keep: bool,
+}
+
+impl Selection {
+    /// Update the selection by `key` pressed in a group of `num_files` files
+    ///
+    /// Returns the key of the chosen action and the numbers of the files it is taken on, or None
+    /// if no action was chosen yet.
+    fn press(&mut self, key: Key, num_files: usize) -> Option<(char, Vec<usize>)> {
+        let action_rep = match key {
+            Key::ArrowUp | Key::ArrowLeft => {
+                self.cursor = self.cursor.saturating_sub(1);
+                return None;
+            }
+            Key::ArrowDown | Key::ArrowRight => {
+                self.cursor = (self.cursor + 1).min(num_files - 1);
+                return None;
+            }
+            Key::Char(' ') => {
+                if !self.marked.remove(&self.cursor) {
+                    self.marked.insert(self.cursor);
+                }
+                return None;
+            }
+            Key::Escape => {
+                self.marked.clear();
+                self.keep = false;
+                return None;
+            }
+            Key::Char(c) => match c.to_digit(10) {
+                Some(num) if (num as usize) < num_files => {
+                    self.cursor = num as usize;
+                    return None;
+                }
+                Some(_) => return None,
+                None => c.to_ascii_uppercase(),
+            },
+            _ => return None,
+        };
+
+        // Files the action is taken on
+        let file_nums: Vec<usize> = match action_rep {
+            'D' | 'H' | 'S' if self.keep => (0..num_files).filter(|x| *x != self.cursor).collect(),
+            _ if self.keep => {
+                self.keep = false;
+                return None;
+            }
+            'K' => {
+                self.keep = true;
+                return None;
+            }
+            'O' | 'F' | 'V' | 'D' | 'H' | 'S' if self.marked.is_empty() => vec![self.cursor],
+            'O' | 'F' | 'V' | 'D' | 'H' | 'S' | 'C' | 'P' => self.marked.iter().copied().collect(),
+            'I' | 'U' | 'N' | 'Q' => vec![],
+            _ => return None,
+        };
+        self.keep = false;
+        Some((action_rep, file_nums))
+    }
+}
+
+/// Get line showing the file under the cursor and the files marked
+fn get_status(files: &[OsString], selection: &Selection) -> String {
+    let mut status = format!("> {}. {:?}", selection.cursor, files[selection.cursor]);
+    if !selection.marked.is_empty() {
+        let marked: Vec<_> = selection.marked.iter().map(|x| x.to_string()).collect();
+        status += &format!(" (marked: {})", marked.join(", "));
+    }
+    if selection.keep {
+        status +=
+            " Keep it and [D]elete, ReplaceWith[H]ardlink or ReplaceWith[S]oftlink the others";
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Press `keys` in a group of `num_files` files and get the first action chosen
+    fn press_keys(keys: &[Key], num_files: usize) -> Option<(char, Vec<usize>)> {
+        let mut selection = Selection::default();
+        keys.iter().find_map(|key| selection.press(key.clone(), num_files))
+    }
+
+    #[test]
+    /// Check that K followed by an action acts on all files except the one under the cursor
+    fn keep_shorthand_test() {
+        let keys = [Key::Char('2'), Key::Char('k'), Key::Char('d')];
+        assert_eq!(press_keys(&keys, 4), Some(('D', vec![0, 1, 3])));
+
+        let keys = [Key::ArrowDown, Key::Char('K'), Key::Char('H')];
+        assert_eq!(press_keys(&keys, 3), Some(('H', vec![0, 2])));
+
+        // Other keys and Esc cancel the shorthand
+        let keys = [Key::Char('k'), Key::Char('o'), Key::Char('d')];
+        assert_eq!(press_keys(&keys, 3), Some(('D', vec![0])));
+        let keys = [Key::Char('k'), Key::Escape, Key::Char('s')];
+        assert_eq!(press_keys(&keys, 3), Some(('S', vec![0])));
+    }
+
+    #[test]
+    /// Check that actions are taken on the marked files instead of the file under the cursor
+    fn marked_files_test() {
+        let keys = [Key::Char(' '), Key::Char('2'), Key::Char(' '), Key::Char('c')];
+        assert_eq!(press_keys(&keys, 3), Some(('C', vec![0, 2])));
+
+        // Numbers out of the group and the cursor past the last file are ignored
+        let keys = [Key::Char('7'), Key::ArrowDown, Key::ArrowDown, Key::Char('v')];
+        assert_eq!(press_keys(&keys, 2), Some(('V', vec![1])));
+    }
+
+    #[test]
+    /// Check that the status shows the marked files and the pending K
+    fn get_status_test() {
+        let files: Vec<OsString> = vec!["a".into(), "b".into()];
+        let mut selection = Selection::default();
+        assert_eq!(get_status(&files, &selection), "> 0. \"a\"");
+
+        for key in [Key::Char(' '), Key::ArrowDown, Key::Char('k')] {
+            assert_eq!(selection.press(key, files.len()), None);
+        }
+        assert_eq!(
+            get_status(&files, &selection),
+            "> 1. \"b\" (marked: 0) Keep it and [D]elete, ReplaceWith[H]ardlink or \
+            ReplaceWith[S]oftlink the others"
+        );
+    }
+}
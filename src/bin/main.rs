@@ -14,10 +14,11 @@
 //!   0. "path/to/dir/some_dir/A"
 //!   1. "path/to/dir/other_dir/B"
 //! --------------------------------
-//! Size: 8kB
+//! Size: 8.2kB
 //! -----------
 //! Select action and paths.
-//! [O]pen, Open [F]older, [D]elete, ReplaceWith[H]ardlink, ReplaceWith[S]oftlink, [N]othing
+//! [O]pen, Open [F]older, Pre[V]iew, [D]elete, ReplaceWith[H]ardlink, ReplaceWith[S]oftlink,
+//! [I]gnore forever, [N]othing
 //! ```
 //! To act on the items found type the letter of action and file numbers. E.g.
 //! ```bash
@@ -25,91 +26,821 @@
 //! ```
 //! will open both files.
 //! ```
+//! V 1
+//! ```
+//! will show the first lines (or a hexdump) of "path/to/dir/other_dir/B" in a pager.
+//! ```
 //! D 0
 //! ```
 //! will delete "path/to/dir/some_dir/A" in our example.
+//! ```
+//! K 0 H
+//! ```
+//! will keep "path/to/dir/some_dir/A" and replace all other paths with hardlinks to it.
+//!
+//! In a terminal, the actions are chosen by single keys instead (see [`key_input`]).
 
+mod act;
 mod actions;
+mod audit;
+mod batch;
+mod bench;
+mod cache;
+mod czkawka;
+mod diff;
+mod exec;
+mod find_copies;
+mod freed_space;
+mod group_order;
 mod helper_functions;
+mod ignore_list;
+mod keep_policy;
+mod key_input;
+mod manifest;
+mod mapping;
+mod path_filter;
 mod progress_bar;
+mod protection;
+mod report;
+mod script;
+mod syslog;
+mod template;
+mod verify;
 
 use std::cell::RefCell;
 use std::cmp::max;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use dialoguer::console::Term;
+use digest::Digest;
 use regex::Regex;
+use serde::Serialize;
 
 use actions::*;
-use duplicate_destroyer::{DuplicateObject, HashAlgorithm};
+use audit::AuditLog;
+use batch::LinkMode;
+use duplicate_destroyer::{
+    ancestor_clusters, root_statistics, size_bands, Analytics, AncestorCluster, Category,
+    DirectoryDuplication, DuplicateObject, DuplicationEstimate, GroupExpansion, HashAlgorithm,
+    Preset, ScanError, ScanErrorKind, ScanStats, ScanTimings, Scanner, SizeBand,
+};
+use group_order::GroupOrder;
+use ignore_list::{ignore_group, IgnoreList};
+use keep_policy::KeepPolicy;
+use path_filter::PathFilter;
+use protection::ProtectedPaths;
+use report::InputRoot;
+use script::ScriptAction;
+
+/// Width of the bar showing savings of a group in interactive handling
+const SIZE_BAR_WIDTH: usize = 30;
+
+/// Number of top-level directories printed by `--analytics`
+const TOP_DIRECTORIES: usize = 10;
+
+/// Number of directories printed by `--rank-dirs`
+const RANKED_DIRECTORIES: usize = 20;
+
+/// Number of directories printed by `--by-ancestor`
+const ANCESTOR_CLUSTERS: usize = 20;
+
+/// Number of hexadecimal digits of the digest shown in the header of each group
+const SHORT_DIGEST_LENGTH: usize = 12;
 
 /// CLI argument parser
+///
+/// Without a subcommand the arguments of the `scan` subcommand are used.
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
-struct Args {
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Send the log messages and a summary of the executed actions to syslog instead of stderr
+    #[clap(long, global = true)]
+    syslog: bool,
+
+    /// Don't show the progress of the scan (it is not shown when stderr is not a terminal either)
+    #[clap(long, global = true)]
+    no_progress: bool,
+
+    #[clap(flatten)]
+    scan: ScanArgs,
+}
+
+/// Subcommands of the DuDe
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Scan directories for duplicates and handle them (default)
+    Scan(ScanArgs),
+    /// Print the statistics and the duplicate groups stored in a json file
+    Report(ReportArgs),
+    /// Link or delete the duplicates stored in a json file without scanning again
+    Apply(ApplyArgs),
+    /// Verify and link or delete the duplicate groups written by `--output groups`
+    Act(ActArgs),
+    /// Check that the duplicates stored in a json file did not change since the scan
+    Verify(VerifyArgs),
+    /// Show the files in one directory that have no copy in another one
+    Diff(DiffArgs),
+    /// Find the copies of files in directories without searching for all duplicates
+    FindCopies(FindCopiesArgs),
+    /// Write the digests of the scanned files (and directories) to a json manifest
+    Manifest(ManifestArgs),
+    /// Show the files and directories of one manifest that have a copy in another one
+    Compare(CompareArgs),
+    /// Measure the speed of walking and hashing with several numbers of jobs and algorithms
+    Bench(BenchArgs),
+    /// Show or remove the checksum journals left in the cache directory by unfinished scans
+    Cache(CacheArgs),
+}
+
+/// Arguments of the `scan` subcommand
+#[derive(Args, Debug)]
+struct ScanArgs {
     /// Add path to be scanned
     #[clap(short, long, required = true)]
     path: Vec<OsString>,
 
-    /// Minimum size of duplicates considered (can have a metric prefix) [default=100]
-    #[clap(short, long)]
+    #[clap(flatten)]
+    config: ConfigArgs,
+
+    /// Output the list of duplicates, skipped paths and statistics to a file in json format (`-` for
+    /// stdout)
+    #[clap(long, value_name = "FILE")]
+    json_file: Option<OsString>,
+
+    /// Hash the digests of the groups in the json output with the key (1 to 64 bytes) read from the
+    /// file, so that they can't be matched against the digests of known content
+    #[clap(long, value_name = "FILE")]
+    key_file: Option<PathBuf>,
+
+    /// Compute the saved space from the blocks allocated on disk instead of the sizes of the
+    /// duplicates (smaller for sparse files)
+    #[clap(long)]
+    allocated_savings: bool,
+
+    /// Units used to print sizes
+    #[clap(long, value_enum, default_value = "si")]
+    units: Units,
+
+    /// Print the time spent in each phase of the scan
+    #[clap(long)]
+    timings: bool,
+
+    /// Print the groups by number of copies and the top-level directories wasting the most space
+    #[clap(long)]
+    analytics: bool,
+
+    /// Print the directories with the largest fraction of their bytes copied elsewhere, even if
+    /// they are not duplicates as a whole
+    #[clap(long)]
+    rank_dirs: bool,
+
+    /// Print the directories with the most savings in the groups all of whose members they contain
+    /// (the groups are clustered by the nearest common ancestor of their members)
+    #[clap(long)]
+    by_ancestor: bool,
+
+    /// Only estimate the duplicates by grouping the files by size and name, without reading them
+    #[clap(
+        long,
+        conflicts_with_all = ["json", "json_file", "output", "link_dupes", "delete_dupes", "exec", "format"]
+    )]
+    estimate: bool,
+
+    /// Do not record the calculated checksums, so that a scan interrupted by a crash starts over
+    #[clap(long)]
+    no_journal: bool,
+
+    /// Print the json output to stdout instead of handling the duplicates (same as `--json-file -`)
+    #[clap(
+        long,
+        conflicts_with_all = ["json_file", "output", "link_dupes", "delete_dupes", "exec", "format"]
+    )]
+    json: bool,
+
+    /// Disable interactive duplicate handling
+    #[clap(long)]
+    no_interactive: bool,
+
+    /// Show the matching files of each group of directories in interactive handling
+    #[clap(long)]
+    expand: bool,
+
+    /// Command used by the compare actions in interactive handling (the paths are appended)
+    #[clap(long, value_name = "CMD", default_value = DEFAULT_DIFF_TOOL)]
+    diff_tool: String,
+
+    /// Number of lines (or hexdump lines for binary files) shown by the preview action
+    #[clap(long, value_name = "N", default_value = "40")]
+    preview_lines: usize,
+
+    /// Leave out the groups ignored forever in earlier interactive handling
+    #[clap(long)]
+    respect_ignores: bool,
+
+    /// Ignore list read by `--respect-ignores` and written by the ignore action
+    /// [default: $XDG_DATA_HOME/dude/ignored.jsonl]
+    #[clap(long, value_name = "FILE")]
+    ignore_list: Option<PathBuf>,
+
+    /// Instead of interactive handling, print the results in this format to stdout
+    #[clap(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+
+    /// Order in which the duplicate groups are presented
+    #[clap(long, value_enum, default_value = "size")]
+    order: GroupOrder,
+
+    /// What the generated script does with the members that are not kept
+    #[clap(long, value_enum, default_value = "delete")]
+    script_action: ScriptAction,
+
+    /// Replace all duplicates with links to the member chosen by `--keep` without interaction
+    #[clap(
+        long,
+        value_enum,
+        value_name = "TYPE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "hard",
+        conflicts_with = "output"
+    )]
+    link_dupes: Option<LinkMode>,
+
+    /// Delete all duplicates except the member chosen by `--keep` without interaction
+    #[clap(long, conflicts_with_all = ["output", "link_dupes"])]
+    delete_dupes: bool,
+
+    /// Instead of interactive handling, run a shell command for each group with placeholders
+    /// {paths}, {keep}, {size}, {hash} and {count} replaced by quoted values of the group
+    #[clap(long, value_name = "CMD", conflicts_with_all = ["output", "link_dupes", "delete_dupes"])]
+    exec: Option<String>,
+
+    /// Instead of interactive handling, print a line in this format for each group (or for each
+    /// path if it contains {path}) with placeholders {path}, {paths}, {keep}, {group}, {size},
+    /// {hash} and {count}
+    #[clap(
+        long,
+        value_name = "TEMPLATE",
+        conflicts_with_all = ["output", "link_dupes", "delete_dupes", "exec"]
+    )]
+    format: Option<String>,
+
+    #[clap(flatten)]
+    actions: ActionArgs,
+}
+
+/// Arguments configuring the search for duplicates
+#[derive(Args, Debug)]
+struct ConfigArgs {
+    /// Minimum size of duplicates considered (e.g. 1.5G or 100KiB) [default=100]
+    #[clap(short, long, visible_alias = "min-size")]
     minimum_size: Option<String>,
 
+    /// Maximum size of duplicates considered (e.g. 1.5G or 100KiB)
+    #[clap(long, value_name = "SIZE")]
+    max_size: Option<String>,
+
+    /// Show only groups with at least this many copies [default=2]
+    #[clap(long, value_name = "N")]
+    min_copies: Option<usize>,
+
+    /// Show only groups whose members are all in the same directory (e.g. `a.jpg` and `a (1).jpg`)
+    #[clap(long, conflicts_with = "cross_dir_only")]
+    same_dir_only: bool,
+
+    /// Hide groups whose members are all in the same directory (and the duplicates inside them)
+    #[clap(long)]
+    cross_dir_only: bool,
+
+    /// Do not read directories more than N levels below the input paths [default=1024]
+    #[clap(long, value_name = "N")]
+    max_depth: Option<usize>,
+
     /// Number of jobs that run simultaneously [default=0]
     #[clap(short, long)]
     jobs: Option<usize>,
 
-    /// Output the list of duplicates to a file in json format
+    /// Hash the files on each device in a separate queue with its own jobs (one job on HDDs)
+    #[clap(long)]
+    per_device_queues: bool,
+
+    /// Skip files and directories whose name starts with a dot
+    #[clap(long)]
+    skip_hidden: bool,
+
+    /// Skip files with no content
+    #[clap(long)]
+    skip_empty: bool,
+
+    /// Skip files owned by other users than the one running DuDe
+    #[clap(long)]
+    own_files_only: bool,
+
+    /// Skip files and directories matching this rsync-style glob (can be repeated)
+    #[clap(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+
+    /// Skip files and directories matching globs or paths listed in a file (one per line)
     #[clap(long, value_name = "FILE")]
-    json_file: Option<OsString>,
+    exclude_from: Vec<PathBuf>,
 
-    /// Disable interactive duplicate handling
+    /// Skip files and directories in a built-in list (can be repeated)
+    #[clap(long, value_enum)]
+    preset: Vec<Preset>,
+
+    /// Skip file system snapshots and directories mounted at another path as well
     #[clap(long)]
-    no_interactive: bool,
+    skip_snapshots: bool,
+
+    /// Skip files open for writing by any process (e.g. active databases or downloads), Linux only
+    #[clap(long)]
+    skip_open_for_writing: bool,
+
+    /// Search only files in these categories, recognised by their extensions (comma separated)
+    #[clap(long, value_enum, value_delimiter = ',', value_name = "CATEGORIES")]
+    only: Vec<Category>,
+
+    /// Maximum number of files kept open at once
+    #[clap(long, value_name = "NUM")]
+    max_open_files: Option<usize>,
+
+    /// Hash files larger than this (e.g. 4G) in chunks of this size in parallel
+    #[clap(long, value_name = "SIZE")]
+    parallel_hash_threshold: Option<String>,
 
     /// Hash algorithm used to compare files
-    #[clap(short, long)]
+    #[clap(short, long, visible_alias = "hash")]
     algorithm: Option<HashAlgorithm>,
+
+    /// Hash algorithm used to compare the beginnings of files before hashing them whole [default:
+    /// --algorithm]
+    #[clap(long, value_name = "ALGORITHM")]
+    prefix_algorithm: Option<HashAlgorithm>,
+
+    /// Compare MP3, FLAC and M4A files only by their audio stream, ignoring their tags
+    #[clap(long)]
+    ignore_audio_tags: bool,
+
+    /// Compare MP4, MOV, MKV and WebM videos only by their video and audio streams
+    #[clap(long)]
+    video_streams: bool,
+
+    /// Stop hashing files after this many seconds and report only the duplicates found so far
+    #[clap(long, value_name = "SECONDS")]
+    deadline: Option<u64>,
+
+    /// Prefer keeping duplicates in this path (can be repeated, most preferred first)
+    #[clap(long, value_name = "PATH")]
+    prefer: Vec<PathBuf>,
 }
 
-/// Get duplicates for user-specified directories and let user handle them
+/// Arguments of the destructive actions
+#[derive(Args, Debug)]
+struct ActionArgs {
+    /// Never delete or replace paths matching this glob (can be repeated)
+    #[clap(long, value_name = "PATTERN")]
+    protect: Vec<String>,
+
+    /// Never delete or replace paths matching globs listed in a file (one per line)
+    #[clap(long, value_name = "FILE")]
+    protect_from: Vec<PathBuf>,
+
+    /// Never delete or replace mount points
+    #[clap(long)]
+    protect_mount_points: bool,
+
+    /// Allow deleting or replacing system directories, whole input paths and directories
+    /// containing the kept original
+    #[clap(long)]
+    i_know_what_im_doing: bool,
+
+    /// Append a record of each executed action to a file in json lines format
+    #[clap(long, value_name = "FILE")]
+    audit_log: Option<PathBuf>,
+
+    /// Which member of each group is kept by the generated script and by non-interactive actions
+    /// (`--prefer` takes precedence)
+    #[clap(long, value_enum, default_value = "first")]
+    keep: KeepPolicy,
+
+    /// Only print what the destructive actions (e.g. `--delete-dupes`) would do
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Allow replacing files with hard links or reflinks to originals with different owner, group
+    /// or mode
+    #[clap(long)]
+    allow_access_change: bool,
+
+    /// Allow deleting files that have hardlinks outside of the deleted paths
+    #[clap(long)]
+    allow_multiply_linked: bool,
+
+    /// Restore modification times of the directories changed by destructive actions
+    #[clap(long)]
+    preserve_dir_mtimes: bool,
+}
+
+/// Arguments of the `report` subcommand
+#[derive(Args, Debug)]
+struct ReportArgs {
+    /// Json file written by `--json-file`
+    #[clap(value_name = "FILE")]
+    json_file: PathBuf,
+
+    /// Units used to print sizes
+    #[clap(long, value_enum, default_value = "si")]
+    units: Units,
+}
+
+/// Arguments of the `verify` subcommand
+#[derive(Args, Debug)]
+struct VerifyArgs {
+    /// Json file written by `--json-file`
+    #[clap(value_name = "FILE")]
+    json_file: PathBuf,
+
+    /// Units used to print sizes
+    #[clap(long, value_enum, default_value = "si")]
+    units: Units,
+
+    /// Options of the scan (e.g. `--algorithm` or `--exclude`) that affect the content compared
+    #[clap(flatten)]
+    config: ConfigArgs,
+}
+
+/// Arguments of the `apply` subcommand
+#[derive(Args, Debug)]
+#[clap(group(ArgGroup::new("action").required(true).args(["link_dupes", "delete_dupes"])))]
+struct ApplyArgs {
+    /// Json file written by `--json-file`
+    #[clap(value_name = "FILE")]
+    json_file: PathBuf,
+
+    /// Replace all duplicates with links to the member chosen by `--keep`
+    #[clap(
+        long,
+        value_enum,
+        value_name = "TYPE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "hard"
+    )]
+    link_dupes: Option<LinkMode>,
+
+    /// Delete all duplicates except the member chosen by `--keep`
+    #[clap(long)]
+    delete_dupes: bool,
+
+    /// Units used to print sizes
+    #[clap(long, value_enum, default_value = "si")]
+    units: Units,
+
+    #[clap(flatten)]
+    actions: ActionArgs,
+}
+
+/// Arguments of the `act` subcommand
+#[derive(Args, Debug)]
+#[clap(group(
+    ArgGroup::new("action").required(true).args(["hardlink", "softlink", "reflink", "delete"])
+))]
+struct ActArgs {
+    /// Groups written by `--output groups` (`-` for stdin)
+    #[clap(value_name = "FILE")]
+    input: PathBuf,
+
+    /// Replace the duplicates with hard links to the member chosen by `--keep`
+    #[clap(long)]
+    hardlink: bool,
+
+    /// Replace the duplicates with symbolic links to the member chosen by `--keep`
+    #[clap(long)]
+    softlink: bool,
+
+    /// Replace the duplicates with reflinks to the member chosen by `--keep`
+    #[clap(long)]
+    reflink: bool,
+
+    /// Delete the duplicates except the member chosen by `--keep`
+    #[clap(long)]
+    delete: bool,
+
+    /// Units used to print sizes
+    #[clap(long, value_enum, default_value = "si")]
+    units: Units,
+
+    /// Options of the scan (e.g. `--algorithm` or `--exclude`) that affect the content compared
+    #[clap(flatten)]
+    config: ConfigArgs,
+
+    #[clap(flatten)]
+    actions: ActionArgs,
+}
+
+/// Arguments of the `diff` subcommand
+#[derive(Args, Debug)]
+struct DiffArgs {
+    /// Directory whose files are looked for in the other one
+    left: OsString,
+
+    /// Directory whose files are looked for in the first one
+    right: OsString,
+
+    #[clap(flatten)]
+    config: ConfigArgs,
+}
+
+/// Arguments of the `find-copies` subcommand
+#[derive(Args, Debug)]
+struct FindCopiesArgs {
+    /// Files whose copies are searched for
+    #[clap(required = true)]
+    file: Vec<OsString>,
+
+    /// Add path to be searched for the copies
+    #[clap(short, long, required = true)]
+    path: Vec<OsString>,
+
+    #[clap(flatten)]
+    config: ConfigArgs,
+}
+
+/// Arguments of the `manifest` subcommand
+#[derive(Args, Debug)]
+struct ManifestArgs {
+    /// Directories to be scanned
+    #[clap(required = true)]
+    path: Vec<OsString>,
+
+    /// Include the digests of directories, not only of files
+    #[clap(long)]
+    tree: bool,
+
+    /// Write the manifest to a file instead of stdout
+    #[clap(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Hash the digests with the key (1 to 64 bytes) read from the file, so that the manifest can
+    /// only be compared with manifests written with the same key
+    #[clap(long, value_name = "FILE")]
+    key_file: Option<PathBuf>,
+
+    #[clap(flatten)]
+    config: ConfigArgs,
+}
+
+/// Arguments of the `compare` subcommand
+#[derive(Args, Debug)]
+struct CompareArgs {
+    /// Manifest whose entries are looked for in the other one
+    left: PathBuf,
+
+    /// Manifest searched for the copies
+    right: PathBuf,
+}
+
+/// Arguments of the `cache` subcommand
+#[derive(Args, Debug)]
+struct CacheArgs {
+    /// Remove the journals instead of showing them (except the ones used by a running scan)
+    #[clap(long)]
+    clear: bool,
+
+    /// Units used to print sizes
+    #[clap(long, value_enum, default_value = "si")]
+    units: Units,
+}
+
+/// Arguments of the `bench` subcommand
+#[derive(Args, Debug)]
+struct BenchArgs {
+    /// Add path to be scanned
+    #[clap(short, long, required = true)]
+    path: Vec<OsString>,
+
+    /// Comma-separated numbers of jobs to try
+    #[clap(short, long, value_delimiter = ',', default_value = "1,2,4,8")]
+    jobs: Vec<usize>,
+
+    /// Comma-separated hash algorithms to try [default: all]
+    #[clap(long, value_enum, value_delimiter = ',')]
+    algorithm: Vec<HashAlgorithm>,
+
+    /// Units used to print sizes
+    #[clap(long, value_enum, default_value = "si")]
+    units: Units,
+}
+
+/// Contents of the json file
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    groups: &'a [DuplicateObject],
+    /// Whether the digests of the groups are hashed with a key
+    keyed: bool,
+    /// Scanned paths, which `dude apply` refuses to delete or replace as a whole
+    roots: Vec<InputRoot>,
+    /// Paths skipped during the scan
+    errors: Vec<ScanError>,
+    stats: JsonStats,
+}
+
+/// Statistics in the json file
+#[derive(Serialize)]
+struct JsonStats {
+    #[serde(flatten)]
+    scan: ScanStats,
+    groups: usize,
+    /// The deadline expired before all files were hashed
+    truncated: bool,
+    max_saved_space: u64,
+    size_bands: Vec<SizeBand>,
+    analytics: Analytics,
+    /// Directories by the fraction of their bytes copied elsewhere
+    directory_duplication: Vec<DirectoryDuplication>,
+    /// Groups clustered by the nearest common ancestor of their members
+    ancestor_clusters: Vec<AncestorCluster>,
+}
+
+/// Format of the results printed to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human readable statistics followed by interactive handling of the groups
+    Text,
+    /// Reviewable POSIX shell script handling the duplicates
+    Sh,
+    /// Json lines with one duplicate group per line, handled by `dude act`
+    Groups,
+    /// Json lines mapping each duplicate file to the copy kept by `--keep`
+    Mapping,
+    /// Json with the layout of the duplicate files saved by czkawka
+    Czkawka,
+}
+
+/// Units used to print sizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Units {
+    /// Powers of 1000 (kB, MB, ...)
+    Si,
+    /// Powers of 1024 (KiB, MiB, ...) as used by `du -h`
+    Binary,
+}
+
+/// Run the subcommand chosen by user
 ///
-/// The function checks CLI arguments, then finds duplicates for specified directories and prints
-/// them. User can choose actions for each file in each duplicate group.
+/// Without a subcommand, the directories given by `--path` are scanned (see [`scan`]).
 fn main() -> io::Result<()> {
-    env_logger::init();
-
-    let args = Args::parse();
+    let cli = Cli::parse();
+    if cli.syslog {
+        syslog::init();
+    } else {
+        env_logger::init();
+    }
 
-    // Get DuDe configuration
-    let mut config: duplicate_destroyer::Config = Default::default();
+    let progress = !cli.no_progress && Term::stderr().is_term();
 
-    // Get minimum size of elements of duplicate groups
-    if let Some(ms) = args.minimum_size {
-        match parse_human_readable_size(&ms) {
-            None => {
-                log::error!("Could not parse minimum size: {}", ms);
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    format!("Bad form of minimum size: {}. Use e.g. 1k", ms),
-                ));
+    match cli.command {
+        Some(Command::Scan(args)) => scan(args, progress),
+        Some(Command::Report(args)) => report::print_report(&args.json_file, args.units),
+        Some(Command::Apply(args)) => apply(args),
+        Some(Command::Act(args)) => {
+            let mut config = args.config.to_config()?;
+            let (duplicates, roots) = act::read_groups(&args.input)?;
+            let duplicates = act::verified_groups(duplicates, args.units, &mut config);
+            let mut context = args.actions.context(&roots, false, args.units)?;
+            let link_mode = if args.hardlink {
+                Some(LinkMode::Hard)
+            } else if args.softlink {
+                Some(LinkMode::Soft)
+            } else if args.reflink {
+                Some(LinkMode::Reflink)
+            } else {
+                None
+            };
+            let result = match link_mode {
+                Some(mode) => {
+                    batch::link_duplicates(&duplicates, args.actions.keep, mode, &mut context)
+                }
+                None => batch::delete_duplicates(&duplicates, args.actions.keep, &mut context),
+            };
+            context.summary.log();
+            result
+        }
+        Some(Command::Verify(args)) => {
+            let mut config = args.config.to_config()?;
+            if !verify::verify_report(&args.json_file, args.units, &mut config)? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Diff(args)) => {
+            let mut config = args.config.to_config()?;
+            set_progress_bars(&mut config, progress);
+            diff::print_diff(args.left, args.right, &config)
+        }
+        Some(Command::FindCopies(args)) => {
+            let config = args.config.to_config()?;
+            find_copies::print_copies(args.file, args.path, &config)
+        }
+        Some(Command::Manifest(args)) => {
+            let mut config = args.config.to_config()?;
+            set_progress_bars(&mut config, progress);
+            let key = args.key_file.as_deref().map(manifest::read_key).transpose()?;
+            let key = key.as_deref();
+            match args.output {
+                Some(path) => {
+                    let mut file = File::create(path)?;
+                    manifest::write_manifest(args.path, args.tree, key, &mut config, &mut file)
+                }
+                None => {
+                    let mut out = io::stdout();
+                    manifest::write_manifest(args.path, args.tree, key, &mut config, &mut out)
+                }
+            }
+        }
+        Some(Command::Compare(args)) => manifest::print_shared(&args.left, &args.right),
+        Some(Command::Bench(args)) => {
+            bench::run_bench(&args.path, &args.jobs, &args.algorithm, args.units);
+            Ok(())
+        }
+        Some(Command::Cache(args)) => {
+            let cache_dir = get_cache_dir()?;
+            if args.clear {
+                cache::clear_cache(&cache_dir)
+            } else {
+                cache::print_cache(&cache_dir, args.units)
             }
-            Some(val) => config.set_minimum_size(val),
         }
+        None => scan(cli.scan, progress),
     }
+}
+
+/// Link or delete the duplicates stored in a json file written by `--json-file`
+///
+/// The paths scanned by the run that wrote the file are protected by the built-in guards.
+fn apply(args: ApplyArgs) -> io::Result<()> {
+    let (duplicates, roots) = report::read_report(&args.json_file)?;
+    let mut context = args.actions.context(&roots, false, args.units)?;
+    let result = if let Some(mode) = args.link_dupes {
+        batch::link_duplicates(&duplicates, args.actions.keep, mode, &mut context)
+    } else {
+        batch::delete_duplicates(&duplicates, args.actions.keep, &mut context)
+    };
+    context.summary.log();
+    result
+}
 
-    // Get hashing algorithm
-    if let Some(hashing_algo) = args.algorithm {
-        config.set_hash_algorithm(hashing_algo);
+/// Get duplicates for user-specified directories and let user handle them
+///
+/// The function finds duplicates for specified directories and prints them. User can choose actions
+/// for each file in each duplicate group. The progress of the scan is shown only with `progress`.
+fn scan(args: ScanArgs, progress: bool) -> io::Result<()> {
+    let json_stdout = args.json || args.json_file.as_deref() == Some(OsStr::new("-"));
+    if json_stdout
+        && (args.output != OutputFormat::Text
+            || args.link_dupes.is_some()
+            || args.delete_dupes
+            || args.exec.is_some()
+            || args.format.is_some())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Json output to stdout can not be combined with other output or actions.",
+        ));
     }
 
-    // Get number of threads
-    if let Some(num) = args.jobs {
-        config.set_num_threads(max(num - 1, 0));
+    let key = args.key_file.as_deref().map(manifest::read_key).transpose()?;
+    let mut config = args.config.to_config()?;
+    if args.estimate {
+        let start = Instant::now();
+        let estimate = duplicate_destroyer::estimate_duplicates(args.path.clone(), &config);
+        return print_estimate(&estimate, start.elapsed(), args.units, &mut io::stdout());
+    }
+    if !args.no_journal {
+        if let Some(journal) = get_journal_path(&args.path, &config) {
+            config.set_journal(journal);
+        }
+    }
+    let mut context = args.actions.context(
+        &args.path,
+        args.link_dupes.is_none() && !args.delete_dupes,
+        args.units,
+    )?;
+    context.diff_tool = args.diff_tool;
+    context.preview_lines = args.preview_lines;
+    if let Some(path) = args.ignore_list {
+        context.ignore_list = path;
     }
 
     log::trace!("Got directories:");
@@ -117,53 +848,552 @@ fn main() -> io::Result<()> {
         log::trace!("{:?}", dir)
     }
 
-    let pb = Rc::new(RefCell::new(progress_bar::Progress::new()));
-    let add_dir_pb = Rc::new(RefCell::new(progress_bar::MultiProgressBar::new()));
-    config.set_multiline_progress(add_dir_pb);
-    config.set_progress_indicator(pb);
+    set_progress_bars(&mut config, progress);
 
     // Run Duplicate Destroyer
-    let duplicates = duplicate_destroyer::get_duplicates(args.path, &config).unwrap();
+    let mut scanner = Scanner::new(args.path.clone(), &config)?;
+    let mut duplicates = scanner.topmost_duplicates(config.get_minimum_size());
+    args.order.sort(&mut duplicates, &args.path);
+    if args.respect_ignores {
+        let ignore_list = IgnoreList::open(&context.ignore_list)?;
+        let count = duplicates.len();
+        duplicates.retain(|x| !ignore_list.ignores(x));
+        if duplicates.len() < count {
+            eprintln!("Left out {} ignored groups.", count - duplicates.len());
+        }
+    }
+    if scanner.is_truncated() {
+        eprintln!(
+            "The deadline expired before all files were hashed, some duplicates may be missing."
+        );
+    }
+    let collisions: Vec<_> = scanner
+        .scan_errors()
+        .into_iter()
+        .filter(|x| x.kind == ScanErrorKind::CollisionDetected)
+        .collect();
+    if !collisions.is_empty() {
+        eprintln!(
+            "WARNING: Hash collision detected! {} files have the same checksum as files of a \
+            different size. They were left out of all groups, so no action touches them:",
+            collisions.len()
+        );
+        for collision in collisions {
+            eprintln!("  {:?}: {}", collision.path, collision.message);
+        }
+    }
+    let open_for_writing =
+        scanner.scan_errors().iter().filter(|x| x.kind == ScanErrorKind::OpenForWriting).count();
+    if open_for_writing > 0 {
+        eprintln!("Skipped {} files open for writing.", open_for_writing);
+    }
+    let unreadable = scanner
+        .scan_errors()
+        .iter()
+        .filter(|x| matches!(x.kind, ScanErrorKind::Inaccessible | ScanErrorKind::SpecialFile))
+        .count();
+    if unreadable > 0 {
+        eprintln!(
+            "Skipped {} paths that could not be read. They are listed in the `errors` of the json \
+            output (see --json-file).",
+            unreadable
+        );
+    }
+
+    // Keep stdout clean for the script, formatted lines or json
+    let mut out: Box<dyn Write> =
+        if args.output != OutputFormat::Text || args.format.is_some() || json_stdout {
+            Box::new(io::stderr())
+        } else {
+            Box::new(io::stdout())
+        };
+    print_statistics(&duplicates, &args.path, args.allocated_savings, args.units, &mut out)?;
+    if args.timings {
+        print_timings(&scanner.timings(), args.units, &mut out)?;
+    }
+    if args.analytics {
+        print_analytics(&scanner.analytics(&duplicates), args.units, &mut out)?;
+    }
+    if args.rank_dirs {
+        print_ranking(&scanner.directory_duplication(), args.units, &mut out)?;
+    }
+    if args.by_ancestor {
+        print_ancestor_clusters(&ancestor_clusters(&duplicates), args.units, &mut out)?;
+    }
+
+    // Print json results to file or stdout
+    if json_stdout || args.json_file.is_some() {
+        let mut groups = duplicates.clone();
+        if let Some(key) = &key {
+            manifest::key_group_digests(&mut groups, key);
+        }
+        let report = JsonReport {
+            groups: &groups,
+            keyed: key.is_some(),
+            roots: InputRoot::from_paths(&args.path),
+            errors: scanner.scan_errors(),
+            stats: JsonStats {
+                scan: scanner.scan_stats(),
+                groups: duplicates.len(),
+                truncated: scanner.is_truncated(),
+                max_saved_space: get_max_saved_space(&duplicates, false),
+                size_bands: size_bands(&duplicates),
+                analytics: scanner.analytics(&duplicates),
+                directory_duplication: scanner.directory_duplication(),
+                ancestor_clusters: ancestor_clusters(&duplicates),
+            },
+        };
+        let serialized = serde_json::to_string_pretty(&report).unwrap();
+        match args.json_file {
+            Some(json_file) if !json_stdout => {
+                let mut file = File::create(json_file)?;
+                write!(file, "{}", serialized)
+                    .expect("An error occurred when writing output to file.");
+            }
+            _ => {
+                println!("{}", serialized);
+                return Ok(());
+            }
+        }
+    }
+
+    if args.output == OutputFormat::Groups {
+        let roots = InputRoot::from_paths(&args.path);
+        return act::write_groups(&duplicates, &roots, &mut io::stdout().lock());
+    }
+
+    if args.output == OutputFormat::Mapping {
+        let mut stdout = io::stdout().lock();
+        return mapping::write_mapping(&duplicates, &scanner, args.actions.keep, &mut stdout);
+    }
 
-    print_statistics(&duplicates);
+    if args.output == OutputFormat::Czkawka {
+        return czkawka::write_czkawka(&duplicates, &scanner, &mut io::stdout().lock());
+    }
+
+    if args.output == OutputFormat::Sh {
+        let mut stdout = io::stdout().lock();
+        return script::write_script(
+            &duplicates,
+            args.actions.keep,
+            args.script_action,
+            &context.protected,
+            &mut stdout,
+        );
+    }
+
+    if args.delete_dupes {
+        let result = batch::delete_duplicates(&duplicates, args.actions.keep, &mut context);
+        context.summary.log();
+        return result;
+    }
+
+    if let Some(mode) = args.link_dupes {
+        let result = batch::link_duplicates(&duplicates, args.actions.keep, mode, &mut context);
+        context.summary.log();
+        return result;
+    }
 
-    // Print json results to file
-    if let Some(json_file) = args.json_file {
-        let serialized = serde_json::to_string_pretty(&duplicates).unwrap();
-        let mut file = File::create(json_file)?;
-        write!(file, "{}", serialized).expect("An error occurred when writing output to file.");
+    if let Some(template) = &args.format {
+        let mut stdout = io::stdout().lock();
+        return template::write_formatted(&duplicates, template, args.actions.keep, &mut stdout);
+    }
+
+    if let Some(command) = &args.exec {
+        return exec::run_for_groups(&duplicates, command, args.actions.keep);
     }
 
     if !args.no_interactive {
-        return interactive_loop(&duplicates);
+        let expand = if args.expand { Some(&scanner) } else { None };
+        let result = interactive_loop(
+            &duplicates,
+            &args.path,
+            expand,
+            args.allocated_savings,
+            args.units,
+            &mut context,
+        );
+        context.summary.log();
+        return result;
     }
 
     Ok(())
 }
 
+impl ConfigArgs {
+    /// Get DuDe configuration from the arguments
+    fn to_config(&self) -> io::Result<duplicate_destroyer::Config> {
+        let mut config: duplicate_destroyer::Config = Default::default();
+
+        // Get minimum size of elements of duplicate groups
+        if let Some(ref ms) = self.minimum_size {
+            config.set_minimum_size(parse_size_arg("minimum size", ms)?);
+        }
+
+        // Get maximum size of elements of duplicate groups
+        if let Some(ref ms) = self.max_size {
+            config.set_max_size(parse_size_arg("maximum size", ms)?);
+        }
+
+        // Get minimum number of copies in duplicate groups
+        if let Some(min_copies) = self.min_copies {
+            config.set_min_copies(min_copies);
+        }
+        config.set_same_directory_only(self.same_dir_only);
+        config.set_cross_directory_only(self.cross_dir_only);
+        if let Some(max_depth) = self.max_depth {
+            config.set_max_depth(max_depth);
+        }
+
+        // Get hashing algorithm
+        if let Some(hashing_algo) = self.algorithm {
+            config.set_hash_algorithm(hashing_algo);
+        }
+        if let Some(prefix_algo) = self.prefix_algorithm {
+            config.set_prefix_hash_algorithm(prefix_algo);
+        }
+
+        // Get preferred roots
+        if !self.prefer.is_empty() {
+            config.set_preferred_roots(self.prefer.clone());
+        }
+
+        config.set_skip_hidden(self.skip_hidden);
+        config.set_skip_empty_files(self.skip_empty);
+        config.set_skip_snapshots(self.skip_snapshots);
+        config.set_skip_open_for_writing(self.skip_open_for_writing);
+        config.set_ignore_audio_tags(self.ignore_audio_tags);
+        config.set_video_streams(self.video_streams);
+        if self.own_files_only {
+            // SAFETY: getuid has no preconditions and can't fail
+            config.set_owner_uid(unsafe { libc::getuid() });
+        }
+        for preset in self.preset.iter() {
+            config.enable_preset(*preset);
+        }
+        for category in self.only.iter() {
+            config.add_category(*category);
+        }
+
+        // Get excluded paths
+        for pattern in self.exclude.iter() {
+            config.add_exclude(pattern);
+        }
+        for file in self.exclude_from.iter() {
+            for pattern in protection::read_pattern_file(file)? {
+                config.add_exclude(&pattern);
+            }
+        }
+
+        // Get limit of open files
+        if let Some(max) = self.max_open_files {
+            config.set_max_open_files(max);
+        }
+
+        // Get size of files hashed in chunks in parallel
+        if let Some(ref threshold) = self.parallel_hash_threshold {
+            config
+                .set_parallel_hash_threshold(parse_size_arg("parallel hash threshold", threshold)?);
+        }
+
+        // Get time budget of the scan
+        if let Some(seconds) = self.deadline {
+            config.set_deadline(Duration::from_secs(seconds));
+        }
+
+        // Get number of threads
+        if let Some(num) = self.jobs {
+            config.set_num_threads(max(num - 1, 0));
+        }
+        config.set_per_device_queues(self.per_device_queues);
+
+        Ok(config)
+    }
+}
+
+impl ActionArgs {
+    /// Get settings of executed actions from the arguments
+    ///
+    /// # Arguments
+    /// * `input_paths` - scanned paths that are protected by built-in guards
+    /// * `confirm` - ask user before each destructive action
+    /// * `units` - units used to print the space freed by destructive actions
+    fn context(
+        &self,
+        input_paths: &[OsString],
+        confirm: bool,
+        units: Units,
+    ) -> io::Result<ActionContext> {
+        // Get paths protected from destructive actions
+        let mut protected = ProtectedPaths::new();
+        for pattern in self.protect.iter() {
+            protected.add_pattern(pattern);
+        }
+        for file in self.protect_from.iter() {
+            protected.add_patterns_from_file(file)?;
+        }
+        protected.set_protect_mount_points(self.protect_mount_points);
+        protected.set_input_roots(input_paths);
+        protected.set_builtin_guards(!self.i_know_what_im_doing);
+
+        let audit_log = match self.audit_log {
+            Some(ref path) => Some(AuditLog::open(path)?),
+            None => None,
+        };
+
+        Ok(ActionContext {
+            protected,
+            audit_log,
+            confirm,
+            dry_run: self.dry_run,
+            diff_tool: DEFAULT_DIFF_TOOL.to_owned(),
+            preview_lines: DEFAULT_PREVIEW_LINES,
+            ignore_list: ignore_list::default_path(),
+            units,
+            allow_access_change: self.allow_access_change,
+            allow_multiply_linked: self.allow_multiply_linked,
+            preserve_dir_mtimes: self.preserve_dir_mtimes,
+            summary: Default::default(),
+            created_links: vec![],
+        })
+    }
+}
+
+/// Get path of the checksum journal of a scan of `input_paths` in the cache directory of the user
+///
+/// Scans of the same paths (in any order) that hash the files the same way share the journal, so
+/// a scan that crashed is resumed by running it again. None if the cache directory can't be used
+/// (see [`get_cache_dir`]).
+fn get_journal_path(
+    input_paths: &[OsString],
+    config: &duplicate_destroyer::Config,
+) -> Option<PathBuf> {
+    let cache_dir = match get_cache_dir() {
+        Ok(cache_dir) => cache_dir,
+        Err(e) => {
+            log::warn!("Not recording the checksums in a journal: {}", e);
+            return None;
+        }
+    };
+
+    let mut roots: Vec<_> =
+        input_paths.iter().map(|x| std::fs::canonicalize(x).unwrap_or_else(|_| x.into())).collect();
+    roots.sort();
+    roots.dedup();
+    Some(cache_dir.join(get_journal_name(&roots, config)))
+}
+
+/// Get name of the journal of a scan of the sorted `roots`
+///
+/// The name is derived from a BLAKE2 digest, so that it stays the same across the builds of DuDe
+/// and the journal of a crashed scan is found by an upgraded one as well. The roots are separated
+/// by null bytes, which can't be a part of a path.
+fn get_journal_name(roots: &[PathBuf], config: &duplicate_destroyer::Config) -> String {
+    let mut hasher = blake2::Blake2b512::new();
+    for root in roots {
+        hasher.update(get_path_bytes(root));
+        hasher.update([0]);
+    }
+    hasher.update(format!(
+        "{:?}\0{}\0{}\0{:?}",
+        config.get_hash_algorithm(),
+        config.get_ignore_audio_tags(),
+        config.get_video_streams(),
+        config.get_parallel_hash_threshold()
+    ));
+    let digest: String = hasher.finalize()[..8].iter().map(|x| format!("{x:02x}")).collect();
+    format!("{}{}", cache::JOURNAL_PREFIX, digest)
+}
+
+/// Get bytes of `path` (on platforms other than Unix, of its lossy conversion to Unicode)
+#[cfg(unix)]
+fn get_path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn get_path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().as_bytes().to_vec()
+}
+
+/// Get the cache directory of DuDe (`$XDG_CACHE_HOME/dude` or `~/.cache/dude`), creating it
+///
+/// The directory is created only accessible by the current user. An existing directory has to be
+/// owned by the current user and not accessible by anyone else, otherwise an error is returned.
+fn get_cache_dir() -> io::Result<PathBuf> {
+    let cache_home = match std::env::var_os("XDG_CACHE_HOME").map(PathBuf::from) {
+        Some(path) if path.is_absolute() => path,
+        _ => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".cache"),
+            None => {
+                let message = "Neither XDG_CACHE_HOME nor HOME is set.";
+                return Err(io::Error::new(io::ErrorKind::NotFound, message));
+            }
+        },
+    };
+    let cache_dir = cache_home.join("dude");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+        std::fs::DirBuilder::new().recursive(true).mode(0o700).create(&cache_dir)?;
+        let metadata = std::fs::symlink_metadata(&cache_dir)?;
+        if !metadata.is_dir()
+            || metadata.uid() != unsafe { libc::geteuid() }
+            || metadata.mode() & 0o077 != 0
+        {
+            let message = format!(
+                "{:?} has to be a directory owned by the current user and not accessible by others.",
+                cache_dir
+            );
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, message));
+        }
+    }
+    #[cfg(not(unix))]
+    std::fs::create_dir_all(&cache_dir)?;
+
+    Ok(cache_dir)
+}
+
+/// Show progress of the scan in the terminal
+///
+/// Without `progress` the indicators of `config` are left as they are (not displaying anything by
+/// default), e.g. so that output redirected to a file is not cluttered with control sequences.
+fn set_progress_bars(config: &mut duplicate_destroyer::Config, progress: bool) {
+    if !progress {
+        return;
+    }
+    let pb = Rc::new(RefCell::new(progress_bar::Progress::new()));
+    let add_dir_pb = Rc::new(RefCell::new(progress_bar::MultiProgressBar::new()));
+    config.set_multiline_progress(add_dir_pb);
+    config.set_progress_indicator(pb);
+}
+
 /// Print all duplicate groups, let user pick actions and execute them
 ///
+/// Each group is shown with a bar comparing its savings to the group with the largest savings and
+/// with the space reclaimed so far and the space that can still be reclaimed in this and the
+/// following groups.
+///
+/// User can restrict the rest of the session to groups with a path matching a pattern (see
+/// [`PathFilter`]), the other groups are skipped.
+///
 /// # Arguments
 /// * `duplicates` - slice of all duplicate groups
-fn interactive_loop(duplicates: &[DuplicateObject]) -> io::Result<()> {
+/// * `input_paths` - input paths that were searched for duplicates
+/// * `expand` - scanner used to show the matching files of directory groups (None to not show them)
+/// * `allocated` - compute savings from the space allocated on disk
+/// * `units` - units used to print sizes
+/// * `context` - settings shared by all executed actions
+fn interactive_loop(
+    duplicates: &[DuplicateObject],
+    input_paths: &[OsString],
+    expand: Option<&Scanner>,
+    allocated: bool,
+    units: Units,
+    context: &mut ActionContext,
+) -> io::Result<()> {
     let num_groups = duplicates.len();
+    let savings: Vec<_> = duplicates.iter().map(|x| get_savings(x, allocated)).collect();
+    let max_savings = savings.iter().max().copied().unwrap_or(0);
+    let mut reclaimed = 0;
+    let mut remaining: u64 = savings.iter().sum();
+    let mut filter: Option<PathFilter> = None;
+    // Destructive action executed last, the space it freed and the links it created
+    let mut last_action: Option<(Actions, u64, Vec<OsString>)> = None;
+    let single_key = key_input::is_available();
 
     for (index, group) in duplicates.iter().enumerate() {
-        println!("Group {}/{}", index + 1, num_groups);
+        if !filter.as_ref().map_or(true, |x| x.matches(group)) {
+            remaining -= savings[index];
+            continue;
+        }
 
-        let mut paths: Vec<_> = group.duplicates.iter().map(|x| x.to_owned()).collect();
-        paths.sort_unstable();
+        println!("{}", get_group_header(index, num_groups, group));
 
-        print_group(&paths[..], group.size);
+        let paths: Vec<OsString> = group.duplicates.iter().map(|x| x.into()).collect();
+        let canonical: Option<OsString> = group.canonical.as_ref().map(|x| x.into());
 
+        print_group(&paths[..], group, input_paths, units);
+        if let Some(expansion) = expand.and_then(|scanner| scanner.expand_group(group)) {
+            print_expansion(group, &expansion, units);
+        }
+        println!(
+            "Savings: {} {}",
+            get_size_bar(savings[index], max_savings),
+            get_human_readable_size(savings[index], units)
+        );
+        print_totals(reclaimed, remaining, units);
+
+        // Savings of this group that were not reclaimed yet
+        let mut group_remaining = savings[index];
         loop {
-            let action = Actions::get_from_input(&paths[..])?;
-            if let Err(e) = action.execute() {
-                println!("Error running action: {}\nChoose another action.", e);
-            } else if !action.should_get_another() {
-                break; // Move to another duplicate group
+            let action = if single_key {
+                key_input::get_from_keys(&paths[..], canonical.as_ref())?
+            } else {
+                Actions::get_from_input(&paths[..], canonical.as_ref())?
+            };
+            if let Actions::Ignore = action {
+                match ignore_group(&context.ignore_list, group) {
+                    Ok(()) => println!("Ignoring the group in scans with --respect-ignores."),
+                    Err(e) => {
+                        println!("Could not ignore the group: {}\nChoose another action.", e);
+                        continue;
+                    }
+                }
+                break;
+            }
+            if let Actions::Undo = action {
+                match last_action.take() {
+                    Some((last, freed, links)) => match last.undo(&links, context) {
+                        Ok(restored) => {
+                            println!("Restored {} paths.", restored);
+                            reclaimed -= freed;
+                            remaining += freed;
+                            print_totals(reclaimed, remaining, units);
+                        }
+                        Err(e) => println!("Could not undo the last action: {}", e),
+                    },
+                    None => println!("There is no action to undo."),
+                }
+                continue;
+            }
+            if let Actions::Filter(pattern) = &action {
+                filter = PathFilter::new(pattern);
+                match filter {
+                    Some(_) => println!("Showing only groups matching {:?}.", pattern),
+                    None => println!("Showing all groups."),
+                }
+                if filter.as_ref().map_or(false, |x| !x.matches(group)) {
+                    break; // Move to the next matching group
+                }
+                continue;
+            }
+            match action.execute(context) {
+                Err(e) => println!("Error running action: {}\nChoose another action.", e),
+                Ok(changed) => {
+                    if changed > 0 {
+                        // Each member that is not kept saves the same share of the savings
+                        let freed = (savings[index] * changed as u64
+                            / group.len().saturating_sub(1).max(1) as u64)
+                            .min(group_remaining);
+                        group_remaining -= freed;
+                        remaining -= freed;
+                        reclaimed += freed;
+                        print_totals(reclaimed, remaining, units);
+                        let links = std::mem::take(&mut context.created_links);
+                        last_action = Some((action, freed, links));
+                        break; // Destructive actions are followed by another duplicate group
+                    }
+                    if !action.should_get_another() {
+                        break; // Move to another duplicate group
+                    }
+                }
             }
         }
+        remaining -= group_remaining;
     }
 
     Ok(())
@@ -175,71 +1405,490 @@ fn interactive_loop(duplicates: &[DuplicateObject]) -> io::Result<()> {
 
 /// Print number of groups found and max space saved
 ///
+/// If there are multiple input paths, print also how much of the duplicated data is in each path.
+///
 /// # Arguments
 /// * `duplicates` - Vector of all duplicate groups
-fn print_statistics(duplicates: &Vec<DuplicateObject>) {
-    println!();
-    println!("{}", "-".repeat(40));
+/// * `paths` - input paths that were searched for duplicates
+/// * `allocated` - compute the saved space from the space allocated on disk
+/// * `units` - units used to print sizes
+/// * `out` - destination of the statistics
+fn print_statistics(
+    duplicates: &[DuplicateObject],
+    paths: &[OsString],
+    allocated: bool,
+    units: Units,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    writeln!(out)?;
+    writeln!(out, "{}", "-".repeat(40))?;
     let num_groups = duplicates.len();
-    println!("Found {} groups.", num_groups);
-    let max_saved_space: u64 =
-        duplicates.iter().map(|x| x.size * (x.duplicates.len() - 1) as u64).sum();
-    println!("Max saved space in this iteration: {}", get_human_readable_size(max_saved_space));
-    println!("{}", "-".repeat(40));
-    println!();
+    writeln!(out, "Found {} groups.", num_groups)?;
+    let max_saved_space = get_max_saved_space(duplicates, allocated);
+    writeln!(
+        out,
+        "Max saved space in this iteration: {}{}",
+        get_human_readable_size(max_saved_space, units),
+        if allocated { " (allocated on disk)" } else { "" }
+    )?;
+    if num_groups > 0 {
+        writeln!(out, "Duplicates by size:")?;
+        for band in size_bands(duplicates).iter().filter(|x| x.groups > 0) {
+            let range = match band.max {
+                Some(max) if band.min == 0 => {
+                    format!("below {}", get_human_readable_size(max, units))
+                }
+                Some(max) => format!(
+                    "{} - {}",
+                    get_human_readable_size(band.min, units),
+                    get_human_readable_size(max, units)
+                ),
+                None => format!("above {}", get_human_readable_size(band.min, units)),
+            };
+            writeln!(
+                out,
+                "  {}: {} groups, {} can be saved",
+                range,
+                band.groups,
+                get_human_readable_size(band.savings, units)
+            )?;
+        }
+    }
+    if paths.len() > 1 {
+        for (root, stats) in root_statistics(duplicates) {
+            writeln!(
+                out,
+                "{:?}: {} duplicated within path, {} duplicated in other paths",
+                paths[root],
+                get_human_readable_size(stats.within_root, units),
+                get_human_readable_size(stats.across_roots, units)
+            )?;
+        }
+    }
+    writeln!(out, "{}", "-".repeat(40))?;
+    writeln!(out)
+}
+
+/// Print time spent in the phases of the scan
+///
+/// # Arguments
+/// * `timings` - timings of the scan
+/// * `units` - units used to print the number of bytes hashed
+/// * `out` - destination of the timings
+fn print_timings(timings: &ScanTimings, units: Units, out: &mut dyn Write) -> io::Result<()> {
+    let hashing_secs = timings.hashing.as_secs_f64();
+    let speed = if hashing_secs > 0.0 {
+        let per_second = (timings.bytes_hashed as f64 / hashing_secs) as u64;
+        format!(", {}/s", get_human_readable_size(per_second, units))
+    } else {
+        String::new()
+    };
+    writeln!(out, "Timings:")?;
+    writeln!(out, "  walking directories: {:.2}s", timings.walking.as_secs_f64())?;
+    writeln!(
+        out,
+        "  hashing: {:.2}s ({} hashed{})",
+        hashing_secs,
+        get_human_readable_size(timings.bytes_hashed, units),
+        speed
+    )?;
+    writeln!(out, "  resolving duplicate directories: {:.2}s", timings.resolving.as_secs_f64())?;
+    writeln!(out, "  curating the list: {:.2}s", timings.curating.as_secs_f64())?;
+    writeln!(out)
+}
+
+/// Print analytics of the duplicate groups
+///
+/// Prints the number of groups with each number of copies and the top-level directories with the
+/// most wasted space (at most [`TOP_DIRECTORIES`] of them) with the fraction of their size it makes up.
+///
+/// # Arguments
+/// * `analytics` - analytics of the duplicate groups
+/// * `units` - units used to print sizes
+/// * `out` - destination of the analytics
+fn print_analytics(analytics: &Analytics, units: Units, out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "Groups by number of copies:")?;
+    for count in analytics.copy_counts.iter() {
+        writeln!(
+            out,
+            "  {} copies: {} groups, {} can be saved",
+            count.copies,
+            count.groups,
+            get_human_readable_size(count.savings, units)
+        )?;
+    }
+    writeln!(out, "Top-level directories with the most wasted space:")?;
+    for directory in analytics.directories.iter().take(TOP_DIRECTORIES) {
+        let ratio = match directory.ratio {
+            Some(ratio) => format!(" ({:.1}% of its size)", ratio * 100.0),
+            None => String::new(),
+        };
+        writeln!(
+            out,
+            "  {:?}: {} wasted{}",
+            directory.path,
+            get_human_readable_size(directory.wasted, units),
+            ratio
+        )?;
+    }
+    writeln!(out)
+}
+
+/// Print the directories with the largest fraction of their bytes copied elsewhere
+///
+/// At most [`RANKED_DIRECTORIES`] directories are printed.
+///
+/// # Arguments
+/// * `ranking` - directories ranked by the fraction of their bytes copied elsewhere
+/// * `units` - units used to print sizes
+/// * `out` - destination of the ranking
+fn print_ranking(
+    ranking: &[DirectoryDuplication],
+    units: Units,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    writeln!(out, "Directories with the most of their content copied elsewhere:")?;
+    for directory in ranking.iter().take(RANKED_DIRECTORIES) {
+        writeln!(
+            out,
+            "  {:?}: {:.1}% ({} of {})",
+            directory.path,
+            directory.ratio * 100.0,
+            get_human_readable_size(directory.duplicated, units),
+            get_human_readable_size(directory.size, units)
+        )?;
+    }
+    writeln!(out)
+}
+
+/// Print the directories with the most savings in the groups clustered under them
+///
+/// # Arguments
+/// * `clusters` - groups clustered by the nearest common ancestor of their members
+/// * `units` - units used to print sizes
+/// * `out` - destination of the clusters
+fn print_ancestor_clusters(
+    clusters: &[AncestorCluster],
+    units: Units,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    writeln!(out, "Directories containing all copies of the most duplicates:")?;
+    for cluster in clusters.iter().take(ANCESTOR_CLUSTERS) {
+        writeln!(
+            out,
+            "  {:?} contains {} duplicate groups, {}",
+            cluster.path,
+            cluster.groups,
+            get_human_readable_size(cluster.savings, units)
+        )?;
+    }
+    writeln!(out)
+}
+
+/// Print the estimate of the duplicates made from the sizes and names of the files
+///
+/// # Arguments
+/// * `estimate` - estimate of the duplicates
+/// * `elapsed` - time taken by the estimate
+/// * `units` - units used to print sizes
+/// * `out` - destination of the estimate
+fn print_estimate(
+    estimate: &DuplicationEstimate,
+    elapsed: Duration,
+    units: Units,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    writeln!(out, "----------------------------------------")?;
+    writeln!(out, "APPROXIMATE: files were grouped by size and name only, none of them was read.")?;
+    writeln!(
+        out,
+        "Listed {} files ({}) in {:.1}s.",
+        estimate.files,
+        get_human_readable_size(estimate.total_size, units),
+        elapsed.as_secs_f64()
+    )?;
+    writeln!(
+        out,
+        "Found {} groups of files with the same size and name ({} files).",
+        estimate.groups, estimate.grouped_files
+    )?;
+    writeln!(
+        out,
+        "Max saved space (upper bound, copies with different names are missed): {}",
+        get_human_readable_size(estimate.max_saved_space, units)
+    )?;
+    writeln!(out, "----------------------------------------")
+}
+
+/// Print space reclaimed in interactive handling so far and space that can still be reclaimed
+fn print_totals(reclaimed: u64, remaining: u64, units: Units) {
+    println!(
+        "Reclaimed so far: {}, remaining potential: {}",
+        get_human_readable_size(reclaimed, units),
+        get_human_readable_size(remaining, units)
+    );
+}
+
+/// Get a bar of `SIZE_BAR_WIDTH` characters filled in proportion to `value / max`
+fn get_size_bar(value: u64, max: u64) -> String {
+    let filled =
+        if max == 0 { 0 } else { (value as u128 * SIZE_BAR_WIDTH as u128 / max as u128) as usize };
+    format!("[{}{}]", "#".repeat(filled), "-".repeat(SIZE_BAR_WIDTH - filled))
+}
+
+/// Get space freed by keeping only one member of each group
+///
+/// # Arguments
+/// * `duplicates` - Vector of all duplicate groups
+/// * `allocated` - compute the space from the blocks allocated on disk instead of the sizes
+fn get_max_saved_space(duplicates: &[DuplicateObject], allocated: bool) -> u64 {
+    duplicates.iter().map(|x| get_savings(x, allocated)).sum()
 }
 
-/// Get human readable size in SI units from bytes
+/// Get space freed by keeping only one member of `group`
+///
+/// The space is computed from the blocks allocated on disk if `allocated` is true (see
+/// [`DuplicateObject::allocated_savings`]).
+fn get_savings(group: &DuplicateObject, allocated: bool) -> u64 {
+    if allocated {
+        group.allocated_savings()
+    } else {
+        group.savings()
+    }
+}
+
+/// Get human readable size from bytes
+///
+/// Sizes below 10 units are rounded to one decimal place (e.g. 1.5MB), larger sizes are
+/// truncated to whole units.
 ///
 /// # Arguments
 /// `size` - size in bytes
-fn get_human_readable_size(size: u64) -> String {
-    let mut number = size;
-    for unit in ["B", "kB", "MB", "GB", "TB", "PB", "EB", "ZB"] {
-        if number < 1000 {
-            return format!("{number}{unit}");
+/// `units` - SI or binary units
+fn get_human_readable_size(size: u64, units: Units) -> String {
+    let (base, prefixes) = match units {
+        Units::Si => (1000, ["k", "M", "G", "T", "P", "E"]),
+        Units::Binary => (1024, ["Ki", "Mi", "Gi", "Ti", "Pi", "Ei"]),
+    };
+    if size < base {
+        return format!("{size}B");
+    }
+
+    let mut divisor = base;
+    for (index, prefix) in prefixes.iter().enumerate() {
+        if size / divisor < base || index == prefixes.len() - 1 {
+            let value = size as f64 / divisor as f64;
+            // Values rounded up to 10 are printed as whole units as well
+            return if value < 9.95 {
+                let formatted = format!("{value:.1}");
+                format!("{}{prefix}B", formatted.trim_end_matches(".0"))
+            } else {
+                format!("{}{prefix}B", (size / divisor).max(10))
+            };
+        }
+        divisor *= base;
+    }
+    unreachable!("The last prefix is always used.")
+}
+
+/// Get header of the group with `index` (from 0) with the beginning of the digest of its content
+///
+/// The digest identifies the content of the group, so the same group can be recognized in other
+/// scans, reports and manifests.
+fn get_group_header(index: usize, num_groups: usize, group: &DuplicateObject) -> String {
+    match &group.digest {
+        Some(digest) => {
+            let short = digest.get(..SHORT_DIGEST_LENGTH).unwrap_or(digest);
+            format!("Group {}/{} [{}]", index + 1, num_groups, short)
         }
-        number /= 1000;
+        None => format!("Group {}/{}", index + 1, num_groups),
     }
-    format!("{number}YB")
 }
 
 /// Print group info
-fn print_group(paths: &[OsString], size: u64) {
+///
+/// If there are multiple input paths, each path in group is annotated with the input path it was
+/// found in.
+fn print_group(
+    paths: &[OsString],
+    group: &DuplicateObject,
+    input_paths: &[OsString],
+    units: Units,
+) {
     // Print files in group
     let max_length = paths.iter().map(|x| x.len()).max().unwrap_or(60) + 7;
     println!("{}", "-".repeat(max_length));
     for (index, path) in paths.iter().enumerate() {
-        println!("{:3}. {:?}", index, path);
+        let path = Path::new(path);
+        let preferred = if group.canonical.as_deref() == Some(path) { " [preferred]" } else { "" };
+        match group.roots.get(path) {
+            Some(root) if input_paths.len() > 1 => {
+                println!("{:3}. {:?} (in {:?}){}", index, path, input_paths[*root], preferred)
+            }
+            _ => println!("{:3}. {:?}{}", index, path, preferred),
+        }
     }
     println!("{}", "-".repeat(max_length));
-    println!("Size: {}", get_human_readable_size(size));
+    println!("Size: {}", get_human_readable_size(group.size, units));
+    if group.same_device == Some(false) {
+        println!(
+            "The paths are on different file systems, hardlinks and reflinks are not possible."
+        );
+    }
+    if group.nested {
+        println!("The first path contains the copy, only the copy can be removed.");
+    }
+    if group.shared_storage {
+        println!("Some paths are in snapshots or mounted twice, removing them frees no space.");
+    }
+    if group.different_container {
+        println!("The videos have the same streams in different files, their metadata may differ.");
+    }
     println!("{}", "-".repeat(11));
 }
 
-/// Parse size given in SI units to bytes
+/// Print files backing a group of directories
+///
+/// Nothing is printed for groups of files, where the expansion is the group itself.
+fn print_expansion(group: &DuplicateObject, expansion: &GroupExpansion, units: Units) {
+    if let [file_match] = &expansion.matches[..] {
+        if expansion.extras.is_empty() && file_match.paths.iter().eq(group.duplicates.iter()) {
+            return;
+        }
+    }
+    println!("Matching files:");
+    for file_match in expansion.matches.iter() {
+        println!(
+            "  {} ({})",
+            file_match.paths.len(),
+            get_human_readable_size(file_match.size, units)
+        );
+        for path in file_match.paths.iter() {
+            println!("      {:?}", path);
+        }
+    }
+    if !expansion.extras.is_empty() {
+        println!("Files without a match in every member:");
+        for path in expansion.extras.iter() {
+            println!("      {:?}", path);
+        }
+    }
+    println!("{}", "-".repeat(11));
+}
+
+/// Parse size argument given in SI or binary units to bytes
 ///
 /// # Arguments
-/// * `input` - size in SI units
+/// * `name` - name of the argument used in the error message
+/// * `input` - size in SI or binary units
+fn parse_size_arg(name: &str, input: &str) -> io::Result<u64> {
+    parse_human_readable_size(input).ok_or_else(|| {
+        log::error!("Could not parse {}: {}", name, input);
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Bad form of {}: {}. Use e.g. 1k, 1.5G or 100KiB", name, input),
+        )
+    })
+}
+
+/// Parse size given in SI or binary units to bytes
+///
+/// The size can be fractional and can have an SI prefix (e.g. `1.5G` or `100k`) or a binary
+/// prefix (e.g. `100Ki` or `2MiB`), optionally followed by `B`.
+///
+/// # Arguments
+/// * `input` - size in SI or binary units
 fn parse_human_readable_size(input: &str) -> Option<u64> {
-    let mut result = None;
-
-    let re = Regex::new(r"(?P<value>\d+)(?P<prefix>[kMGTPE])?$").unwrap();
-    let captures = re.captures(input);
-    if let Some(cap) = captures {
-        let cap_value: u64 = cap.name("value").unwrap().as_str().parse().unwrap();
-        let multiplier: u64 = match cap.name("prefix").map(|x| x.as_str()) {
-            None => 1,
-            Some("k") => 1000,
-            Some("M") => 1_000_000,
-            Some("G") => 10u64.pow(9),
-            Some("T") => 10u64.pow(12),
-            Some("P") => 10u64.pow(15),
-            Some("E") => 10u64.pow(18),
-            Some(err) => panic!("There should not be {err} in captured prefixes."),
-        };
-        result = Some(cap_value * multiplier);
+    let re =
+        Regex::new(r"^(?P<whole>\d+)(\.(?P<fraction>\d+))?(?P<prefix>[kKMGTPE])?(?P<binary>i)?B?$")
+            .unwrap();
+    let cap = re.captures(input)?;
+
+    let binary = cap.name("binary").is_some();
+    let exponent = match cap.name("prefix").map(|x| x.as_str()) {
+        None if binary => return None,
+        None => 0,
+        Some("k") | Some("K") => 1,
+        Some("M") => 2,
+        Some("G") => 3,
+        Some("T") => 4,
+        Some("P") => 5,
+        Some("E") => 6,
+        Some(err) => panic!("There should not be {err} in captured prefixes."),
+    };
+    let multiplier: u128 = if binary { 1024u128.pow(exponent) } else { 1000u128.pow(exponent) };
+
+    // Parse the digits without the decimal point and divide by the power of ten it moves
+    let fraction = cap.name("fraction").map_or("", |x| x.as_str());
+    let digits: u128 = format!("{}{}", &cap["whole"], fraction).parse().ok()?;
+    let scale = 10u128.checked_pow(fraction.len() as u32)?;
+    u64::try_from(digits.checked_mul(multiplier)? / scale).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use tempdir::TempDir;
+
+    /// Get arguments of `dude apply` with `args`
+    fn apply_args(args: &[&OsStr]) -> ApplyArgs {
+        let mut argv = vec![OsStr::new("dude"), OsStr::new("apply")];
+        argv.extend(args);
+        match Cli::parse_from(argv).command {
+            Some(Command::Apply(args)) => args,
+            _ => unreachable!(),
+        }
     }
 
-    result
+    #[test]
+    /// Check that `dude apply` refuses to delete a scanned path as a whole
+    ///
+    /// Create directory structure:
+    /// tmp_dir/
+    /// ├── a/
+    /// │   └── file
+    /// └── b/
+    ///     └── file
+    ///
+    /// and a report of the scan of `a` and `b` in which they are duplicates.
+    fn apply_protects_input_roots_test() -> Result<(), Box<dyn Error>> {
+        let tmp_dir = TempDir::new("apply_roots")?;
+        let mut roots = vec![];
+        for name in ["a", "b"] {
+            let dir = tmp_dir.path().join(name);
+            std::fs::create_dir(&dir)?;
+            std::fs::write(dir.join("file"), "Duplicate content")?;
+            roots.push(dir.into_os_string());
+        }
+        let report = serde_json::json!({
+            "groups": [{"duplicates": [roots[0].to_str(), roots[1].to_str()], "elementSize": 17}],
+            "roots": InputRoot::from_paths(&roots),
+        });
+        let report_path = tmp_dir.path().join("report.json");
+        std::fs::write(&report_path, report.to_string())?;
+
+        let args = apply_args(&[OsStr::new("--delete-dupes"), report_path.as_os_str()]);
+        assert!(apply(args).is_err());
+        for root in roots.iter() {
+            assert!(Path::new(root).join("file").exists());
+        }
+
+        tmp_dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    /// Check that the journal name does not change between builds and depends on the roots and on
+    /// the way the files are hashed
+    fn journal_name_test() {
+        let roots = [PathBuf::from("/home/user/photos"), PathBuf::from("/mnt/backup")];
+        let mut config = duplicate_destroyer::Config::default();
+        let name = get_journal_name(&roots, &config);
+        assert_eq!(name, "journal-e4f096a33e3fdff0");
+        assert_ne!(get_journal_name(&roots[..1], &config), name);
+
+        config.set_hash_algorithm(duplicate_destroyer::HashAlgorithm::SHA2_256);
+        assert_ne!(get_journal_name(&roots, &config), name);
+    }
 }
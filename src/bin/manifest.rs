@@ -0,0 +1,186 @@
+//! Digests of scanned trees that can be compared offline
+//!
+//! A manifest lists the scanned files (and with `--tree` also the directories) together with the
+//! digests of their content. Manifests exported on different machines can then be compared to find
+//! the files and directories present on both of them without copying any data.
+//!
+//! Manifests that leave the machine can be written with a key. The digests are then replaced by
+//! their keyed BLAKE2b hashes, which can be compared with manifests written with the same key, but
+//! can't be matched against the digests of known content by anyone without the key. The digests of
+//! the duplicate groups in the json report of a scan are hashed the same way.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use blake2::digest::Mac;
+use blake2::Blake2bMac512;
+use duplicate_destroyer::{Config, DuplicateObject, NodeKind, Scanner, TreeNode};
+use serde::{Deserialize, Serialize};
+
+/// Contents of the manifest file
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    /// Hash algorithm the digests were calculated with
+    algorithm: String,
+    /// Whether the digests are hashed with a key
+    #[serde(default)]
+    keyed: bool,
+    /// Scanned files and directories, each directory precedes its contents
+    entries: Vec<ManifestEntry>,
+}
+
+/// File or directory in a manifest
+#[derive(Serialize, Deserialize)]
+struct ManifestEntry {
+    #[serde(with = "duplicate_destroyer::path_serde")]
+    path: PathBuf,
+    dir: bool,
+    size: Option<u64>,
+    /// None if the content could not be read
+    digest: Option<String>,
+}
+
+/// Scan `paths` and write their manifest to `out`
+///
+/// # Arguments
+/// * `tree` - include directories, not only files
+/// * `key` - key the digests are hashed with (1 to 64 bytes)
+pub fn write_manifest<W: Write>(
+    paths: Vec<OsString>,
+    tree: bool,
+    key: Option<&[u8]>,
+    config: &mut Config,
+    out: &mut W,
+) -> io::Result<()> {
+    if let Some(key) = key {
+        check_key(key)?;
+    }
+
+    config.set_hash_all_files(true);
+    let scanner = Scanner::new(paths, config)?;
+
+    let mut entries = vec![];
+    for root in scanner.roots() {
+        add_entries(root, tree, &mut entries);
+    }
+    if let Some(key) = key {
+        for digest in entries.iter_mut().filter_map(|x| x.digest.as_mut()) {
+            *digest = keyed_digest(key, digest);
+        }
+    }
+    let manifest = Manifest {
+        algorithm: format!("{:?}", config.get_hash_algorithm()),
+        keyed: key.is_some(),
+        entries,
+    };
+
+    serde_json::to_writer_pretty(&mut *out, &manifest)?;
+    writeln!(out)
+}
+
+/// Add `node` and all its descendants to `entries`, each directory before its contents
+fn add_entries(node: TreeNode, tree: bool, entries: &mut Vec<ManifestEntry>) {
+    let mut stack = vec![node];
+    while let Some(node) = stack.pop() {
+        let dir = node.kind() == NodeKind::Dir;
+        if tree || !dir {
+            entries.push(ManifestEntry {
+                path: node.path(),
+                dir,
+                size: node.size(),
+                digest: node.digest(),
+            });
+        }
+        // The first child is popped first
+        stack.extend(node.children().into_iter().rev());
+    }
+}
+
+/// Read the key the digests are hashed with from the file at `path`
+pub fn read_key(path: &Path) -> io::Result<Vec<u8>> {
+    let key = fs::read(path)?;
+    check_key(&key)?;
+    Ok(key)
+}
+
+/// Check that `key` can be used for the keyed BLAKE2b hashes
+fn check_key(key: &[u8]) -> io::Result<()> {
+    if key.is_empty() || key.len() > 64 {
+        let message = format!("The key must be 1 to 64 bytes long, not {}.", key.len());
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    }
+    Ok(())
+}
+
+/// Replace the digests of `groups` by their hashes with `key`
+pub fn key_group_digests(groups: &mut [DuplicateObject], key: &[u8]) {
+    for digest in groups.iter_mut().filter_map(|x| x.digest.as_mut()) {
+        *digest = keyed_digest(key, digest);
+    }
+}
+
+/// Hash `digest` with `key`
+fn keyed_digest(key: &[u8], digest: &str) -> String {
+    let mut mac = <Blake2bMac512 as Mac>::new_from_slice(key).expect("Key length is checked.");
+    mac.update(digest.as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+/// Read a manifest written by `dude manifest`
+fn read_manifest(path: &Path) -> io::Result<Manifest> {
+    let reader = BufReader::new(File::open(path)?);
+    serde_json::from_reader(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Print the topmost entries of `left` manifest that have a copy in `right` manifest, each
+/// followed by its copies
+pub fn print_shared(left: &Path, right: &Path) -> io::Result<()> {
+    let left = read_manifest(left)?;
+    let right = read_manifest(right)?;
+    if left.algorithm != right.algorithm {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "The manifests use different hash algorithms ({} and {}).",
+                left.algorithm, right.algorithm
+            ),
+        ));
+    }
+    if left.keyed != right.keyed {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Only one of the manifests is written with a key.",
+        ));
+    }
+
+    let mut copies: HashMap<&str, Vec<&PathBuf>> = HashMap::new();
+    for entry in &right.entries {
+        if let Some(digest) = &entry.digest {
+            copies.entry(digest).or_default().push(&entry.path);
+        }
+    }
+
+    let mut shared_dirs: Vec<&Path> = vec![];
+    for entry in &left.entries {
+        // Contents of directories already printed are not printed again
+        if shared_dirs.iter().any(|dir| entry.path.starts_with(dir)) {
+            continue;
+        }
+        let entry_copies = match entry.digest.as_deref().and_then(|x| copies.get(x)) {
+            Some(entry_copies) => entry_copies,
+            None => continue,
+        };
+        if entry.dir {
+            shared_dirs.push(&entry.path);
+        }
+        println!("{:?}", entry.path);
+        for copy in entry_copies {
+            println!("  = {:?}", copy);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,68 @@
+//! Mapping of duplicate files to their kept copies
+//!
+//! The mapping lets other tools (e.g. backup software) use the decisions of DuDe without DuDe
+//! changing anything. Each duplicate file is written as one line of json with its path and the path
+//! of the copy kept by the keep policy. Files in duplicate directories are mapped to the files with
+//! the same content in the kept directory.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use duplicate_destroyer::{DuplicateObject, Scanner};
+use serde::Serialize;
+
+use crate::keep_policy::KeepPolicy;
+
+/// One line of the mapping
+#[derive(Serialize)]
+struct MappedFile<'a> {
+    #[serde(serialize_with = "duplicate_destroyer::path_serde::serialize")]
+    duplicate: &'a Path,
+    #[serde(serialize_with = "duplicate_destroyer::path_serde::serialize")]
+    original: &'a Path,
+}
+
+/// Write the mapping of the duplicate files in `duplicates` to their kept copies to `out`
+///
+/// # Arguments
+/// * `duplicates` - duplicate groups found by `scanner`
+/// * `scanner` - scanner used to match the files of duplicate directories
+/// * `policy` - which member of each group is kept
+/// * `out` - destination of the mapping
+pub fn write_mapping<W: Write>(
+    duplicates: &[DuplicateObject],
+    scanner: &Scanner,
+    policy: KeepPolicy,
+    out: &mut W,
+) -> io::Result<()> {
+    for group in duplicates {
+        let original = policy.choose(group);
+        let expansion = match scanner.expand_group(group) {
+            Some(expansion) => expansion,
+            // The members were not scanned, so they can't be matched by files
+            None => {
+                for duplicate in group.duplicates.iter().filter(|x| *x != original) {
+                    write_line(duplicate, original, out)?;
+                }
+                continue;
+            }
+        };
+
+        for file_match in expansion.matches {
+            let kept = match file_match.paths.iter().find(|x| x.starts_with(original)) {
+                Some(kept) => kept,
+                None => continue,
+            };
+            for duplicate in file_match.paths.iter().filter(|x| !x.starts_with(original)) {
+                write_line(duplicate, kept, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write one line of the mapping
+fn write_line<W: Write>(duplicate: &Path, original: &Path, out: &mut W) -> io::Result<()> {
+    let line = MappedFile { duplicate, original };
+    writeln!(out, "{}", serde_json::to_string(&line)?)
+}
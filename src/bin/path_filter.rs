@@ -0,0 +1,42 @@
+//! Filter restricting interactive handling to some of the duplicate groups
+//!
+//! The filter is entered as `/pattern` in interactive handling. A pattern containing `*` or `?` is
+//! a glob that has to match a whole path (e.g. `**/*.jpg`), any other pattern has to be contained
+//! in a path. The leading `/` of the command is not a part of the pattern, so globs are matched
+//! with the leading `/` of absolute paths removed (e.g. `/srv/**` matches everything in `/srv`).
+
+use duplicate_destroyer::DuplicateObject;
+use regex::Regex;
+
+use crate::protection::glob_to_regex;
+
+/// Pattern matched against paths of duplicate groups
+#[derive(Debug)]
+pub enum PathFilter {
+    Substring(String),
+    Glob(Regex),
+}
+
+impl PathFilter {
+    /// Get filter for `pattern`, None if the pattern is empty
+    pub fn new(pattern: &str) -> Option<Self> {
+        if pattern.is_empty() {
+            None
+        } else if pattern.contains(['*', '?']) {
+            Some(PathFilter::Glob(glob_to_regex(pattern)))
+        } else {
+            Some(PathFilter::Substring(pattern.to_owned()))
+        }
+    }
+
+    /// Returns true if any member of `group` matches the filter
+    pub fn matches(&self, group: &DuplicateObject) -> bool {
+        group.duplicates.iter().any(|path| {
+            let path = path.to_string_lossy();
+            match self {
+                PathFilter::Substring(pattern) => path.contains(&pattern[..]),
+                PathFilter::Glob(re) => re.is_match(path.strip_prefix('/').unwrap_or(&path)),
+            }
+        })
+    }
+}
@@ -1,55 +1,95 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::cell::Cell;
+use std::ffi::{OsStr, OsString};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
-use crate::OsString;
-use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 
-use duplicate_destroyer::{ProgressIndicator, ProgressMultiline};
+use duplicate_destroyer::{Phase, ProgressHandle, ProgressIndicator, ProgressMultiline};
 
-/// Struct with one progress bar for overall progress of search for file duplicates and one spinner
-/// to display the directory currently processed.
+/// Struct with one progress bar for overall progress of search for file duplicates and two spinners
+/// to display the directory currently processed and the file currently hashed.
+///
+/// The number of paths skipped so far is displayed in front of the directory.
 pub struct MultiProgressBar {
     multiprogress: MultiProgress,
     dir_spinner: ProgressBar,
+    skipped: Cell<u64>,
+    /// The overall progress bar, its prefix shows the current phase
+    files_bar: ProgressBar,
+    phase: Phase,
 }
 
 impl MultiProgressBar {
     /// Constructor.
     pub fn new() -> Self {
-        Self { multiprogress: MultiProgress::new(), dir_spinner: ProgressBar::new_spinner() }
+        Self {
+            multiprogress: MultiProgress::new(),
+            dir_spinner: ProgressBar::new_spinner(),
+            skipped: Cell::new(0),
+            files_bar: ProgressBar::hidden(),
+            phase: Phase::Walking,
+        }
+    }
+
+    /// Get prefix of the dir spinner with the number of paths skipped (empty if none was skipped)
+    fn skipped_prefix(&self) -> String {
+        match self.skipped.get() {
+            0 => String::new(),
+            1 => "[1 path skipped] ".to_string(),
+            count => format!("[{} paths skipped] ", count),
+        }
     }
 }
 
 impl ProgressMultiline for MultiProgressBar {
-    /// Create a new multiprogress with one directory spinner and one overall progress bar
-    fn create(
-        &mut self,
-        _message: String,
-        total_iterations: u64,
-    ) -> Rc<RefCell<dyn ProgressIndicator>> {
+    /// Show spinner while counting the files and display the phase in the overall progress bar
+    fn start_phase(&mut self, phase: Phase, total: u64) {
+        self.phase = phase;
+        match phase {
+            Phase::Counting => {
+                self.dir_spinner = ProgressBar::new_spinner()
+                    .with_style(spinner_style())
+                    .with_message(format!("{} in {} paths", phase, total));
+                self.dir_spinner.enable_steady_tick(Duration::from_millis(200));
+            }
+            _ => self.files_bar.set_prefix(phase.to_string()),
+        }
+    }
+
+    /// Create a new multiprogress with a directory spinner, a file spinner and an overall progress
+    /// bar
+    fn create(&mut self, _message: String, total_iterations: u64) -> Box<dyn ProgressHandle> {
+        // Stop the spinner of the counting phase
+        self.dir_spinner.finish_and_clear();
         // Set slower update frequency to make the dir print less overwhelming
         self.multiprogress = MultiProgress::with_draw_target(ProgressDrawTarget::stderr_with_hz(5));
-        // Dir spinner style
-        let spinner_style = ProgressStyle::with_template("{spinner} {wide_msg}")
-            .unwrap()
-            .tick_strings(&["▹▹▹▹", "▸▹▹▹", "▹▸▹▹", "▹▹▸▹", "▹▹▹▸", "▪▪▪▪"]);
-        let dir_spinner = ProgressBar::new_spinner().with_style(spinner_style);
+        let dir_spinner = ProgressBar::new_spinner()
+            .with_style(spinner_style())
+            .with_prefix(self.skipped_prefix());
         self.dir_spinner = self.multiprogress.add(dir_spinner);
+        let file_spinner =
+            self.multiprogress.add(ProgressBar::new_spinner().with_style(spinner_style()));
 
-        // overall progress style
+        // overall progress style, the prefix shows the phase and the message the hashing throughput
         let pb_style = ProgressStyle::with_template(
-            "{msg} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7}",
+            "{prefix} {msg} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} files ({per_sec}, ETA {eta})",
         )
         .unwrap()
         .progress_chars("##-");
         let checksum_pb = ProgressBar::new(total_iterations)
             .with_style(pb_style)
-            .with_message("Calculating hashes:");
+            .with_prefix(self.phase.to_string())
+            .with_message(BarHandle::message(0, Duration::ZERO));
+        self.files_bar = self.multiprogress.add(checksum_pb);
 
         // return the overall progress bar
-        let mut out_progress = Progress::new();
-        out_progress.set_progress_bar(self.multiprogress.add(checksum_pb));
-        Rc::new(RefCell::new(out_progress))
+        Box::new(BarHandle {
+            progress_bar: self.files_bar.clone(),
+            file_spinner,
+            bytes: AtomicU64::new(0),
+            start: Instant::now(),
+        })
     }
 
     /// Set the dir displayed by the dir spinner
@@ -58,6 +98,12 @@ impl ProgressMultiline for MultiProgressBar {
         self.dir_spinner.tick();
     }
 
+    /// Count the skipped path in the dir spinner
+    fn skip_path(&self, _path: &OsStr) {
+        self.skipped.set(self.skipped.get() + 1);
+        self.dir_spinner.set_prefix(self.skipped_prefix());
+    }
+
     /// Finalise dir spinner
     fn finalise(&self) {
         self.dir_spinner.finish_with_message("Checking directories: Done");
@@ -70,34 +116,78 @@ impl ProgressMultiline for MultiProgressBar {
     }
 }
 
-/// Struct holding simple progress bar/spinner
+/// Handle of the overall progress bar created by MultiProgressBar
 ///
-/// We either set a progress bar in the create method of MultiProgressBar or set a progress spinner
-/// in its own create method.
+/// Besides the files processed it displays the amount of data hashed per second and the file whose
+/// hashing started last.
+struct BarHandle {
+    progress_bar: ProgressBar,
+    file_spinner: ProgressBar,
+    bytes: AtomicU64,
+    start: Instant,
+}
+
+impl BarHandle {
+    /// Get message of the progress bar after hashing `bytes` in `elapsed` time
+    fn message(bytes: u64, elapsed: Duration) -> String {
+        let per_sec = match elapsed.as_secs_f64() {
+            secs if secs > 0.0 => (bytes as f64 / secs) as u64,
+            _ => 0,
+        };
+        format!("({}/s):", HumanBytes(per_sec))
+    }
+}
+
+impl ProgressHandle for BarHandle {
+    fn inc(&self, delta: u64) {
+        self.progress_bar.inc(delta)
+    }
+
+    fn inc_bytes(&self, bytes: u64) {
+        let total = self.bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.progress_bar.set_message(BarHandle::message(total, self.start.elapsed()));
+    }
+
+    fn set_current_file(&self, path: &OsStr) {
+        self.file_spinner.set_message(format!("Hashing: {:?}", path));
+        self.file_spinner.tick();
+    }
+
+    fn set_len(&self, len: u64) {
+        self.progress_bar.set_length(len)
+    }
+
+    fn finish(&self) {
+        self.file_spinner.finish_and_clear();
+        self.progress_bar.finish()
+    }
+}
+
+/// Struct holding simple progress spinner
 pub struct Progress {
     progress_bar: ProgressBar,
+    /// Phase started before the next call of `create`
+    phase: Option<Phase>,
 }
 
 impl Progress {
     /// Constructor. Yay...
     pub fn new() -> Self {
-        Self { progress_bar: ProgressBar::new(0) }
-    }
-
-    /// Set the progress bar to `new_pb`
-    pub fn set_progress_bar(&mut self, new_pb: ProgressBar) {
-        self.progress_bar = new_pb;
+        Self { progress_bar: ProgressBar::new(0), phase: None }
     }
 }
 
 impl ProgressIndicator for Progress {
-    /// Create simple progress indicator with spinner and `message`.
+    /// Remember the phase to display it instead of the message passed to `create`
+    fn start_phase(&mut self, phase: Phase, _total: u64) {
+        self.phase = Some(phase);
+    }
+
+    /// Create simple progress indicator with spinner and `message` (or the phase, if it started).
     fn create(&mut self, message: String, _total_iterations: u64) {
-        let spinner_style = ProgressStyle::with_template("{spinner} {wide_msg}")
-            .unwrap()
-            .tick_strings(&["▹▹▹▹", "▸▹▹▹", "▹▸▹▹", "▹▹▸▹", "▹▹▹▸", "▪▪▪▪"]);
+        let message = self.phase.take().map_or(message, |phase| phase.to_string());
         self.progress_bar =
-            ProgressBar::new_spinner().with_style(spinner_style).with_message(message);
+            ProgressBar::new_spinner().with_style(spinner_style()).with_message(message);
     }
 
     /// Update position in progress indicator to `iterations_done` or spin spinner.
@@ -116,3 +206,15 @@ impl ProgressIndicator for Progress {
         "Progress Bar".to_string()
     }
 }
+
+/// Style of the spinners
+fn spinner_style() -> ProgressStyle {
+    ProgressStyle::with_template("{spinner} {prefix}{wide_msg}").unwrap().tick_strings(&[
+        "▹▹▹▹",
+        "▸▹▹▹",
+        "▹▸▹▹",
+        "▹▹▸▹",
+        "▹▹▹▸",
+        "▪▪▪▪",
+    ])
+}
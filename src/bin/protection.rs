@@ -0,0 +1,193 @@
+//! Paths protected from destructive actions
+//!
+//! The protected paths are specified by glob patterns (`*` matches within one path component,
+//! `**` matches across components and `?` matches a single character). A path is protected if it,
+//! any of its ancestors or any of its descendants matches one of the patterns, so that neither a
+//! protected directory nor anything that contains it can be deleted or replaced.
+//!
+//! In addition, there are built-in guards that refuse system directories, whole input paths and
+//! directories containing the kept original. These can be turned off only explicitly.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use walkdir::WalkDir;
+
+/// System directories that are never deleted or replaced by built-in guards
+const SYSTEM_PATHS: [&str; 15] = [
+    "/", "/bin", "/boot", "/dev", "/etc", "/home", "/lib", "/lib64", "/opt", "/proc", "/root",
+    "/sbin", "/sys", "/usr", "/var",
+];
+
+/// List of paths that destructive actions refuse to touch
+#[derive(Debug)]
+pub struct ProtectedPaths {
+    patterns: Vec<(String, Regex)>,
+    protect_mount_points: bool,
+    /// Canonicalized input paths
+    input_roots: Vec<PathBuf>,
+    builtin_guards: bool,
+}
+
+impl ProtectedPaths {
+    /// Create an empty list of protected paths with built-in guards turned on
+    pub fn new() -> Self {
+        ProtectedPaths {
+            patterns: vec![],
+            protect_mount_points: false,
+            input_roots: vec![],
+            builtin_guards: true,
+        }
+    }
+
+    /// Set input paths that can't be deleted or replaced as a whole
+    pub fn set_input_roots(&mut self, roots: &[OsString]) {
+        self.input_roots = roots.iter().filter_map(|x| std::fs::canonicalize(x).ok()).collect();
+    }
+
+    /// Turn the built-in guards on or off
+    pub fn set_builtin_guards(&mut self, on: bool) {
+        self.builtin_guards = on;
+    }
+
+    /// Protect paths matching glob `pattern`
+    pub fn add_pattern(&mut self, pattern: &str) {
+        let pattern = pattern.trim_end_matches('/');
+        self.patterns.push((pattern.to_owned(), glob_to_regex(pattern)));
+    }
+
+    /// Protect paths matching glob patterns in `file`
+    ///
+    /// The file contains one pattern per line. Empty lines and lines starting with `#` are
+    /// ignored.
+    pub fn add_patterns_from_file(&mut self, file: &Path) -> io::Result<()> {
+        for pattern in read_pattern_file(file)? {
+            self.add_pattern(&pattern);
+        }
+        Ok(())
+    }
+
+    /// Protect also all mount points
+    pub fn set_protect_mount_points(&mut self, protect: bool) {
+        self.protect_mount_points = protect;
+    }
+
+    /// Check that `path` can be deleted or replaced while `original` is kept
+    ///
+    /// Returns an error of kind PermissionDenied if the path is protected.
+    pub fn check(&self, path: &OsString, original: &OsString) -> io::Result<()> {
+        let path = std::fs::canonicalize(path)?;
+
+        if self.builtin_guards {
+            self.check_builtin_guards(&path, &std::fs::canonicalize(original)?)?;
+        }
+
+        // The path itself or one of its ancestors is protected
+        for ancestor in path.ancestors() {
+            if let Some(pattern) = self.matching_pattern(ancestor) {
+                return Err(protected_error(&path, &format!("it matches {pattern:?}")));
+            }
+        }
+        if self.protect_mount_points && is_mount_point(&path)? {
+            return Err(protected_error(&path, "it is a mount point"));
+        }
+
+        // One of descendants is protected
+        if path.is_dir() {
+            let device = path.metadata()?.dev();
+            for entry in WalkDir::new(&path).min_depth(1).into_iter().filter_map(|x| x.ok()) {
+                if let Some(pattern) = self.matching_pattern(entry.path()) {
+                    let reason = format!("it contains {:?} matching {pattern:?}", entry.path());
+                    return Err(protected_error(&path, &reason));
+                }
+                if self.protect_mount_points
+                    && entry.file_type().is_dir()
+                    && entry.metadata().map_or(false, |x| x.dev() != device)
+                {
+                    let reason = format!("it contains mount point {:?}", entry.path());
+                    return Err(protected_error(&path, &reason));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that canonical `path` is not refused by built-in guards
+    fn check_builtin_guards(&self, path: &Path, original: &Path) -> io::Result<()> {
+        let override_hint = "Use --i-know-what-im-doing to override";
+        if SYSTEM_PATHS.iter().any(|x| path == Path::new(x)) {
+            let reason = format!("it is a system directory. {override_hint}");
+            return Err(protected_error(path, &reason));
+        }
+        if self.input_roots.iter().any(|x| x == path) {
+            let reason = format!("it is a whole input path. {override_hint}");
+            return Err(protected_error(path, &reason));
+        }
+        if original.starts_with(path) {
+            let reason = format!("it contains the kept original {original:?}. {override_hint}");
+            return Err(protected_error(path, &reason));
+        }
+        Ok(())
+    }
+
+    /// Get the first pattern matching `path`
+    fn matching_pattern(&self, path: &Path) -> Option<&str> {
+        let path = path.to_string_lossy();
+        self.patterns.iter().find(|(_, re)| re.is_match(&path)).map(|(pattern, _)| &pattern[..])
+    }
+}
+
+/// Returns true if `path` is on a different device than its parent
+fn is_mount_point(path: &Path) -> io::Result<bool> {
+    match path.parent() {
+        Some(parent) => Ok(path.metadata()?.dev() != parent.metadata()?.dev()),
+        None => Ok(true),
+    }
+}
+
+/// Create error for protected `path`
+fn protected_error(path: &Path, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("{:?} is protected, because {}.", path, reason),
+    )
+}
+
+/// Read patterns listed in `file`
+///
+/// The file contains one pattern per line. Empty lines and lines starting with `#` are ignored.
+pub fn read_pattern_file(file: &Path) -> io::Result<Vec<String>> {
+    let mut patterns = vec![];
+    for line in BufReader::new(File::open(file)?).lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            patterns.push(line.to_owned());
+        }
+    }
+    Ok(patterns)
+}
+
+/// Convert glob pattern to a regular expression matching whole paths
+pub fn glob_to_regex(pattern: &str) -> Regex {
+    let mut re = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re.push_str(".*");
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).expect("Escaped glob should be a valid regex.")
+}
@@ -0,0 +1,82 @@
+//! Results of an earlier scan
+//!
+//! The json file written by `--json-file` can be printed again or its duplicates can be handled
+//! without scanning the directories again. The paths in the file are not verified, so the
+//! duplicates could have changed since the scan.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use duplicate_destroyer::DuplicateObject;
+use serde::{Deserialize, Serialize};
+
+use crate::Units;
+
+/// Part of the json file needed to handle the duplicates
+#[derive(Deserialize)]
+struct StoredReport {
+    groups: Vec<DuplicateObject>,
+    /// Whether the digests of the groups are hashed with a key (see `--key-file`)
+    #[serde(default)]
+    keyed: bool,
+    #[serde(default)]
+    roots: Vec<InputRoot>,
+}
+
+/// Canonical path given to the scan, which must not be deleted or replaced as a whole
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct InputRoot(#[serde(with = "duplicate_destroyer::path_serde")] pub OsString);
+
+impl InputRoot {
+    /// Get the stored roots of a scan of `paths`
+    ///
+    /// Paths that can't be canonicalized are stored as they are.
+    pub fn from_paths(paths: &[OsString]) -> Vec<InputRoot> {
+        paths
+            .iter()
+            .map(|x| std::fs::canonicalize(x).map_or_else(|_| x.clone(), |x| x.into()))
+            .map(InputRoot)
+            .collect()
+    }
+}
+
+/// Read duplicate groups from a json file written by `--json-file`
+///
+/// The digests hashed with a key are left out, so that the groups are verified by comparing their
+/// members with each other.
+pub fn read_groups(path: &Path) -> io::Result<Vec<DuplicateObject>> {
+    Ok(read_report(path)?.0)
+}
+
+/// Read duplicate groups and the scanned paths from a json file written by `--json-file`
+///
+/// Reports written before the scanned paths were stored have no paths.
+pub fn read_report(path: &Path) -> io::Result<(Vec<DuplicateObject>, Vec<OsString>)> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut report: StoredReport = serde_json::from_reader(reader)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if report.keyed {
+        for group in report.groups.iter_mut() {
+            group.digest = None;
+        }
+    }
+    let roots = report.roots.into_iter().map(|x| x.0).collect();
+    Ok((report.groups, roots))
+}
+
+/// Print statistics and all duplicate groups stored in a json file written by `--json-file`
+pub fn print_report(path: &Path, units: Units) -> io::Result<()> {
+    let duplicates = read_groups(path)?;
+    crate::print_statistics(&duplicates, &[], false, units, &mut io::stdout())?;
+
+    let num_groups = duplicates.len();
+    for (index, group) in duplicates.iter().enumerate() {
+        println!("{}", crate::get_group_header(index, num_groups, group));
+        let paths: Vec<_> = group.duplicates.iter().map(|x| x.into()).collect();
+        crate::print_group(&paths[..], group, &[], units);
+    }
+
+    Ok(())
+}
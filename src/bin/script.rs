@@ -0,0 +1,231 @@
+//! Shell script performing the chosen keep policy
+//!
+//! Instead of changing anything, DuDe can write a POSIX shell script that removes or replaces the
+//! duplicates. The script can be reviewed and edited before it is run. Each command checks that the
+//! kept original still exists and that the duplicate is still its exact copy before touching it.
+
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use clap::ValueEnum;
+use duplicate_destroyer::DuplicateObject;
+
+use crate::keep_policy::KeepPolicy;
+use crate::protection::ProtectedPaths;
+
+/// What the script does with the duplicates that are not kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ScriptAction {
+    /// Delete the duplicates
+    Delete,
+    /// Replace files in the duplicates with hardlinks to the kept original
+    Hardlink,
+    /// Replace the duplicates with symlinks to the kept original
+    Softlink,
+}
+
+/// Functions used by the generated commands
+const PRELUDE: &str = r#"#!/bin/sh
+# Generated by DuDe (Duplicate Destroyer).
+# Review the commands at the end of this script before running it.
+
+set -u
+
+# Succeeds if $2 is an exact copy of $1
+same() {
+    if [ -d "$1" ] && [ ! -L "$1" ]; then
+        diff -r -- "$1" "$2" >/dev/null 2>&1
+    else
+        cmp -s -- "$1" "$2"
+    fi
+}
+
+# Succeeds if $2 can be changed while $1 is kept
+check() {
+    if [ ! -e "$1" ]; then
+        echo "Skipping $2: original $1 does not exist" >&2
+        return 1
+    fi
+    if [ ! -e "$2" ]; then
+        echo "Skipping $2: it does not exist" >&2
+        return 1
+    fi
+    if ! same "$1" "$2"; then
+        echo "Skipping $2: it is not a copy of $1" >&2
+        return 1
+    fi
+}
+
+# Delete $2 keeping $1
+remove() {
+    check "$1" "$2" && rm -rf -- "$2"
+}
+
+# Replace $2 (or each file in it) with a hardlink to $1
+hardlink() {
+    check "$1" "$2" || return
+    if [ -d "$2" ]; then
+        (cd -- "$2" && find . -type f -exec sh -c \
+            'o=$1; shift; for f; do ln -f -- "$o/$f" "$f"; done' sh "$1" {} +)
+    else
+        ln -f -- "$1" "$2"
+    fi
+}
+
+# Replace $2 with a symlink to $1
+softlink() {
+    check "$1" "$2" && rm -rf -- "$2" && ln -s -- "$1" "$2"
+}
+
+"#;
+
+/// Write shell script handling `duplicates` to `writer`
+///
+/// Members chosen by `policy` are kept, other members are handled by `action`. Members refused by
+/// `protected` are left in the script only as comments with the reason.
+///
+/// # Arguments
+/// * `duplicates` - duplicate groups to handle
+/// * `policy` - policy choosing the kept member of each group
+/// * `action` - action performed on the other members
+/// * `protected` - paths the script must not change
+/// * `writer` - destination of the script
+pub fn write_script(
+    duplicates: &[DuplicateObject],
+    policy: KeepPolicy,
+    action: ScriptAction,
+    protected: &ProtectedPaths,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    writer.write_all(PRELUDE.as_bytes())?;
+
+    let command = match action {
+        ScriptAction::Delete => "remove",
+        ScriptAction::Hardlink => "hardlink",
+        ScriptAction::Softlink => "softlink",
+    };
+
+    for (index, group) in duplicates.iter().enumerate() {
+        let kept = policy.choose(group);
+        // Symlinks have to point to an absolute path to be valid from any directory
+        let original = absolute(kept);
+
+        let paths: Vec<_> = group.duplicates.iter().filter(|x| *x != kept).collect();
+
+        // Paths in comments are escaped, since a newline in them would end the comment
+        writeln!(writer, "# {}", crate::get_group_header(index, duplicates.len(), group))?;
+        writeln!(writer, "# keep {:?}", original)?;
+
+        for path in paths {
+            let path = absolute(path);
+            if let Err(e) = protected.check(&path, &original) {
+                writeln!(writer, "# {} {:?} {:?}", command, original, path)?;
+                writeln!(writer, "#   {}", e.to_string().escape_debug())?;
+                continue;
+            }
+            write!(writer, "{} ", command)?;
+            writer.write_all(&quote(&original))?;
+            writer.write_all(b" ")?;
+            writer.write_all(&quote(&path))?;
+            writeln!(writer)?;
+        }
+        writeln!(writer)?;
+    }
+
+    writer.flush()
+}
+
+/// Get absolute form of `path` without resolving symlinks
+fn absolute(path: &Path) -> OsString {
+    if path.is_absolute() {
+        return path.as_os_str().to_owned();
+    }
+    match std::env::current_dir() {
+        Ok(dir) => dir.join(path).into_os_string(),
+        Err(_) => path.as_os_str().to_owned(),
+    }
+}
+
+/// Quote `path` for POSIX shell
+///
+/// The path is written in single quotes as raw bytes, so that even paths that are not valid
+/// UTF-8 are preserved.
+pub fn quote(path: &OsStr) -> Vec<u8> {
+    let mut quoted = vec![b'\''];
+    for byte in path.as_bytes() {
+        if *byte == b'\'' {
+            quoted.extend_from_slice(b"'\\''");
+        } else {
+            quoted.push(*byte);
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use std::os::unix::ffi::OsStringExt;
+    use std::process::Command;
+    use tempdir::TempDir;
+
+    /// Get paths with a quote, a newline and bytes that are not valid UTF-8 in their names
+    fn special_names() -> Vec<OsString> {
+        vec!["it's".into(), "new\nline".into(), OsString::from_vec(b"caf\xe9".to_vec())]
+    }
+
+    #[test]
+    /// Check that quoted paths are read back by the shell as the same bytes
+    fn quote_test() -> Result<(), Box<dyn Error>> {
+        for name in special_names() {
+            let mut command = b"printf %s ".to_vec();
+            command.extend(quote(&name));
+            let out = Command::new("sh").arg("-c").arg(OsStr::from_bytes(&command)).output()?;
+            assert!(out.status.success());
+            assert_eq!(out.stdout, name.as_bytes());
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Check that the generated script deletes duplicates with special characters in their names
+    ///
+    /// Create directory structure:
+    /// tmp_dir/
+    /// ├── a
+    /// ├── it's
+    /// ├── new\nline
+    /// └── caf\xe9
+    ///
+    /// where all files are duplicates and `a` is kept.
+    fn script_special_names_test() -> Result<(), Box<dyn Error>> {
+        let tmp_dir = TempDir::new("script_names")?;
+        let kept = tmp_dir.path().join("a");
+        std::fs::write(&kept, "Duplicate content")?;
+        let mut paths = vec![kept.clone()];
+        for name in special_names() {
+            let path = tmp_dir.path().join(name);
+            std::fs::write(&path, "Duplicate content")?;
+            paths.push(path);
+        }
+        let group = DuplicateObject::new(17, paths.clone());
+
+        let mut script = vec![];
+        let protected = ProtectedPaths::new();
+        write_script(&[group], KeepPolicy::First, ScriptAction::Delete, &protected, &mut script)?;
+        let status = Command::new("sh").arg("-c").arg(OsStr::from_bytes(&script)).status()?;
+        assert!(status.success());
+
+        assert!(kept.exists());
+        for path in paths[1..].iter() {
+            assert!(!path.exists(), "{:?} was not deleted", path);
+        }
+
+        tmp_dir.close()?;
+        Ok(())
+    }
+}
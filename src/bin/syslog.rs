@@ -0,0 +1,63 @@
+//! Logging to syslog
+//!
+//! Unattended runs (e.g. from cron) log to syslog instead of stderr with `--syslog`. On systems
+//! with systemd the messages end up in the journal as well. The level is read from `RUST_LOG` if
+//! it is a plain level (e.g. `info`), otherwise only warnings and errors are logged. Records with
+//! the [`SUMMARY_TARGET`] target (summaries of the executed actions) are logged at any level.
+
+use std::ffi::CString;
+use std::str::FromStr;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Target of the log records summarising the executed actions
+pub const SUMMARY_TARGET: &str = "dude::summary";
+
+/// Identifier of the messages in syslog
+const IDENT: &[u8] = b"dude\0";
+
+/// Logger sending the records to syslog
+struct SyslogLogger {
+    /// Level of the records logged (except for the summaries)
+    level: LevelFilter,
+}
+
+impl Log for SyslogLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level || metadata.target() == SUMMARY_TARGET
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let priority = match record.level() {
+            Level::Error => libc::LOG_ERR,
+            Level::Warn => libc::LOG_WARNING,
+            Level::Info => libc::LOG_INFO,
+            Level::Debug | Level::Trace => libc::LOG_DEBUG,
+        };
+        // Interior nul bytes can't be passed to syslog
+        let message = format!("{}: {}", record.target(), record.args()).replace('\0', "\\0");
+        let message = CString::new(message).expect("Nul bytes were replaced.");
+        unsafe {
+            libc::syslog(priority, b"%s\0".as_ptr() as *const libc::c_char, message.as_ptr())
+        };
+    }
+
+    fn flush(&self) {}
+}
+
+/// Send the log records to syslog instead of stderr
+pub fn init() {
+    unsafe { libc::openlog(IDENT.as_ptr() as *const libc::c_char, libc::LOG_PID, libc::LOG_USER) };
+    let level = std::env::var("RUST_LOG")
+        .ok()
+        .and_then(|x| LevelFilter::from_str(&x).ok())
+        .unwrap_or(LevelFilter::Warn);
+    // The logger lives until the end of the program
+    let logger = Box::leak(Box::new(SyslogLogger { level }));
+    log::set_logger(logger).expect("The logger should be set only once.");
+    // The summaries are logged at info level, the rest is filtered by the logger
+    log::set_max_level(level.max(LevelFilter::Info));
+}
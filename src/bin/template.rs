@@ -0,0 +1,144 @@
+//! Templates with placeholders filled by the values of duplicate groups
+//!
+//! Used by `--exec` to build the command run for each group and by `--format` to print a line of
+//! the chosen format for each group or each of its members. A placeholder is a name in braces
+//! (e.g. `{size}`), braces that do not start a known placeholder are kept as they are.
+
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+
+use duplicate_destroyer::DuplicateObject;
+
+use crate::keep_policy::KeepPolicy;
+
+/// Replace the placeholders in `template` with their values
+///
+/// # Arguments
+/// * `template` - text with placeholders
+/// * `values` - placeholders (with braces) and the bytes they are replaced with
+pub fn fill(template: &str, values: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    let mut filled = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        filled.extend_from_slice(&rest.as_bytes()[..start]);
+        rest = &rest[start..];
+        match values.iter().find(|(name, _)| rest.starts_with(name)) {
+            Some((name, value)) => {
+                filled.extend_from_slice(value);
+                rest = &rest[name.len()..];
+            }
+            None => {
+                filled.push(b'{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.extend_from_slice(rest.as_bytes());
+    filled
+}
+
+/// Write a line filled from `template` for each of the `duplicates`
+///
+/// If the template contains `{path}`, a line is written for each path in each group instead.
+/// The placeholders are:
+/// * `{path}` - path in the group
+/// * `{paths}` - all paths in the group separated by spaces
+/// * `{keep}` - path chosen by `policy`
+/// * `{group}` - number of the group (starting from 1)
+/// * `{size}` - size of one path in bytes
+/// * `{hash}` - digest of the content of the paths (empty if it is not known)
+/// * `{count}` - number of paths in the group
+///
+/// The escapes `\t`, `\n` and `\\` in the template are replaced by a tab, a newline and a
+/// backslash.
+pub fn write_formatted(
+    duplicates: &[DuplicateObject],
+    template: &str,
+    policy: KeepPolicy,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    let template = unescape(template);
+    let per_path = template.contains("{path}");
+
+    for (index, group) in duplicates.iter().enumerate() {
+        let paths: Vec<_> = group.duplicates.iter().map(|x| x.as_os_str().as_bytes()).collect();
+        let mut values = vec![
+            ("{paths}", paths.join(&b' ')),
+            ("{keep}", policy.choose(group).as_os_str().as_bytes().to_vec()),
+            ("{group}", (index + 1).to_string().into_bytes()),
+            ("{size}", group.size.to_string().into_bytes()),
+            ("{hash}", group.digest.clone().unwrap_or_default().into_bytes()),
+            ("{count}", group.len().to_string().into_bytes()),
+        ];
+        if per_path {
+            for path in paths {
+                values.push(("{path}", path.to_vec()));
+                writer.write_all(&fill(&template, &values))?;
+                writeln!(writer)?;
+                values.pop();
+            }
+        } else {
+            writer.write_all(&fill(&template, &values))?;
+            writeln!(writer)?;
+        }
+    }
+
+    writer.flush()
+}
+
+/// Replace escape sequences in `template`
+fn unescape(template: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Check that `{path}` and `{paths}` are filled with their own values in any order
+    fn fill_placeholder_order_test() {
+        let values = [("{paths}", b"a b".to_vec()), ("{path}", b"a".to_vec())];
+        assert_eq!(fill("{path}|{paths}", &values), b"a|a b");
+        assert_eq!(fill("{paths}|{path}", &values), b"a b|a");
+
+        let values = [("{path}", b"a".to_vec()), ("{paths}", b"a b".to_vec())];
+        assert_eq!(fill("{path}|{paths}", &values), b"a|a b");
+        assert_eq!(fill("{paths}|{path}", &values), b"a b|a");
+    }
+
+    #[test]
+    /// Check that unknown placeholders and lone braces are kept as they are
+    fn fill_unknown_test() {
+        let values = [("{size}", b"17".to_vec())];
+        assert_eq!(fill("{ {size}} {sizes} {", &values), b"{ 17} {sizes} {");
+    }
+
+    #[test]
+    /// Check that each member is written on its own line with `{path}`
+    fn write_formatted_per_path_test() -> io::Result<()> {
+        let group = DuplicateObject::new(17, ["b", "a"]);
+        let mut out = vec![];
+        write_formatted(&[group], "{group}\\t{path}\\t{paths}", KeepPolicy::First, &mut out)?;
+        assert_eq!(out, b"1\ta\ta b\n1\tb\ta b\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+//! Verification of a saved report against the current state of the disk
+//!
+//! The duplicates stored by `--json-file` are often handled long after the scan. Before that, each
+//! member of each group is checked to still exist, to have the same size and the same content as
+//! during the scan, so that no copy is removed based on stale data.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use duplicate_destroyer::{Config, DuplicateObject, Scanner};
+
+use crate::{get_human_readable_size, Units};
+
+/// Difference of a group member from the state stored in the report
+#[derive(Debug, PartialEq, Eq)]
+pub enum Drift {
+    Missing(PathBuf),
+    SizeChanged { path: PathBuf, size: Option<u64> },
+    ContentChanged(PathBuf),
+}
+
+impl Drift {
+    /// Get description of the drift with sizes printed in `units`
+    pub fn describe(&self, units: Units) -> String {
+        match self {
+            Drift::Missing(path) => format!("missing: {:?}", path),
+            Drift::SizeChanged { path, size: Some(size) } => {
+                format!("size changed: {:?} (now {})", path, get_human_readable_size(*size, units))
+            }
+            Drift::SizeChanged { path, size: None } => {
+                format!("size changed: {:?} (now unknown)", path)
+            }
+            Drift::ContentChanged(path) => format!("content changed: {:?}", path),
+        }
+    }
+}
+
+/// Check all groups stored in a json file written by `--json-file` and print the members that
+/// changed since the scan
+///
+/// Returns true if none of the members changed. The `config` should match the options of the scan
+/// (e.g. the hash algorithm and the excluded paths), otherwise directories can be reported as
+/// changed.
+pub fn verify_report(path: &Path, units: Units, config: &mut Config) -> io::Result<bool> {
+    let duplicates = crate::report::read_groups(path)?;
+    config.set_hash_all_files(true);
+
+    let mut changed = 0;
+    let num_groups = duplicates.len();
+    for (index, group) in duplicates.iter().enumerate() {
+        let drift = get_drift(group, config);
+        if drift.is_empty() {
+            continue;
+        }
+        changed += 1;
+        println!("{}", crate::get_group_header(index, num_groups, group));
+        for item in drift {
+            println!("  {}", item.describe(units));
+        }
+    }
+
+    println!("{} of {} groups changed since the scan.", changed, num_groups);
+    Ok(changed == 0)
+}
+
+/// Get differences of the members of `group` from their state during the scan
+///
+/// The content is compared with the digest stored in the group. Groups stored without a digest
+/// are checked for their members having the same content as the first of them.
+pub fn get_drift(group: &DuplicateObject, config: &Config) -> Vec<Drift> {
+    let mut drift = vec![];
+    let mut existing = vec![];
+    for path in &group.duplicates {
+        if Path::new(path).symlink_metadata().is_ok() {
+            existing.push(path.clone());
+        } else {
+            drift.push(Drift::Missing(path.clone()));
+        }
+    }
+    if existing.is_empty() {
+        return drift;
+    }
+
+    let scanner = Scanner::new(existing, config).unwrap();
+
+    let roots = scanner.roots();
+    let expected = group.digest.clone().or_else(|| roots.first().and_then(|x| x.digest()));
+    for root in roots {
+        if root.size() != Some(group.size) {
+            drift.push(Drift::SizeChanged { path: root.path(), size: root.size() });
+        } else if expected.is_none() || root.digest() != expected {
+            drift.push(Drift::ContentChanged(root.path()));
+        }
+    }
+    drift
+}
@@ -0,0 +1,60 @@
+//! Built-in categories of files recognised by their extensions
+//!
+//! A scan restricted to some categories (e.g. only images and videos of a photo archive) skips
+//! all other files without reading them, so that much less data is hashed than in a full scan.
+
+use std::path::Path;
+
+/// Category of files with a known set of extensions
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Category {
+    /// Raster, vector and camera raw images (e.g. `jpg`, `png`, `svg` or `cr2`)
+    Images,
+    /// Video files (e.g. `mp4`, `mkv` or `mov`)
+    Video,
+    /// Audio files (e.g. `mp3`, `flac` or `ogg`)
+    Audio,
+    /// Text documents, spreadsheets, presentations and e-books (e.g. `pdf`, `docx` or `epub`)
+    Documents,
+}
+
+const IMAGE_EXTENSIONS: [&str; 24] = [
+    "jpg", "jpeg", "jpe", "png", "gif", "bmp", "tif", "tiff", "webp", "heic", "heif", "avif",
+    "svg", "ico", "psd", "xcf", "raw", "dng", "cr2", "cr3", "nef", "arw", "orf", "rw2",
+];
+
+const VIDEO_EXTENSIONS: [&str; 15] = [
+    "mp4", "m4v", "mkv", "mov", "avi", "wmv", "flv", "webm", "mpg", "mpeg", "m2ts", "mts", "3gp",
+    "ogv", "vob",
+];
+
+const AUDIO_EXTENSIONS: [&str; 13] =
+    ["mp3", "flac", "wav", "ogg", "oga", "opus", "m4a", "aac", "wma", "aiff", "aif", "alac", "ape"];
+
+const DOCUMENT_EXTENSIONS: [&str; 20] = [
+    "pdf", "doc", "docx", "odt", "rtf", "txt", "md", "tex", "xls", "xlsx", "ods", "csv", "ppt",
+    "pptx", "odp", "epub", "mobi", "djvu", "pages", "numbers",
+];
+
+impl Category {
+    /// Get extensions (in lowercase) of the files in the category
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Category::Images => &IMAGE_EXTENSIONS,
+            Category::Video => &VIDEO_EXTENSIONS,
+            Category::Audio => &AUDIO_EXTENSIONS,
+            Category::Documents => &DOCUMENT_EXTENSIONS,
+        }
+    }
+
+    /// Returns true if the file at `path` belongs to the category
+    ///
+    /// Only the extension of the file is compared (ignoring its case), the content is not read.
+    pub fn contains(&self, path: impl AsRef<Path>) -> bool {
+        match path.as_ref().extension().and_then(|x| x.to_str()) {
+            Some(extension) => self.extensions().iter().any(|x| x.eq_ignore_ascii_case(extension)),
+            None => false,
+        }
+    }
+}
@@ -1,46 +1,189 @@
 //! Checksum calculation module
+//!
+//! The scan compares files by the checksum of their first [`PREFIX_LENGTH`] bytes first and only
+//! files with the same prefix are hashed as a whole. [`hash_file`] and [`hash_prefix`] calculate
+//! the same checksums as the scan (with the default settings), so that frontends and scripts can
+//! hash individual files consistently with it.
+//!
+//! # Example usage
+//! ```
+//! use duplicate_destroyer::checksum::{hash_file, hash_prefix, PREFIX_LENGTH};
+//! use duplicate_destroyer::HashAlgorithm;
+//!
+//! let path = "tests/fixtures/A/a.txt";
+//! let checksum = hash_file(path, HashAlgorithm::Blake2).unwrap();
+//! let prefix = hash_prefix(path, PREFIX_LENGTH, HashAlgorithm::Blake2).unwrap();
+//! assert_eq!(checksum.len(), 128);
+//! assert_eq!(prefix.len(), 128);
+//! ```
 use std::ffi::OsString;
 use std::fs::File;
 use std::io::{self, prelude::Read, BufReader};
+use std::panic;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 use digest::Digest;
 
+use crate::content::{self, ContentFilter};
+use crate::open_files::OpenFileLimit;
+
+/// Hexadecimal checksum of the whole content of a file
+pub type Checksum = String;
+
+/// Number of bytes at the start of files whose checksum is compared before hashing whole files
+pub const PREFIX_LENGTH: usize = 1024;
+
+/// Number of bytes of a file hashed between two progress reports
+pub(crate) const PROGRESS_CHUNK: u64 = 64 * 1024 * 1024;
+
+/// Function calculating checksum of a whole file (in chunks hashed in parallel if its content is
+/// larger than the chunk size) and reporting the number of bytes hashed
+pub(crate) type ChecksumFn =
+    fn(&OsString, ContentFilter, Option<Chunking>, &dyn Fn(u64)) -> io::Result<Checksum>;
+
+/// Hashing of the content of large files in chunks in parallel
+#[derive(Clone, Debug)]
+pub(crate) struct Chunking {
+    /// Size of the chunks, larger content is hashed in chunks
+    pub(crate) size: u64,
+    /// Maximum number of threads hashing the chunks of one file
+    ///
+    /// The checksum does not depend on the number of threads, with one thread the chunks are
+    /// hashed one after another.
+    pub(crate) threads: usize,
+    /// Limit the readers of the file opened by all threads except the first one are counted in
+    pub(crate) open_files: Arc<OpenFileLimit>,
+}
+
+/// Function calculating checksum of the first bytes of a file
+pub(crate) type PartialChecksumFn = fn(&OsString, ContentFilter) -> io::Result<String>;
+
 #[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 /// Hash Algorithm types supported
 pub enum HashAlgorithm {
     Blake2,
     SHA3_256,
     SHA3_512,
+    SHA2_256,
+    SHA2_512,
+}
+
+/// Calculate the checksum of the whole file at `path`
+///
+/// The checksum is the same as the [`digest`](crate::DuplicateObject::digest) of the file in a
+/// scan with `algorithm` (unless only the streams of audio or video files are compared, or the
+/// file is hashed in chunks, see [`Config`](crate::Config)).
+///
+/// # Arguments
+/// * `path` - path to the file
+/// * `algorithm` - hash algorithm used to calculate the checksum
+pub fn hash_file(path: impl AsRef<Path>, algorithm: HashAlgorithm) -> io::Result<Checksum> {
+    let path = path.as_ref().as_os_str().to_owned();
+    get_checksum_fn(&algorithm)(&path, ContentFilter::default(), None, &|_| {})
+}
+
+/// Calculate the checksum of the first `len` bytes of the file at `path` (or of the whole file if
+/// it is shorter)
+///
+/// With `len` equal to [`PREFIX_LENGTH`], this is the checksum the scan compares files by before
+/// hashing them as a whole.
+///
+/// # Arguments
+/// * `path` - path to the file
+/// * `len` - maximum number of bytes hashed
+/// * `algorithm` - hash algorithm used to calculate the checksum
+pub fn hash_prefix(
+    path: impl AsRef<Path>,
+    len: usize,
+    algorithm: HashAlgorithm,
+) -> io::Result<Checksum> {
+    let file = File::open(path)?;
+    match algorithm {
+        HashAlgorithm::Blake2 => hash_reader::<blake2::Blake2b512>(file.take(len as u64)),
+        HashAlgorithm::SHA3_256 => hash_reader::<sha3::Sha3_256>(file.take(len as u64)),
+        HashAlgorithm::SHA3_512 => hash_reader::<sha3::Sha3_512>(file.take(len as u64)),
+        HashAlgorithm::SHA2_256 => hash_reader::<sha2::Sha256>(file.take(len as u64)),
+        HashAlgorithm::SHA2_512 => hash_reader::<sha2::Sha512>(file.take(len as u64)),
+    }
+}
+
+/// Calculate checksum of all bytes read from `reader`
+fn hash_reader<H>(reader: impl Read) -> io::Result<Checksum>
+where
+    H: Digest,
+    digest::Output<H>: std::fmt::LowerHex,
+{
+    let mut hasher = H::new();
+    let mut buf_reader = BufReader::new(reader);
+    let mut buffer = [0u8; 1024];
+    loop {
+        let count = buf_reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
 }
 
 /// Get function that calculates checksum of whole file
 ///
 /// # Arguments
 /// * `ha` - hash algorithm that is used to calculate the checksum
-pub(crate) fn get_checksum_fn(ha: &HashAlgorithm) -> fn(&OsString) -> io::Result<String> {
+pub(crate) fn get_checksum_fn(ha: &HashAlgorithm) -> ChecksumFn {
     match ha {
         HashAlgorithm::Blake2 => get_checksum::<blake2::Blake2b512>,
         HashAlgorithm::SHA3_256 => get_checksum::<sha3::Sha3_256>,
         HashAlgorithm::SHA3_512 => get_checksum::<sha3::Sha3_512>,
+        HashAlgorithm::SHA2_256 => get_checksum::<sha2::Sha256>,
+        HashAlgorithm::SHA2_512 => get_checksum::<sha2::Sha512>,
     }
 }
 
 /// Calculate checksum for a whole file
 ///
+/// The number of bytes hashed is passed to `report` after each PROGRESS_CHUNK bytes and once the
+/// whole file is hashed, so that hashing of huge files can be followed.
+///
+/// If the selected content is larger than the chunk size of `chunking`, it is hashed by
+/// [`get_chunked_checksum`] instead.
+///
 /// # Arguments
 /// * `path` - path to the file to be checksummed
+/// * `filter` - selects the content of the file that is hashed
+/// * `chunking` - hashing of large content in chunks in parallel
+/// * `report` - function called with the number of bytes hashed since its last call
 /// * `H` - hasher structure that is used for checksum calculation
-fn get_checksum<H>(path: &OsString) -> io::Result<String>
+fn get_checksum<H>(
+    path: &OsString,
+    filter: ContentFilter,
+    chunking: Option<Chunking>,
+    report: &dyn Fn(u64),
+) -> io::Result<String>
 where
     H: Digest,
     digest::Output<H>: std::fmt::LowerHex,
 {
     log::trace!("Getting checksum for {:?}", path);
+    let reader = content::open(path, filter)?;
+    if let Some(chunking) = chunking.filter(|x| x.size > 0) {
+        let len = reader.len()?;
+        if len > chunking.size {
+            // The first chunk thread opens its own reader with the permit of this one
+            drop(reader);
+            return get_chunked_checksum::<H>(path, filter, len, chunking, report);
+        }
+    }
+
     let mut hasher = H::new();
     let mut buffer = [0u8; 1024];
+    let mut unreported = 0u64;
 
-    let mut buf_reader = BufReader::new(File::open(path)?);
+    let mut buf_reader = BufReader::new(reader);
 
     loop {
         let count = buf_reader.read(&mut buffer)?;
@@ -48,23 +191,197 @@ where
             break;
         }
         hasher.update(&buffer[..count]);
+
+        unreported += count as u64;
+        if unreported >= PROGRESS_CHUNK {
+            report(unreported);
+            unreported = 0;
+        }
+    }
+    if unreported > 0 {
+        report(unreported);
     }
 
     let result = format!("{:x}", hasher.finalize());
     Ok(result)
 }
 
+/// Calculate checksum of a file from the checksums of chunks of its content hashed in parallel
+///
+/// The content is split into chunks of the size given by `chunking`, which are hashed by at most
+/// as many threads as `chunking` allows (each with its own reader of the file). The caller's permit
+/// to open the file covers the reader of the first thread, the other threads are started only if
+/// they get a permit from the open file limit of `chunking` without waiting. The checksum is
+/// the hash of the checksums of the chunks in order, so it differs from the checksum of the same
+/// content hashed as a whole. The bytes hashed by all threads are reported by the calling thread.
+///
+/// # Arguments
+/// * `path` - path to the file to be checksummed
+/// * `filter` - selects the content of the file that is hashed
+/// * `len` - number of bytes of the selected content
+/// * `chunking` - size of the chunks (except for the last one) and number of threads
+/// * `report` - function called with the number of bytes hashed since its last call
+/// * `H` - hasher structure that is used for checksum calculation
+fn get_chunked_checksum<H>(
+    path: &OsString,
+    filter: ContentFilter,
+    len: u64,
+    chunking: Chunking,
+    report: &dyn Fn(u64),
+) -> io::Result<String>
+where
+    H: Digest,
+    digest::Output<H>: std::fmt::LowerHex,
+{
+    let chunk_size = chunking.size;
+    let chunk_count = (len + chunk_size - 1) / chunk_size;
+    let permits: Vec<_> = (1..chunking.threads.clamp(1, chunk_count as usize))
+        .map_while(|_| chunking.open_files.try_acquire())
+        .collect();
+    let threads = permits.len() + 1;
+    log::debug!("Hashing {:?} in {} chunks by {} threads", path, chunk_count, threads);
+
+    let chunks = Chunks {
+        path,
+        filter,
+        len,
+        chunk_size,
+        next: AtomicU64::new(0),
+        unreported: AtomicU64::new(0),
+        checksums: Mutex::new(vec![String::new(); chunk_count as usize]),
+    };
+    thread::scope(|scope| {
+        let handles: Vec<_> =
+            (1..threads).map(|_| scope.spawn(|| chunks.hash::<H>(None))).collect();
+        let result = chunks.hash::<H>(Some(report));
+        for handle in handles {
+            handle.join().unwrap_or_else(|e| panic::resume_unwind(e))?;
+        }
+        result
+    })?;
+    let unreported = chunks.unreported.swap(0, Ordering::Relaxed);
+    if unreported > 0 {
+        report(unreported);
+    }
+
+    let mut hasher = H::new();
+    for checksum in chunks.checksums.into_inner().expect("Chunk checksums poisoned") {
+        hasher.update(checksum.as_bytes());
+        hasher.update(b"\n");
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Chunks of the content of a file shared by the threads hashing them
+struct Chunks<'a> {
+    path: &'a OsString,
+    filter: ContentFilter,
+    /// Number of bytes of the selected content
+    len: u64,
+    chunk_size: u64,
+    /// Index of the next chunk that is not hashed yet
+    next: AtomicU64,
+    /// Number of bytes hashed but not reported yet
+    unreported: AtomicU64,
+    /// Checksums of the chunks by their index
+    checksums: Mutex<Vec<Checksum>>,
+}
+
+impl Chunks<'_> {
+    /// Hash chunks until all of them are taken
+    ///
+    /// If `report` is set, the bytes hashed by all threads are reported after each chunk.
+    fn hash<H>(&self, report: Option<&dyn Fn(u64)>) -> io::Result<()>
+    where
+        H: Digest,
+        digest::Output<H>: std::fmt::LowerHex,
+    {
+        let mut reader = content::open(self.path, self.filter)?;
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let index = self.next.fetch_add(1, Ordering::Relaxed);
+            let start = index * self.chunk_size;
+            if start >= self.len {
+                return Ok(());
+            }
+            reader.seek_content(start)?;
+
+            let mut hasher = H::new();
+            let mut left = self.chunk_size.min(self.len - start);
+            while left > 0 {
+                let max = left.min(buffer.len() as u64) as usize;
+                let count = reader.read(&mut buffer[..max])?;
+                if count == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..count]);
+                left -= count as u64;
+                self.unreported.fetch_add(count as u64, Ordering::Relaxed);
+            }
+
+            self.checksums.lock().expect("Chunk checksums poisoned")[index as usize] =
+                format!("{:x}", hasher.finalize());
+            if let Some(report) = report {
+                report(self.unreported.swap(0, Ordering::Relaxed));
+            }
+        }
+    }
+}
+
+/// Function combining checksums of the children of a directory into the digest of the directory
+pub(crate) type CombineFn = fn(&[&Checksum]) -> Checksum;
+
+/// Get function that combines checksums into the digest of a directory
+///
+/// # Arguments
+/// * `ha` - hash algorithm that is used to calculate the digest
+pub(crate) fn get_combine_fn(ha: &HashAlgorithm) -> CombineFn {
+    match ha {
+        HashAlgorithm::Blake2 => combine_checksums::<blake2::Blake2b512>,
+        HashAlgorithm::SHA3_256 => combine_checksums::<sha3::Sha3_256>,
+        HashAlgorithm::SHA3_512 => combine_checksums::<sha3::Sha3_512>,
+        HashAlgorithm::SHA2_256 => combine_checksums::<sha2::Sha256>,
+        HashAlgorithm::SHA2_512 => combine_checksums::<sha2::Sha512>,
+    }
+}
+
+/// Calculate digest of a directory from the checksums of its children
+///
+/// The checksums are sorted and deduplicated first, so the digest does not depend on the names
+/// of the children nor on the number of copies of the same content. Directories with the same
+/// digest are duplicates.
+///
+/// # Arguments
+/// * `checksums` - checksums (or digests) of the children of the directory
+/// * `H` - hasher structure that is used for digest calculation
+fn combine_checksums<H>(checksums: &[&Checksum]) -> Checksum
+where
+    H: Digest,
+    digest::Output<H>: std::fmt::LowerHex,
+{
+    let mut sorted = checksums.to_vec();
+    sorted.sort();
+    sorted.dedup();
+
+    let mut hasher = H::new();
+    for checksum in sorted {
+        hasher.update(checksum.as_bytes());
+        hasher.update(b"\n");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
 /// Get function that calculates checksum of first LEN bytes of file
 ///
 /// # Arguments
 /// * `ha` - hash algorithm that is used to calculate the checksum
-pub(crate) fn get_partial_checksum_fn<const LEN: usize>(
-    ha: &HashAlgorithm,
-) -> fn(&OsString) -> io::Result<String> {
+pub(crate) fn get_partial_checksum_fn<const LEN: usize>(ha: &HashAlgorithm) -> PartialChecksumFn {
     match *ha {
         HashAlgorithm::Blake2 => get_partial_checksum::<LEN, blake2::Blake2b512>,
         HashAlgorithm::SHA3_256 => get_partial_checksum::<LEN, sha3::Sha3_256>,
         HashAlgorithm::SHA3_512 => get_partial_checksum::<LEN, sha3::Sha3_512>,
+        HashAlgorithm::SHA2_256 => get_partial_checksum::<LEN, sha2::Sha256>,
+        HashAlgorithm::SHA2_512 => get_partial_checksum::<LEN, sha2::Sha512>,
     }
 }
 
@@ -76,10 +393,14 @@ pub(crate) fn get_partial_checksum_fn<const LEN: usize>(
 ///
 /// # Arguments
 /// * `LEN` - constant, max number of bytes of file used for checksum calculation.
-///           If file size is smaller than LEN, get_partial_checksum uses the whole file.
+///   If file size is smaller than LEN, get_partial_checksum uses the whole file.
 /// * `path` - path to file to be checksummed
+/// * `filter` - selects the content of the file that is hashed
 /// * `H` - hasher structure that is used for checksum calculation
-fn get_partial_checksum<const LEN: usize, H>(path: &OsString) -> io::Result<String>
+fn get_partial_checksum<const LEN: usize, H>(
+    path: &OsString,
+    filter: ContentFilter,
+) -> io::Result<String>
 where
     H: Digest,
     digest::Output<H>: std::fmt::LowerHex,
@@ -87,8 +408,14 @@ where
     let mut hasher = H::new();
     let mut buffer = [0u8; LEN];
 
-    let mut input = File::open(path)?;
-    let count = input.read(&mut buffer)?;
+    let mut input = content::open(path, filter)?;
+    let mut count = 0;
+    while count < LEN {
+        match input.read(&mut buffer[count..])? {
+            0 => break,
+            read => count += read,
+        }
+    }
     hasher.update(&buffer[..count]);
     let result = format!("{:x}", hasher.finalize());
     Ok(result)
@@ -97,6 +424,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs::File;
     use std::io::prelude::*;
     use tempdir::TempDir;
 
@@ -110,7 +438,10 @@ mod tests {
         drop(tmp_file);
 
         // Check get_partial_checksum
-        let checksum = get_partial_checksum::<100, blake2::Blake2b512>(&OsString::from(file_path));
+        let checksum = get_partial_checksum::<100, blake2::Blake2b512>(
+            &OsString::from(file_path),
+            ContentFilter::default(),
+        );
         let expected_result = String::from(
             "fa9ecc82691c5939c7872dc3e39d26a50831e122cbcfc1738001c980233e213dc\
             e9e16feb07bdfb93a60ea73e6fa90aca9ce6dd56e5b0626224627b6bc3ad278",
@@ -120,4 +451,61 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn checksum_report_test() -> io::Result<()> {
+        // Prepare test file
+        let tmp_dir = TempDir::new("duplicate_destroyer_test_dir")?;
+        let file_path = tmp_dir.path().join("test_file.txt");
+        let mut tmp_file = File::create(file_path.clone())?;
+        writeln!(tmp_file, "{}", "This is a test string.".repeat(100))?;
+        drop(tmp_file);
+
+        // Check that all bytes hashed are reported
+        let reported = std::cell::Cell::new(0);
+        let checksum = get_checksum::<blake2::Blake2b512>(
+            &OsString::from(&file_path),
+            ContentFilter::default(),
+            None,
+            &|bytes| reported.set(reported.get() + bytes),
+        );
+        assert!(checksum.is_ok());
+        assert_eq!(reported.get(), file_path.metadata()?.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn chunked_checksum_test() -> io::Result<()> {
+        // Prepare test file
+        let tmp_dir = TempDir::new("duplicate_destroyer_test_dir")?;
+        let file_path = tmp_dir.path().join("test_file.txt");
+        let content = "This is a test string.".repeat(100);
+        std::fs::write(&file_path, &content)?;
+
+        let mut hasher = blake2::Blake2b512::new();
+        for chunk in content.as_bytes().chunks(1000) {
+            hasher.update(format!("{:x}\n", blake2::Blake2b512::digest(chunk)));
+        }
+        let expected = format!("{:x}", hasher.finalize());
+
+        // Check that the checksum is the hash of the checksums of the chunks regardless of the
+        // number of threads (even if the open file limit allows only one) and that all bytes
+        // hashed by all threads are reported
+        for (threads, max_open_files) in [(1, None), (3, None), (3, Some(1))] {
+            let reported = std::cell::Cell::new(0);
+            let open_files = Arc::new(OpenFileLimit::new(max_open_files));
+            let _permit = open_files.acquire();
+            let checksum = get_checksum::<blake2::Blake2b512>(
+                &OsString::from(&file_path),
+                ContentFilter::default(),
+                Some(Chunking { size: 1000, threads, open_files: open_files.clone() }),
+                &|bytes| reported.set(reported.get() + bytes),
+            )?;
+            assert_eq!(reported.get(), 2200);
+            assert_eq!(checksum, expected);
+        }
+
+        Ok(())
+    }
 }
@@ -2,10 +2,15 @@
 //!
 //! This module provides the structure that contains all configuration of duplicate destroyer.
 use std::cell::RefCell;
+use std::fmt::Debug;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use crate::{
-    HashAlgorithm, NoProgressIndicator, NoProgressMultiline, ProgressIndicator, ProgressMultiline,
+    Category, DuplicateObject, HashAlgorithm, NoProgressIndicator, NoProgressMultiline, Preset,
+    ProgressIndicator, ProgressMultiline,
 };
 
 /// Stores all configuration of Duplicate Destroyer
@@ -19,9 +24,156 @@ pub struct Config {
     /// lose some small but important data.
     pub minimum_size: Option<u64>,
 
+    /// Maximum size of elements in [`DuplicateObjects`](crate::DuplicateObject) returned.
+    /// [default = None (unlimited)]
+    ///
+    /// Larger groups are not reported, but their descendants still can be if they are small
+    /// enough.
+    pub max_size: Option<u64>,
+
+    /// Minimum number of elements in [`DuplicateObjects`](crate::DuplicateObject) returned.
+    /// [default = 2]
+    ///
+    /// Groups with fewer elements are not reported, but their descendants still can be if they
+    /// are duplicated enough times.
+    pub min_copies: Option<usize>,
+
+    /// Report only groups whose members are all in the same directory [default = false]
+    ///
+    /// This finds copies like `IMG_1234.jpg` and `IMG_1234 (1).jpg` in a downloads folder. Groups
+    /// spanning several directories are not reported, but their descendants still can be if they
+    /// are copied within a directory.
+    pub same_directory_only: Option<bool>,
+
+    /// Do not report groups whose members are all in the same directory [default = false]
+    ///
+    /// The duplicates inside such groups (e.g. the files of two sibling copies of a directory) are
+    /// not reported either. Only the copies spanning several directories are left.
+    pub cross_directory_only: Option<bool>,
+
+    /// Maximum depth of the directories read below the input paths [default = 1024]
+    ///
+    /// Deeper directories (e.g. in a maliciously nested archive extracted to disk) are not read
+    /// and are reported as inaccessible, so that their parents are not reported as duplicates.
+    pub max_depth: Option<usize>,
+
+    /// Skip files and directories whose name starts with a dot [default = false]
+    ///
+    /// The directories searched for duplicates are never skipped, even if they are hidden.
+    pub skip_hidden: Option<bool>,
+
+    /// Skip files with no content [default = false]
+    ///
+    /// All empty files have the same checksum, so they would otherwise form large groups with no
+    /// space to save. With this option, directories that differ only in empty files are found as
+    /// duplicates.
+    pub skip_empty_files: Option<bool>,
+
+    /// Skip files owned by other users than the one with this user id [default = None]
+    ///
+    /// Directories of other users are still searched for the files of the user. The owner is not
+    /// known for the entries of a listing and on platforms other than Unix, so no files are skipped
+    /// there.
+    pub owner_uid: Option<u32>,
+
+    /// Calculate checksums of all files, not only of the ones with possible duplicates
+    /// [default = false]
+    ///
+    /// Files whose first bytes differ from all other files are normally not read as a whole.
+    /// With this option they are hashed as well, so that every file and directory has a
+    /// [digest](crate::TreeNode::digest) that can be compared with the digests from other scans.
+    pub hash_all_files: Option<bool>,
+
+    /// Compare MP3, FLAC and M4A files only by their audio stream [default = false]
+    ///
+    /// The tags of the files (ID3, APE, Vorbis comments and iTunes metadata including embedded
+    /// cover art) are not hashed, so copies of a recording that were tagged differently are
+    /// duplicates. The format is recognised by the extension of the file, other files and files
+    /// whose structure is not recognised are compared as a whole.
+    pub ignore_audio_tags: Option<bool>,
+
+    /// Compare videos only by their video and audio streams [default = false]
+    ///
+    /// The encoded frames of the video and audio tracks of MP4, QuickTime, Matroska and WebM files
+    /// are hashed, but not their containers, metadata or subtitles, so a video and its remux to
+    /// another container (e.g. from `mp4` to `mkv`) are duplicates. Such groups are marked as
+    /// [`different_container`](crate::DuplicateObject::different_container). The format is
+    /// recognised by the extension of the file, other files and files whose structure is not
+    /// recognised are compared as a whole.
+    pub video_streams: Option<bool>,
+
+    /// Presets of names of files and directories that are skipped [default = []]
+    ///
+    /// See [`Preset`](crate::Preset) for the names skipped by each preset. The directories
+    /// searched for duplicates are never skipped.
+    pub presets: Option<Vec<Preset>>,
+
+    /// Skip file system snapshots and directories reached through another path [default = false]
+    ///
+    /// Snapshots are the `.zfs` directories of ZFS datasets and btrfs subvolumes that are not
+    /// mount points. A directory is reached through another path if it was mounted twice (e.g. by
+    /// a bind mount). If they are not skipped, the groups with duplicates in them are marked as
+    /// [`shared_storage`](crate::DuplicateObject::shared_storage). The directories searched for
+    /// duplicates are never skipped, and nothing is detected for the entries of a listing.
+    pub skip_snapshots: Option<bool>,
+
+    /// Skip files that are open for writing by any process [default = false]
+    ///
+    /// Such files (e.g. database files, images of running virtual machines or downloads in
+    /// progress) are likely to change, so they are reported as skipped instead of being put in
+    /// duplicate groups, and their directories are not duplicates either. The files are detected
+    /// once before the scan from `/proc`, so this works only on Linux and sees only the processes
+    /// the user can inspect. Nothing is detected for the entries of a listing.
+    pub skip_open_for_writing: Option<bool>,
+
+    /// Categories of files that are searched for duplicates [default = [] (all files)]
+    ///
+    /// If any categories are set, files that don't belong to any of them (by their extension) are
+    /// skipped without being read. See [`Category`](crate::Category). Directories are searched
+    /// regardless of their name.
+    pub categories: Option<Vec<Category>>,
+
+    /// Patterns of paths of files and directories that are skipped [default = []]
+    ///
+    /// The patterns are rsync-style globs: a pattern without `/` is matched against the names of
+    /// files and directories, a pattern with `/` against the end of their paths (or the whole path
+    /// if it starts with `/`). `*` matches within one path component, `**` matches across
+    /// components and `?` matches a single character. The directories searched for duplicates are
+    /// never skipped.
+    pub exclude: Option<Vec<String>>,
+
     /// Number of threads spawned for calculating the checksums of files [default = 0]
     pub num_threads: Option<usize>,
 
+    /// Hash the files on each device in a separate queue [default = false]
+    ///
+    /// Each device gets its own `num_threads` threads, except for rotational disks (detected on
+    /// Linux), which get one thread, so that they are read sequentially instead of seeking between
+    /// files. Has no effect if `num_threads` is 0.
+    pub per_device_queues: Option<bool>,
+
+    /// Maximum number of files kept open at once by the directory walker and all of the threads
+    /// calculating checksums [default = None (unlimited)]
+    ///
+    /// The walker keeps at most one file open at a time, so without this limit the number of open
+    /// files is at most `num_threads + 1` (plus the readers of the threads hashing the chunks of
+    /// large files, see [`parallel_hash_threshold`](Config::parallel_hash_threshold)). With the
+    /// limit, the chunks are hashed by fewer threads when no more files can be opened.
+    pub max_open_files: Option<usize>,
+
+    /// Size of content above which a file is hashed in chunks in parallel
+    /// [default = None (files are hashed as a whole)]
+    ///
+    /// The content of such a file is split into chunks of this size, which are hashed in parallel,
+    /// and its checksum is the hash of the checksums of the chunks. So a single huge file (e.g. a
+    /// disk image) is not hashed by one core while the others are idle. The CPUs are shared by the
+    /// [threads](Config::num_threads) hashing files at once, and the chunks of the files on
+    /// rotational disks hashed in [per-device queues](Config::per_device_queues) are hashed one
+    /// after another.
+    /// The checksums of such files depend on this size, so they can only be compared with
+    /// checksums calculated with the same size.
+    pub parallel_hash_threshold: Option<u64>,
+
     /// Simple progress indicator.
     ///
     /// To add a progress indicator to the DuDe, set to a trait object implementing the
@@ -39,6 +191,44 @@ pub struct Config {
 
     /// Hashing algorithm used to compare the files [default = Blake3]
     pub hash_algorithm: Option<HashAlgorithm>,
+
+    /// Hashing algorithm used to compare the beginnings of the files [default = `hash_algorithm`]
+    ///
+    /// The checksums of the first bytes only sort out files that differ before they are hashed
+    /// whole with [`hash_algorithm`](Config::hash_algorithm), so a faster algorithm can be used
+    /// here without weakening the comparison of the files.
+    pub prefix_hash_algorithm: Option<HashAlgorithm>,
+
+    /// Directories whose contents should be preferably kept, most preferred first [default = []]
+    ///
+    /// Each duplicate group returned has its [`canonical`](crate::DuplicateObject::canonical)
+    /// path set to the first of its paths under the first matching preferred directory.
+    pub preferred_roots: Option<Vec<PathBuf>>,
+
+    /// Time budget for the scan [default = None]
+    ///
+    /// Once the budget (measured from the start of the scan) expires, no more files are hashed.
+    /// Files that were not hashed are not reported as duplicates, so the groups returned are only
+    /// those confirmed so far. See [`Scanner::is_truncated`](crate::Scanner::is_truncated).
+    pub deadline: Option<Duration>,
+
+    /// File the checksums calculated during the scan are recorded in [default = None]
+    ///
+    /// If the scan is interrupted (e.g. by a crash), the next scan with the same journal reuses
+    /// the checksums of the files whose size and modification time did not change. The journal is
+    /// removed once the scan finishes hashing. It has one line per file with its size,
+    /// modification time in nanoseconds since the Unix epoch, checksum and path separated by tabs.
+    ///
+    /// The journal should be in a directory only the current user can write to. On Unix, a
+    /// journal that is a symlink, is owned by another user or is writable by other users is not
+    /// used, and neither is a journal used by another scan at the same time. The scan then runs
+    /// without a journal.
+    pub journal: Option<PathBuf>,
+
+    /// Function called with each topmost duplicate group as soon as it is found [default = None]
+    ///
+    /// Set with [`on_group_found`](Config::on_group_found).
+    pub group_observer: Option<GroupObserver>,
 }
 
 impl Config {
@@ -52,6 +242,172 @@ impl Config {
         self.minimum_size.unwrap_or(100)
     }
 
+    /// Set [`max_size`](Config::max_size)
+    pub fn set_max_size(&mut self, max_size: u64) {
+        self.max_size = Some(max_size);
+    }
+
+    /// Get [`max_size`](Config::max_size)
+    pub fn get_max_size(&self) -> Option<u64> {
+        self.max_size
+    }
+
+    /// Set [`min_copies`](Config::min_copies)
+    pub fn set_min_copies(&mut self, min_copies: usize) {
+        self.min_copies = Some(min_copies);
+    }
+
+    /// Get [`min_copies`](Config::min_copies)
+    pub fn get_min_copies(&self) -> usize {
+        self.min_copies.unwrap_or(2)
+    }
+
+    /// Set [`same_directory_only`](Config::same_directory_only)
+    pub fn set_same_directory_only(&mut self, same_directory_only: bool) {
+        self.same_directory_only = Some(same_directory_only);
+    }
+
+    /// Get [`same_directory_only`](Config::same_directory_only)
+    pub fn get_same_directory_only(&self) -> bool {
+        self.same_directory_only.unwrap_or(false)
+    }
+
+    /// Set [`cross_directory_only`](Config::cross_directory_only)
+    pub fn set_cross_directory_only(&mut self, cross_directory_only: bool) {
+        self.cross_directory_only = Some(cross_directory_only);
+    }
+
+    /// Get [`cross_directory_only`](Config::cross_directory_only)
+    pub fn get_cross_directory_only(&self) -> bool {
+        self.cross_directory_only.unwrap_or(false)
+    }
+
+    /// Set [`max_depth`](Config::max_depth)
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = Some(max_depth);
+    }
+
+    /// Get [`max_depth`](Config::max_depth)
+    pub fn get_max_depth(&self) -> usize {
+        self.max_depth.unwrap_or(1024)
+    }
+
+    /// Set [`skip_hidden`](Config::skip_hidden)
+    pub fn set_skip_hidden(&mut self, skip_hidden: bool) {
+        self.skip_hidden = Some(skip_hidden);
+    }
+
+    /// Get [`skip_hidden`](Config::skip_hidden)
+    pub fn get_skip_hidden(&self) -> bool {
+        self.skip_hidden.unwrap_or(false)
+    }
+
+    /// Set [`skip_empty_files`](Config::skip_empty_files)
+    pub fn set_skip_empty_files(&mut self, skip_empty_files: bool) {
+        self.skip_empty_files = Some(skip_empty_files);
+    }
+
+    /// Get [`skip_empty_files`](Config::skip_empty_files)
+    pub fn get_skip_empty_files(&self) -> bool {
+        self.skip_empty_files.unwrap_or(false)
+    }
+
+    /// Set [`owner_uid`](Config::owner_uid)
+    pub fn set_owner_uid(&mut self, owner_uid: u32) {
+        self.owner_uid = Some(owner_uid);
+    }
+
+    /// Get [`owner_uid`](Config::owner_uid)
+    pub fn get_owner_uid(&self) -> Option<u32> {
+        self.owner_uid
+    }
+
+    /// Set [`hash_all_files`](Config::hash_all_files)
+    pub fn set_hash_all_files(&mut self, hash_all_files: bool) {
+        self.hash_all_files = Some(hash_all_files);
+    }
+
+    /// Get [`hash_all_files`](Config::hash_all_files)
+    pub fn get_hash_all_files(&self) -> bool {
+        self.hash_all_files.unwrap_or(false)
+    }
+
+    /// Set [`ignore_audio_tags`](Config::ignore_audio_tags)
+    pub fn set_ignore_audio_tags(&mut self, ignore_audio_tags: bool) {
+        self.ignore_audio_tags = Some(ignore_audio_tags);
+    }
+
+    /// Get [`ignore_audio_tags`](Config::ignore_audio_tags)
+    pub fn get_ignore_audio_tags(&self) -> bool {
+        self.ignore_audio_tags.unwrap_or(false)
+    }
+
+    /// Set [`video_streams`](Config::video_streams)
+    pub fn set_video_streams(&mut self, video_streams: bool) {
+        self.video_streams = Some(video_streams);
+    }
+
+    /// Get [`video_streams`](Config::video_streams)
+    pub fn get_video_streams(&self) -> bool {
+        self.video_streams.unwrap_or(false)
+    }
+
+    /// Add `preset` to [`presets`](Config::presets)
+    pub fn enable_preset(&mut self, preset: Preset) {
+        let presets = self.presets.get_or_insert_with(Vec::new);
+        if !presets.contains(&preset) {
+            presets.push(preset);
+        }
+    }
+
+    /// Get [`presets`](Config::presets)
+    pub fn get_presets(&self) -> Vec<Preset> {
+        self.presets.clone().unwrap_or_default()
+    }
+
+    /// Set [`skip_snapshots`](Config::skip_snapshots)
+    pub fn set_skip_snapshots(&mut self, skip_snapshots: bool) {
+        self.skip_snapshots = Some(skip_snapshots);
+    }
+
+    /// Get [`skip_snapshots`](Config::skip_snapshots)
+    pub fn get_skip_snapshots(&self) -> bool {
+        self.skip_snapshots.unwrap_or(false)
+    }
+
+    /// Set [`skip_open_for_writing`](Config::skip_open_for_writing)
+    pub fn set_skip_open_for_writing(&mut self, skip_open_for_writing: bool) {
+        self.skip_open_for_writing = Some(skip_open_for_writing);
+    }
+
+    /// Get [`skip_open_for_writing`](Config::skip_open_for_writing)
+    pub fn get_skip_open_for_writing(&self) -> bool {
+        self.skip_open_for_writing.unwrap_or(false)
+    }
+
+    /// Add `category` to [`categories`](Config::categories)
+    pub fn add_category(&mut self, category: Category) {
+        let categories = self.categories.get_or_insert_with(Vec::new);
+        if !categories.contains(&category) {
+            categories.push(category);
+        }
+    }
+
+    /// Get [`categories`](Config::categories)
+    pub fn get_categories(&self) -> Vec<Category> {
+        self.categories.clone().unwrap_or_default()
+    }
+
+    /// Add `pattern` to [`exclude`](Config::exclude)
+    pub fn add_exclude(&mut self, pattern: &str) {
+        self.exclude.get_or_insert_with(Vec::new).push(pattern.to_owned());
+    }
+
+    /// Get [`exclude`](Config::exclude)
+    pub fn get_exclude(&self) -> Vec<String> {
+        self.exclude.clone().unwrap_or_default()
+    }
+
     /// Set [`num_threads`](Config::num_threads)
     pub fn set_num_threads(&mut self, num_threads: usize) {
         self.num_threads = Some(num_threads);
@@ -62,6 +418,36 @@ impl Config {
         self.num_threads.unwrap_or(0)
     }
 
+    /// Set [`per_device_queues`](Config::per_device_queues)
+    pub fn set_per_device_queues(&mut self, per_device_queues: bool) {
+        self.per_device_queues = Some(per_device_queues);
+    }
+
+    /// Get [`per_device_queues`](Config::per_device_queues)
+    pub fn get_per_device_queues(&self) -> bool {
+        self.per_device_queues.unwrap_or(false)
+    }
+
+    /// Set [`max_open_files`](Config::max_open_files)
+    pub fn set_max_open_files(&mut self, max_open_files: usize) {
+        self.max_open_files = Some(max_open_files);
+    }
+
+    /// Get [`max_open_files`](Config::max_open_files)
+    pub fn get_max_open_files(&self) -> Option<usize> {
+        self.max_open_files
+    }
+
+    /// Set [`parallel_hash_threshold`](Config::parallel_hash_threshold)
+    pub fn set_parallel_hash_threshold(&mut self, parallel_hash_threshold: u64) {
+        self.parallel_hash_threshold = Some(parallel_hash_threshold);
+    }
+
+    /// Get [`parallel_hash_threshold`](Config::parallel_hash_threshold)
+    pub fn get_parallel_hash_threshold(&self) -> Option<u64> {
+        self.parallel_hash_threshold
+    }
+
     /// Set [`progress_indicator`](Config::progress_indicator)
     pub fn set_progress_indicator(
         &mut self,
@@ -105,4 +491,187 @@ impl Config {
     pub fn get_hash_algorithm(&self) -> HashAlgorithm {
         self.hash_algorithm.unwrap_or(HashAlgorithm::Blake2)
     }
+
+    /// Set [`prefix_hash_algorithm`](Config::prefix_hash_algorithm)
+    pub fn set_prefix_hash_algorithm(&mut self, prefix_hash_algorithm: HashAlgorithm) {
+        self.prefix_hash_algorithm = Some(prefix_hash_algorithm);
+    }
+
+    /// Get [`prefix_hash_algorithm`](Config::prefix_hash_algorithm)
+    pub fn get_prefix_hash_algorithm(&self) -> HashAlgorithm {
+        self.prefix_hash_algorithm.unwrap_or_else(|| self.get_hash_algorithm())
+    }
+
+    /// Set [`preferred_roots`](Config::preferred_roots)
+    pub fn set_preferred_roots(&mut self, preferred_roots: Vec<PathBuf>) {
+        self.preferred_roots = Some(preferred_roots);
+    }
+
+    /// Get [`preferred_roots`](Config::preferred_roots)
+    pub fn get_preferred_roots(&self) -> Vec<PathBuf> {
+        self.preferred_roots.clone().unwrap_or_default()
+    }
+
+    /// Set [`deadline`](Config::deadline)
+    pub fn set_deadline(&mut self, deadline: Duration) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Get [`deadline`](Config::deadline)
+    pub fn get_deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    /// Set [`journal`](Config::journal)
+    pub fn set_journal(&mut self, journal: PathBuf) {
+        self.journal = Some(journal);
+    }
+
+    /// Get [`journal`](Config::journal)
+    pub fn get_journal(&self) -> Option<&PathBuf> {
+        self.journal.as_ref()
+    }
+
+    /// Call `observer` with each topmost duplicate group as soon as it is found
+    ///
+    /// The groups are passed while the rest of the tree is still being searched, so they can be
+    /// acted on (or persisted) before the search finishes. A group is passed before its
+    /// [`canonical`](crate::DuplicateObject::canonical) path is set. It can be passed again if the
+    /// duplicates are queried again (e.g. by [`Scanner::topmost_duplicates`](crate::Scanner)).
+    ///
+    /// A group passed can later turn out to be contained in a larger group found in another input
+    /// directory, in which case it is not among the groups returned at the end. The groups within
+    /// one directory are not passed if only [cross-directory](Config::set_cross_directory_only)
+    /// groups are requested.
+    pub fn on_group_found<F>(&mut self, observer: F)
+    where
+        F: FnMut(&DuplicateObject) + Send + 'static,
+    {
+        self.group_observer = Some(GroupObserver(Arc::new(Mutex::new(observer))));
+    }
+
+    /// Get [`group_observer`](Config::group_observer)
+    pub fn get_group_observer(&self) -> Option<GroupObserver> {
+        self.group_observer.clone()
+    }
+}
+
+type ObserverFn = dyn FnMut(&DuplicateObject) + Send;
+
+/// Function called with duplicate groups found, see [`Config::on_group_found`]
+#[derive(Clone)]
+pub struct GroupObserver(Arc<Mutex<ObserverFn>>);
+
+impl GroupObserver {
+    /// Pass `group` to the observer
+    pub fn notify(&self, group: &DuplicateObject) {
+        let mut observer = self.0.lock().expect("Group observer lock poisoned.");
+        observer(group);
+    }
+}
+
+impl Debug for GroupObserver {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Group observer")
+    }
+}
+
+/// Part of [`Config`] that can be sent to a background thread
+#[cfg(any(feature = "async", feature = "events"))]
+pub(crate) struct Settings {
+    minimum_size: Option<u64>,
+    max_size: Option<u64>,
+    min_copies: Option<usize>,
+    same_directory_only: Option<bool>,
+    cross_directory_only: Option<bool>,
+    max_depth: Option<usize>,
+    skip_hidden: Option<bool>,
+    skip_empty_files: Option<bool>,
+    owner_uid: Option<u32>,
+    hash_all_files: Option<bool>,
+    ignore_audio_tags: Option<bool>,
+    video_streams: Option<bool>,
+    presets: Option<Vec<Preset>>,
+    skip_snapshots: Option<bool>,
+    skip_open_for_writing: Option<bool>,
+    categories: Option<Vec<Category>>,
+    exclude: Option<Vec<String>>,
+    num_threads: Option<usize>,
+    per_device_queues: Option<bool>,
+    max_open_files: Option<usize>,
+    parallel_hash_threshold: Option<u64>,
+    hash_algorithm: Option<HashAlgorithm>,
+    prefix_hash_algorithm: Option<HashAlgorithm>,
+    preferred_roots: Option<Vec<PathBuf>>,
+    deadline: Option<Duration>,
+    journal: Option<PathBuf>,
+    group_observer: Option<GroupObserver>,
+}
+
+#[cfg(any(feature = "async", feature = "events"))]
+impl Settings {
+    pub(crate) fn from_config(config: &Config) -> Self {
+        Settings {
+            minimum_size: config.minimum_size,
+            max_size: config.max_size,
+            min_copies: config.min_copies,
+            same_directory_only: config.same_directory_only,
+            cross_directory_only: config.cross_directory_only,
+            max_depth: config.max_depth,
+            skip_hidden: config.skip_hidden,
+            skip_empty_files: config.skip_empty_files,
+            owner_uid: config.owner_uid,
+            hash_all_files: config.hash_all_files,
+            ignore_audio_tags: config.ignore_audio_tags,
+            video_streams: config.video_streams,
+            presets: config.presets.clone(),
+            skip_snapshots: config.skip_snapshots,
+            skip_open_for_writing: config.skip_open_for_writing,
+            categories: config.categories.clone(),
+            exclude: config.exclude.clone(),
+            num_threads: config.num_threads,
+            per_device_queues: config.per_device_queues,
+            max_open_files: config.max_open_files,
+            parallel_hash_threshold: config.parallel_hash_threshold,
+            hash_algorithm: config.hash_algorithm,
+            prefix_hash_algorithm: config.prefix_hash_algorithm,
+            preferred_roots: config.preferred_roots.clone(),
+            deadline: config.deadline,
+            journal: config.journal.clone(),
+            group_observer: config.group_observer.clone(),
+        }
+    }
+
+    pub(crate) fn into_config(self) -> Config {
+        Config {
+            minimum_size: self.minimum_size,
+            max_size: self.max_size,
+            min_copies: self.min_copies,
+            same_directory_only: self.same_directory_only,
+            cross_directory_only: self.cross_directory_only,
+            max_depth: self.max_depth,
+            skip_hidden: self.skip_hidden,
+            skip_empty_files: self.skip_empty_files,
+            owner_uid: self.owner_uid,
+            hash_all_files: self.hash_all_files,
+            ignore_audio_tags: self.ignore_audio_tags,
+            video_streams: self.video_streams,
+            presets: self.presets,
+            skip_snapshots: self.skip_snapshots,
+            skip_open_for_writing: self.skip_open_for_writing,
+            categories: self.categories,
+            exclude: self.exclude,
+            num_threads: self.num_threads,
+            per_device_queues: self.per_device_queues,
+            max_open_files: self.max_open_files,
+            parallel_hash_threshold: self.parallel_hash_threshold,
+            hash_algorithm: self.hash_algorithm,
+            prefix_hash_algorithm: self.prefix_hash_algorithm,
+            preferred_roots: self.preferred_roots,
+            deadline: self.deadline,
+            journal: self.journal,
+            group_observer: self.group_observer,
+            ..Default::default()
+        }
+    }
 }
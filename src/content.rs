@@ -0,0 +1,139 @@
+//! Selection of the content of files that is compared
+//!
+//! The whole content of files is hashed by default. With `ignore_audio_tags` set, only the audio
+//! stream of MP3, FLAC and M4A files is hashed (see [`audio`](crate::audio)), so that copies of a
+//! recording with different tags get the same checksum. With `video_streams` set, only the frames
+//! of the video and audio tracks of MP4, QuickTime, Matroska and WebM files are hashed (see
+//! [`video`](crate::video)), so that a video and its remux to another container get the same
+//! checksum.
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::audio::{audio_ranges, is_audio};
+use crate::video::{is_video, video_ranges};
+
+/// Parts of files that are hashed
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub(crate) struct ContentFilter {
+    /// Hash only the audio stream of audio files
+    pub(crate) ignore_audio_tags: bool,
+    /// Hash only the video and audio streams of video files
+    pub(crate) video_streams: bool,
+}
+
+impl ContentFilter {
+    /// Returns true if only a part of the file at `path` may be hashed (by its extension)
+    ///
+    /// The checksums of such files don't depend on their size, so copies of different sizes can
+    /// have the same checksum.
+    pub(crate) fn may_select_part(&self, path: &Path) -> bool {
+        (self.ignore_audio_tags && is_audio(path)) || (self.video_streams && is_video(path))
+    }
+}
+
+/// Reader of the selected content of a file
+pub(crate) enum ContentReader {
+    /// The whole file is read
+    Whole(File),
+    /// Only the ranges of bytes of the file are read (in order), `next` is the index of the range
+    /// read after the `left` bytes of the current one
+    Ranges { file: File, ranges: Vec<Range<u64>>, next: usize, left: u64 },
+}
+
+/// Open the file at `path` for reading the content selected by `filter`
+pub(crate) fn open(path: &OsString, filter: ContentFilter) -> io::Result<ContentReader> {
+    let mut file = File::open(path)?;
+    if filter.ignore_audio_tags {
+        if let Some(ranges) = audio_ranges(Path::new(path), &mut file)? {
+            log::trace!("Reading audio stream of {:?}", path);
+            return Ok(ContentReader::Ranges { file, ranges, next: 0, left: 0 });
+        }
+    }
+    if filter.video_streams {
+        if let Some(ranges) = video_ranges(Path::new(path), &mut file)? {
+            log::trace!("Reading video streams of {:?}", path);
+            return Ok(ContentReader::Ranges { file, ranges, next: 0, left: 0 });
+        }
+    }
+    file.seek(SeekFrom::Start(0))?;
+    Ok(ContentReader::Whole(file))
+}
+
+impl ContentReader {
+    /// Get the number of bytes of the selected content
+    pub(crate) fn len(&self) -> io::Result<u64> {
+        match self {
+            ContentReader::Whole(file) => Ok(file.metadata()?.len()),
+            ContentReader::Ranges { ranges, .. } => {
+                Ok(ranges.iter().map(|x| x.end.saturating_sub(x.start)).sum())
+            }
+        }
+    }
+
+    /// Continue reading at `offset` bytes from the start of the selected content
+    pub(crate) fn seek_content(&mut self, offset: u64) -> io::Result<()> {
+        match self {
+            ContentReader::Whole(file) => {
+                file.seek(SeekFrom::Start(offset))?;
+            }
+            ContentReader::Ranges { file, ranges, next, left } => {
+                let mut skipped = offset;
+                *next = ranges.len();
+                *left = 0;
+                for (index, range) in ranges.iter().enumerate() {
+                    let range_len = range.end.saturating_sub(range.start);
+                    if skipped < range_len {
+                        file.seek(SeekFrom::Start(range.start + skipped))?;
+                        *next = index + 1;
+                        *left = range_len - skipped;
+                        break;
+                    }
+                    skipped -= range_len;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for ContentReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match self {
+            ContentReader::Whole(file) => file.read(buf),
+            ContentReader::Ranges { file, ranges, next, left } => {
+                while *left == 0 {
+                    match ranges.get(*next) {
+                        Some(range) => {
+                            file.seek(SeekFrom::Start(range.start))?;
+                            *left = range.end.saturating_sub(range.start);
+                            *next += 1;
+                        }
+                        None => return Ok(0),
+                    }
+                }
+                let max = (*left).min(buf.len() as u64) as usize;
+                let count = file.read(&mut buf[..max])?;
+                *left = if count == 0 { 0 } else { *left - count as u64 };
+                Ok(count)
+            }
+        }
+    }
+}
+
+/// Fill `buffer` with the bytes of `file` at `position`
+///
+/// Returns false if the file ends before the buffer is filled.
+pub(crate) fn read_at(file: &mut File, position: u64, buffer: &mut [u8]) -> io::Result<bool> {
+    file.seek(SeekFrom::Start(position))?;
+    match file.read_exact(buffer) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
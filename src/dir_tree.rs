@@ -20,22 +20,42 @@
 
 use core::fmt::Write;
 use std::cell::RefCell;
-use std::collections::HashSet;
-use std::ffi::OsString;
-use std::fs::{read_dir, DirEntry, Metadata};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fs::{read_dir, Metadata};
 use std::io;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use id_tree::{InsertBehavior::*, Node, NodeId, Tree};
+use regex::Regex;
 
 use walkdir::WalkDir;
 
-use crate::checksum::{get_partial_checksum_fn, HashAlgorithm};
-use crate::duplicate_table::DuplicateTable;
+use crate::categories::Category;
+use crate::checksum::{
+    get_combine_fn, get_partial_checksum_fn, Checksum, CombineFn, HashAlgorithm, PartialChecksumFn,
+};
+use crate::config::GroupObserver;
+use crate::content::ContentFilter;
+use crate::duplicate_table::{DuplicateTable, FileId};
+use crate::exclude::exclude_regex;
+use crate::group_expansion::{FileMatch, GroupExpansion};
+use crate::journal::Journal;
+use crate::listing::ListedEntry;
+use crate::member_metadata::{get_file_id, get_link_id, get_owner};
+use crate::open_files::OpenFileLimit;
+use crate::open_for_writing::{files_open_for_writing, OpenForWriting};
 use crate::progress_trait::*;
+use crate::scan_report::{ScanError, ScanErrorKind, ScanStats};
+use crate::snapshots::SnapshotDetector;
+use crate::tree_view::NodeKind;
+use crate::video::is_video;
 use crate::DuplicateObject;
 
-const CHCKSUM_LENGTH: usize = 1024;
+const CHCKSUM_LENGTH: usize = crate::checksum::PREFIX_LENGTH;
 // FIXME: this might differ per directory, get it dynamically
 const DIR_SIZE: u64 = 4096;
 
@@ -50,12 +70,16 @@ enum NodeType {
         path: OsString,
         size: u64,
         part_checksum: String,
+        /// Checksum of the whole file (None if it was not calculated)
+        digest: Option<Checksum>,
         duplicates: HashSet<NodeId>,
         is_contained: IsContained,
     },
     Dir {
         path: OsString,
         size: Option<u64>,
+        /// Digest combining the digests of all children (None if any of them is unknown)
+        digest: Option<Checksum>,
         duplicates: HashSet<NodeId>,
         is_contained: IsContained,
     },
@@ -101,6 +125,16 @@ impl NodeType {
         }
     }
 
+    /// Get digest of the content of node
+    fn digest(&self) -> Option<&Checksum> {
+        match self {
+            Self::File { digest, .. } => digest.as_ref(),
+            Self::Dir { digest, .. } => digest.as_ref(),
+            Self::Symlink { .. } => None,
+            Self::Inaccessible { .. } => None,
+        }
+    }
+
     fn get_size(&self) -> Option<u64> {
         match *self {
             Self::File { size, .. } => Some(size),
@@ -154,7 +188,42 @@ pub(crate) struct DirTree {
     /// Displays progress indicator for all operations when calculating duplicate dirs
     progress_indicator: Rc<RefCell<dyn ProgressIndicator>>,
     /// Calculates the keys of duplicate table
-    partial_checksum_fn: fn(&OsString) -> io::Result<String>,
+    partial_checksum_fn: PartialChecksumFn,
+    /// Selects the content of the files that is hashed
+    content_filter: ContentFilter,
+    /// Combines the digests of children into the digest of a directory
+    combine_fn: CombineFn,
+    /// Indices of input roots reported for each of the topmost nodes
+    root_indices: Vec<usize>,
+    /// Limit of open files shared with duplicate table
+    open_files: Arc<OpenFileLimit>,
+    /// Called with each group added to the topmost duplicates
+    group_observer: Option<GroupObserver>,
+    /// Files and directories that are not added to the tree
+    skip_rules: SkipRules,
+    /// Partial checksums of the files with multiple hardlinks that were already read
+    link_checksums: HashMap<FileId, String>,
+    /// Detects snapshots and directories walked through more than one path
+    snapshots: SnapshotDetector,
+    /// Directories that are snapshots or were already walked through another path
+    shared_nodes: HashSet<NodeId>,
+    /// Files left out of the duplicate groups because their checksum is shared with a file of a
+    /// different size
+    collisions: Vec<ScanError>,
+    /// Directories containing a copy of themselves, found once the topmost duplicates are first
+    /// curated (see `add_nested_duplicates`)
+    nested_copies: Option<Vec<NestedCopy>>,
+}
+
+/// Directory containing a copy of itself further down
+#[derive(Debug)]
+struct NestedCopy {
+    outer_id: NodeId,
+    copy_id: NodeId,
+    /// Children of the outer directory outside of the branch leading to the copy and the copy
+    covered: Vec<NodeId>,
+    digest: Checksum,
+    size: u64,
 }
 
 impl DirTree {
@@ -173,6 +242,7 @@ impl DirTree {
         let root_node = NodeType::Dir {
             path: "ROOT_NODE".into(),
             size: None,
+            digest: None,
             duplicates: HashSet::new(),
             is_contained: IsContained::No,
         };
@@ -187,6 +257,17 @@ impl DirTree {
             multiline_indicator,
             progress_indicator,
             partial_checksum_fn,
+            content_filter: ContentFilter::default(),
+            combine_fn: get_combine_fn(&hash_algorithm),
+            root_indices: vec![],
+            open_files: Arc::new(OpenFileLimit::new(None)),
+            group_observer: None,
+            skip_rules: SkipRules::default(),
+            link_checksums: HashMap::new(),
+            snapshots: SnapshotDetector::default(),
+            shared_nodes: HashSet::new(),
+            collisions: Vec::new(),
+            nested_copies: None,
         }
     }
 
@@ -210,12 +291,15 @@ impl DirTree {
     pub(crate) fn add_directories<T: WithMetadata>(&mut self, dirs: Vec<T>) {
         let progress_message =
             format!("Adding dirs: {:?}", dirs.iter().map(|x| x.filepath()).collect::<Vec<_>>());
+        self.multiline_indicator.borrow_mut().start_phase(Phase::Counting, dirs.len() as u64);
         let mut total_files = 0u64;
+        let mut snapshots = SnapshotDetector::default();
         for dir in &dirs {
-            total_files += DirTree::get_file_count(dir.filepath())
+            total_files += DirTree::get_file_count(dir.filepath(), &self.skip_rules, &mut snapshots)
         }
-        let pi = self.multiline_indicator.borrow_mut().create(progress_message, total_files);
-        self.duplicate_table.set_progress_indicator(pi);
+        self.multiline_indicator.borrow_mut().start_phase(Phase::Walking, total_files);
+        let progress = self.multiline_indicator.borrow_mut().create(progress_message, total_files);
+        self.duplicate_table.set_progress(progress);
 
         for dir in dirs {
             log::info!("Adding directory {:?} to DirTree.", dir.filepath());
@@ -240,42 +324,495 @@ impl DirTree {
         self.multiline_indicator.borrow().finalise();
     }
 
-    /// Get the list of topmost duplicate groups.
+    /// Add directories listed in `entries` to the DirTree without walking them
     ///
-    /// First we find duplicates for all nodes in DirTree. Then we create the list of duplicates -
-    /// we go recursively through the DirTree, whenever we find that a node has duplicates we add
-    /// the duplicate group to the list and we don't search its children.
-    pub(crate) fn get_duplicates(&mut self, min_size: u64) -> Vec<DuplicateObject> {
-        log::info!("Getting duplicates.");
+    /// Each of the `roots` has to be listed in `entries` together with all of its descendants.
+    /// The parent of each entry is taken from its path. Entries that are not under any of the
+    /// roots are ignored. Only the files are read (to calculate their checksums).
+    pub(crate) fn add_listing(&mut self, roots: Vec<OsString>, entries: Vec<ListedEntry>) {
+        let total_files =
+            entries.iter().filter(|x| x.kind == NodeKind::File).fold(0, |count, _| count + 1);
+        self.multiline_indicator.borrow_mut().start_phase(Phase::Walking, total_files);
+        let progress = self
+            .multiline_indicator
+            .borrow_mut()
+            .create(format!("Adding listed dirs: {:?}", roots), total_files);
+        self.duplicate_table.set_progress(progress);
+
+        let mut children: HashMap<OsString, Vec<ListedEntry>> = HashMap::new();
+        let mut listed_roots: HashMap<OsString, ListedEntry> = HashMap::new();
+        for entry in entries {
+            if roots.iter().any(|x| x == entry.path.as_os_str()) {
+                listed_roots.insert(entry.path.clone().into_os_string(), entry);
+            } else if let Some(parent) = entry.path.parent() {
+                children.entry(parent.as_os_str().to_owned()).or_default().push(entry);
+            }
+        }
+
+        for root in roots {
+            log::info!("Adding listed directory {:?} to DirTree.", root);
+            match listed_roots.remove(&root) {
+                Some(entry) => {
+                    self.add_listed_subtree(entry, &mut children, &self.root_id.clone(), 0)
+                }
+                None => {
+                    let e = io::Error::new(io::ErrorKind::NotFound, "Root is not in the listing.");
+                    let inac_node = NodeType::Inaccessible {
+                        path: root,
+                        err: e,
+                        is_contained: IsContained::No,
+                    };
+                    self.insert_node(inac_node, &self.root_id.clone());
+                }
+            }
+        }
+
+        self.multiline_indicator.borrow().finalise();
+    }
+
+    /// Add listed `entry` and its descendants in `children` under `parent_node`
+    ///
+    /// Directories deeper than the maximum depth (`entry` is at `depth`) are added as inaccessible
+    /// nodes without their descendants.
+    fn add_listed_subtree(
+        &mut self,
+        entry: ListedEntry,
+        children: &mut HashMap<OsString, Vec<ListedEntry>>,
+        parent_node: &NodeId,
+        depth: usize,
+    ) {
+        let mut stack = vec![(entry, parent_node.clone(), depth)];
+        while let Some((entry, parent_node, depth)) = stack.pop() {
+            let parent_node = &parent_node;
+            match entry.kind {
+                NodeKind::Dir if self.skip_rules.max_depth.map_or(false, |max| depth > max) => {
+                    let e = io::Error::new(
+                        io::ErrorKind::Other,
+                        "Directory is deeper than the maximum depth.",
+                    );
+                    let inac_node = NodeType::Inaccessible {
+                        path: entry.path.into(),
+                        err: e,
+                        is_contained: IsContained::No,
+                    };
+                    self.insert_node(inac_node, parent_node);
+                }
+                NodeKind::Dir => {
+                    let path = entry.path.into_os_string();
+                    self.multiline_indicator.borrow().update_dir(path.clone());
+                    let mut dir_children = children.remove(&path).unwrap_or_default();
+                    dir_children.retain(|x| !self.skip_rules.skips(x.path.as_os_str()));
+                    let node = NodeType::Dir {
+                        path,
+                        size: None,
+                        digest: None,
+                        duplicates: HashSet::new(),
+                        is_contained: IsContained::No,
+                    };
+                    let node_id = self.insert_node(node, parent_node);
+                    // The first child is popped first
+                    stack.extend(
+                        dir_children.into_iter().rev().map(|x| (x, node_id.clone(), depth + 1)),
+                    );
+                }
+                NodeKind::File if !self.skip_rules.keeps_listed_file(&entry.path, entry.size) => {}
+                NodeKind::File => {
+                    self.add_file(entry.path.into_os_string(), entry.size, None, parent_node)
+                }
+                NodeKind::Symlink => {
+                    let symlink_node = NodeType::Symlink {
+                        path: entry.path.into(),
+                        is_contained: IsContained::No,
+                    };
+                    self.insert_node(symlink_node, parent_node);
+                }
+                NodeKind::Inaccessible => {
+                    let e = io::Error::new(io::ErrorKind::Other, "Listed as inaccessible.");
+                    let inac_node = NodeType::Inaccessible {
+                        path: entry.path.into(),
+                        err: e,
+                        is_contained: IsContained::No,
+                    };
+                    self.insert_node(inac_node, parent_node);
+                }
+            }
+        }
+    }
+
+    /// Find duplicates for all nodes in DirTree
+    ///
+    /// Has to be called (after `finalise`) before any of the methods querying duplicates.
+    pub(crate) fn resolve_duplicates(&mut self) {
+        log::info!("Resolving duplicates.");
         let total_iterations = self.get_children_count(&self.root_id);
         // There are 2 iterations over all nodes in _find_duplicates
+        self.progress_indicator
+            .borrow_mut()
+            .start_phase(Phase::ResolvingDirs, total_iterations * 2);
         self.progress_indicator
             .borrow_mut()
             .create("Getting duplicate directories".into(), total_iterations * 2);
-        // Get duplicates for all nodes
         self.find_duplicates();
         self.progress_indicator.borrow().finalise();
+    }
+
+    /// Get the list of topmost duplicate groups.
+    ///
+    /// We go recursively through the DirTree, whenever we find that a node has duplicates we add
+    /// the duplicate group to the list and we don't search its children. The duplicates have to
+    /// be resolved by `resolve_duplicates` first.
+    pub(crate) fn get_duplicates(&mut self, filter: &GroupFilter) -> Vec<DuplicateObject> {
+        log::info!("Getting duplicates.");
+        let total_iterations = self.get_children_count(&self.root_id);
+        // Forget the results of previous calls
+        self.reset_contained();
 
         let mut duplicates: Vec<DuplicateObject> = vec![];
 
+        self.progress_indicator.borrow_mut().start_phase(Phase::Curating, total_iterations);
         self.progress_indicator
             .borrow_mut()
             .create("Curating duplicate list".into(), total_iterations);
         let mut progress_counter: u64 = 0;
         let root_ids = self.get_root_ids();
         for r_id in root_ids {
-            self.recursively_get_duplicates(
-                &r_id,
-                min_size,
-                &mut duplicates,
-                &mut progress_counter,
-            );
+            self.get_subtree_duplicates(&r_id, filter, &mut duplicates, &mut progress_counter);
+        }
+        if self.nested_copies.is_none() {
+            self.nested_copies = Some(self.find_nested_copies());
         }
+        self.add_nested_duplicates(filter, &mut duplicates);
+        // The groups within one directory are removed only now, so that their descendants are not
+        // reported instead of them
+        duplicates.retain(|group| filter.accepts_parents(group));
         self.progress_indicator.borrow().finalise();
 
         duplicates
     }
 
+    /// Get all duplicate groups, including the ones contained in other duplicate groups
+    pub(crate) fn get_all_groups(&self) -> Vec<DuplicateObject> {
+        let mut visited: HashSet<NodeId> = HashSet::new();
+        let mut groups = vec![];
+
+        for id in self.get_all_ids() {
+            if visited.contains(&id) {
+                continue;
+            }
+            let node = &*self.get_node_data(&id).borrow();
+            match node.duplicates() {
+                Some(node_duplicates) if !node_duplicates.is_empty() => {
+                    visited.extend(node_duplicates.iter().cloned());
+                    visited.insert(id.clone());
+                    groups.push(self.make_duplicate_object_from_node(&id, node));
+                }
+                _ => {}
+            }
+        }
+
+        groups
+    }
+
+    /// Get the duplicate group the node at `path` belongs to
+    ///
+    /// Returns None if there is no node with `path` in the DirTree or if it has no duplicates.
+    pub(crate) fn get_duplicates_of(&self, path: &Path) -> Option<DuplicateObject> {
+        let id = self.get_all_ids().find(|id| Path::new(&self.get_node_path(id)) == path)?;
+        let node = &*self.get_node_data(&id).borrow();
+        match node.duplicates() {
+            Some(node_duplicates) if !node_duplicates.is_empty() => {
+                Some(self.make_duplicate_object_from_node(&id, node))
+            }
+            _ => None,
+        }
+    }
+
+    /// Expand the group of nodes at `paths` to files matched by their content
+    ///
+    /// Returns None if any of the `paths` is not in the DirTree.
+    pub(crate) fn get_group_expansion<'a, I>(&self, paths: I) -> Option<GroupExpansion>
+    where
+        I: IntoIterator<Item = &'a PathBuf>,
+    {
+        // Map each file in the members to the index of its member
+        let mut member_of: HashMap<NodeId, usize> = HashMap::new();
+        let mut num_members = 0;
+        for path in paths {
+            let member_id =
+                self.get_all_ids().find(|id| Path::new(&self.get_node_path(id)) == path)?;
+            let file_ids = self
+                .dir_tree
+                .traverse_pre_order_ids(&member_id)
+                .expect("Could not traverse DirTree.")
+                .filter(|id| self.get_node_kind(id) == NodeKind::File);
+            member_of.extend(file_ids.map(|id| (id, num_members)));
+            num_members += 1;
+        }
+
+        let mut file_ids: Vec<_> = member_of.keys().cloned().collect();
+        file_ids.sort_by_key(|id| self.get_node_path(id));
+
+        let mut expansion = GroupExpansion::default();
+        let mut visited: HashSet<NodeId> = HashSet::new();
+        for id in file_ids {
+            if visited.contains(&id) {
+                continue;
+            }
+
+            // Files with the same content in the members of the group
+            let mut same_content = self.get_node_duplicates(&id);
+            same_content.retain(|x| member_of.contains_key(x));
+            same_content.push(id.clone());
+            visited.extend(same_content.iter().cloned());
+
+            let mut paths: Vec<PathBuf> =
+                same_content.iter().map(|x| self.get_node_path(x).into()).collect();
+            paths.sort_unstable();
+
+            let members: HashSet<_> = same_content.iter().map(|x| member_of[x]).collect();
+            if members.len() == num_members {
+                let size = self.get_node_size(&id).unwrap_or(0);
+                let digest = self.get_node_digest(&id);
+                expansion.matches.push(FileMatch { paths, size, digest });
+            } else {
+                expansion.extras.extend(paths);
+            }
+        }
+        expansion.extras.sort_unstable();
+
+        Some(expansion)
+    }
+
+    /// Get paths of all files that have no duplicates
+    pub(crate) fn get_unique_files(&self) -> Vec<PathBuf> {
+        self.get_all_ids()
+            .filter_map(|id| match &*self.get_node_data(&id).borrow() {
+                NodeType::File { path, duplicates, .. } if duplicates.is_empty() => {
+                    Some(path.into())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Get size of node with `node_id` (None if the size is unknown)
+    pub(crate) fn get_node_size(&self, node_id: &NodeId) -> Option<u64> {
+        self.get_node_data(node_id).borrow().get_size()
+    }
+
+    /// Get digest of the content of node with `node_id` (None if it is unknown)
+    pub(crate) fn get_node_digest(&self, node_id: &NodeId) -> Option<Checksum> {
+        self.get_node_data(node_id).borrow().digest().cloned()
+    }
+
+    /// Get kind of node with `node_id`
+    pub(crate) fn get_node_kind(&self, node_id: &NodeId) -> NodeKind {
+        match &*self.get_node_data(node_id).borrow() {
+            NodeType::File { .. } => NodeKind::File,
+            NodeType::Dir { .. } => NodeKind::Dir,
+            NodeType::Symlink { .. } => NodeKind::Symlink,
+            NodeType::Inaccessible { .. } => NodeKind::Inaccessible,
+        }
+    }
+
+    /// Get ids of children of node with `node_id`
+    pub(crate) fn get_children_ids(&self, node_id: &NodeId) -> Vec<NodeId> {
+        self.dir_tree
+            .children_ids(node_id)
+            .unwrap_or_else(|_| panic!("Could not get children of node: {node_id:?}"))
+            .map(|x| x.to_owned())
+            .collect()
+    }
+
+    /// Get ids of duplicates of node with `node_id` (the node itself is not included)
+    pub(crate) fn get_node_duplicates(&self, node_id: &NodeId) -> Vec<NodeId> {
+        match self.get_node_data(node_id).borrow().duplicates() {
+            Some(duplicates) => duplicates.iter().cloned().collect(),
+            None => vec![],
+        }
+    }
+
+    /// Get all groups of files with the same content, keyed by their checksum
+    ///
+    /// Groups with files whose checksums collided are left out.
+    pub(crate) fn get_file_clusters(&self) -> HashMap<Checksum, Vec<PathBuf>> {
+        let collided: HashSet<_> = self.collisions.iter().map(|x| &x.path).collect();
+        let mut clusters = self.duplicate_table.get_clusters();
+        clusters.retain(|_, paths| !paths.iter().any(|x| collided.contains(x)));
+        clusters
+    }
+
+    /// Keep at most `max` files open at once while scanning and hashing
+    pub(crate) fn set_max_open_files(&mut self, max: usize) {
+        self.open_files = Arc::new(OpenFileLimit::new(Some(max)));
+        self.duplicate_table.set_open_files(self.open_files.clone());
+    }
+
+    /// Skip hidden files and directories (other than the added directories themselves)
+    pub(crate) fn set_skip_hidden(&mut self, skip_hidden: bool) {
+        self.skip_rules.hidden = skip_hidden;
+    }
+
+    /// Skip files with no content
+    pub(crate) fn set_skip_empty_files(&mut self, skip_empty_files: bool) {
+        self.skip_rules.empty_files = skip_empty_files;
+    }
+
+    /// Skip files owned by other users than the one with `uid`
+    pub(crate) fn set_owner_uid(&mut self, uid: u32) {
+        self.skip_rules.owner_uid = Some(uid);
+    }
+
+    /// Skip files that belong to none of the `categories` (if there are any)
+    pub(crate) fn set_categories(&mut self, categories: Vec<Category>) {
+        self.skip_rules.categories = categories;
+    }
+
+    /// Skip files and directories matching exclude `pattern` (other than the added directories
+    /// themselves). See the exclude module for the syntax of the patterns.
+    pub(crate) fn exclude(&mut self, pattern: &str) {
+        self.skip_rules.patterns.push(exclude_regex(pattern));
+    }
+
+    /// Skip files and directories named `name` (other than the added directories themselves)
+    pub(crate) fn skip_name(&mut self, name: OsString) {
+        self.skip_rules.names.insert(name);
+    }
+
+    /// Call `observer` with each group added to the topmost duplicates
+    pub(crate) fn set_group_observer(&mut self, observer: GroupObserver) {
+        self.group_observer = Some(observer);
+    }
+
+    /// Compare the first bytes of files by their checksums calculated with `hash_algorithm`
+    pub(crate) fn set_prefix_hash_algorithm(&mut self, hash_algorithm: HashAlgorithm) {
+        self.partial_checksum_fn = get_partial_checksum_fn::<CHCKSUM_LENGTH>(&hash_algorithm);
+    }
+
+    /// Hash files whose content is larger than `threshold` in chunks in parallel
+    pub(crate) fn set_parallel_hash_threshold(&mut self, threshold: u64) {
+        self.duplicate_table.set_parallel_hash_threshold(threshold);
+    }
+
+    /// Hash the files on each device in a separate threadpool
+    pub(crate) fn set_per_device_queues(&mut self, per_device_queues: bool) {
+        self.duplicate_table.set_per_device_queues(per_device_queues);
+    }
+
+    /// Hash files with no possible duplicates as well, so that all nodes have a digest
+    pub(crate) fn set_hash_all_files(&mut self, hash_all_files: bool) {
+        self.duplicate_table.set_hash_all_files(hash_all_files);
+    }
+
+    /// Skip snapshots and directories already walked through another path (see
+    /// [`SnapshotDetector`])
+    pub(crate) fn set_skip_snapshots(&mut self, skip_snapshots: bool) {
+        self.skip_rules.snapshots = skip_snapshots;
+    }
+
+    /// Do not read directories more than `max_depth` levels below the input paths
+    ///
+    /// The directories are added as inaccessible nodes, so that their parents are not duplicates.
+    pub(crate) fn set_max_depth(&mut self, max_depth: usize) {
+        self.skip_rules.max_depth = Some(max_depth);
+    }
+
+    /// Skip files that are open for writing by any process now (see [`files_open_for_writing`])
+    ///
+    /// The skipped files are added as inaccessible nodes, so that their directories are not
+    /// duplicates.
+    pub(crate) fn skip_open_for_writing(&mut self) {
+        self.skip_rules.open_for_writing = Some(files_open_for_writing());
+    }
+
+    /// Compare audio files only by their audio stream, ignoring their tags
+    pub(crate) fn set_ignore_audio_tags(&mut self, ignore_audio_tags: bool) {
+        self.content_filter.ignore_audio_tags = ignore_audio_tags;
+        self.duplicate_table.set_ignore_audio_tags(ignore_audio_tags);
+    }
+
+    /// Compare videos only by their video and audio streams, ignoring their containers
+    pub(crate) fn set_video_streams(&mut self, video_streams: bool) {
+        self.content_filter.video_streams = video_streams;
+        self.duplicate_table.set_video_streams(video_streams);
+    }
+
+    /// Record the calculated checksums in `journal` and reuse the ones recorded before
+    pub(crate) fn set_journal(&mut self, journal: Journal) {
+        self.duplicate_table.set_journal(journal);
+    }
+
+    /// Stop hashing files at `deadline`
+    pub(crate) fn set_deadline(&mut self, deadline: Instant) {
+        self.duplicate_table.set_deadline(deadline);
+    }
+
+    /// Get time spent hashing in the main thread and the number of bytes hashed
+    pub(crate) fn get_hashing_stats(&self) -> (Duration, u64) {
+        self.duplicate_table.get_hashing_stats()
+    }
+
+    /// Returns true if some files were not hashed because of the deadline
+    pub(crate) fn is_truncated(&self) -> bool {
+        self.duplicate_table.is_truncated()
+    }
+
+    /// Get all paths that were skipped during the scan (including the files that could not be
+    /// hashed) and the files whose checksums collided
+    pub(crate) fn get_scan_errors(&self) -> Vec<ScanError> {
+        self.get_all_ids()
+            .filter_map(|id| match &*self.get_node_data(&id).borrow() {
+                NodeType::Inaccessible { path, err, .. } => Some(ScanError {
+                    path: path.into(),
+                    kind: match err.kind() {
+                        io::ErrorKind::Unsupported => ScanErrorKind::SpecialFile,
+                        _ if err.get_ref().map_or(false, |x| x.is::<OpenForWriting>()) => {
+                            ScanErrorKind::OpenForWriting
+                        }
+                        _ => ScanErrorKind::Inaccessible,
+                    },
+                    message: err.to_string(),
+                }),
+                _ => None,
+            })
+            .chain(self.duplicate_table.get_errors())
+            .chain(self.collisions.iter().cloned())
+            .collect()
+    }
+
+    /// Get the number of scanned items of each kind
+    pub(crate) fn get_scan_stats(&self) -> ScanStats {
+        let mut stats: ScanStats = Default::default();
+        for id in self.get_all_ids() {
+            match &*self.get_node_data(&id).borrow() {
+                NodeType::File { size, .. } => {
+                    stats.files += 1;
+                    stats.total_size += size;
+                }
+                NodeType::Dir { .. } => stats.dirs += 1,
+                NodeType::Symlink { .. } => stats.symlinks += 1,
+                NodeType::Inaccessible { .. } => stats.errors += 1,
+            }
+        }
+        stats.errors += self.duplicate_table.get_errors().len() as u64;
+        stats
+    }
+
+    /// Get ids of all nodes in DirTree except for the root node
+    fn get_all_ids(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.dir_tree
+            .traverse_pre_order_ids(&self.root_id)
+            .expect("Could not traverse DirTree.")
+            .filter(|id| *id != self.root_id)
+    }
+
+    /// Set IsContained status of all nodes to No
+    fn reset_contained(&mut self) {
+        for id in self.get_all_ids() {
+            self.get_node_data(&id).borrow_mut().set_contained(IsContained::No);
+        }
+    }
+
     /// Get the RefCell contained in node with `node_id`.
     fn get_node_data(&self, node_id: &NodeId) -> &RefCell<NodeType> {
         let node_data = self
@@ -287,11 +824,17 @@ impl DirTree {
     }
 
     /// Get path of node with `node_id`
-    fn get_node_path(&self, node_id: &NodeId) -> OsString {
+    pub(crate) fn get_node_path(&self, node_id: &NodeId) -> OsString {
         let node = &*self.get_node_data(node_id).borrow();
         node.path().to_owned()
     }
 
+    /// Returns true if all nodes in `duplicate_ids` are in the same directory as `path`
+    fn have_same_parent(&self, path: &OsString, duplicate_ids: &HashSet<NodeId>) -> bool {
+        let parent = Path::new(path).parent();
+        duplicate_ids.iter().all(|id| Path::new(&self.get_node_path(id)).parent() == parent)
+    }
+
     /// Returns true if node is flagged as ParentOfDuplicate or as Duplicate
     fn is_node_parent_or_duplicate(&self, node_id: &NodeId) -> bool {
         use IsContained::*;
@@ -311,89 +854,99 @@ impl DirTree {
         self.dir_tree
             .traverse_post_order_ids(node_id)
             .unwrap_or_else(|_| panic!("Could not get children of node: {node_id:?}."))
-            .count() as u64
+            .fold(0, |count: u64, _| count + 1)
             - 1
     }
 
     /// Go through DirTree and add the largest duplicate groups to duplicate list
     ///
     /// Check whether node with `node_id` contains duplicates. If so, add them to duplicate vector.
-    /// Otherwise recursively check all its children for duplicates as well.
+    /// Otherwise check all its children for duplicates as well.
     ///
-    /// Adds duplicate group to duplicate list only if each its item is larger than `min_size`.
+    /// Adds duplicate group to duplicate list only if it is accepted by `filter`. The nodes are
+    /// visited in pre-order with an explicit stack, so that deep trees can't overflow the call
+    /// stack.
     ///
     /// # Arguments
     /// * `node_id` - NodeId of the node that we want to search for duplicates
+    /// * `filter` - filter of the duplicate groups added
     /// * `duplicates` - Vector to add duplicate groups to
-    /// * `min_size` - minimum size of each element of duplicate object that
     /// * `progress_counter` - number of nodes already processed
-    fn recursively_get_duplicates(
+    fn get_subtree_duplicates(
         &mut self,
         node_id: &NodeId,
-        min_size: u64,
+        filter: &GroupFilter,
         duplicates: &mut Vec<DuplicateObject>,
         progress_counter: &mut u64,
     ) {
-        //progress counter
-        *progress_counter += 1;
-        //let node: &NodeType = &*self._get_node_data(node_id).borrow();
-        let dupl_data: Option<(OsString, u64, HashSet<NodeId>)> = match &*self
-            .get_node_data(node_id)
-            .borrow()
-        {
-            // Node has no duplicates, search children
-            NodeType::Dir { duplicates: dir_duplicates, .. } if dir_duplicates.is_empty() => None,
-            // Node has duplicates, add it to dupl. list
-            NodeType::Dir { duplicates: dir_duplicates, size, path, .. }
-                if !dir_duplicates.is_empty() =>
+        let mut stack = vec![node_id.clone()];
+        while let Some(id) = stack.pop() {
+            //progress counter
+            *progress_counter += 1;
+            //let node: &NodeType = &*self._get_node_data(&id).borrow();
+            let dupl_data: Option<(OsString, u64, HashSet<NodeId>)> = match &*self
+                .get_node_data(&id)
+                .borrow()
             {
-                // Check that dir is not already present in some duplicate group
-                if !DirTree::duplicates_contain_path(duplicates, path)
-                    && size.expect("Dir without size should not have duplicates.") > min_size
-                {
-                    let mut node_duplicates: HashSet<_> =
-                        dir_duplicates.iter().map(|x| x.to_owned()).collect();
-                    node_duplicates.insert(node_id.clone());
-                    Some((path.clone(), size.unwrap(), node_duplicates))
-                } else {
+                // Node has no duplicates, search children
+                NodeType::Dir { duplicates: dir_duplicates, .. } if dir_duplicates.is_empty() => {
                     None
                 }
-            }
+                // Node has duplicates, add it to dupl. list
+                NodeType::Dir { duplicates: dir_duplicates, size, path, .. }
+                    if !dir_duplicates.is_empty() =>
+                {
+                    // Check that dir is not already present in some duplicate group
+                    let size = size.expect("Dir without size should not have duplicates.");
+                    if !DirTree::duplicates_contain_path(duplicates, path)
+                        && filter.accepts(size, dir_duplicates.len() + 1)
+                        && filter.accepts_placement(|| self.have_same_parent(path, dir_duplicates))
+                    {
+                        let mut node_duplicates: HashSet<_> =
+                            dir_duplicates.iter().map(|x| x.to_owned()).collect();
+                        node_duplicates.insert(id.clone());
+                        Some((path.clone(), size, node_duplicates))
+                    } else {
+                        None
+                    }
+                }
 
-            // File Node has duplicates, add it to dupl. list
-            NodeType::File { duplicates: file_duplicates, size, path, .. }
-                if !file_duplicates.is_empty() =>
-            {
-                if !DirTree::duplicates_contain_path(duplicates, path) && *size > min_size {
-                    let mut node_duplicates: HashSet<_> =
-                        file_duplicates.iter().map(|x| x.to_owned()).collect();
-                    node_duplicates.insert(node_id.clone());
-                    Some((path.clone(), *size, node_duplicates))
-                } else {
-                    None
+                // File Node has duplicates, add it to dupl. list
+                NodeType::File { duplicates: file_duplicates, size, path, .. }
+                    if !file_duplicates.is_empty() =>
+                {
+                    if !DirTree::duplicates_contain_path(duplicates, path)
+                        && filter.accepts(*size, file_duplicates.len() + 1)
+                        && filter.accepts_placement(|| self.have_same_parent(path, file_duplicates))
+                    {
+                        let mut node_duplicates: HashSet<_> =
+                            file_duplicates.iter().map(|x| x.to_owned()).collect();
+                        node_duplicates.insert(id.clone());
+                        Some((path.clone(), *size, node_duplicates))
+                    } else {
+                        None
+                    }
                 }
-            }
 
-            // For other node types do nothing
-            _ => None,
-        };
+                // For other node types do nothing
+                _ => None,
+            };
 
-        if let Some((path, size, node_duplicates)) = dupl_data {
-            self.add_duplicates_to_list(path, size, node_duplicates, duplicates);
-            *progress_counter += self.get_children_count(node_id);
-        } else {
-            // If there are no duplicates, recursively search all children
-            let child_ids: Vec<_> = self
-                .dir_tree
-                .children_ids(node_id)
-                .expect("Could not get children for id {node_id}")
-                .map(|x| x.to_owned())
-                .collect();
-            for child_id in child_ids {
-                self.recursively_get_duplicates(&child_id, min_size, duplicates, progress_counter);
+            if let Some((path, size, node_duplicates)) = dupl_data {
+                self.add_duplicates_to_list(path, size, node_duplicates, filter, duplicates);
+                *progress_counter += self.get_children_count(&id);
+            } else {
+                // If there are no duplicates, search all children (the first one is popped first)
+                let child_ids: Vec<_> = self
+                    .dir_tree
+                    .children_ids(&id)
+                    .expect("Could not get children for id {node_id}")
+                    .map(|x| x.to_owned())
+                    .collect();
+                stack.extend(child_ids.into_iter().rev());
             }
+            self.progress_indicator.borrow().update(*progress_counter);
         }
-        self.progress_indicator.borrow().update(*progress_counter);
     }
 
     /// Add duplicate group to the list of duplicates
@@ -411,6 +964,7 @@ impl DirTree {
     /// * `path` - path of the first member of the duplicate group
     /// * `size` - size ofeach member of the group
     /// * `data` - set of duplicates of `path`
+    /// * `filter` - filter deciding whether the observer is notified of the new group
     /// * `duplicates` - list of duplicate groups where the new group is added
     ///
     /// # Further explanation:
@@ -435,6 +989,7 @@ impl DirTree {
         path: OsString,
         size: u64,
         data: HashSet<NodeId>,
+        filter: &GroupFilter,
         duplicates: &mut Vec<DuplicateObject>,
     ) {
         // Be careful when modifying this fction or any of its helper fctions. It's easy to make
@@ -456,9 +1011,10 @@ impl DirTree {
         }
 
         if !is_contained {
-            let paths: HashSet<_> = data.iter().map(|x| self.get_node_path(x)).collect();
-            log::trace!("Adding {:?} to duplicates", paths);
-            duplicates.push(DuplicateObject::new(size, paths));
+            let dup_obj = self.make_duplicate_object(size, &data);
+            log::trace!("Adding {:?} to duplicates", dup_obj.duplicates);
+            self.notify_group_found(filter, &dup_obj);
+            duplicates.push(dup_obj);
 
             for id in &data {
                 // Set all children as contained
@@ -469,7 +1025,7 @@ impl DirTree {
                     .map(|x| x.to_owned())
                     .collect();
                 for child in children {
-                    self.recursively_tag_as_contained(&child);
+                    self.tag_subtree_as_contained(&child);
                 }
                 // Flag parents as containing duplicate
                 self.set_parents_of_duplicate(id);
@@ -479,7 +1035,7 @@ impl DirTree {
             }
         } else {
             for id in &data {
-                self.recursively_tag_as_contained(id);
+                self.tag_subtree_as_contained(id);
             }
         }
     }
@@ -505,38 +1061,41 @@ impl DirTree {
         }
     }
 
-    /// Tag node and all of its children as contained
-    fn recursively_tag_as_contained(&mut self, node_id: &NodeId) {
-        {
-            let mut node = self.get_node_data(node_id).borrow_mut();
-            // Don't descend to children if node is already tagged as ChildOfDuplicates
-            if let IsContained::ChildOfDuplicate = node.is_contained() {
-                return;
-            } else {
-                node.set_contained(IsContained::ChildOfDuplicate);
+    /// Tag node and all of its descendants as contained
+    ///
+    /// The nodes are visited with an explicit stack, so that deep trees can't overflow the call
+    /// stack.
+    fn tag_subtree_as_contained(&mut self, node_id: &NodeId) {
+        let mut stack = vec![node_id.clone()];
+        while let Some(id) = stack.pop() {
+            {
+                let mut node = self.get_node_data(&id).borrow_mut();
+                // Don't descend to children if node is already tagged as ChildOfDuplicates
+                if let IsContained::ChildOfDuplicate = node.is_contained() {
+                    continue;
+                } else {
+                    node.set_contained(IsContained::ChildOfDuplicate);
+                }
             }
-        }
 
-        let children: Vec<_> = self
-            .dir_tree
-            .children_ids(node_id)
-            .expect("Could not get children of node: {node_id}")
-            .map(|x| x.to_owned())
-            .collect();
-        for child_id in children {
-            self.recursively_tag_as_contained(&child_id)
+            stack.extend(
+                self.dir_tree
+                    .children_ids(&id)
+                    .expect("Could not get children of node: {node_id}")
+                    .map(|x| x.to_owned()),
+            );
         }
     }
 
     /// Find children of node that are in list of duplicates and remove them
     ///
-    /// Recursively goes over all children of `node_id` that are marked as parents of duplicate. If
+    /// Goes over `node_id` and all of its descendants that are marked as parents of duplicate. If
     /// it finds any nodes marked as being in duplicate list, removes the duplicate object
     /// corresponding to the node.
     ///
     /// # Arguments
     /// * `node_id` - NodeId of the node that should contain duplicate as one (or more) of its
-    ///               descendants
+    ///   descendants
     /// * `duplicates` - vector of duplicates from which the duplicate(s) should be removed
     fn remove_duplicate_from_list(
         &mut self,
@@ -545,94 +1104,239 @@ impl DirTree {
     ) {
         use IsContained::*;
 
-        let mut dupl_nodes = HashSet::new();
-        {
-            let node = &*self.get_node_data(node_id).borrow();
-            // If node is duplicate, make a duplicate object out of it and move it from duplicates to
-            // contained.
-            if let Duplicate = node.is_contained() {
-                log::debug!("Removing duplicate: {:?}", node.path());
-                let dup_obj = self.make_duplicate_object_from_node(node);
-                // FIXME: Let this fail loudly or replace with retain method?
-                duplicates.remove(
-                    duplicates
-                        .iter()
-                        .position(|x| *x == dup_obj)
-                        .unwrap_or_else(|| panic!("Duplicate object not found {dup_obj:?}")),
-                );
-                dupl_nodes = node
-                    .duplicates()
-                    .expect("Node is marked as IsContained::Duplicate, but has no duplicates")
-                    .clone();
-                dupl_nodes.insert(node_id.clone());
+        let mut stack = vec![node_id.clone()];
+        while let Some(id) = stack.pop() {
+            let mut dupl_nodes = HashSet::new();
+            {
+                let node = &*self.get_node_data(&id).borrow();
+                // If node is duplicate, make a duplicate object out of it and move it from
+                // duplicates to contained.
+                if let Duplicate = node.is_contained() {
+                    log::debug!("Removing duplicate: {:?}", node.path());
+                    let dup_obj = self.make_duplicate_object_from_node(&id, node);
+                    // FIXME: Let this fail loudly or replace with retain method?
+                    duplicates.remove(
+                        duplicates
+                            .iter()
+                            .position(|x| *x == dup_obj)
+                            .unwrap_or_else(|| panic!("Duplicate object not found {dup_obj:?}")),
+                    );
+                    dupl_nodes = node
+                        .duplicates()
+                        .expect("Node is marked as IsContained::Duplicate, but has no duplicates")
+                        .clone();
+                    dupl_nodes.insert(id.clone());
+                }
             }
-        }
 
-        // Flag all nodes removed from duplicates as contained
-        for id in dupl_nodes {
-            let mut node = self.get_node_data(&id).borrow_mut();
-            node.set_contained(ChildOfDuplicate);
-        }
+            // Flag all nodes removed from duplicates as contained
+            for dupl_id in dupl_nodes {
+                let mut node = self.get_node_data(&dupl_id).borrow_mut();
+                node.set_contained(ChildOfDuplicate);
+            }
 
-        // FIXME: Return here if the node was Duplicate? //
+            // FIXME: Skip the children if the node was Duplicate? //
 
-        // Recursively go over all children that are parents or Duplicates
-        let children: Vec<_> = self
-            .dir_tree
-            .children_ids(node_id)
-            .expect("Could not get children of node: {node_id}")
-            .filter(|x| self.is_node_parent_or_duplicate(x))
-            .map(|x| x.to_owned())
-            .collect();
-        for child_id in children {
-            self.remove_duplicate_from_list(&child_id, duplicates);
+            // Go over all children that are parents or Duplicates (the first one is popped first)
+            let children: Vec<_> = self
+                .dir_tree
+                .children_ids(&id)
+                .expect("Could not get children of node: {node_id}")
+                .filter(|x| self.is_node_parent_or_duplicate(x))
+                .map(|x| x.to_owned())
+                .collect();
+            stack.extend(children.into_iter().rev());
         }
     }
 
     /// Makes DuplicateObject based on duplicates and size attributes of node
-    fn make_duplicate_object_from_node(&self, node: &NodeType) -> DuplicateObject {
-        let mut paths: HashSet<_> = node
+    fn make_duplicate_object_from_node(
+        &self,
+        node_id: &NodeId,
+        node: &NodeType,
+    ) -> DuplicateObject {
+        let mut ids = node
             .duplicates()
             .expect("Node is of type IsContained::Duplicate, but has no duplicates.")
-            .iter()
-            .map(|x| self.get_node_path(x))
-            .collect();
-        paths.insert(node.path().clone());
+            .clone();
+        ids.insert(node_id.clone());
         let size =
             node.get_size().expect("Node is of type IsContained::Duplicate, but has no size.");
-        DuplicateObject { duplicates: paths, size }
+        self.make_duplicate_object(size, &ids)
+    }
+
+    /// Makes DuplicateObject from nodes with `ids` annotated with the roots and the devices of the
+    /// nodes
+    fn make_duplicate_object(&self, size: u64, ids: &HashSet<NodeId>) -> DuplicateObject {
+        let mut dup_obj = DuplicateObject::new(size, ids.iter().map(|x| self.get_node_path(x)));
+        dup_obj.roots =
+            ids.iter().map(|x| (self.get_node_path(x).into(), self.get_root_index(x))).collect();
+        dup_obj.digest = ids.iter().next().and_then(|x| self.get_node_digest(x));
+        dup_obj.set_same_device();
+        if self.content_filter.video_streams {
+            dup_obj.different_container = self.is_remux(ids);
+        }
+        dup_obj.shared_storage = ids.iter().any(|x| self.is_in_shared_node(x));
+        dup_obj
+    }
+
+    /// Returns true if node with `node_id` is (or is under) a snapshot or a directory walked
+    /// through another path
+    fn is_in_shared_node(&self, node_id: &NodeId) -> bool {
+        if self.shared_nodes.is_empty() {
+            return false;
+        }
+        self.shared_nodes.contains(node_id)
+            || self
+                .dir_tree
+                .ancestor_ids(node_id)
+                .expect("Could not get ancestor ids for {node_id}")
+                .any(|x| self.shared_nodes.contains(x))
+    }
+
+    /// Returns true if the nodes with `ids` are videos whose files differ in size or extension, or
+    /// directories whose sizes differ
+    ///
+    /// Such nodes are duplicates only because the videos were compared by their streams.
+    fn is_remux(&self, ids: &HashSet<NodeId>) -> bool {
+        let sizes: HashSet<_> = ids.iter().map(|x| self.get_node_size(x)).collect();
+        let paths: Vec<_> = ids.iter().map(|x| PathBuf::from(self.get_node_path(x))).collect();
+        match ids.iter().next().map(|x| self.get_node_kind(x)) {
+            Some(NodeKind::File) if paths.iter().all(|x| is_video(x)) => {
+                let extensions: HashSet<_> =
+                    paths.iter().map(|x| x.extension().map(|x| x.to_ascii_lowercase())).collect();
+                sizes.len() > 1 || extensions.len() > 1
+            }
+            Some(NodeKind::Dir) => sizes.len() > 1,
+            _ => false,
+        }
+    }
+
+    /// Get the index of the input root that contains node with `node_id`
+    ///
+    /// The index is the position of the root in the paths passed to `add_directories`, or the
+    /// corresponding value in root indices if they were set by `set_root_indices`.
+    fn get_root_index(&self, node_id: &NodeId) -> usize {
+        let mut top_id = node_id.clone();
+        for ancestor in
+            self.dir_tree.ancestor_ids(node_id).expect("Could not get ancestor ids for {node_id}")
+        {
+            if *ancestor != self.root_id {
+                top_id = ancestor.clone();
+            }
+        }
+        let position = self
+            .get_root_ids()
+            .iter()
+            .position(|x| *x == top_id)
+            .expect("Node should be a descendant of one of the root nodes.");
+        self.root_indices.get(position).copied().unwrap_or(position)
+    }
+
+    /// Set the indices of input roots reported in duplicate objects
+    ///
+    /// The n-th index corresponds to the n-th path passed to `add_directories`.
+    pub(crate) fn set_root_indices(&mut self, root_indices: Vec<usize>) {
+        self.root_indices = root_indices;
     }
 
-    /// Recursively go through all folders/files and create nodes with metadata for each
+    /// Go through all folders/files under `item` and create nodes with metadata for each
+    ///
+    /// The directories are walked depth-first with an explicit stack, so that deep trees can't
+    /// overflow the call stack.
     ///
     /// # Arguments
     /// * `item` - a path to a file/directory to be included in the DirTree
     /// * `parent_node` - NodeId of the parent directory. Is id of root, if there is no parent dir.
     fn create_subtree<T: WithMetadata>(&mut self, item: &T, parent_node: &NodeId) {
+        // Entries still to be added with the ids of their parents and their depths, next one last
+        let mut stack: Vec<(DirChild, NodeId, usize)> = vec![];
+        let mut added = self.add_entry(item, parent_node, 0).map(|(id, entries)| (id, entries, 0));
+        loop {
+            if let Some((node_id, entries, depth)) = added {
+                stack.extend(entries.into_iter().rev().map(|x| (x, node_id.clone(), depth + 1)));
+            }
+            let (entry, parent, depth) = match stack.pop() {
+                Some(next) => next,
+                None => break,
+            };
+            added =
+                self.add_entry(&entry, &parent, depth).map(|(id, entries)| (id, entries, depth));
+        }
+    }
+
+    /// Create node with metadata for `item`
+    ///
+    /// Directories deeper than the maximum depth are not read and are added as inaccessible
+    /// nodes.
+    ///
+    /// Returns the id of the node and the entries that should be added under it if `item` is a
+    /// readable directory.
+    ///
+    /// # Arguments
+    /// * `item` - a path to a file/directory to be included in the DirTree
+    /// * `parent_node` - NodeId of the parent directory. Is id of root, if there is no parent dir.
+    /// * `depth` - depth of `item` below the input path it was found in
+    fn add_entry<T: WithMetadata>(
+        &mut self,
+        item: &T,
+        parent_node: &NodeId,
+        depth: usize,
+    ) -> Option<(NodeId, Vec<DirChild>)> {
         let name = item.filepath();
 
         match item.metadata() {
             Ok(metadata) => {
                 // item is dir
                 if metadata.is_dir() {
+                    let shared = self.snapshots.detect(Path::new(&name), &metadata);
+                    if shared && self.skip_rules.snapshots && *parent_node != self.root_id {
+                        log::info!("Skipping snapshot {:?}", name);
+                        return None;
+                    }
+                    if self.skip_rules.max_depth.map_or(false, |max| depth > max) {
+                        log::warn!("Not reading dir deeper than the maximum depth: {:?}", name);
+                        let e = io::Error::new(
+                            io::ErrorKind::Other,
+                            "Directory is deeper than the maximum depth.",
+                        );
+                        let inac_node = NodeType::Inaccessible {
+                            path: name,
+                            err: e,
+                            is_contained: IsContained::No,
+                        };
+                        self.insert_node(inac_node, parent_node);
+                        return None;
+                    }
                     self.multiline_indicator.borrow().update_dir(name.clone());
                     // first check if we have permissions to read dir
                     log::info!("Reading dir: {name:?}");
-                    match read_dir(&name) {
+                    // Read all entries first, so that the dir is closed before we descend into it
+                    let permit = self.open_files.acquire();
+                    let entries = read_dir(&name).and_then(|dir| {
+                        dir.map(|entry| entry.map(|x| DirChild(x.path().into_os_string())))
+                            .collect::<io::Result<Vec<_>>>()
+                    });
+                    drop(permit);
+                    match entries {
                         Ok(file_iter) => {
                             let node = NodeType::Dir {
                                 path: name,
                                 size: None,
+                                digest: None,
                                 duplicates: HashSet::new(),
                                 is_contained: IsContained::No,
                             };
                             let node_id = self.insert_node(node, parent_node);
-                            // FIXME: This contains 1 unnecessary allocation, maybe redo? <05-11-22> //
-                            // FIXME: This will probably crash on non-owned dirs. <05-11-22> //
-                            for file in file_iter {
-                                let file = file.expect("Could not reach a file.");
-                                self.create_subtree(&file, &node_id);
+                            if shared {
+                                self.shared_nodes.insert(node_id.clone());
                             }
+                            // FIXME: This will probably crash on non-owned dirs. <05-11-22> //
+                            let entries: Vec<_> = file_iter
+                                .into_iter()
+                                .filter(|x| !self.skip_rules.skips(&x.0))
+                                .collect();
+                            return Some((node_id, entries));
                         }
 
                         // Dir not readable
@@ -649,32 +1353,22 @@ impl DirTree {
 
                 // item is a file
                 } else if metadata.is_file() {
-                    // Symlinks get extra treatment
-                    match (self.partial_checksum_fn)(&name) {
-                        Ok(checksum) => {
-                            let node = NodeType::File {
-                                path: name,
-                                size: metadata.len(),
-                                part_checksum: checksum.clone(),
-                                duplicates: HashSet::new(),
-                                is_contained: IsContained::No,
-                            };
-                            let node_id = self.insert_node(node, parent_node);
-                            self.duplicate_table.register_item(
-                                checksum,
-                                TableData { path: item.filepath(), node_id },
-                            );
-                        }
-                        Err(e) => {
-                            log::info!("Could not access dir {:?}: {}", name, e);
-                            let inac_node = NodeType::Inaccessible {
-                                path: name,
-                                err: e,
-                                is_contained: IsContained::No,
-                            };
-                            self.insert_node(inac_node, parent_node);
-                        }
-                    };
+                    if !self.skip_rules.keeps_file(Path::new(&name), &metadata) {
+                        return None;
+                    }
+                    if self.skip_rules.is_open_for_writing(&metadata) {
+                        log::info!("Skipping file open for writing {:?}", name);
+                        let e = io::Error::new(io::ErrorKind::Other, OpenForWriting);
+                        let inac_node = NodeType::Inaccessible {
+                            path: name,
+                            err: e,
+                            is_contained: IsContained::No,
+                        };
+                        self.insert_node(inac_node, parent_node);
+                    } else {
+                        let link_id = get_link_id(&metadata);
+                        self.add_file(name, metadata.len(), link_id, parent_node);
+                    }
                 // item is not a file nor a dir.
                 } else if metadata.is_symlink() {
                     let symlink_node =
@@ -686,8 +1380,8 @@ impl DirTree {
                 } else {
                     log::warn!("File is not a dir nor file: {name:?}");
                     let e = std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        "Can not process named pipes.",
+                        std::io::ErrorKind::Unsupported,
+                        "Can not process special files (e.g. named pipes).",
                     );
                     let inac_node = NodeType::Inaccessible {
                         path: name,
@@ -706,10 +1400,64 @@ impl DirTree {
                 self.insert_node(inac_node, parent_node);
             }
         }
+        None
+    }
+
+    /// Add file node under `parent_node` and register the file in duplicate table
+    ///
+    /// If the file can't be read, an inaccessible node is added instead. Files with the same
+    /// `link_id` (hardlinks of one inode) are read only once.
+    fn add_file(
+        &mut self,
+        name: OsString,
+        size: u64,
+        link_id: Option<FileId>,
+        parent_node: &NodeId,
+    ) {
+        let known_checksum = link_id.and_then(|id| self.link_checksums.get(&id)).cloned();
+        let (part_checksum, bytes_read) = match known_checksum {
+            Some(checksum) => (Ok(checksum), 0),
+            None => {
+                let permit = self.open_files.acquire();
+                let part_checksum = (self.partial_checksum_fn)(&name, self.content_filter);
+                drop(permit);
+                (part_checksum, size.min(CHCKSUM_LENGTH as u64))
+            }
+        };
+        match part_checksum {
+            Ok(checksum) => {
+                if let Some(link_id) = link_id {
+                    self.link_checksums.insert(link_id, checksum.clone());
+                }
+                let node = NodeType::File {
+                    path: name.clone(),
+                    size,
+                    part_checksum: checksum.clone(),
+                    digest: None,
+                    duplicates: HashSet::new(),
+                    is_contained: IsContained::No,
+                };
+                let node_id = self.insert_node(node, parent_node);
+                self.duplicate_table.register_item(
+                    checksum,
+                    TableData { path: name, node_id },
+                    bytes_read,
+                    link_id,
+                );
+            }
+            Err(e) => {
+                log::info!("Could not access file {:?}: {}", name, e);
+                let inac_node =
+                    NodeType::Inaccessible { path: name, err: e, is_contained: IsContained::No };
+                self.insert_node(inac_node, parent_node);
+            }
+        };
     }
 
     /// Wrapper over tree insert method. Panics, if insertion throws error.
     ///
+    /// Inaccessible nodes are reported to the multiline indicator as skipped paths.
+    ///
     /// # Arguments
     /// * `node` - Contents of the node to be inserted
     /// * `parent_node` - NodeId of the node the `node` should be inserted under
@@ -718,6 +1466,9 @@ impl DirTree {
     /// Panics if the insertion fails. We don't remove nodes from the tree, so if that happens
     /// something is really broken.
     fn insert_node(&mut self, node: NodeType, parent_node: &NodeId) -> NodeId {
+        if let NodeType::Inaccessible { path, .. } = &node {
+            self.multiline_indicator.borrow().skip_path(path);
+        }
         self.dir_tree
             .insert(Node::new(RefCell::new(node)), UnderNode(parent_node))
             .unwrap_or_else(|_| panic!("Could not a insert node under this node: {parent_node:?}"))
@@ -726,7 +1477,7 @@ impl DirTree {
     /// Get `NodeId`s of the topmost directories in the DirTree
     ///
     /// (Returns NodeIds of nodes directly below root.)
-    fn get_root_ids(&self) -> Vec<NodeId> {
+    pub(crate) fn get_root_ids(&self) -> Vec<NodeId> {
         let root_ids: Vec<NodeId> = self
             .dir_tree
             .children_ids(&self.root_id)
@@ -736,13 +1487,36 @@ impl DirTree {
         root_ids
     }
 
-    /// Returns total number of files in `dir`
-    fn get_file_count(dir: OsString) -> u64 {
+    /// Returns total number of files in `dir` that are not skipped by `skip_rules`
+    ///
+    /// The directories walked are recorded in `snapshots` if they are skipped as well.
+    fn get_file_count(
+        dir: OsString,
+        skip_rules: &SkipRules,
+        snapshots: &mut SnapshotDetector,
+    ) -> u64 {
+        // Files are read one level below the deepest directories that are read
+        let max_depth = skip_rules.max_depth.map_or(usize::MAX, |x| x.saturating_add(1));
         WalkDir::new(dir)
+            .max_depth(max_depth)
             .into_iter()
+            .filter_entry(|x| {
+                if x.depth() > 0 && skip_rules.skips(x.path().as_os_str()) {
+                    return false;
+                }
+                let shared = skip_rules.snapshots
+                    && x.file_type().is_dir()
+                    && x.metadata().map_or(false, |m| snapshots.detect(x.path(), &m));
+                x.depth() == 0 || !shared
+            })
             .filter_map(|x| x.ok())
             .filter(|x| x.file_type().is_file())
-            .count() as u64
+            .filter(|x| {
+                x.metadata().map_or(true, |m| {
+                    skip_rules.keeps_file(x.path(), &m) && !skip_rules.is_open_for_writing(&m)
+                })
+            })
+            .fold(0, |count, _| count + 1)
     }
 
     /// Gets the duplicates for each node in DirTree.
@@ -755,6 +1529,7 @@ impl DirTree {
         let root_ids: Vec<_> = self.get_root_ids();
 
         let mut progress_counter = 0u64;
+        let mut collisions = vec![];
         // Go through all root dirs and get duplicates for each node
         for root_id in &root_ids {
             for id in self
@@ -765,22 +1540,37 @@ impl DirTree {
                 progress_counter += 1;
                 let node_data = self.get_node_data(&id);
                 match *node_data.borrow_mut() {
-                    NodeType::File { ref mut duplicates, ref part_checksum, ref path, .. } => {
+                    NodeType::File {
+                        ref mut duplicates,
+                        ref mut digest,
+                        ref part_checksum,
+                        ref path,
+                        size,
+                        ..
+                    } => {
                         self.add_duplicates_to_file_entry(
                             id,
                             duplicates,
+                            digest,
                             part_checksum,
                             path.to_owned(),
                         );
+                        if let Some(collision) =
+                            self.check_collision(duplicates, digest, path, size)
+                        {
+                            collisions.push(collision);
+                        }
                     }
-                    NodeType::Dir { ref mut duplicates, ref path, .. } => {
+                    NodeType::Dir { ref mut duplicates, ref mut digest, ref path, .. } => {
                         self.get_possible_dupl_for_dirs(&id, duplicates, path);
+                        *digest = self.get_dir_digest(&id);
                     }
                     _ => {}
                 }
                 self.progress_indicator.borrow().update(progress_counter);
             }
         }
+        self.collisions = collisions;
 
         // Go through root_dirs again filtering out false dir duplicates and setting dir size
         for root_id in root_ids {
@@ -791,10 +1581,15 @@ impl DirTree {
             {
                 progress_counter += 1;
                 let node_data = self.get_node_data(&id);
-                if let NodeType::Dir { ref mut duplicates, ref mut size, ref path, .. } =
-                    *node_data.borrow_mut()
+                if let NodeType::Dir {
+                    ref mut duplicates,
+                    ref mut size,
+                    ref digest,
+                    ref path,
+                    ..
+                } = *node_data.borrow_mut()
                 {
-                    self.filter_dir_duplicates(&id, duplicates, path);
+                    self.filter_dir_duplicates(duplicates, digest, path);
                     self.set_dir_size(&id, size, path);
                 }
                 self.progress_indicator.borrow().update(progress_counter);
@@ -808,7 +1603,9 @@ impl DirTree {
     /// # Arguments
     /// * `node_id` - node id of the file node in the DirTree
     /// * `entry` - the node data where the duplicates should be added
+    /// * `digest` - the node data where the checksum of the file should be added
     /// * `table` - duplicate table where the duplicates are searched
+    ///
     /// `entry` corresponds to the data of the node with `node_id`
     ///
     /// # Panics
@@ -817,6 +1614,7 @@ impl DirTree {
         &self,
         node_id: NodeId,
         node_duplicates: &mut HashSet<NodeId>,
+        digest: &mut Option<Checksum>,
         part_checksum: &str,
         path: OsString,
     ) {
@@ -826,12 +1624,48 @@ impl DirTree {
 
         match rec_duplicates {
             Err(e) => panic!("Getting duplicates failed: {e}"),
-            Ok(dupl) => {
+            Ok((checksum, dupl)) => {
                 *node_duplicates = dupl.into_iter().map(|table_data| table_data.node_id).collect();
+                *digest = checksum;
             }
         }
     }
 
+    /// Check that the duplicates of a file don't contradict its checksum
+    ///
+    /// Files with the same checksum must have the same size, unless only a part of them was
+    /// hashed (see [`ContentFilter::may_select_part`]). If any duplicate has a different size,
+    /// the checksum can't be trusted: all duplicates are removed, so that the file is not put in
+    /// any group (nor are the directories containing it), and the collision is returned.
+    ///
+    /// # Arguments
+    /// * `node_duplicates` - duplicates of the file found in the duplicate table
+    /// * `digest` - checksum of the file
+    /// * `path` - path to the file
+    /// * `size` - size of the file
+    fn check_collision(
+        &self,
+        node_duplicates: &mut HashSet<NodeId>,
+        digest: &Option<Checksum>,
+        path: &OsString,
+        size: u64,
+    ) -> Option<ScanError> {
+        let partial = self.content_filter.may_select_part(Path::new(path));
+        let other = node_duplicates.iter().find(|x| {
+            self.get_node_size(x) != Some(size)
+                && !(partial
+                    && self.content_filter.may_select_part(Path::new(&self.get_node_path(x))))
+        })?;
+        let message = format!(
+            "Checksum {} is shared with {:?} of a different size",
+            digest.as_deref().unwrap_or_default(),
+            self.get_node_path(other)
+        );
+        log::error!("Hash collision detected for {:?}: {}", path, message);
+        node_duplicates.clear();
+        Some(ScanError { path: path.into(), kind: ScanErrorKind::CollisionDetected, message })
+    }
+
     /// Find all potential duplicate directories for a dir node
     ///
     /// Goes through all children of a `dir_node`, finds parents of their duplicates and gets the
@@ -889,6 +1723,26 @@ impl DirTree {
         *node_duplicates = result;
     }
 
+    /// Get digest of a dir node from the digests of its children
+    ///
+    /// Returns None if the dir is empty or if a digest of any of its children is unknown (e.g. the
+    /// child is a file with no duplicates that was not hashed as a whole).
+    ///
+    /// # Arguments
+    /// * `node_id` - NodeId of the dir node whose children were already processed
+    fn get_dir_digest(&self, node_id: &NodeId) -> Option<Checksum> {
+        let children: Vec<_> = self
+            .dir_tree
+            .children(node_id)
+            .expect("Could not get dirtree children.")
+            .map(|child| child.data().borrow().digest().cloned())
+            .collect::<Option<_>>()?;
+        if children.is_empty() {
+            return None;
+        }
+        Some((self.combine_fn)(&children.iter().collect::<Vec<_>>()))
+    }
+
     /// Get TableData for a parent dir
     ///
     /// # Arguments
@@ -904,6 +1758,8 @@ impl DirTree {
     }
 
     pub(crate) fn finalise(&mut self) {
+        let pending = self.duplicate_table.get_pending_jobs();
+        self.multiline_indicator.borrow_mut().start_phase(Phase::Hashing, pending);
         self.duplicate_table.finalise();
     }
 
@@ -951,35 +1807,154 @@ impl DirTree {
     /// If a dir A contains all files in dir B, but dir B contains files not in dir A, we would
     /// get that B is contained in duplicates of A even though they are not duplicates.
     ///
-    /// This function goes through all duplicates of a node and removes the duplicates that don't
-    /// have the node in their duplicates as well.
+    /// This function goes through all duplicates of a node and removes the duplicates whose
+    /// digest differs from the digest of the node.
     ///
     /// # Arguments
-    /// * `node_id` - NodeId of the node whose duplicates should be filtered
-    /// * `node` - node whose duplicates should be filtered
-    /// `node_id` should be id of `node`.
+    /// * `node_duplicates` - duplicates of the node that should be filtered
+    /// * `node_digest` - digest of the node
     fn filter_dir_duplicates(
         &self,
-        node_id: &NodeId,
         node_duplicates: &mut HashSet<NodeId>,
+        node_digest: &Option<Checksum>,
         node_path: &OsString,
     ) {
         log::info!("Filtering duplicates for: {:?}", node_path);
-        node_duplicates.retain(|x| self.is_duplication_mutual(node_id, x));
+        match node_digest {
+            Some(digest) => {
+                node_duplicates.retain(|x| self.get_node_data(x).borrow().digest() == Some(digest))
+            }
+            None => node_duplicates.clear(),
+        }
+    }
+
+    /// Add groups of directories containing a copy of themselves to the list of duplicates
+    ///
+    /// A directory A with a copy of itself further down (e.g. `A/backup/A`) is not a duplicate of
+    /// the copy, as it also contains the branch leading to the copy. Without that branch their
+    /// content is the same, so only the groups of their children would be reported. Such A and
+    /// its copy make up a [nested](DuplicateObject::nested) group of the size of the copy.
+    ///
+    /// The nested group replaces the groups of the children, if all their members are under A
+    /// (outside of the branch) or under the copy. It is not added if A or the copy is already
+    /// contained in some duplicate group.
+    ///
+    /// The directories containing a copy of themselves have to be found by `find_nested_copies`
+    /// first.
+    fn add_nested_duplicates(&self, filter: &GroupFilter, duplicates: &mut Vec<DuplicateObject>) {
+        let nested_copies = self.nested_copies.as_deref().unwrap_or_default();
+        for nested in nested_copies {
+            if !filter.accepts(nested.size, 2) {
+                continue;
+            }
+            // The copy is inside the outer directory, so they never have the same parent
+            if !filter.accepts_placement(|| false) {
+                continue;
+            }
+
+            let outer_path = self.get_node_path(&nested.outer_id);
+            let copy_path = self.get_node_path(&nested.copy_id);
+            let covered: Vec<_> =
+                nested.covered.iter().map(|x| PathBuf::from(self.get_node_path(x))).collect();
+            let is_covered = |path: &PathBuf| covered.iter().any(|x| path.starts_with(x));
+
+            let mut replaced = vec![];
+            let mut conflict = false;
+            for (index, group) in duplicates.iter().enumerate() {
+                let contains_member = |path: &OsString| {
+                    group.duplicates.iter().any(|x| Path::new(path).starts_with(x))
+                };
+                if contains_member(&outer_path) || contains_member(&copy_path) {
+                    conflict = true;
+                } else if group.duplicates.iter().all(is_covered) {
+                    replaced.push(index);
+                } else if group.duplicates.iter().any(is_covered) {
+                    conflict = true;
+                }
+            }
+            if conflict {
+                continue;
+            }
+
+            log::debug!("Found nested copy {:?} of {:?}", copy_path, outer_path);
+            for index in replaced.into_iter().rev() {
+                duplicates.remove(index);
+            }
+            let ids = HashSet::from([nested.outer_id.clone(), nested.copy_id.clone()]);
+            let mut dup_obj = self.make_duplicate_object(nested.size, &ids);
+            dup_obj.digest = Some(nested.digest.clone());
+            dup_obj.nested = true;
+            self.notify_group_found(filter, &dup_obj);
+            duplicates.push(dup_obj);
+        }
+    }
+
+    /// Pass the group to the group observer (if set), unless `filter` removes it after the search
+    fn notify_group_found(&self, filter: &GroupFilter, group: &DuplicateObject) {
+        if let Some(observer) = &self.group_observer {
+            if filter.accepts_parents(group) {
+                observer.notify(group);
+            }
+        }
     }
 
-    /// Check whether node with `first_id` is in duplicates of node with `other_id`
+    /// Find all directories containing a copy of themselves further down
+    ///
+    /// The digests of the directories do not change once the duplicates are resolved, so the
+    /// directories are found only once for all the queries of the topmost duplicates.
+    fn find_nested_copies(&self) -> Vec<NestedCopy> {
+        let mut nested_copies = vec![];
+        for copy_id in self.get_all_ids() {
+            let (digest, size) = match &*self.get_node_data(&copy_id).borrow() {
+                NodeType::Dir { digest: Some(digest), size: Some(size), .. } => {
+                    (digest.clone(), *size)
+                }
+                _ => continue,
+            };
+            let (outer_id, mut covered) = match self.find_nested_original(&copy_id) {
+                Some(found) => found,
+                None => continue,
+            };
+            covered.push(copy_id.clone());
+            nested_copies.push(NestedCopy { outer_id, copy_id, covered, digest, size });
+        }
+        nested_copies
+    }
+
+    /// Find the closest ancestor of a dir node whose children outside of the branch leading to
+    /// the node have the same digests as the children of the node
+    ///
+    /// The digests of the children are compared including their counts, so that e.g. a directory
+    /// with two copies of another directory is not mistaken for containing a copy of itself.
+    ///
+    /// Returns the ancestor together with its children outside of the branch.
     ///
     /// # Arguments
-    /// * `first_id` - NodeId of the node that should be in `other_id`s duplicates
-    /// * `other_id` - NodeId of the node that should have `first_id` as a duplicate
-    fn is_duplication_mutual(&self, first_id: &NodeId, other_id: &NodeId) -> bool {
-        let other_node = self.get_node_data(other_id).borrow();
-        if let Some(hs) = other_node.duplicates() {
-            hs.iter().any(|x| x == first_id)
-        } else {
-            false
+    /// * `node_id` - NodeId of the possible copy
+    fn find_nested_original(&self, node_id: &NodeId) -> Option<(NodeId, Vec<NodeId>)> {
+        let get_digests = |ids: &[NodeId]| {
+            let mut digests: Vec<_> =
+                ids.iter().map(|x| self.get_node_digest(x)).collect::<Option<_>>()?;
+            digests.sort_unstable();
+            Some(digests)
+        };
+        let digests = get_digests(&self.get_children_ids(node_id))?;
+
+        let mut branch = node_id.clone();
+        for ancestor in
+            self.dir_tree.ancestor_ids(node_id).expect("Could not get ancestor ids for {node_id}")
+        {
+            if *ancestor == self.root_id {
+                return None;
+            }
+            let others: Vec<_> =
+                self.get_children_ids(ancestor).into_iter().filter(|x| *x != branch).collect();
+            if !others.is_empty() && get_digests(&others).as_ref() == Some(&digests) {
+                return Some((ancestor.clone(), others));
+            }
+            branch = ancestor.clone();
         }
+        None
     }
 
     fn duplicates_contain_path(duplicates: &[DuplicateObject], path: &OsString) -> bool {
@@ -987,6 +1962,115 @@ impl DirTree {
     }
 }
 
+/***************************/
+/*   GroupFilter Structure */
+/***************************/
+
+/// Conditions a duplicate group has to fulfill to be included in the topmost duplicates
+#[derive(Debug, Clone)]
+pub(crate) struct GroupFilter {
+    /// Each element of the group has to be larger than min_size
+    pub(crate) min_size: u64,
+    /// Each element of the group has to be at most max_size large (if set)
+    pub(crate) max_size: Option<u64>,
+    /// The group has to have at least min_copies elements
+    pub(crate) min_copies: usize,
+    /// All elements of the group have to be in the same directory
+    pub(crate) same_directory_only: bool,
+    /// The elements of the group must not all be in the same directory
+    pub(crate) cross_directory_only: bool,
+}
+
+impl GroupFilter {
+    /// Returns true if group with `copies` elements of size `size` should be included
+    fn accepts(&self, size: u64, copies: usize) -> bool {
+        size > self.min_size
+            && self.max_size.map_or(true, |max| size <= max)
+            && copies >= self.min_copies
+    }
+
+    /// Returns true if group should be included given whether its elements have the same parent
+    ///
+    /// The parents are compared (by calling `same_parent`) only if the filter depends on them.
+    fn accepts_placement(&self, same_parent: impl FnOnce() -> bool) -> bool {
+        !self.same_directory_only || same_parent()
+    }
+
+    /// Returns true if group should be included given the parents of its elements
+    ///
+    /// Unlike [`accepts_placement`](Self::accepts_placement), this is checked only on the found
+    /// groups, so that the descendants of a group within one directory are not reported instead.
+    fn accepts_parents(&self, group: &DuplicateObject) -> bool {
+        if !self.cross_directory_only {
+            return true;
+        }
+        let parents: HashSet<_> = group.duplicates.iter().map(|x| x.parent()).collect();
+        parents.len() > 1
+    }
+}
+
+/*************************/
+/*   SkipRules Structure */
+/*************************/
+
+/// Rules for files and directories that are skipped during the walk
+#[derive(Debug, Clone, Default)]
+struct SkipRules {
+    /// Skip names starting with a dot
+    hidden: bool,
+    /// Skip files with no content
+    empty_files: bool,
+    /// Skip files owned by other users
+    owner_uid: Option<u32>,
+    /// Skip files in none of these categories (no files are skipped if empty)
+    categories: Vec<Category>,
+    /// Skip snapshots and directories already walked through another path
+    snapshots: bool,
+    /// Skip these names
+    names: HashSet<OsString>,
+    /// Skip paths matching these patterns
+    patterns: Vec<Regex>,
+    /// Skip files with these device and inode numbers, open for writing when the scan started
+    open_for_writing: Option<HashSet<FileId>>,
+    /// Do not read directories deeper than this below the input paths (unlimited if None)
+    max_depth: Option<usize>,
+}
+
+impl SkipRules {
+    /// Returns true if file or directory at `path` should be skipped
+    fn skips(&self, path: &OsStr) -> bool {
+        match Path::new(path).file_name() {
+            Some(name) => {
+                (self.hidden && name.to_string_lossy().starts_with('.'))
+                    || self.names.contains(name)
+                    || self.patterns.iter().any(|x| x.is_match(&path.to_string_lossy()))
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true if file with `metadata` was open for writing and should be skipped
+    fn is_open_for_writing(&self, metadata: &Metadata) -> bool {
+        match (&self.open_for_writing, get_file_id(metadata)) {
+            (Some(files), Some(id)) => files.contains(&id),
+            _ => false,
+        }
+    }
+
+    /// Returns true if file at `path` with `metadata` should not be skipped
+    fn keeps_file(&self, path: &Path, metadata: &Metadata) -> bool {
+        self.keeps_listed_file(path, metadata.len())
+            && self.owner_uid.map_or(true, |uid| get_owner(metadata).map_or(true, |x| x == uid))
+    }
+
+    /// Returns true if file at `path` with `size` should not be skipped (without reading its
+    /// metadata)
+    fn keeps_listed_file(&self, path: &Path, size: u64) -> bool {
+        (size > 0 || !self.empty_files)
+            && (self.categories.is_empty() || self.categories.iter().any(|x| x.contains(path)))
+    }
+}
+
 /**************************/
 /*   WithMetadata Trait   */
 /**************************/
@@ -1007,13 +2091,18 @@ impl WithMetadata for OsString {
     }
 }
 
-impl WithMetadata for DirEntry {
+/// Path of an entry read from a directory
+///
+/// Unlike the paths added by the user, symlinks among the entries are not followed.
+pub(crate) struct DirChild(OsString);
+
+impl WithMetadata for DirChild {
     fn metadata(&self) -> std::io::Result<Metadata> {
-        self.metadata()
+        std::fs::symlink_metadata(&self.0)
     }
 
     fn filepath(&self) -> OsString {
-        self.path().into_os_string()
+        self.0.clone()
     }
 }
 
@@ -1056,7 +2145,7 @@ mod tests {
         let mut out = String::new();
         dt.print(&mut out);
         let expected_tree =
-            "RefCell { value: Dir { path: \"ROOT_NODE\", size: None, duplicates: {}, is_contained: No } }\n";
+            "RefCell { value: Dir { path: \"ROOT_NODE\", size: None, digest: None, duplicates: {}, is_contained: No } }\n";
         assert_eq!(expected_tree, out);
     }
 }
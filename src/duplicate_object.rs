@@ -1,38 +1,230 @@
-use serde::ser::{SerializeSeq, Serializer};
-use serde::Serialize;
-use std::collections::HashSet;
-use std::ffi::OsString;
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::checksum::Checksum;
+use crate::member_metadata::read_device;
+use crate::MemberMetadata;
 
 /// Holds data of duplicate groups that are returned by DuDe.
-#[derive(Debug, Clone, Serialize)]
+///
+/// In the serialized form, each of the duplicates is an object with its
+/// [metadata](crate::MemberMetadata), which is read during the serialization. The object can be
+/// deserialized from this form (or from the older form with bare paths), in which case
+/// [`canonical`](DuplicateObject::canonical) is None.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "StoredGroup")]
 pub struct DuplicateObject {
     /// Set of all duplicate paths in group
-    #[serde(serialize_with = "osstring_serialize")]
-    pub duplicates: HashSet<OsString>,
+    ///
+    /// The paths are iterated in sorted order, so that the order does not change between runs.
+    pub duplicates: BTreeSet<PathBuf>,
     /// Size of one element in duplicates
-    #[serde(rename = "elementSize")]
     pub size: u64,
+    /// Index of the input directory each of the duplicate paths was found in
+    ///
+    /// The index refers to the position of the directory in the input of
+    /// [`get_duplicates`](crate::get_duplicates).
+    pub roots: HashMap<PathBuf, usize>,
+    /// Path that should be preferably kept when removing the duplicates
+    ///
+    /// This is the first path (in alphabetical order) under the first of the
+    /// [`preferred_roots`](crate::Config::preferred_roots) that contains any of the duplicates.
+    /// None if no duplicate is under any of the preferred roots.
+    pub canonical: Option<PathBuf>,
+    /// True if all duplicate paths are on the same file system, so that they can be replaced by
+    /// hardlinks or reflinks to each other
+    ///
+    /// None if the file system of any of the paths could not be read (or is not available on the
+    /// platform).
+    pub same_device: Option<bool>,
+    /// Checksum of the content shared by all duplicate paths
+    ///
+    /// For files this is the checksum of the whole file, for directories a digest combining the
+    /// checksums of the files and digests of the directories they contain (see
+    /// [`TreeNode::digest`](crate::TreeNode::digest)). None if the content was not hashed.
+    pub digest: Option<Checksum>,
+    /// True if the group is made of a directory and a copy of it that the directory contains
+    ///
+    /// The copy (e.g. `A/backup/A`) has the same content as the containing directory without the
+    /// branch leading to the copy. Only the copy can be removed, removing the containing directory
+    /// would remove the copy as well.
+    pub nested: bool,
+    /// True if the group is made of videos with the same streams in files that differ (e.g. a
+    /// video and its remux to another container), or of directories containing such videos
+    ///
+    /// Set only when the videos are compared by their
+    /// [streams](crate::Config::video_streams). The members are not identical, so their metadata
+    /// (e.g. titles, chapters or subtitles) may be lost by keeping only one of them.
+    pub different_container: bool,
+    /// True if any of the duplicates is in a file system snapshot or in a directory that was
+    /// reached through another path as well (e.g. by a bind mount)
+    ///
+    /// Such duplicates may share their data on disk with the other members, or even be the same
+    /// files, so no space is counted as [saved](DuplicateObject::savings) by removing them.
+    pub shared_storage: bool,
 }
 
-fn osstring_serialize<S>(hs: &HashSet<OsString>, s: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    let mut seq = s.serialize_seq(Some(hs.len()))?;
-    for item in hs.iter() {
-        let stringy: String = item
-            .to_owned()
-            .into_string()
-            .unwrap_or_else(|osstr| format!("Error decoding this: {:?}", osstr));
-        seq.serialize_element(&stringy)?;
+impl Serialize for DuplicateObject {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = s.serialize_struct("DuplicateObject", 7)?;
+        state.serialize_field("duplicates", &self.members())?;
+        state.serialize_field("elementSize", &self.size)?;
+        state.serialize_field("sameDevice", &self.same_device)?;
+        state.serialize_field("digest", &self.digest)?;
+        state.serialize_field("nested", &self.nested)?;
+        state.serialize_field("differentContainer", &self.different_container)?;
+        state.serialize_field("sharedStorage", &self.shared_storage)?;
+        state.end()
+    }
+}
+
+/// Serialized form of DuplicateObject
+#[derive(Deserialize)]
+struct StoredGroup {
+    duplicates: Vec<StoredMember>,
+    #[serde(rename = "elementSize")]
+    size: u64,
+    #[serde(rename = "sameDevice", default)]
+    same_device: Option<bool>,
+    #[serde(default)]
+    digest: Option<Checksum>,
+    #[serde(default)]
+    nested: bool,
+    #[serde(rename = "differentContainer", default)]
+    different_container: bool,
+    #[serde(rename = "sharedStorage", default)]
+    shared_storage: bool,
+}
+
+/// Serialized duplicate path, either bare or with its metadata
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredMember {
+    Path(#[serde(deserialize_with = "crate::path_serde::deserialize")] PathBuf),
+    Metadata {
+        #[serde(deserialize_with = "crate::path_serde::deserialize")]
+        path: PathBuf,
+        root: Option<usize>,
+    },
+}
+
+impl From<StoredGroup> for DuplicateObject {
+    fn from(group: StoredGroup) -> Self {
+        let mut roots = HashMap::new();
+        let paths = group.duplicates.into_iter().map(|member| match member {
+            StoredMember::Path(path) => path,
+            StoredMember::Metadata { path, root } => {
+                if let Some(root) = root {
+                    roots.insert(path.clone(), root);
+                }
+                path
+            }
+        });
+        let mut object = DuplicateObject::new(group.size, paths.collect::<Vec<_>>());
+        object.roots = roots;
+        object.same_device = group.same_device;
+        object.digest = group.digest;
+        object.nested = group.nested;
+        object.different_container = group.different_container;
+        object.shared_storage = group.shared_storage;
+        object
     }
-    seq.end()
 }
 
 impl DuplicateObject {
     /// Get new DuplicateObject
-    pub fn new(size: u64, duplicates: HashSet<OsString>) -> Self {
-        DuplicateObject { duplicates, size }
+    pub fn new(size: u64, duplicates: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        DuplicateObject {
+            duplicates: duplicates.into_iter().map(Into::into).collect(),
+            size,
+            roots: HashMap::new(),
+            canonical: None,
+            same_device: None,
+            digest: None,
+            nested: false,
+            different_container: false,
+            shared_storage: false,
+        }
+    }
+
+    /// Get the number of duplicate paths in group
+    pub fn len(&self) -> usize {
+        self.duplicates.len()
+    }
+
+    /// Returns true if the group has no paths
+    pub fn is_empty(&self) -> bool {
+        self.duplicates.is_empty()
+    }
+
+    /// Returns true if `path` is one of the duplicates in group
+    pub fn contains<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.duplicates.contains(path.as_ref())
+    }
+
+    /// Get space freed by keeping only one of the duplicates in group
+    ///
+    /// No space is freed in groups with [`shared_storage`](DuplicateObject::shared_storage).
+    pub fn savings(&self) -> u64 {
+        if self.shared_storage {
+            return 0;
+        }
+        self.size * self.len().saturating_sub(1) as u64
+    }
+
+    /// Get space freed on disk by keeping only one of the duplicates in group
+    ///
+    /// Unlike [`savings`](DuplicateObject::savings), this is computed from the space allocated for
+    /// the duplicates, which is smaller than their size for sparse files. The duplicate taking the
+    /// most space is assumed to be kept. The size is used for duplicates whose allocated space
+    /// can't be read. No space is freed in groups with
+    /// [`shared_storage`](DuplicateObject::shared_storage).
+    pub fn allocated_savings(&self) -> u64 {
+        if self.shared_storage {
+            return 0;
+        }
+        let allocated: Vec<_> =
+            self.members().iter().map(|x| x.disk_size.unwrap_or(self.size)).collect();
+        allocated.iter().sum::<u64>() - allocated.iter().max().unwrap_or(&0)
+    }
+
+    /// Read metadata of all duplicate paths in group (in the order of the paths)
+    pub fn members(&self) -> Vec<MemberMetadata> {
+        self.duplicates
+            .iter()
+            .map(|path| MemberMetadata::read(path.to_owned(), self.roots.get(path).copied()))
+            .collect()
+    }
+
+    /// Set [`same_device`](DuplicateObject::same_device) from the devices of the duplicate paths
+    pub(crate) fn set_same_device(&mut self) {
+        let devices: Option<HashSet<u64>> = self.duplicates.iter().map(read_device).collect();
+        self.same_device = devices.map(|x| x.len() <= 1);
+    }
+
+    /// Set [`canonical`](DuplicateObject::canonical) path according to `preferred_roots`
+    ///
+    /// # Arguments
+    /// * `preferred_roots` - canonicalized paths ordered from the most preferred
+    pub(crate) fn set_canonical(&mut self, preferred_roots: &[PathBuf]) {
+        self.canonical = preferred_roots.iter().find_map(|root| {
+            self.duplicates
+                .iter()
+                .find(|path| {
+                    let canonical_path = std::fs::canonicalize(path);
+                    path.starts_with(root) || canonical_path.map_or(false, |x| x.starts_with(root))
+                })
+                .cloned()
+        });
     }
 }
 
@@ -53,9 +245,43 @@ impl PartialEq for DuplicateObject {
 
 impl Eq for DuplicateObject {}
 
+// Has to be consistent with PartialEq, so only the duplicate paths are hashed and compared.
+impl Hash for DuplicateObject {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.duplicates.hash(state);
+    }
+}
+
+impl PartialOrd for DuplicateObject {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DuplicateObject {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.duplicates.cmp(&other.duplicates)
+    }
+}
+
 /// Placeholder for Duplicate Destroyer Error.
 /// Maybe unnecessary?
 #[derive(Debug, Eq, PartialEq)]
 pub struct DuDeError {
     error: String,
 }
+
+impl fmt::Display for DuDeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for DuDeError {}
+
+/// Lets the callers returning `io::Result` propagate the error with `?`
+impl From<DuDeError> for io::Error {
+    fn from(error: DuDeError) -> Self {
+        io::Error::new(io::ErrorKind::Other, error)
+    }
+}
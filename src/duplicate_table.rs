@@ -14,38 +14,98 @@
 //!
 //! To get the duplicates of an item we check the value corresponding to the partial checksum and if there are
 //! multiple entries, we get the vector containing the specified item.
-use std::cell::RefCell;
+//!
+//! Files registered with a [`FileId`] (hardlinks to the same inode) are hashed only once, the
+//! checksum is reused for the other paths of the inode.
+//!
+//! With a [`Journal`] set, the checksums are recorded as they are calculated and the checksums
+//! recorded by an interrupted scan are reused for the files that did not change.
+//!
+//! Files that can't be hashed (e.g. they were removed or became unreadable after they were walked)
+//! are not added to the table, they are kept as scan errors and have no duplicates.
 use std::collections::{HashMap, HashSet};
 use std::ffi::OsString;
 use std::io;
-use std::rc::Rc;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Arc;
 use std::thread;
-use std::time;
+use std::time::{Duration, Instant};
 
 use threadpool::ThreadPool;
 
-use crate::checksum::get_checksum_fn;
+use crate::checksum::{get_checksum_fn, Checksum, ChecksumFn, Chunking};
+use crate::content::ContentFilter;
 use crate::dir_tree::TableData;
-use crate::{HashAlgorithm, NoProgressIndicator, ProgressIndicator};
+use crate::journal::{FileStamp, Journal};
+use crate::member_metadata::{is_rotational, read_device};
+use crate::open_files::OpenFileLimit;
+use crate::scan_report::{ScanError, ScanErrorKind};
+use crate::{HashAlgorithm, NoProgressHandle, ProgressHandle};
 
 type PartialChecksum = String;
-type Checksum = String;
 
-const HUNDRED_MILIS: time::Duration = time::Duration::from_millis(100);
+/// Device and inode number identifying a file with multiple hardlinks
+pub(crate) type FileId = (u64, u64);
+
+/// Checksum of a file (None if it was not calculated because of the deadline)
+type HashResult = io::Result<Option<Checksum>>;
+
+/// Result of a checksum job sent back by the job (Err if the job panicked) with the stamp of the
+/// file read before it was hashed
+type JobResult =
+    (PartialChecksum, Option<FileStamp>, thread::Result<HashResult>, TableData, Option<FileId>);
 
 #[derive(Debug)]
 pub(crate) struct DuplicateTable {
     table: HashMap<String, DTEntry>,
     threadpool: Option<ThreadPool>,
-    checksum_rx: Receiver<(PartialChecksum, Checksum, TableData)>,
-    checksum_tx: Sender<(PartialChecksum, Checksum, TableData)>,
-    job_counter: u32, // Counts if DT got a checksum for each job created
-    file_count: u64,
+    /// Threadpools hashing the files on each device (None if all files share `threadpool`), each
+    /// with the number of threads hashing the chunks of a large file in one of its jobs
+    device_pools: Option<HashMap<u64, (ThreadPool, usize)>>,
+    /// Number of threads of `threadpool`
+    num_threads: usize,
+    checksum_rx: Receiver<JobResult>,
+    checksum_tx: Sender<JobResult>,
+    /// Number of jobs whose result was not received yet
+    job_counter: u64,
     multithreaded: bool,
-    progress_indicator: Rc<RefCell<dyn ProgressIndicator>>,
-    checksum_fn: Arc<fn(&OsString) -> io::Result<String>>,
+    progress: Arc<dyn ProgressHandle>,
+    checksum_fn: Arc<ChecksumFn>,
+    /// Selects the content of the files that is hashed
+    content_filter: ContentFilter,
+    /// Size of content above which files are hashed in chunks in parallel
+    parallel_hash_threshold: Option<u64>,
+    /// No files are hashed after the deadline
+    deadline: Option<Instant>,
+    /// Hash files with unique partial checksums as well
+    hash_all_files: bool,
+    /// Time spent hashing in the main thread (including waiting for the jobs in `finalise`)
+    hashing_time: Duration,
+    /// Number of bytes read to calculate full checksums
+    bytes_hashed: Arc<AtomicU64>,
+    /// Some files were not hashed because of the deadline
+    truncated: bool,
+    /// Limit of open files shared with the directory walker
+    open_files: Arc<OpenFileLimit>,
+    /// Checksums of hardlinked files, so that each inode is hashed once
+    linked_checksums: HashMap<FileId, LinkedChecksum>,
+    /// Journal the calculated checksums are recorded in
+    journal: Option<Journal>,
+    /// Files that could not be hashed
+    failed: HashMap<TableData, ScanError>,
+}
+
+/// Checksum of a file with multiple hardlinks
+#[derive(Debug)]
+enum LinkedChecksum {
+    /// The checksum is known (None if it was not calculated because of the deadline)
+    Done(Option<Checksum>),
+    /// The checksum is being calculated by a job, the listed entries (with their partial checksums
+    /// and whether they advance the progress) are added once it is known
+    Pending(Vec<(PartialChecksum, TableData, bool)>),
 }
 
 impl DuplicateTable {
@@ -63,30 +123,87 @@ impl DuplicateTable {
             multithreaded = true;
         }
 
-        let (checksum_tx, checksum_rx) = channel::<(PartialChecksum, Checksum, TableData)>();
-
-        let progress_indicator = Rc::new(RefCell::new(NoProgressIndicator {}));
+        let (checksum_tx, checksum_rx) = channel::<JobResult>();
 
         let checksum_fn = get_checksum_fn(&hash_algorithm);
 
         DuplicateTable {
             table: HashMap::new(),
             threadpool,
+            device_pools: None,
+            num_threads,
             multithreaded,
             checksum_rx,
             checksum_tx,
             job_counter: 0,
-            file_count: 0,
-            progress_indicator,
+            progress: Arc::new(NoProgressHandle {}),
             checksum_fn: Arc::new(checksum_fn),
+            content_filter: ContentFilter::default(),
+            parallel_hash_threshold: None,
+            deadline: None,
+            hash_all_files: false,
+            hashing_time: Duration::ZERO,
+            bytes_hashed: Arc::new(AtomicU64::new(0)),
+            truncated: false,
+            open_files: Arc::new(OpenFileLimit::new(None)),
+            linked_checksums: HashMap::new(),
+            journal: None,
+            failed: HashMap::new(),
         }
     }
 
-    pub(crate) fn set_progress_indicator(
-        &mut self,
-        progress_indicator: Rc<RefCell<dyn ProgressIndicator>>,
-    ) {
-        self.progress_indicator = progress_indicator;
+    /// Set handle advanced by one for each registered file once it is processed
+    pub(crate) fn set_progress(&mut self, progress: Box<dyn ProgressHandle>) {
+        self.progress = Arc::from(progress);
+    }
+
+    /// Acquire a permit from `open_files` before opening each hashed file
+    pub(crate) fn set_open_files(&mut self, open_files: Arc<OpenFileLimit>) {
+        self.open_files = open_files;
+    }
+
+    /// Calculate full checksums of files with unique partial checksums as well
+    pub(crate) fn set_hash_all_files(&mut self, hash_all_files: bool) {
+        self.hash_all_files = hash_all_files;
+    }
+
+    /// Hash only the audio stream of audio files (see [`ContentFilter`])
+    pub(crate) fn set_ignore_audio_tags(&mut self, ignore_audio_tags: bool) {
+        self.content_filter.ignore_audio_tags = ignore_audio_tags;
+    }
+
+    /// Hash only the video and audio streams of video files (see [`ContentFilter`])
+    pub(crate) fn set_video_streams(&mut self, video_streams: bool) {
+        self.content_filter.video_streams = video_streams;
+    }
+
+    /// Hash files whose content is larger than `threshold` in chunks of that size in parallel
+    pub(crate) fn set_parallel_hash_threshold(&mut self, threshold: u64) {
+        self.parallel_hash_threshold = Some(threshold);
+    }
+
+    /// Hash the files on each device in a separate threadpool
+    ///
+    /// Each pool has as many threads as the shared one, except for the pools of rotational disks,
+    /// which have one thread, so that the disk is read sequentially. Has no effect if the table
+    /// is not multithreaded.
+    pub(crate) fn set_per_device_queues(&mut self, per_device_queues: bool) {
+        self.device_pools = (per_device_queues && self.multithreaded).then(HashMap::new);
+    }
+
+    /// Record the calculated checksums in `journal` and reuse the ones recorded before
+    pub(crate) fn set_journal(&mut self, journal: Journal) {
+        self.journal = Some(journal);
+    }
+
+    /// Stop hashing files at `deadline`
+    pub(crate) fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Returns true if some files were not hashed because of the deadline
+    pub(crate) fn is_truncated(&self) -> bool {
+        self.truncated
     }
 
     /// Adds a file to duplicate table.
@@ -94,76 +211,170 @@ impl DuplicateTable {
     /// # Arguments
     /// `part_checksum` - partial checksum of the file
     /// `data` - table data corresponding to the file
-    pub(crate) fn register_item(&mut self, part_checksum: String, data: TableData) {
-        // Stop early if any thread panicked
-        if self.multithreaded && self.threadpool.as_ref().unwrap().panic_count() > 0 {
-            panic!("There is at least one panicked checksum thread.");
-        }
+    /// `bytes_read` - number of bytes read to calculate the partial checksum
+    /// `file_id` - identifier of the file shared by its hardlinks (None if it has no other links)
+    pub(crate) fn register_item(
+        &mut self,
+        part_checksum: String,
+        data: TableData,
+        bytes_read: u64,
+        file_id: Option<FileId>,
+    ) {
+        self.progress.inc_bytes(bytes_read);
 
-        self.file_count += 1;
+        // Add the checksums calculated so far (and stop early if any job panicked)
+        if self.multithreaded {
+            while let Ok(result) = self.checksum_rx.try_recv() {
+                self.add_job_result(result);
+            }
+        }
 
         match self.table.get(&part_checksum) {
             // There is single entry for part_checksum key
-            Some(DTEntry::Single(_)) => {
+            Some(DTEntry::Single(..)) => {
                 // change value type to multiple entries and add both single entries
                 let single_entry =
                     self.table.insert(part_checksum.clone(), DTEntry::new_multi_entry());
-                if let Some(DTEntry::Single(se)) = single_entry {
-                    self.add_item(part_checksum.clone(), se);
+                // The single entry was already counted as processed
+                if let Some(DTEntry::Single(se, se_id)) = single_entry {
+                    self.add_item(part_checksum.clone(), se, false, se_id);
                 } else {
                     panic!("Duplicate table should contain single entry at {part_checksum}");
                 }
-                self.add_item(part_checksum, data);
+                self.add_item(part_checksum, data, true, file_id);
             }
 
             // There are multiple entries for part_checksum key
             Some(DTEntry::Multiple(_)) => {
-                self.add_item(part_checksum, data);
+                self.add_item(part_checksum, data, true, file_id);
+            }
+
+            // Table doesn't have an entry for part_checksum key yet, but all files are hashed
+            None if self.hash_all_files => {
+                self.table.insert(part_checksum.clone(), DTEntry::new_multi_entry());
+                self.add_item(part_checksum, data, true, file_id);
             }
 
             // Table doesn't have an entry for part_checksum key yet
             None => {
-                self.table.insert(part_checksum, DTEntry::Single(data));
-                self.progress_indicator.borrow().update(self.file_count - self.job_counter as u64);
+                self.table.insert(part_checksum, DTEntry::Single(data, file_id));
+                self.progress.inc(1);
             }
         }
     }
 
     /// Makes sure the table is finished if multithreading is on
+    ///
+    /// Each job sends exactly one result, so the table waits for as many results as there were
+    /// jobs created.
     pub(crate) fn finalise(&mut self) {
+        let start = Instant::now();
         if self.multithreaded {
-            log::debug!("Waiting for jobs in duplicate table.");
-            // Wait for all jobs to finish
-            let threadpool = self.threadpool.as_ref().unwrap();
-            let mut num_not_done = threadpool.active_count() + threadpool.queued_count();
-            while num_not_done > 0 {
-                num_not_done = threadpool.active_count() + threadpool.queued_count();
-                self.progress_indicator.borrow().update(self.file_count - num_not_done as u64);
-                log::info!("Tracking progress.");
-                thread::sleep(2 * HUNDRED_MILIS);
+            log::debug!("Waiting for {} jobs in duplicate table.", self.job_counter);
+            while self.job_counter > 0 {
+                // The table holds a sender itself, so the channel can't be disconnected
+                let result = self.checksum_rx.recv().expect("Checksum channel disconnected.");
+                self.add_job_result(result);
             }
+            log::debug!("All jobs in duplicate table finished");
+        }
+        self.hashing_time += start.elapsed();
 
-            log::debug!("All jobs in dupllicate table finished");
-
-            // Panic if any thread panicked
-            if self.threadpool.as_ref().unwrap().panic_count() > 0 {
-                panic!("There is at least one panicked checksum thread.");
+        if let Some(journal) = self.journal.take() {
+            if let Err(e) = journal.finish() {
+                log::warn!("Could not remove checksum journal: {}", e);
             }
+        }
+
+        self.progress.finish();
+    }
+
+    /// Get the number of checksums that are still being calculated in the threadpool
+    pub(crate) fn get_pending_jobs(&self) -> u64 {
+        self.job_counter
+    }
+
+    /// Get time spent hashing in the main thread and the number of bytes hashed
+    pub(crate) fn get_hashing_stats(&self) -> (Duration, u64) {
+        (self.hashing_time, self.bytes_hashed.load(Ordering::Relaxed))
+    }
+
+    /// Get the files that could not be hashed, sorted by their paths
+    pub(crate) fn get_errors(&self) -> Vec<ScanError> {
+        let mut errors: Vec<_> = self.failed.values().cloned().collect();
+        errors.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        errors
+    }
 
-            // Add all calculated checksums to dupl. table
-            for (part_checksum, checksum, entry) in
-                self.checksum_rx.try_iter().collect::<Vec<(PartialChecksum, Checksum, TableData)>>()
-            {
+    /// Add result received from a checksum job to the table
+    ///
+    /// # Panics
+    /// Panics if the job panicked
+    fn add_job_result(&mut self, (part_checksum, stamp, checksum, entry, file_id): JobResult) {
+        self.job_counter -= 1;
+        match checksum {
+            Ok(checksum) => self.add_hash_result(part_checksum, stamp, checksum, entry, file_id),
+            Err(_) => panic!("Checksum job for {:?} panicked.", entry.path()),
+        }
+    }
+
+    /// Add calculated checksum of the entry (and of the other links to it) to the table
+    ///
+    /// The checksum is recorded in the journal with the `stamp` read before the file was hashed. If
+    /// the file could not be hashed, the entry and the links waiting for its checksum are recorded
+    /// as scan errors instead.
+    fn add_hash_result(
+        &mut self,
+        part_checksum: PartialChecksum,
+        stamp: Option<FileStamp>,
+        checksum: HashResult,
+        entry: TableData,
+        file_id: Option<FileId>,
+    ) {
+        match checksum {
+            Ok(checksum) => {
                 log::trace!("Adding {:?} to mult entries", entry.path());
+                self.record(entry.path(), stamp, &checksum);
+                if let Some(file_id) = file_id {
+                    self.add_pending_links(file_id, &checksum);
+                }
                 self.add_to_mult_entries(part_checksum, checksum, entry);
             }
-            log::trace!("Done adding checksums to duplicate table.");
+            Err(e) => {
+                log::warn!("Could not calculate checksum of {:?}: {}", entry.path(), e);
+                let pending = file_id.and_then(|x| self.linked_checksums.remove(&x));
+                if let Some(LinkedChecksum::Pending(entries)) = pending {
+                    for (_, entry, count) in entries {
+                        self.add_failed(entry, &e);
+                        if count {
+                            self.progress.inc(1);
+                        }
+                    }
+                }
+                self.add_failed(entry, &e);
+            }
+        }
+    }
 
-            self.progress_indicator.borrow().finalise();
+    /// Record that the entry could not be hashed because of `error`
+    fn add_failed(&mut self, entry: TableData, error: &io::Error) {
+        let error = ScanError {
+            path: entry.path().into(),
+            kind: ScanErrorKind::Inaccessible,
+            message: error.to_string(),
+        };
+        self.failed.insert(entry, error);
+    }
 
-            // Panic if we are missing any checksum
-            if self.job_counter > 0 {
-                panic!("There were more jobs created ")
+    /// Add entries waiting for the checksum of hardlinked file `file_id` to the table
+    fn add_pending_links(&mut self, file_id: FileId, checksum: &Option<Checksum>) {
+        let state = self.linked_checksums.insert(file_id, LinkedChecksum::Done(checksum.clone()));
+        if let Some(LinkedChecksum::Pending(entries)) = state {
+            for (part_checksum, entry, count) in entries {
+                self.add_to_mult_entries(part_checksum, checksum.clone(), entry);
+                if count {
+                    self.progress.inc(1);
+                }
             }
         }
     }
@@ -171,17 +382,91 @@ impl DuplicateTable {
     /// Calculate full checksum and add item to multiple-item entry
     ///
     /// If the table is multithreaded creates a job to calculate the checksum, otherwise calculates
-    /// checksum and adds the entry to duplicate table.
+    /// checksum and adds the entry to duplicate table. If another link to the same file was
+    /// already hashed (or is being hashed), its checksum is used instead.
     ///
     /// # Arguments
     /// * `part_checksum` - partial checksum of the item
     /// * `entry` - entry data
-    fn add_item(&mut self, part_checksum: String, entry: TableData) {
-        if self.multithreaded {
-            self.add_job(part_checksum, entry);
+    /// * `count` - advance the progress once the checksum is calculated
+    /// * `file_id` - identifier of the file shared by its hardlinks
+    fn add_item(
+        &mut self,
+        part_checksum: String,
+        entry: TableData,
+        count: bool,
+        file_id: Option<FileId>,
+    ) {
+        if let Some(file_id) = file_id {
+            match self.linked_checksums.get_mut(&file_id) {
+                Some(LinkedChecksum::Done(checksum)) => {
+                    log::debug!("Reusing checksum of a hardlink for {:?}", entry.path());
+                    let checksum = checksum.clone();
+                    self.add_to_mult_entries(part_checksum, checksum, entry);
+                    if count {
+                        self.progress.inc(1);
+                    }
+                    return;
+                }
+                Some(LinkedChecksum::Pending(entries)) => {
+                    entries.push((part_checksum, entry, count));
+                    return;
+                }
+                None => {
+                    if self.multithreaded {
+                        self.linked_checksums.insert(file_id, LinkedChecksum::Pending(vec![]));
+                    }
+                }
+            }
+        }
+
+        if let Some(checksum) = self.journal.as_ref().and_then(|x| x.lookup(entry.path())) {
+            log::debug!("Reusing checksum from journal for {:?}", entry.path());
+            if let Some(file_id) = file_id {
+                self.add_pending_links(file_id, &Some(checksum.clone()));
+            }
+            self.add_to_mult_entries(part_checksum, Some(checksum), entry);
+            if count {
+                self.progress.inc(1);
+            }
+        } else if self.multithreaded {
+            self.add_job(part_checksum, entry, count, file_id);
         } else {
-            let checksum = (self.checksum_fn)(entry.path()).expect("Could not calculate checksum");
-            self.add_to_mult_entries(part_checksum, checksum, entry);
+            let progress = &self.progress;
+            let bytes_hashed = &self.bytes_hashed;
+            // Only one file is hashed at a time, so its chunks can be hashed on all CPUs
+            let chunking = self.get_chunking(get_cpu_count());
+            let start = Instant::now();
+            let stamp = if self.journal.is_some() { FileStamp::read(entry.path()) } else { None };
+            let checksum = if past_deadline(self.deadline) {
+                Ok(None)
+            } else {
+                let _permit = self.open_files.acquire();
+                progress.set_current_file(entry.path());
+                (self.checksum_fn)(entry.path(), self.content_filter, chunking, &|bytes| {
+                    bytes_hashed.fetch_add(bytes, Ordering::Relaxed);
+                    progress.inc_bytes(bytes)
+                })
+                .map(Some)
+            };
+            self.hashing_time += start.elapsed();
+            self.add_hash_result(part_checksum, stamp, checksum, entry, file_id);
+            if count {
+                self.progress.inc(1);
+            }
+        }
+    }
+
+    /// Record checksum of the file at `path` in the journal (if it was calculated and the `stamp`
+    /// of the file was read)
+    ///
+    /// The journal is dropped if it can't be written to, the scan continues without it.
+    fn record(&mut self, path: &OsString, stamp: Option<FileStamp>, checksum: &Option<Checksum>) {
+        if let (Some(journal), Some(stamp), Some(checksum)) = (&mut self.journal, stamp, checksum) {
+            if let Err(e) = journal.record(path, stamp, checksum) {
+                log::warn!("Could not write to checksum journal: {}", e);
+                self.journal = None;
+            }
         }
     }
 
@@ -190,14 +475,87 @@ impl DuplicateTable {
     /// # Arguments
     /// * `part_checksum` - partial checksum of the item
     /// * `entry` - entry data
-    fn add_job(&mut self, part_checksum: String, entry: TableData) {
+    /// * `count` - advance the progress once the checksum is calculated
+    /// * `file_id` - identifier of the file shared by its hardlinks
+    fn add_job(
+        &mut self,
+        part_checksum: String,
+        entry: TableData,
+        count: bool,
+        file_id: Option<FileId>,
+    ) {
         log::debug!("Adding job for {:?}", entry.path());
         self.job_counter += 1;
         let checksum_tx = self.checksum_tx.clone();
         let checksum_fn = self.checksum_fn.clone();
-        self.threadpool.as_ref().unwrap().execute(move || {
-            let checksum = checksum_fn(entry.path()).expect("Could not calculate checksum");
-            checksum_tx.send((part_checksum, checksum, entry)).expect("Could not send data.");
+        let content_filter = self.content_filter;
+        let progress = self.progress.clone();
+        let deadline = self.deadline;
+        let open_files = self.open_files.clone();
+        let bytes_hashed = self.bytes_hashed.clone();
+        let journaled = self.journal.is_some();
+        let (threadpool, chunk_threads) = self.get_threadpool(entry.path());
+        let chunking = self.get_chunking(chunk_threads);
+        threadpool.execute(move || {
+            // The stamp is read before hashing, so that a later change of the file is noticed
+            let stamp = if journaled { FileStamp::read(entry.path()) } else { None };
+            // A result is sent even if the job panics, so that the table does not wait for it
+            let checksum = panic::catch_unwind(AssertUnwindSafe(|| {
+                // Jobs queued before the deadline are dropped as well
+                if past_deadline(deadline) {
+                    Ok(None)
+                } else {
+                    let _permit = open_files.acquire();
+                    progress.set_current_file(entry.path());
+                    checksum_fn(entry.path(), content_filter, chunking, &|bytes| {
+                        bytes_hashed.fetch_add(bytes, Ordering::Relaxed);
+                        progress.inc_bytes(bytes)
+                    })
+                    .map(Some)
+                }
+            }));
+            if count {
+                progress.inc(1);
+            }
+            checksum_tx
+                .send((part_checksum, stamp, checksum, entry, file_id))
+                .expect("Could not send data.");
+        })
+    }
+
+    /// Get threadpool that hashes the file at `path` and the number of threads hashing the chunks
+    /// of a large file in one of its jobs
+    ///
+    /// The pool of the device of the file is created when it is first needed. Files whose device
+    /// can't be read are hashed by the shared pool. The CPUs are shared by the jobs running at once
+    /// in a pool, so that the pool does not run more threads than there are CPUs. The chunks of
+    /// the files on rotational disks are hashed one after another, so that the disk is still read
+    /// sequentially.
+    fn get_threadpool(&mut self, path: &OsString) -> (ThreadPool, usize) {
+        let num_threads = self.num_threads;
+        let share = |threads: usize| (get_cpu_count() / threads).max(1);
+        match (&mut self.device_pools, read_device(path)) {
+            (Some(pools), Some(device)) => {
+                let (pool, chunk_threads) = pools.entry(device).or_insert_with(|| {
+                    let rotational = is_rotational(device) == Some(true);
+                    let threads = if rotational { 1 } else { num_threads };
+                    log::debug!("Creating queue with {} threads for device {}", threads, device);
+                    (ThreadPool::new(threads), if rotational { 1 } else { share(threads) })
+                });
+                (pool.clone(), *chunk_threads)
+            }
+            _ => (self.threadpool.clone().unwrap(), share(num_threads)),
+        }
+    }
+
+    /// Get hashing of large files in chunks by at most `threads` threads (None if files are hashed
+    /// as a whole)
+    fn get_chunking(&self, threads: usize) -> Option<Chunking> {
+        let open_files = &self.open_files;
+        self.parallel_hash_threshold.map(|size| Chunking {
+            size,
+            threads,
+            open_files: open_files.clone(),
         })
     }
 
@@ -205,33 +563,42 @@ impl DuplicateTable {
     ///
     /// # Arguments
     /// * `part_checksum` - partial checksum of the item
-    /// * `checksum` - checksum of the whole file in entry
+    /// * `checksum` - checksum of the whole file in entry (None if it was not calculated because
+    ///   of the deadline)
     /// * `entry` - entry data
     ///
     /// # Panics
     /// Panics if the value at `partial_checksum` is not of type MultipleEntries
-    fn add_to_mult_entries(&mut self, part_checksum: String, checksum: String, entry: TableData) {
-        if self.multithreaded {
-            self.job_counter -= 1;
-        }
+    fn add_to_mult_entries(
+        &mut self,
+        part_checksum: String,
+        checksum: Option<Checksum>,
+        entry: TableData,
+    ) {
         if let Some(DTEntry::Multiple(me)) = self.table.get_mut(&part_checksum) {
-            match me.hashes.get_mut(&checksum) {
-                Some(v) => {
-                    v.push(entry);
-                }
+            match checksum {
                 None => {
-                    me.hashes.insert(checksum, vec![entry]);
+                    self.truncated = true;
+                    me.unhashed.push(entry);
                 }
+                Some(checksum) => match me.hashes.get_mut(&checksum) {
+                    Some(v) => {
+                        v.push(entry);
+                    }
+                    None => {
+                        me.hashes.insert(checksum, vec![entry]);
+                    }
+                },
             }
         } else {
             panic!("Duplicate Table should contain Multiple entries with key:\n{part_checksum}")
         }
-        self.progress_indicator.borrow().update(self.file_count - self.job_counter as u64);
     }
 
     /// Get duplicates of entry
     ///
-    /// Given partial checksum and entry data, get data of all duplicates of the entry.
+    /// Given partial checksum and entry data, get full checksum of the entry (None if it was not
+    /// calculated) and data of all duplicates of the entry.
     ///
     /// # Arguments
     /// `part_checksum` - Partial checksum of the file
@@ -241,26 +608,35 @@ impl DuplicateTable {
         &self,
         part_checksum: &str,
         entry: &TableData,
-    ) -> Result<HashSet<TableData>, &str> {
+    ) -> Result<(Option<Checksum>, HashSet<TableData>), &str> {
+        // Files that could not be hashed have no duplicates
+        if self.failed.contains_key(entry) {
+            return Ok((None, HashSet::new()));
+        }
         if let Some(val) = self.table.get(part_checksum) {
             match val {
-                DTEntry::Single(data) => {
+                DTEntry::Single(data, _) => {
                     if data == entry {
-                        Ok(HashSet::new())
+                        Ok((None, HashSet::new()))
                     } else {
                         Err("There is unexpected data at {part_checksum}")
                     }
                 }
 
-                DTEntry::Multiple(MultipleEntries { hashes }) => {
+                // Files that were not hashed have no confirmed duplicates
+                DTEntry::Multiple(MultipleEntries { unhashed, .. }) if unhashed.contains(entry) => {
+                    Ok((None, HashSet::new()))
+                }
+
+                DTEntry::Multiple(MultipleEntries { hashes, .. }) => {
                     // Find vector that contains the entry
-                    for duplicates in hashes.values() {
+                    for (checksum, duplicates) in hashes.iter() {
                         if duplicates.contains(entry) {
                             let mut result: HashSet<TableData> =
                                 duplicates.iter().map(|x| x.to_owned()).collect();
                             // Remove the entry itself from returned vector
                             result.remove(entry);
-                            return Ok(result);
+                            return Ok((Some(checksum.clone()), result));
                         }
                     }
                     Err("Could not find specified entry {entry:?} in MultipleEntries at {part_checksum}")
@@ -272,18 +648,35 @@ impl DuplicateTable {
             Err("There is no entry with the specified partial checksum {part_checksum:?}.")
         }
     }
+
+    /// Get all groups of files with the same content
+    ///
+    /// Returns a map from the checksum of the files to the paths of all the files with that
+    /// checksum. Only files that have at least one duplicate are included.
+    pub(crate) fn get_clusters(&self) -> HashMap<Checksum, Vec<PathBuf>> {
+        let mut clusters = HashMap::new();
+        for entry in self.table.values() {
+            if let DTEntry::Multiple(MultipleEntries { hashes, .. }) = entry {
+                for (checksum, files) in hashes.iter().filter(|(_, files)| files.len() > 1) {
+                    let paths = files.iter().map(|x| PathBuf::from(x.path())).collect();
+                    clusters.insert(checksum.clone(), paths);
+                }
+            }
+        }
+        clusters
+    }
 }
 
 /// Structure for DuplicateTable entries
 #[derive(Debug)]
 enum DTEntry {
-    Single(TableData),
+    Single(TableData, Option<FileId>),
     Multiple(MultipleEntries),
 }
 
 impl DTEntry {
     fn new_multi_entry() -> DTEntry {
-        DTEntry::Multiple(MultipleEntries { hashes: HashMap::new() })
+        DTEntry::Multiple(MultipleEntries { hashes: HashMap::new(), unhashed: vec![] })
     }
 }
 
@@ -292,4 +685,16 @@ impl DTEntry {
 #[derive(Debug)]
 struct MultipleEntries {
     hashes: HashMap<String, Vec<TableData>>,
+    /// Items that were not hashed because of the deadline
+    unhashed: Vec<TableData>,
+}
+
+/// Returns true if `deadline` has passed
+fn past_deadline(deadline: Option<Instant>) -> bool {
+    deadline.map_or(false, |x| Instant::now() >= x)
+}
+
+/// Get number of CPUs available to the process (1 if it can't be read)
+fn get_cpu_count() -> usize {
+    std::thread::available_parallelism().map_or(1, |x| x.get())
 }
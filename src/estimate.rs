@@ -0,0 +1,94 @@
+//! Quick estimate of the duplicates without reading the files
+//!
+//! Files are grouped only by their size and name, so the estimate takes as long as listing the
+//! directories. Files with the same size and name are likely to be copies, but their content is
+//! not compared, so the estimate is an upper bound of the duplicates a scan finds among the files
+//! with the same names. Copies with different names are missed.
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use walkdir::WalkDir;
+
+use crate::exclude::exclude_regex;
+use crate::member_metadata::get_file_id;
+use crate::{merge_overlapping_roots, Config};
+
+/// Estimate of the duplicates made by [`estimate_duplicates`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DuplicationEstimate {
+    /// Number of files listed
+    pub files: u64,
+    /// Total size of the files listed
+    pub total_size: u64,
+    /// Number of groups of files with the same size and name
+    pub groups: u64,
+    /// Number of files in the groups
+    pub grouped_files: u64,
+    /// Space that would be freed by keeping only one file of each group
+    pub max_saved_space: u64,
+}
+
+/// Estimate the duplicates in `directories` from the sizes and names of the files
+///
+/// No file is read (see [`DuplicationEstimate`]). The files smaller than the minimum size (or
+/// larger than the maximum size), empty files, hidden files and excluded paths are skipped as set
+/// in `config`, and hardlinks to the same file are counted once. Entries that can't be read are
+/// skipped.
+///
+/// # Arguments:
+/// * `directories` - paths that will be listed
+/// * `config` - configuration of duplicate destroyer. See [`Config`](crate::Config) struct
+pub fn estimate_duplicates(
+    directories: impl IntoIterator<Item = impl Into<PathBuf>>,
+    config: &Config,
+) -> DuplicationEstimate {
+    let minimum_size = config.get_minimum_size();
+    let max_size = config.get_max_size().unwrap_or(u64::MAX);
+    let skip_hidden = config.get_skip_hidden();
+    let skip_empty_files = config.get_skip_empty_files();
+    let patterns: Vec<_> = config.get_exclude().iter().map(|x| exclude_regex(x)).collect();
+
+    let mut estimate = DuplicationEstimate::default();
+    let mut counts: HashMap<(u64, OsString), u64> = HashMap::new();
+    let mut seen = HashSet::new();
+
+    let directories: Vec<OsString> =
+        directories.into_iter().map(|x| x.into().into_os_string()).collect();
+    for (_, dir) in merge_overlapping_roots(directories) {
+        let entries = WalkDir::new(dir)
+            .into_iter()
+            .filter_entry(|x| {
+                let hidden = x.file_name().to_string_lossy().starts_with('.');
+                let excluded = patterns.iter().any(|p| p.is_match(&x.path().to_string_lossy()));
+                x.depth() == 0 || !((skip_hidden && hidden) || excluded)
+            })
+            .filter_map(|x| x.ok())
+            .filter(|x| x.file_type().is_file());
+        for entry in entries {
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            let size = metadata.len();
+            if size < minimum_size || size > max_size || (skip_empty_files && size == 0) {
+                continue;
+            }
+            if let Some(id) = get_file_id(&metadata) {
+                if !seen.insert(id) {
+                    continue;
+                }
+            }
+            estimate.files += 1;
+            estimate.total_size += size;
+            *counts.entry((size, entry.file_name().to_owned())).or_default() += 1;
+        }
+    }
+
+    for ((size, _), count) in counts.into_iter().filter(|(_, count)| *count > 1) {
+        estimate.groups += 1;
+        estimate.grouped_files += count;
+        estimate.max_saved_space += size * (count - 1);
+    }
+    estimate
+}
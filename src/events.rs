@@ -0,0 +1,182 @@
+//! Scan events sent from a background thread
+//!
+//! This module runs the duplicate search on a background thread and sends its progress and
+//! results over a channel, so that a frontend can render them from its own thread. Enable it with
+//! the `events` feature.
+use std::cell::RefCell;
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::thread::{self, JoinHandle};
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+
+use crate::config::Settings;
+use crate::{
+    Config, DuDeError, DuplicateObject, Phase, ProgressHandle, ProgressIndicator,
+    ProgressMultiline, Scanner,
+};
+
+/// Event of a scan running on a background thread
+#[derive(Clone, Debug)]
+pub enum ScanEvent {
+    /// Phase of the search started. It is followed by the other events of the phase.
+    PhaseChanged {
+        phase: Phase,
+        /// Total for the phase (see [`ProgressMultiline::start_phase`] and
+        /// [`ProgressIndicator::start_phase`])
+        total: u64,
+    },
+    /// Searching of the directories for files started
+    HashingStarted {
+        message: String,
+        /// Total number of files that will be processed
+        total_files: u64,
+    },
+    /// Directory that is currently searched
+    Directory(PathBuf),
+    /// Path that can't be accessed and is skipped
+    PathSkipped(PathBuf),
+    /// Number of files processed since the last event
+    FilesProcessed(u64),
+    /// Number of bytes read since the last event
+    BytesRead(u64),
+    /// File whose hashing as a whole started
+    HashingFile(PathBuf),
+    /// Total number of files that will be processed changed
+    TotalFilesChanged(u64),
+    /// All files were processed
+    HashingFinished,
+    /// Another phase of the search started
+    PhaseStarted {
+        message: String,
+        /// Total number of iterations expected
+        total_iterations: u64,
+    },
+    /// Number of iterations done in the current phase
+    PhaseProgress(u64),
+    /// The current phase finished
+    PhaseFinished,
+    /// Topmost duplicate group was found. The groups are sent from the largest.
+    GroupFound(DuplicateObject),
+}
+
+/// Find the largest duplicate directories or files on a background thread
+///
+/// Works like [`get_duplicates`](crate::get_duplicates), but returns immediately with a receiver
+/// of the events of the scan and a handle of the background thread returning all of the duplicate
+/// groups. The channel is disconnected once the scan finishes.
+///
+/// The progress indicators in `config` are not used, their updates are sent as events instead.
+///
+/// # Arguments:
+/// * `directories` - paths that will be searched for duplicates
+/// * `config` - configuration of duplicate destroyer. See [`Config`](crate::Config) struct
+pub fn get_duplicates_with_events(
+    directories: impl IntoIterator<Item = impl Into<PathBuf>>,
+    config: &Config,
+) -> (Receiver<ScanEvent>, JoinHandle<Result<Vec<DuplicateObject>, DuDeError>>) {
+    let directories: Vec<PathBuf> = directories.into_iter().map(Into::into).collect();
+    let settings = Settings::from_config(config);
+    let (tx, rx) = unbounded();
+
+    let handle = thread::spawn(move || {
+        let mut config = settings.into_config();
+        config.set_multiline_progress(Rc::new(RefCell::new(EventMultiline { tx: tx.clone() })));
+        config.set_progress_indicator(Rc::new(RefCell::new(EventIndicator { tx: tx.clone() })));
+
+        let mut scanner = Scanner::new(directories, &config)?;
+        let duplicates = scanner.topmost_duplicates(config.get_minimum_size());
+        for group in duplicates.iter() {
+            // The receiver may have been dropped, the result is returned anyway
+            let _ = tx.send(ScanEvent::GroupFound(group.clone()));
+        }
+        Ok(duplicates)
+    });
+
+    (rx, handle)
+}
+
+/// Sends updates of the multiline progress as events
+struct EventMultiline {
+    tx: Sender<ScanEvent>,
+}
+
+impl ProgressMultiline for EventMultiline {
+    fn start_phase(&mut self, phase: Phase, total: u64) {
+        let _ = self.tx.send(ScanEvent::PhaseChanged { phase, total });
+    }
+
+    fn create(&mut self, message: String, total_files: u64) -> Box<dyn ProgressHandle> {
+        let _ = self.tx.send(ScanEvent::HashingStarted { message, total_files });
+        Box::new(EventHandle { tx: self.tx.clone() })
+    }
+
+    fn update_dir(&self, new_dir: OsString) {
+        let _ = self.tx.send(ScanEvent::Directory(new_dir.into()));
+    }
+
+    fn skip_path(&self, path: &OsStr) {
+        let _ = self.tx.send(ScanEvent::PathSkipped(path.into()));
+    }
+
+    fn finalise(&self) {}
+
+    fn debug_string(&self) -> String {
+        "Event multiline progress".to_string()
+    }
+}
+
+/// Sends progress of file processing as events
+struct EventHandle {
+    tx: Sender<ScanEvent>,
+}
+
+impl ProgressHandle for EventHandle {
+    fn inc(&self, delta: u64) {
+        let _ = self.tx.send(ScanEvent::FilesProcessed(delta));
+    }
+
+    fn inc_bytes(&self, bytes: u64) {
+        let _ = self.tx.send(ScanEvent::BytesRead(bytes));
+    }
+
+    fn set_current_file(&self, path: &OsStr) {
+        let _ = self.tx.send(ScanEvent::HashingFile(path.into()));
+    }
+
+    fn set_len(&self, len: u64) {
+        let _ = self.tx.send(ScanEvent::TotalFilesChanged(len));
+    }
+
+    fn finish(&self) {
+        let _ = self.tx.send(ScanEvent::HashingFinished);
+    }
+}
+
+/// Sends progress of the other phases as events
+struct EventIndicator {
+    tx: Sender<ScanEvent>,
+}
+
+impl ProgressIndicator for EventIndicator {
+    fn start_phase(&mut self, phase: Phase, total: u64) {
+        let _ = self.tx.send(ScanEvent::PhaseChanged { phase, total });
+    }
+
+    fn create(&mut self, message: String, total_iterations: u64) {
+        let _ = self.tx.send(ScanEvent::PhaseStarted { message, total_iterations });
+    }
+
+    fn update(&self, iterations_done: u64) {
+        let _ = self.tx.send(ScanEvent::PhaseProgress(iterations_done));
+    }
+
+    fn finalise(&self) {
+        let _ = self.tx.send(ScanEvent::PhaseFinished);
+    }
+
+    fn debug_string(&self) -> String {
+        "Event progress indicator".to_string()
+    }
+}
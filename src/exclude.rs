@@ -0,0 +1,55 @@
+//! Exclude patterns
+//!
+//! Files and directories can be excluded from a scan by rsync-style glob patterns (`*` matches
+//! within one path component, `**` matches across components and `?` matches a single character):
+//! * A pattern without `/` is matched against the name of each file and directory.
+//! * A pattern containing `/` is matched against the end of the path (or the whole path if it
+//!   starts with `/`).
+//!
+//! A trailing `/` is ignored, so such patterns match files as well as directories.
+use regex::Regex;
+
+/// Convert exclude pattern to a regular expression matching the paths it excludes
+pub(crate) fn exclude_regex(pattern: &str) -> Regex {
+    let pattern = match pattern.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    };
+
+    let mut re = String::from(if pattern.starts_with('/') { "^" } else { "(^|/)" });
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re.push_str(".*");
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).expect("Escaped glob should be a valid regex.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exclude_regex_test() {
+        let name = exclude_regex("*.tmp");
+        assert!(name.is_match("a.tmp"));
+        assert!(name.is_match("dir/sub/a.tmp"));
+        assert!(!name.is_match("dir/a.tmp/b"));
+
+        let path = exclude_regex("backup/old/");
+        assert!(path.is_match("home/backup/old"));
+        assert!(!path.is_match("home/mybackup/old"));
+
+        let anchored = exclude_regex("/srv/**/cache");
+        assert!(anchored.is_match("/srv/www/site/cache"));
+        assert!(!anchored.is_match("/home/srv/www/cache"));
+    }
+}
@@ -0,0 +1,143 @@
+//! Search for copies of given files
+//!
+//! Unlike a scan, the search does not find all duplicates in the searched directories. Only the
+//! files with the same size as one of the given files are read, first their beginnings and then,
+//! if the beginning matches, their whole content.
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::checksum::{hash_file, hash_prefix, Checksum, PREFIX_LENGTH};
+use crate::exclude::exclude_regex;
+use crate::{merge_overlapping_roots, Config, HashAlgorithm};
+
+/// Copies of a file found by [`find_copies`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileCopies {
+    /// Path of the file as given
+    pub path: PathBuf,
+    /// Checksum of the content of the file
+    pub digest: Checksum,
+    /// Paths of the files with the same content in the searched directories, sorted
+    pub copies: Vec<PathBuf>,
+}
+
+/// File whose copies are searched for
+struct Target {
+    /// Canonical path of the file, so that the file itself is not reported as its copy
+    canonical: PathBuf,
+    prefix: Checksum,
+    copies: FileCopies,
+}
+
+/// Find copies of `files` in `directories`
+///
+/// The files are compared by their whole content (audio tags and video containers are not
+/// ignored). Hidden files and paths matching the exclude patterns are skipped if set in `config`,
+/// the other filters of a scan (e.g. the minimum size) do not apply. Files in the searched
+/// directories that can't be read are skipped.
+///
+/// Returns the copies of each of `files` in the same order.
+///
+/// # Errors
+/// Fails if any of `files` is not a file or can't be read.
+///
+/// # Arguments:
+/// * `files` - files whose copies are searched for
+/// * `directories` - paths that will be searched for the copies
+/// * `config` - configuration of duplicate destroyer. See [`Config`](crate::Config) struct
+pub fn find_copies(
+    files: impl IntoIterator<Item = impl Into<PathBuf>>,
+    directories: impl IntoIterator<Item = impl Into<PathBuf>>,
+    config: &Config,
+) -> io::Result<Vec<FileCopies>> {
+    let algorithm = config.get_hash_algorithm();
+    let prefix_algorithm = config.get_prefix_hash_algorithm();
+
+    let mut targets = vec![];
+    let mut sizes: HashMap<u64, Vec<usize>> = HashMap::new();
+    for path in files {
+        let path = path.into();
+        let metadata = fs::metadata(&path)?;
+        if !metadata.is_file() {
+            let message = format!("{:?} is not a file.", path);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        sizes.entry(metadata.len()).or_default().push(targets.len());
+        targets.push(Target {
+            canonical: fs::canonicalize(&path)?,
+            prefix: hash_prefix(&path, PREFIX_LENGTH, prefix_algorithm)?,
+            copies: FileCopies { digest: hash_file(&path, algorithm)?, path, copies: vec![] },
+        });
+    }
+
+    let patterns: Vec<_> = config.get_exclude().iter().map(|x| exclude_regex(x)).collect();
+    let skip_hidden = config.get_skip_hidden();
+    let skips = |path: &Path| {
+        let hidden = path.file_name().map_or(false, |x| x.to_string_lossy().starts_with('.'));
+        (skip_hidden && hidden) || patterns.iter().any(|x| x.is_match(&path.to_string_lossy()))
+    };
+
+    let directories: Vec<OsString> =
+        directories.into_iter().map(|x| x.into().into_os_string()).collect();
+    for (_, dir) in merge_overlapping_roots(directories) {
+        let entries = WalkDir::new(dir)
+            .into_iter()
+            .filter_entry(|x| x.depth() == 0 || !skips(x.path()))
+            .filter_map(|x| x.ok())
+            .filter(|x| x.file_type().is_file());
+        for entry in entries {
+            let candidates = match entry.metadata().ok().and_then(|x| sizes.get(&x.len())) {
+                Some(candidates) => candidates,
+                None => continue,
+            };
+            if let Err(e) =
+                add_copy(entry.path(), candidates, &mut targets, prefix_algorithm, algorithm)
+            {
+                log::warn!("Could not read {:?}: {}", entry.path(), e);
+            }
+        }
+    }
+
+    Ok(targets
+        .into_iter()
+        .map(|mut x| {
+            x.copies.copies.sort();
+            x.copies
+        })
+        .collect())
+}
+
+/// Add file at `path` to the copies of the `candidates` among `targets` it is a copy of
+///
+/// The file is read only if its beginning (hashed with `prefix_algorithm`) matches one of the
+/// candidates.
+fn add_copy(
+    path: &Path,
+    candidates: &[usize],
+    targets: &mut [Target],
+    prefix_algorithm: HashAlgorithm,
+    algorithm: HashAlgorithm,
+) -> io::Result<()> {
+    let canonical = fs::canonicalize(path)?;
+    let prefix = hash_prefix(path, PREFIX_LENGTH, prefix_algorithm)?;
+    let mut digest = None;
+    for &index in candidates {
+        let target = &mut targets[index];
+        if target.canonical == canonical || target.prefix != prefix {
+            continue;
+        }
+        let digest = match digest {
+            Some(ref digest) => digest,
+            None => digest.insert(hash_file(path, algorithm)?),
+        };
+        if *digest == target.copies.digest {
+            target.copies.copies.push(path.to_owned());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,28 @@
+//! File-level view of duplicate directory groups
+//!
+//! A group of duplicate directories is found by comparing whole subtrees, so before deleting one
+//! of the directories it can be useful to see which files back the claim. The expansion lists the
+//! files of the group members matched by their content.
+use std::path::PathBuf;
+
+use crate::Checksum;
+
+/// Files of a duplicate group expanded to file-level matches
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct GroupExpansion {
+    /// Files with the same content that are present in every member of the group
+    pub matches: Vec<FileMatch>,
+    /// Files whose content is missing in at least one member of the group
+    pub extras: Vec<PathBuf>,
+}
+
+/// Files with the same content found in the members of a group
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FileMatch {
+    /// Sorted paths of all the files with the content
+    pub paths: Vec<PathBuf>,
+    /// Size of one of the files
+    pub size: u64,
+    /// Checksum of the content of the files (None if they were not hashed)
+    pub digest: Option<Checksum>,
+}
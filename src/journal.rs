@@ -0,0 +1,249 @@
+//! Journal of checksums calculated during a scan
+//!
+//! Each checksum of a whole file is appended to the journal as soon as it is known, so a scan
+//! interrupted by a crash leaves behind the work it has done. The next scan with the same journal
+//! reuses the checksums of the files whose size and modification time did not change, and removes
+//! the journal once it finishes.
+//!
+//! The journal has one line per file with its size, modification time in nanoseconds since the
+//! Unix epoch, checksum and path separated by tabs. Backslashes and newlines in the path are
+//! escaped as `\\` and `\n`. Lines that can't be parsed (e.g. the last one written before a crash)
+//! are ignored.
+//!
+//! Forged checksums in a journal would make files that are not duplicates look like duplicates, so
+//! on Unix the journal is only used if it is a regular file (not a symlink) owned by the current
+//! user and not writable by anyone else. It is locked while it is used, so that concurrent scans
+//! with the same journal don't remove it under each other; the later scans run without it.
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::checksum::Checksum;
+
+/// Interval in which the written entries are flushed to the disk
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Checksums recorded by previous scans and the writer recording the current one
+#[derive(Debug)]
+pub(crate) struct Journal {
+    path: PathBuf,
+    /// Stamps and checksums of the files recorded before
+    previous: HashMap<OsString, (FileStamp, Checksum)>,
+    writer: BufWriter<File>,
+    last_flush: Instant,
+}
+
+impl Journal {
+    /// Load the entries of the journal at `path` (if it exists) and open it for appending
+    ///
+    /// Fails if the journal can't be trusted or is used by another scan (see the module
+    /// documentation).
+    pub(crate) fn open(path: PathBuf) -> io::Result<Self> {
+        let mut file = open_file(&path)?;
+        check_file(&file, &path)?;
+
+        let mut previous = HashMap::new();
+        for line in BufReader::new(&mut file).split(b'\n') {
+            if let Some((path, entry)) = parse_line(&line?) {
+                previous.insert(path, entry);
+            }
+        }
+        if !previous.is_empty() {
+            log::info!("Loaded {} checksums from journal {:?}", previous.len(), path);
+        }
+
+        Ok(Journal { path, previous, writer: BufWriter::new(file), last_flush: Instant::now() })
+    }
+
+    /// Get the recorded checksum of the file at `path` if the file did not change since
+    pub(crate) fn lookup(&self, path: &OsStr) -> Option<Checksum> {
+        let (stamp, checksum) = self.previous.get(path)?;
+        if FileStamp::read(path) == Some(*stamp) {
+            Some(checksum.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Append the checksum of the file at `path` with its `stamp` to the journal
+    ///
+    /// The stamp has to be read before the file is hashed, so that a file modified while it is
+    /// hashed is not reused with a checksum of the older content. The entries are flushed to the
+    /// disk at most every [`FLUSH_INTERVAL`].
+    pub(crate) fn record(
+        &mut self,
+        path: &OsStr,
+        stamp: FileStamp,
+        checksum: &Checksum,
+    ) -> io::Result<()> {
+        let encoded = match encode_path(path) {
+            Some(encoded) => encoded,
+            None => return Ok(()),
+        };
+
+        write!(self.writer, "{}\t{}\t{}\t", stamp.size, stamp.modified, checksum)?;
+        self.writer.write_all(&encoded)?;
+        self.writer.write_all(b"\n")?;
+
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.writer.flush()?;
+            self.last_flush = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Remove the journal after the scan finished
+    ///
+    /// The journal is removed while it is still locked, so no other scan can start using it.
+    pub(crate) fn finish(self) -> io::Result<()> {
+        fs::remove_file(&self.path)
+    }
+}
+
+/// Open the journal at `path` for reading and appending, creating it if it does not exist
+///
+/// Symlinks are not followed, so that the journal can't redirect the writes to another file.
+#[cfg(unix)]
+fn open_file(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn open_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new().read(true).append(true).create(true).open(path)
+}
+
+/// Check that the opened journal `file` can be trusted and lock it
+///
+/// The journal has to be a regular file owned by the current user and not writable by the group
+/// or others. Once it is locked, `path` has to still lead to it, otherwise it was removed by a scan
+/// that finished in the meantime.
+#[cfg(unix)]
+fn check_file(file: &File, path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::io::AsRawFd;
+
+    let metadata = file.metadata()?;
+    let untrusted = |message: &str| io::Error::new(io::ErrorKind::PermissionDenied, message);
+    if !metadata.is_file() {
+        return Err(untrusted("The journal is not a regular file."));
+    }
+    if metadata.uid() != unsafe { libc::geteuid() } {
+        return Err(untrusted("The journal is owned by another user."));
+    }
+    if metadata.mode() & 0o022 != 0 {
+        return Err(untrusted("The journal is writable by other users."));
+    }
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let e = io::Error::last_os_error();
+        return match e.kind() {
+            io::ErrorKind::WouldBlock => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "The journal is used by another scan.",
+            )),
+            _ => Err(e),
+        };
+    }
+    let current = fs::symlink_metadata(path)?;
+    if current.dev() != metadata.dev() || current.ino() != metadata.ino() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "The journal was removed."));
+    }
+    Ok(())
+}
+
+/// The journal is not checked on platforms other than Unix
+#[cfg(not(unix))]
+fn check_file(_file: &File, _path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Size and modification time of a file, by which the journal recognizes that it did not change
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct FileStamp {
+    size: u64,
+    /// Modification time in nanoseconds since the Unix epoch
+    modified: u128,
+}
+
+impl FileStamp {
+    /// Read the stamp of the file at `path` (None if its metadata can't be read)
+    pub(crate) fn read(path: &OsStr) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+        Some(FileStamp { size: metadata.len(), modified })
+    }
+}
+
+/// Parse a line of the journal into the path and its recorded entry
+fn parse_line(line: &[u8]) -> Option<(OsString, (FileStamp, Checksum))> {
+    let mut fields = line.splitn(4, |x| *x == b'\t');
+    let size = std::str::from_utf8(fields.next()?).ok()?.parse().ok()?;
+    let modified = std::str::from_utf8(fields.next()?).ok()?.parse().ok()?;
+    let checksum = std::str::from_utf8(fields.next()?).ok()?.to_owned();
+    let path = decode_path(fields.next()?)?;
+    Some((path, (FileStamp { size, modified }, checksum)))
+}
+
+/// Escape backslashes and newlines in the bytes of `path`
+fn encode_path(path: &OsStr) -> Option<Vec<u8>> {
+    let mut encoded = vec![];
+    for byte in path_bytes(path)? {
+        match byte {
+            b'\\' => encoded.extend_from_slice(b"\\\\"),
+            b'\n' => encoded.extend_from_slice(b"\\n"),
+            _ => encoded.push(*byte),
+        }
+    }
+    Some(encoded)
+}
+
+/// Reverse [`encode_path`]
+fn decode_path(encoded: &[u8]) -> Option<OsString> {
+    let mut bytes = vec![];
+    let mut iter = encoded.iter();
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'\\' => match iter.next()? {
+                b'\\' => bytes.push(b'\\'),
+                b'n' => bytes.push(b'\n'),
+                _ => return None,
+            },
+            _ => bytes.push(*byte),
+        }
+    }
+    path_from_bytes(bytes)
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &OsStr) -> Option<&[u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(path.as_bytes())
+}
+
+/// Paths that are not valid Unicode are not recorded on platforms other than Unix
+#[cfg(not(unix))]
+fn path_bytes(path: &OsStr) -> Option<&[u8]> {
+    path.to_str().map(str::as_bytes)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+    Some(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    String::from_utf8(bytes).ok().map(OsString::from)
+}
@@ -7,6 +7,15 @@
 //! directories. It then returns the topmost directories and files for which there exists at least
 //! one duplicate.
 //!
+//! To ask multiple questions about the same scan (e.g. topmost duplicates for different minimum
+//! sizes or the files without duplicates), create a [`Scanner`] instead. With the `async` feature
+//! enabled, `get_duplicates_async` returns the duplicates as a stream instead. With the `events`
+//! feature enabled, `get_duplicates_with_events` sends the progress and the results of a scan
+//! running on a background thread over a channel. To find the copies of just a few files,
+//! [`find_copies`] reads only the files of the same size instead of scanning everything.
+//! [`estimate_duplicates`] groups the files only by their size and name to tell quickly whether a
+//! full scan is worth running.
+//!
 //! # Example usage
 //! Suppose we have directory structure:
 //! ```bash
@@ -34,41 +43,82 @@
 //!  {"tests/fixtures/A", "tests/fixtures/B/A"}:
 //!
 //! ```
-//! # use std::collections::HashSet;
-//! # use std::ffi::OsString;
 //! use duplicate_destroyer::*;
 //!
 //! // Create DuDe configuration
 //! let mut config: Config = Default::default();
 //! config.set_minimum_size(0); // Use non-default minimum size (see Config structure for details)
 //!
-//! // Create vector of paths to search for duplicates
-//! let input_dirs = vec![OsString::from("tests/fixtures")];
-//!
-//! // Get duplicates
-//! let duplicates = duplicate_destroyer::get_duplicates(input_dirs, &config).unwrap();
+//! // Get duplicates in the paths (any of `&str`, `&Path`, `PathBuf`, `OsString`, ...)
+//! let duplicates = duplicate_destroyer::get_duplicates(["tests/fixtures"], &config).unwrap();
 //!
-//! let expected_paths = [OsString::from("tests/fixtures/A"),
-//!                       OsString::from("tests/fixtures/B/A")];
-//! let expected_output = DuplicateObject::new(8235, HashSet::from(expected_paths));
+//! let expected_paths = ["tests/fixtures/A", "tests/fixtures/B/A"];
+//! let expected_output = DuplicateObject::new(8235, expected_paths);
 //! assert_eq!(duplicates[0], expected_output)
 //! ```
 
-mod checksum;
+#[cfg(feature = "async")]
+mod async_api;
+mod audio;
+mod categories;
+pub mod checksum;
 mod config;
+mod content;
 mod dir_tree;
 mod duplicate_object;
 mod duplicate_table;
+mod estimate;
+#[cfg(feature = "events")]
+mod events;
+mod exclude;
+mod find_copies;
+mod group_expansion;
+mod journal;
+mod listing;
+mod member_metadata;
+mod open_files;
+mod open_for_writing;
+pub mod path_serde;
+mod presets;
 mod progress_trait;
+mod scan_report;
+mod scanner;
+mod snapshots;
+mod statistics;
+mod tree_view;
+mod video;
 
-pub use checksum::HashAlgorithm;
-pub use config::Config;
+#[cfg(feature = "async")]
+pub use async_api::{get_duplicates_async, DuplicateStream};
+pub use categories::Category;
+pub use checksum::{Checksum, HashAlgorithm};
+pub use config::{Config, GroupObserver};
 pub use duplicate_object::DuplicateObject;
+pub use estimate::{estimate_duplicates, DuplicationEstimate};
+#[cfg(feature = "events")]
+pub use events::{get_duplicates_with_events, ScanEvent};
+pub use find_copies::{find_copies, FileCopies};
+pub use group_expansion::{FileMatch, GroupExpansion};
+pub use listing::ListedEntry;
+pub use member_metadata::MemberMetadata;
+pub use presets::Preset;
 pub use progress_trait::*;
+pub use scan_report::{ScanError, ScanErrorKind, ScanStats, ScanTimings};
+pub use scanner::Scanner;
+pub use statistics::{
+    ancestor_clusters, root_statistics, size_bands, Analytics, AncestorCluster, CopyCount,
+    DirectoryDuplication, DirectoryStatistics, RootStatistics, SizeBand,
+};
+pub use tree_view::{NodeKind, TreeNode};
 
 use duplicate_object::*;
 
+use std::collections::HashMap;
 use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use duplicate_table::FileId;
+use member_metadata::get_file_id;
 
 /// Find the largest duplicate directories or files
 ///
@@ -77,31 +127,111 @@ use std::ffi::OsString;
 /// `directories`.
 ///
 /// # Arguments:
-/// * `directories` - vector of paths that will be searched for duplicates
+/// * `directories` - paths that will be searched for duplicates
 /// * `config` - configuration of duplicate destroyer. See [`Config`](crate::Config) struct
 pub fn get_duplicates(
-    directories: Vec<OsString>,
+    directories: impl IntoIterator<Item = impl Into<PathBuf>>,
     config: &Config,
 ) -> Result<Vec<DuplicateObject>, DuDeError> {
-    let num_threads: usize = config.get_num_threads();
+    let mut scanner = Scanner::new(directories, config)?;
+
+    Ok(scanner.topmost_duplicates(config.get_minimum_size()))
+}
+
+/// Find all groups of files with the same content
+///
+/// Goes recursively through each dir in `directories` and finds all duplicated files. Unlike
+/// [`get_duplicates`], the files are not combined into duplicate directories, all the groups of
+/// duplicate files are returned instead.
+///
+/// Returns a map from the checksum of the files to the paths of all the files with that checksum.
+///
+/// # Arguments:
+/// * `directories` - paths that will be searched for duplicates
+/// * `config` - configuration of duplicate destroyer. See [`Config`](crate::Config) struct
+pub fn get_file_clusters(
+    directories: impl IntoIterator<Item = impl Into<PathBuf>>,
+    config: &Config,
+) -> Result<HashMap<Checksum, Vec<PathBuf>>, DuDeError> {
+    let scanner = Scanner::new(directories, config)?;
+
+    Ok(scanner.file_clusters())
+}
 
-    let mut tree = dir_tree::DirTree::new(
-        num_threads,
-        config.get_multiline_progress(),
-        config.get_progress_indicator(),
-        config.get_hash_algorithm(),
-    );
+/// Remove input roots that are equal to or nested in other input roots
+///
+/// The roots are compared by their canonical paths, so the same directory reached through a
+/// symlink is recognised as well. Besides that, the device and inode of each root and of its
+/// ancestors are compared to the other roots, which recognises a directory mounted at another path
+/// (e.g. by a bind mount). Of the overlapping roots only the topmost one is kept (or the first one
+/// if they are equal), so that no subtree gets added to the DirTree twice and no file is reported
+/// as a duplicate of itself. Paths that can't be canonicalized are kept as they are.
+///
+/// Returns the kept roots together with their indices in `directories`.
+///
+/// # Arguments
+/// * `directories` - paths to be searched for duplicates as given by the user
+pub(crate) fn merge_overlapping_roots(directories: Vec<OsString>) -> Vec<(usize, OsString)> {
+    let canonical: Vec<Option<CanonicalRoot>> =
+        directories.iter().map(|dir| CanonicalRoot::new(dir.as_ref())).collect();
 
-    tree.add_directories(directories);
-    log::debug!("Finished adding directories");
+    let mut result = vec![];
+    for (index, dir) in directories.iter().enumerate() {
+        let root = match canonical[index] {
+            Some(ref root) => root,
+            None => {
+                result.push((index, dir.to_owned()));
+                continue;
+            }
+        };
 
-    tree.finalise();
+        let containing_root =
+            canonical.iter().enumerate().find(|(other_index, other)| match other {
+                Some(other) if other.is_same(root) => *other_index < index,
+                Some(other) => other.contains(root),
+                None => false,
+            });
+
+        if let Some((other_index, _)) = containing_root {
+            log::warn!(
+                "Path {:?} is already contained in {:?}, skipping it.",
+                dir,
+                directories[other_index]
+            );
+        } else {
+            result.push((index, dir.to_owned()));
+        }
+    }
+
+    result
+}
+
+/// Canonical path of an input root with the device and inode of the root and its ancestors
+struct CanonicalRoot {
+    path: PathBuf,
+    /// Device and inode of the root followed by those of its ancestors (empty if not available)
+    ids: Vec<FileId>,
+}
 
-    let min_size = config.get_minimum_size();
-    let mut duplicates = tree.get_duplicates(min_size);
+impl CanonicalRoot {
+    /// Canonicalize `dir` (None if it can't be canonicalized)
+    fn new(dir: &Path) -> Option<Self> {
+        let path = std::fs::canonicalize(dir).ok()?;
+        let ids = path
+            .ancestors()
+            .map_while(|x| std::fs::metadata(x).ok().as_ref().and_then(get_file_id))
+            .collect();
+        Some(CanonicalRoot { path, ids })
+    }
 
-    duplicates.sort_by_key(|x| x.size);
-    duplicates.reverse();
+    /// Returns true if `other` is the same directory
+    fn is_same(&self, other: &CanonicalRoot) -> bool {
+        self.path == other.path || (!self.ids.is_empty() && self.ids.first() == other.ids.first())
+    }
 
-    Ok(duplicates)
+    /// Returns true if `other` is nested in this root
+    fn contains(&self, other: &CanonicalRoot) -> bool {
+        other.path.starts_with(&self.path)
+            || self.ids.first().map_or(false, |id| other.ids.iter().skip(1).any(|x| x == id))
+    }
 }
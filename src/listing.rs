@@ -0,0 +1,26 @@
+//! Externally supplied directory listings
+//!
+//! Walking a network filesystem can take longer than hashing the files on it. If a listing of
+//! the files is available from a faster source (e.g. an object store or a backup catalog), the
+//! walk can be skipped and only the files with possible duplicates are read from the mount. See
+//! [`Scanner::from_listing`](crate::Scanner::from_listing).
+use std::path::PathBuf;
+
+use crate::NodeKind;
+
+/// Entry of an externally supplied listing
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ListedEntry {
+    /// Path of the file or directory as it can be opened by DuDe
+    pub path: PathBuf,
+    /// Size of the file in bytes (ignored for other kinds of entries)
+    pub size: u64,
+    pub kind: NodeKind,
+}
+
+impl ListedEntry {
+    /// Create new listing entry
+    pub fn new(path: impl Into<PathBuf>, size: u64, kind: NodeKind) -> Self {
+        ListedEntry { path: path.into(), size, kind }
+    }
+}
@@ -0,0 +1,180 @@
+//! File system metadata of the members of duplicate groups
+//!
+//! The metadata is read when it is requested (e.g. when a duplicate group is serialized), so that
+//! tools processing the json output can choose which member to keep without reading it again.
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::duplicate_table::FileId;
+
+/// Metadata of one path in a duplicate group
+///
+/// The values that could not be read (or are not available on the platform) are None.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct MemberMetadata {
+    #[serde(serialize_with = "crate::path_serde::serialize")]
+    pub path: PathBuf,
+    /// Space allocated for the file (or for all files in the directory) in bytes
+    #[serde(rename = "diskSize")]
+    pub disk_size: Option<u64>,
+    /// True if the file (or any file in the directory) has holes, so that it takes less space on
+    /// disk than its size
+    pub sparse: Option<bool>,
+    /// Time of the last modification in seconds since the Unix epoch
+    pub modified: Option<u64>,
+    pub inode: Option<u64>,
+    pub device: Option<u64>,
+    /// Index of the input directory the path was found in (see
+    /// [`DuplicateObject::roots`](crate::DuplicateObject::roots))
+    pub root: Option<usize>,
+}
+
+impl MemberMetadata {
+    /// Read metadata of `path`
+    ///
+    /// Symlinks are not followed.
+    ///
+    /// # Arguments
+    /// * `path` - member of a duplicate group
+    /// * `root` - index of the input directory the path was found in
+    pub fn read(path: PathBuf, root: Option<usize>) -> Self {
+        let metadata = fs::symlink_metadata(&path).ok();
+        let allocation = metadata.as_ref().and_then(|x| {
+            if x.is_dir() {
+                get_dir_allocation(&path)
+            } else {
+                get_allocation(x)
+            }
+        });
+
+        MemberMetadata {
+            disk_size: allocation.map(|x| x.0),
+            sparse: allocation.map(|x| x.1),
+            modified: metadata
+                .as_ref()
+                .and_then(|x| x.modified().ok())
+                .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+                .map(|x| x.as_secs()),
+            inode: metadata.as_ref().and_then(get_inode),
+            device: metadata.as_ref().and_then(get_device),
+            root,
+            path,
+        }
+    }
+}
+
+/// Get the device (file system) `path` is on, None if it can't be read
+pub(crate) fn read_device(path: impl AsRef<Path>) -> Option<u64> {
+    get_device(&fs::symlink_metadata(path).ok()?)
+}
+
+/// Returns true if `device` is a rotational disk (e.g. HDD), None if it is not known
+///
+/// The device is looked up in sysfs, partitions inherit the value of their disk.
+#[cfg(target_os = "linux")]
+pub(crate) fn is_rotational(device: u64) -> Option<bool> {
+    let major = ((device >> 8) & 0xfff) | ((device >> 32) & !0xfff);
+    let minor = (device & 0xff) | ((device >> 12) & !0xff);
+    let dir = Path::new("/sys/dev/block").join(format!("{major}:{minor}"));
+    let value = fs::read_to_string(dir.join("queue/rotational"))
+        .or_else(|_| fs::read_to_string(dir.join("../queue/rotational")))
+        .ok()?;
+    Some(value.trim() == "1")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn is_rotational(_device: u64) -> Option<bool> {
+    None
+}
+
+/// Get the device and inode of a file that has more than one hardlink, None otherwise
+#[cfg(unix)]
+pub(crate) fn get_link_id(metadata: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn get_link_id(_metadata: &Metadata) -> Option<FileId> {
+    None
+}
+
+/// Get the device and inode of a file or directory
+#[cfg(unix)]
+pub(crate) fn get_file_id(metadata: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn get_file_id(_metadata: &Metadata) -> Option<FileId> {
+    None
+}
+
+/// Get sum of space allocated for all entries in `dir` (including `dir` itself) and whether any
+/// of the files is sparse
+fn get_dir_allocation(dir: &Path) -> Option<(u64, bool)> {
+    let mut total = 0;
+    let mut sparse = false;
+    for entry in WalkDir::new(dir) {
+        let (allocated, is_sparse) = get_allocation(&entry.ok()?.metadata().ok()?)?;
+        total += allocated;
+        sparse |= is_sparse;
+    }
+    Some((total, sparse))
+}
+
+/// Get space allocated for a file and whether the file is sparse
+///
+/// The file is sparse if at least one block less than its size is allocated, so that small files
+/// stored together with their metadata are not considered sparse.
+#[cfg(unix)]
+fn get_allocation(metadata: &Metadata) -> Option<(u64, bool)> {
+    use std::os::unix::fs::MetadataExt;
+    let allocated = metadata.blocks() * 512;
+    let sparse = metadata.is_file() && allocated + metadata.blksize() <= metadata.len();
+    Some((allocated, sparse))
+}
+
+#[cfg(not(unix))]
+fn get_allocation(metadata: &Metadata) -> Option<(u64, bool)> {
+    Some((metadata.len(), false))
+}
+
+#[cfg(unix)]
+fn get_inode(metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn get_inode(_metadata: &Metadata) -> Option<u64> {
+    None
+}
+
+/// Get the user id of the owner of a file, None if it is not available on the platform
+#[cfg(unix)]
+pub(crate) fn get_owner(metadata: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.uid())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn get_owner(_metadata: &Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn get_device(metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn get_device(_metadata: &Metadata) -> Option<u64> {
+    None
+}
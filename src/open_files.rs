@@ -0,0 +1,69 @@
+//! Limit of simultaneously open files
+//!
+//! The directory walker and all of the hashing threads acquire a permit from the shared limit
+//! before opening a file or a directory and return it once they close it. The walker holds at most
+//! one permit at a time, so the limit can't deadlock. The threads hashing the chunks of a large
+//! file share the permit of its job and take a permit for each further reader of the file only if
+//! they get it without waiting, otherwise fewer threads hash the chunks.
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+/// Counting semaphore bounding the number of open files
+#[derive(Debug)]
+pub(crate) struct OpenFileLimit {
+    /// Maximum number of open files (None if unlimited)
+    max: Option<usize>,
+    /// Number of files open
+    open: Mutex<usize>,
+    closed: Condvar,
+}
+
+impl OpenFileLimit {
+    /// Create limit allowing at most `max` open files (unlimited if None)
+    pub(crate) fn new(max: Option<usize>) -> Self {
+        OpenFileLimit { max: max.map(|x| x.max(1)), open: Mutex::new(0), closed: Condvar::new() }
+    }
+
+    /// Wait until a file can be opened and get a permit to open it
+    ///
+    /// The permit is returned when the guard is dropped.
+    pub(crate) fn acquire(&self) -> OpenFileGuard<'_> {
+        if let Some(max) = self.max {
+            let mut open = self.lock();
+            while *open >= max {
+                open = self.closed.wait(open).expect("Open file limit lock poisoned.");
+            }
+            *open += 1;
+        }
+        OpenFileGuard { limit: self }
+    }
+
+    /// Get a permit to open a file if it can be opened without waiting
+    pub(crate) fn try_acquire(&self) -> Option<OpenFileGuard<'_>> {
+        if let Some(max) = self.max {
+            let mut open = self.lock();
+            if *open >= max {
+                return None;
+            }
+            *open += 1;
+        }
+        Some(OpenFileGuard { limit: self })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        self.open.lock().expect("Open file limit lock poisoned.")
+    }
+}
+
+/// Permit to have one file open
+pub(crate) struct OpenFileGuard<'a> {
+    limit: &'a OpenFileLimit,
+}
+
+impl Drop for OpenFileGuard<'_> {
+    fn drop(&mut self) {
+        if self.limit.max.is_some() {
+            *self.limit.lock() -= 1;
+            self.limit.closed.notify_one();
+        }
+    }
+}
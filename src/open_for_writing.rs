@@ -0,0 +1,79 @@
+//! Detection of files open for writing
+//!
+//! Files that some process has open for writing (e.g. database files, images of running virtual
+//! machines or downloads in progress) are likely to change during or right after the scan, so
+//! acting on them is risky. On Linux, the open files of each process are listed in
+//! `/proc/<pid>/fd` and the mode they were opened in is in `/proc/<pid>/fdinfo`. Only the processes
+//! the user is allowed to inspect are checked (all of them for root). The files are read once,
+//! before the directories are walked.
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+use crate::duplicate_table::FileId;
+
+/// Error of a file skipped because it was open for writing
+#[derive(Debug)]
+pub(crate) struct OpenForWriting;
+
+impl fmt::Display for OpenForWriting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "File is open for writing by another process.")
+    }
+}
+
+impl Error for OpenForWriting {}
+
+/// Get the device and inode of all files open for writing by the processes that can be inspected
+#[cfg(target_os = "linux")]
+pub(crate) fn files_open_for_writing() -> HashSet<FileId> {
+    use crate::member_metadata::get_file_id;
+    use std::fs;
+
+    let mut files = HashSet::new();
+    let processes = match fs::read_dir("/proc") {
+        Ok(processes) => processes,
+        Err(e) => {
+            log::warn!("Could not list processes to find files open for writing: {}", e);
+            return files;
+        }
+    };
+    for process in processes.filter_map(|x| x.ok()) {
+        // Skip the entries that are not processes and the processes that exited meanwhile
+        let fds = match fs::read_dir(process.path().join("fd")) {
+            Ok(fds) => fds,
+            Err(_) => continue,
+        };
+        for fd in fds.filter_map(|x| x.ok()) {
+            let fdinfo = process.path().join("fdinfo").join(fd.file_name());
+            if !is_open_for_writing(&fs::read_to_string(fdinfo).unwrap_or_default()) {
+                continue;
+            }
+            if let Some(id) = fs::metadata(fd.path()).ok().as_ref().and_then(get_file_id) {
+                files.insert(id);
+            }
+        }
+    }
+    files
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn files_open_for_writing() -> HashSet<FileId> {
+    log::warn!("Files open for writing can be detected only on Linux.");
+    HashSet::new()
+}
+
+/// Returns true if the `flags` in `fdinfo` of a file descriptor allow writing
+#[cfg(target_os = "linux")]
+fn is_open_for_writing(fdinfo: &str) -> bool {
+    /// Mask of the access mode in the flags (read only, write only or read and write)
+    const ACCESS_MODE: u32 = 0o3;
+    /// Access mode of read only file descriptors
+    const READ_ONLY: u32 = 0o0;
+
+    fdinfo
+        .lines()
+        .find_map(|line| line.strip_prefix("flags:"))
+        .and_then(|flags| u32::from_str_radix(flags.trim(), 8).ok())
+        .map_or(false, |flags| flags & ACCESS_MODE != READ_ONLY)
+}
@@ -0,0 +1,75 @@
+//! Lossless serialization of paths
+//!
+//! Paths that are valid Unicode are serialized as strings. Other paths (e.g. file names in a
+//! legacy encoding) are serialized as arrays of their bytes on Unix, so that they can be
+//! deserialized back to the same path and acted upon. On other platforms such paths are
+//! serialized lossily.
+//!
+//! Use with `#[serde(with = "duplicate_destroyer::path_serde")]` on a `PathBuf` or an `OsString`
+//! field.
+use std::ffi::{OsStr, OsString};
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+/// Serialized form of a path
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredPath {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+/// Serialize `path` as a string if it is valid Unicode, as an array of bytes otherwise
+pub fn serialize<P, S>(path: &P, s: S) -> Result<S::Ok, S::Error>
+where
+    P: AsRef<OsStr>,
+    S: Serializer,
+{
+    let path = path.as_ref();
+    match path.to_str() {
+        Some(path) => s.serialize_str(path),
+        None => serialize_bytes(path, s),
+    }
+}
+
+/// Deserialize path serialized either as a string or as an array of bytes
+pub fn deserialize<'de, P, D>(d: D) -> Result<P, D::Error>
+where
+    P: From<OsString>,
+    D: Deserializer<'de>,
+{
+    match StoredPath::deserialize(d)? {
+        StoredPath::Text(path) => Ok(OsString::from(path).into()),
+        StoredPath::Bytes(bytes) => from_bytes(bytes)
+            .map(P::from)
+            .ok_or_else(|| serde::de::Error::custom("path in bytes is not valid on this platform")),
+    }
+}
+
+#[cfg(unix)]
+fn serialize_bytes<S>(path: &OsStr, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    use std::os::unix::ffi::OsStrExt;
+    s.collect_seq(path.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn serialize_bytes<S>(path: &OsStr, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    s.serialize_str(&path.to_string_lossy())
+}
+
+#[cfg(unix)]
+fn from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+    Some(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    String::from_utf8(bytes).ok().map(OsString::from)
+}
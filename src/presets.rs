@@ -0,0 +1,55 @@
+//! Built-in sets of skipped files and directories
+//!
+//! Most scans of home directories or servers should skip the same files and directories (version
+//! control data, build artifacts, metadata of file managers). The presets list their names, so
+//! that they don't have to be excluded one by one.
+
+/// Set of names of files and directories skipped during a scan
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Preset {
+    /// Directories of version control systems (e.g. `.git`)
+    Vcs,
+    /// Version control directories together with dependencies and build artifacts (e.g.
+    /// `node_modules`, `target` or `__pycache__`)
+    Developer,
+    /// Metadata created by operating systems and file managers (e.g. `.DS_Store` or `Thumbs.db`)
+    OsMetadata,
+}
+
+const VCS_NAMES: [&str; 6] = [".git", ".hg", ".svn", ".bzr", "_darcs", "CVS"];
+
+const BUILD_NAMES: [&str; 9] = [
+    "node_modules",
+    "target",
+    "__pycache__",
+    ".venv",
+    ".tox",
+    ".mypy_cache",
+    ".pytest_cache",
+    ".gradle",
+    ".cargo",
+];
+
+const OS_METADATA_NAMES: [&str; 9] = [
+    ".DS_Store",
+    ".AppleDouble",
+    ".Spotlight-V100",
+    ".Trashes",
+    ".fseventsd",
+    "Thumbs.db",
+    "desktop.ini",
+    "$RECYCLE.BIN",
+    "System Volume Information",
+];
+
+impl Preset {
+    /// Get names of files and directories skipped by the preset
+    pub fn names(&self) -> Vec<&'static str> {
+        match self {
+            Preset::Vcs => VCS_NAMES.to_vec(),
+            Preset::Developer => VCS_NAMES.iter().chain(BUILD_NAMES.iter()).copied().collect(),
+            Preset::OsMetadata => OS_METADATA_NAMES.to_vec(),
+        }
+    }
+}
@@ -1,9 +1,57 @@
 //! Interface for progress visualisation handlers
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::ffi::{OsStr, OsString};
+use std::fmt::{self, Debug, Display};
 
-use std::ffi::OsString;
-use std::fmt::Debug;
+//*********************//
+//       Phases        //
+//*********************//
+
+/// Phase of the duplicate search
+///
+/// The phases follow each other in the order they are listed. The first three phases are displayed
+/// by [`ProgressMultiline`], the rest by [`ProgressIndicator`]. Both of them are told when their
+/// phase starts, so that a frontend can display e.g. "Phase 3/5" along with the progress.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Phase {
+    /// Counting the files in the searched directories
+    Counting,
+    /// Walking the directories and hashing the beginnings of the files
+    Walking,
+    /// Waiting for the checksums that are still being calculated
+    Hashing,
+    /// Finding the duplicate directories
+    ResolvingDirs,
+    /// Gathering the topmost duplicate groups
+    Curating,
+}
+
+impl Phase {
+    /// Total number of phases
+    pub const COUNT: usize = 5;
+
+    /// Position of the phase, starting from 1
+    pub fn number(&self) -> usize {
+        *self as usize + 1
+    }
+
+    /// Short description of the phase
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Counting => "Counting files",
+            Phase::Walking => "Walking directories",
+            Phase::Hashing => "Calculating hashes",
+            Phase::ResolvingDirs => "Resolving duplicate directories",
+            Phase::Curating => "Curating duplicate list",
+        }
+    }
+}
+
+/// Displays the phase as e.g. "Phase 3/5: Calculating hashes"
+impl Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Phase {}/{}: {}", self.number(), Phase::COUNT, self.name())
+    }
+}
 
 //*********************//
 // Multiline Progress  //
@@ -16,27 +64,37 @@ use std::fmt::Debug;
 ///
 /// All of the methods will generally be called multiple times.
 pub trait ProgressMultiline {
-    /// This method should initialise the multiline progress indicator and return a simple progress
-    /// indicator for tracking the overall progress of duplicate file search.
+    /// Called when one of the phases displayed by the multiline indicator starts
     ///
-    /// The simple progress indicator returned will indicate approximately how many files have been
-    /// processed (i.e. been hashed) out of the `total_files` number of files. The simple progress
-    /// indicator will be updated independently of the `ProgressAddDir` indicator via its own
-    /// `update` method.
+    /// The `total` is the number of directories to count files in for [`Phase::Counting`], the
+    /// number of files to process for [`Phase::Walking`] (the same as passed to `create`, which
+    /// follows) and the number of checksums still being calculated for [`Phase::Hashing`].
+    fn start_phase(&mut self, _phase: Phase, _total: u64) {}
+
+    /// This method should initialise the multiline progress indicator and return a progress
+    /// handle for tracking the overall progress of duplicate file search.
     ///
-    /// The create() method of the simple progress indicator returned is not called. If you need it
-    /// to be called, call it inside this method.
+    /// The handle returned will be advanced by one for each file processed (i.e. hashed) out of
+    /// the `total_files` number of files. It is owned by the DuDe and updated independently of the
+    /// multiline indicator, possibly from several hashing threads at once.
     ///
     /// This method can in general be called multiple times.
     ///
     /// # Arguments:
     /// * `message` - message to be displayed by the multiline indicator
     /// * `total_files` - total number of files the DuDe will process
-    fn create(&mut self, message: String, total_files: u64) -> Rc<RefCell<dyn ProgressIndicator>>;
+    fn create(&mut self, message: String, total_files: u64) -> Box<dyn ProgressHandle>;
 
     /// Update the directory displayed by the multiline progress indicator
     fn update_dir(&self, new_dir: OsString);
 
+    /// Called when the file or directory at `path` can't be accessed and is skipped, e.g. to
+    /// display a running count of the skipped paths
+    ///
+    /// The skipped paths are listed in detail by [`scan_errors`](crate::Scanner::scan_errors)
+    /// once the scan finishes.
+    fn skip_path(&self, _path: &OsStr) {}
+
     /// Finish the indicator of directory processing in `ProgressMultiline` indicator
     ///
     /// The progress indicator tracking the overall progress of of duplicate file search (that was
@@ -53,6 +111,44 @@ impl Debug for dyn ProgressMultiline {
     }
 }
 
+//*********************//
+//  Progress handle    //
+//*********************//
+
+/// Progress of a single phase of the search
+///
+/// Unlike [`ProgressIndicator`], the handle is owned by the phase it tracks and can be sent to (and
+/// shared by) the threads doing the work, so all of its methods take `&self`.
+pub trait ProgressHandle: Send + Sync {
+    /// Advance the progress by `delta` iterations
+    fn inc(&self, delta: u64);
+
+    /// Add `bytes` to the number of bytes read so far, e.g. to display hashing throughput
+    ///
+    /// Huge files are reported in chunks (of 64 MiB) while they are being hashed, so this is
+    /// called regularly even if hashing of a single file takes minutes.
+    fn inc_bytes(&self, _bytes: u64) {}
+
+    /// Called when hashing of the whole file at `path` starts, e.g. to display the file when
+    /// hashing of a large file takes long
+    ///
+    /// Several files can be hashed at once by different threads, each of them is reported when
+    /// its hashing starts.
+    fn set_current_file(&self, _path: &OsStr) {}
+
+    /// Change the total number of iterations expected
+    fn set_len(&self, len: u64);
+
+    /// Finish the phase. The handle is not used afterwards.
+    fn finish(&self);
+}
+
+impl Debug for dyn ProgressHandle {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Progress handle")
+    }
+}
+
 //*********************//
 // Progress indicator  //
 //*********************//
@@ -63,6 +159,11 @@ impl Debug for dyn ProgressMultiline {
 /// meant to be reused for each of the processes by repeatedly calling `create` and `finalise`
 /// functions.
 pub trait ProgressIndicator {
+    /// Called when one of the phases displayed by the indicator starts, just before `create`
+    ///
+    /// The `total` is the same as the number of iterations passed to `create`.
+    fn start_phase(&mut self, _phase: Phase, _total: u64) {}
+
     /// Initialise the progress indicator or reinitialise it after it has been finalised.
     ///
     /// # Arguments:
@@ -102,16 +203,21 @@ impl ProgressIndicator for NoProgressIndicator {
     }
 }
 
+/// Implements [`ProgressHandle`](ProgressHandle) without displaying anything
+pub struct NoProgressHandle {}
+
+impl ProgressHandle for NoProgressHandle {
+    fn inc(&self, _delta: u64) {}
+    fn set_len(&self, _len: u64) {}
+    fn finish(&self) {}
+}
+
 /// Implements [`ProgressMultiline`](ProgressMultiline) without displaying anything
 pub struct NoProgressMultiline {}
 
 impl ProgressMultiline for NoProgressMultiline {
-    fn create(
-        &mut self,
-        _message: String,
-        _total_iterations: u64,
-    ) -> Rc<RefCell<dyn ProgressIndicator>> {
-        Rc::new(RefCell::new(NoProgressIndicator {}))
+    fn create(&mut self, _message: String, _total_iterations: u64) -> Box<dyn ProgressHandle> {
+        Box::new(NoProgressHandle {})
     }
     fn update_dir(&self, _new_dir: OsString) {}
     fn finalise(&self) {}
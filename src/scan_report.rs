@@ -0,0 +1,66 @@
+//! Problems encountered during a scan and totals of scanned items
+//!
+//! The paths that could not be scanned are not part of any duplicate group, so without this
+//! report a scan that skipped e.g. a broken mount can't be told apart from a clean one.
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// Reason why a path was skipped
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanErrorKind {
+    /// File or directory could not be read
+    Inaccessible,
+    /// Path is neither a file, a directory nor a symlink (e.g. a named pipe or a socket)
+    SpecialFile,
+    /// File has the same checksum as a file of a different size, so the checksum can't be
+    /// trusted. Neither of them is put in any duplicate group.
+    CollisionDetected,
+    /// File was open for writing by another process (see
+    /// [`skip_open_for_writing`](crate::Config::skip_open_for_writing))
+    OpenForWriting,
+}
+
+/// Path that was skipped during a scan (or a file that was left out of the duplicate groups)
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct ScanError {
+    #[serde(serialize_with = "crate::path_serde::serialize")]
+    pub path: PathBuf,
+    pub kind: ScanErrorKind,
+    /// Description of the error
+    pub message: String,
+}
+
+/// Number of items scanned
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug, Serialize)]
+pub struct ScanStats {
+    pub files: u64,
+    pub dirs: u64,
+    pub symlinks: u64,
+    /// Number of skipped paths. See [`ScanError`]
+    pub errors: u64,
+    /// Sum of sizes of all scanned files
+    pub total_size: u64,
+}
+
+/// Time spent in the phases of a scan
+///
+/// With multiple threads the files are hashed while the directories are walked, so `hashing` is
+/// only the time spent waiting for the checksums after the walk finished. Without threads it is
+/// the whole time spent hashing and it is not included in `walking`.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct ScanTimings {
+    /// Walking the directories and reading the first bytes of files
+    pub walking: Duration,
+    /// Calculating checksums of whole files
+    pub hashing: Duration,
+    /// Number of bytes read to calculate checksums of whole files
+    pub bytes_hashed: u64,
+    /// Finding duplicate directories
+    pub resolving: Duration,
+    /// Getting the topmost duplicate groups (the last call of
+    /// [`topmost_duplicates`](crate::Scanner::topmost_duplicates))
+    pub curating: Duration,
+}
@@ -0,0 +1,320 @@
+//! Reusable scan results
+//!
+//! This module provides the Scanner structure that scans the directories once and then allows
+//! multiple queries on the results without rescanning.
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::dir_tree::{DirTree, GroupFilter};
+use crate::journal::Journal;
+use crate::statistics;
+use crate::tree_view::TreeNode;
+use crate::{
+    merge_overlapping_roots, Analytics, Checksum, Config, DirectoryDuplication, DuDeError,
+    DuplicateObject, GroupExpansion, ListedEntry, ScanError, ScanStats, ScanTimings,
+};
+
+/// Holds the scanned directory tree and answers queries about its duplicates
+///
+/// # Example usage
+/// ```
+/// use duplicate_destroyer::*;
+///
+/// let config: Config = Default::default();
+/// let mut scanner = Scanner::new(["tests/fixtures"], &config).unwrap();
+///
+/// let topmost = scanner.topmost_duplicates(0);
+/// let all_groups = scanner.all_groups();
+/// assert!(all_groups.len() >= topmost.len());
+///
+/// let group = scanner.duplicates_of("tests/fixtures/A").unwrap();
+/// assert!(group.contains("tests/fixtures/B/A"));
+/// ```
+#[derive(Debug)]
+pub struct Scanner {
+    tree: DirTree,
+    min_copies: usize,
+    same_directory_only: bool,
+    cross_directory_only: bool,
+    max_size: Option<u64>,
+    /// Canonicalized preferred roots used to mark canonical paths of groups
+    preferred_roots: Vec<PathBuf>,
+    /// Time spent in the phases of the scan
+    timings: ScanTimings,
+}
+
+impl Scanner {
+    /// Scan the directories and find all duplicates in them
+    ///
+    /// # Arguments:
+    /// * `directories` - paths that will be searched for duplicates
+    /// * `config` - configuration of duplicate destroyer. See [`Config`](crate::Config) struct
+    pub fn new(
+        directories: impl IntoIterator<Item = impl Into<PathBuf>>,
+        config: &Config,
+    ) -> Result<Self, DuDeError> {
+        let mut tree = Scanner::create_tree(config);
+
+        let directories = directories.into_iter().map(|x| x.into().into_os_string()).collect();
+        let (root_indices, directories) = merge_overlapping_roots(directories).into_iter().unzip();
+        tree.set_root_indices(root_indices);
+        let start = Instant::now();
+        tree.add_directories(directories);
+        log::debug!("Finished adding directories");
+
+        Ok(Scanner::from_tree(tree, config, start.elapsed()))
+    }
+
+    /// Find all duplicates in directories listed by an external source without walking them
+    ///
+    /// Only the files are read (to calculate their checksums), the structure of the directories
+    /// and the sizes of files are taken from `entries`. Each of the `roots` has to be listed in
+    /// `entries` together with all of its descendants, roots missing in the listing are reported
+    /// as inaccessible. The roots must not overlap.
+    ///
+    /// # Arguments:
+    /// * `roots` - paths of the listed directories that will be searched for duplicates
+    /// * `entries` - listing of the roots and all of their descendants
+    /// * `config` - configuration of duplicate destroyer. See [`Config`](crate::Config) struct
+    pub fn from_listing(
+        roots: impl IntoIterator<Item = impl Into<PathBuf>>,
+        entries: Vec<ListedEntry>,
+        config: &Config,
+    ) -> Result<Self, DuDeError> {
+        let mut tree = Scanner::create_tree(config);
+
+        let roots: Vec<OsString> = roots.into_iter().map(|x| x.into().into_os_string()).collect();
+        tree.set_root_indices((0..roots.len()).collect());
+        let start = Instant::now();
+        tree.add_listing(roots, entries);
+        log::debug!("Finished adding listed directories");
+
+        Ok(Scanner::from_tree(tree, config, start.elapsed()))
+    }
+
+    /// Create empty DirTree set up according to `config`
+    fn create_tree(config: &Config) -> DirTree {
+        let mut tree = DirTree::new(
+            config.get_num_threads(),
+            config.get_multiline_progress(),
+            config.get_progress_indicator(),
+            config.get_hash_algorithm(),
+        );
+
+        tree.set_prefix_hash_algorithm(config.get_prefix_hash_algorithm());
+        tree.set_max_depth(config.get_max_depth());
+        tree.set_skip_hidden(config.get_skip_hidden());
+        tree.set_skip_empty_files(config.get_skip_empty_files());
+        if let Some(uid) = config.get_owner_uid() {
+            tree.set_owner_uid(uid);
+        }
+        tree.set_hash_all_files(config.get_hash_all_files());
+        tree.set_ignore_audio_tags(config.get_ignore_audio_tags());
+        tree.set_video_streams(config.get_video_streams());
+        tree.set_categories(config.get_categories());
+        tree.set_skip_snapshots(config.get_skip_snapshots());
+        if config.get_skip_open_for_writing() {
+            tree.skip_open_for_writing();
+        }
+        tree.set_per_device_queues(config.get_per_device_queues());
+        for pattern in config.get_exclude() {
+            tree.exclude(&pattern);
+        }
+        for preset in config.get_presets() {
+            for name in preset.names() {
+                tree.skip_name(name.into());
+            }
+        }
+        if let Some(observer) = config.get_group_observer() {
+            tree.set_group_observer(observer);
+        }
+        if let Some(max) = config.get_max_open_files() {
+            tree.set_max_open_files(max);
+        }
+        if let Some(threshold) = config.get_parallel_hash_threshold() {
+            tree.set_parallel_hash_threshold(threshold);
+        }
+        if let Some(path) = config.get_journal() {
+            match Journal::open(path.clone()) {
+                Ok(journal) => tree.set_journal(journal),
+                Err(e) => log::warn!("Could not open checksum journal {:?}: {}", path, e),
+            }
+        }
+        if let Some(deadline) = config.get_deadline() {
+            tree.set_deadline(Instant::now() + deadline);
+        }
+
+        tree
+    }
+
+    /// Find duplicates in populated `tree`
+    ///
+    /// `walking` is the time it took to populate the tree.
+    fn from_tree(mut tree: DirTree, config: &Config, walking: Duration) -> Self {
+        let (inline_hashing, _) = tree.get_hashing_stats();
+        tree.finalise();
+        let (hashing, bytes_hashed) = tree.get_hashing_stats();
+        let start = Instant::now();
+        tree.resolve_duplicates();
+        let timings = ScanTimings {
+            walking: walking.saturating_sub(inline_hashing),
+            hashing,
+            bytes_hashed,
+            resolving: start.elapsed(),
+            curating: Duration::ZERO,
+        };
+
+        let preferred_roots = config
+            .get_preferred_roots()
+            .into_iter()
+            .map(|root| std::fs::canonicalize(&root).unwrap_or(root))
+            .collect();
+
+        Scanner {
+            tree,
+            min_copies: config.get_min_copies(),
+            same_directory_only: config.get_same_directory_only(),
+            cross_directory_only: config.get_cross_directory_only(),
+            max_size: config.get_max_size(),
+            preferred_roots,
+            timings,
+        }
+    }
+
+    /// Get the topmost directories or files that are duplicated, sorted by size (largest first)
+    ///
+    /// Only groups with at least [`min_copies`](crate::Config::min_copies) elements and with
+    /// elements of at most [`max_size`](crate::Config::max_size) set in the configuration are
+    /// returned (and only the groups within one directory with
+    /// [`same_directory_only`](crate::Config::same_directory_only) or spanning several directories
+    /// with [`cross_directory_only`](crate::Config::cross_directory_only)).
+    ///
+    /// # Arguments:
+    /// * `min_size` - minimum size of elements in returned duplicate groups
+    pub fn topmost_duplicates(&mut self, min_size: u64) -> Vec<DuplicateObject> {
+        let start = Instant::now();
+        let filter = GroupFilter {
+            min_size,
+            max_size: self.max_size,
+            min_copies: self.min_copies,
+            same_directory_only: self.same_directory_only,
+            cross_directory_only: self.cross_directory_only,
+        };
+        let mut duplicates = self.tree.get_duplicates(&filter);
+        self.mark_canonical(&mut duplicates);
+
+        duplicates.sort_by_key(|x| x.size);
+        duplicates.reverse();
+        self.timings.curating = start.elapsed();
+
+        duplicates
+    }
+
+    /// Get all duplicate groups, including the groups contained in other duplicate groups
+    pub fn all_groups(&self) -> Vec<DuplicateObject> {
+        let mut groups = self.tree.get_all_groups();
+        self.mark_canonical(&mut groups);
+        groups
+    }
+
+    /// Get all groups of files with the same content
+    ///
+    /// Returns a map from the checksum of the files to the paths of all the files with that
+    /// checksum. Unlike the other methods, this works only with files and does not combine them to
+    /// duplicate directories.
+    pub fn file_clusters(&self) -> HashMap<Checksum, Vec<PathBuf>> {
+        self.tree.get_file_clusters()
+    }
+
+    /// Get the duplicate group containing `path`
+    ///
+    /// Returns None if `path` was not scanned or if it has no duplicates. The `path` has to be in
+    /// the same form as the paths in returned duplicate groups.
+    pub fn duplicates_of(&self, path: impl AsRef<Path>) -> Option<DuplicateObject> {
+        let mut group = self.tree.get_duplicates_of(path.as_ref())?;
+        group.set_canonical(&self.preferred_roots);
+        Some(group)
+    }
+
+    /// Expand `group` to the files of its members matched by their content
+    ///
+    /// For a group of directories this shows which files back the duplication and which files (if
+    /// any) have no counterpart in some of the directories. Returns None if any member of `group`
+    /// was not scanned.
+    pub fn expand_group(&self, group: &DuplicateObject) -> Option<GroupExpansion> {
+        self.tree.get_group_expansion(&group.duplicates)
+    }
+
+    /// Get the topmost nodes of the scanned tree (one for each scanned directory)
+    ///
+    /// The nodes can be used to browse the whole scanned directory tree together with the
+    /// duplicates of each node. See [`TreeNode`](crate::TreeNode).
+    pub fn roots(&self) -> Vec<TreeNode<'_>> {
+        self.tree.get_root_ids().into_iter().map(|id| TreeNode::new(&self.tree, id)).collect()
+    }
+
+    /// Get aggregate analytics of `groups` found by this scanner
+    ///
+    /// The analytics contain the histogram of the groups by their number of members and the bytes
+    /// wasted in each top-level directory (a child of a scanned directory) together with the
+    /// fraction of the directory they make up. See [`Analytics`].
+    pub fn analytics(&self, groups: &[DuplicateObject]) -> Analytics {
+        let mut roots = vec![];
+        let mut sizes = HashMap::new();
+        for root in self.roots() {
+            for node in root.children().into_iter().chain([root.clone()]) {
+                sizes.insert(node.path(), node.size());
+            }
+            roots.push(root.path());
+        }
+        statistics::analytics(groups, &roots, &sizes)
+    }
+
+    /// Rank the scanned directories by the fraction of their bytes that have a copy elsewhere
+    ///
+    /// Unlike the duplicate groups, this includes directories that are not duplicates as a whole,
+    /// e.g. a directory most of whose files were copied somewhere else. See
+    /// [`DirectoryDuplication`].
+    pub fn directory_duplication(&self) -> Vec<DirectoryDuplication> {
+        statistics::directory_duplication(&self.roots())
+    }
+
+    /// Get paths of all scanned files that have no duplicates
+    pub fn unique_files(&self) -> Vec<PathBuf> {
+        self.tree.get_unique_files()
+    }
+
+    /// Get all paths that were skipped during the scan, because they could not be read or are
+    /// special files
+    pub fn scan_errors(&self) -> Vec<ScanError> {
+        self.tree.get_scan_errors()
+    }
+
+    /// Get the time spent in the phases of the scan. See [`ScanTimings`]
+    pub fn timings(&self) -> ScanTimings {
+        self.timings
+    }
+
+    /// Get the number of scanned files, directories, symlinks and skipped paths
+    pub fn scan_stats(&self) -> ScanStats {
+        self.tree.get_scan_stats()
+    }
+
+    /// Returns true if the scan ran out of its [`deadline`](crate::Config::deadline)
+    ///
+    /// Some files were then not hashed, so there can be duplicates that were not found.
+    pub fn is_truncated(&self) -> bool {
+        self.tree.is_truncated()
+    }
+
+    /// Set canonical paths of `groups` according to preferred roots
+    fn mark_canonical(&self, groups: &mut [DuplicateObject]) {
+        if !self.preferred_roots.is_empty() {
+            for group in groups {
+                group.set_canonical(&self.preferred_roots);
+            }
+        }
+    }
+}
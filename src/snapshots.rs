@@ -0,0 +1,119 @@
+//! Detection of file system snapshots and of directories reached through more than one path
+//!
+//! The files in a snapshot (e.g. a btrfs snapshot or the `.zfs/snapshot` directory of a ZFS
+//! dataset) share their data with the live files, and a directory mounted at two paths (by a bind
+//! mount or by mounting the same file system twice) is the same directory. Such copies take no
+//! extra space, so removing one of them frees nothing (or removes the only copy).
+//!
+//! A directory is detected as
+//! * a snapshot, if it is the `.zfs` control directory of a ZFS dataset, or the root of a btrfs
+//!   subvolume (inode 256 on another device than its parent) that is not a mount point,
+//! * an alias, if a directory with the same device and inode was already walked.
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+
+use crate::duplicate_table::FileId;
+use crate::member_metadata::get_file_id;
+
+/// Name of the control directory holding the snapshots of a ZFS dataset
+const ZFS_CONTROL_DIR: &str = ".zfs";
+
+/// Inode number of the root directory of a btrfs subvolume
+#[cfg(target_os = "linux")]
+const BTRFS_SUBVOLUME_INODE: u64 = 256;
+
+/// Detects snapshots and directories that were already walked
+#[derive(Debug, Default)]
+pub(crate) struct SnapshotDetector {
+    /// Device and inode of the directories walked so far
+    visited: HashSet<FileId>,
+    /// Mount points of the system (read once they are needed)
+    mount_points: Option<HashSet<PathBuf>>,
+}
+
+impl SnapshotDetector {
+    /// Returns true if the directory at `path` with `metadata` is a snapshot or if it was already
+    /// walked through another path
+    pub(crate) fn detect(&mut self, path: &Path, metadata: &Metadata) -> bool {
+        if path.file_name() == Some(OsStr::new(ZFS_CONTROL_DIR)) {
+            log::info!("Found ZFS snapshots in {:?}", path);
+            return true;
+        }
+        let id = match get_file_id(metadata) {
+            Some(id) => id,
+            None => return false,
+        };
+        if !self.visited.insert(id) {
+            log::info!("Directory {:?} was already walked through another path", path);
+            return true;
+        }
+        if self.is_nested_subvolume(path, id) {
+            log::info!("Found btrfs snapshot (or subvolume) {:?}", path);
+            return true;
+        }
+        false
+    }
+
+    /// Returns true if the directory at `path` with device and inode `id` is the root of a btrfs
+    /// subvolume inside another subvolume
+    #[cfg(target_os = "linux")]
+    fn is_nested_subvolume(&mut self, path: &Path, (device, inode): FileId) -> bool {
+        if inode != BTRFS_SUBVOLUME_INODE {
+            return false;
+        }
+        let path = match std::fs::canonicalize(path) {
+            Ok(path) => path,
+            Err(_) => return false,
+        };
+        match path.parent().and_then(crate::member_metadata::read_device) {
+            Some(parent_device) if parent_device != device => {
+                !self.mount_points.get_or_insert_with(read_mount_points).contains(&path)
+            }
+            _ => false,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn is_nested_subvolume(&mut self, _path: &Path, _id: FileId) -> bool {
+        false
+    }
+}
+
+/// Read the mount points from `/proc/self/mountinfo` (empty if it can't be read)
+#[cfg(target_os = "linux")]
+fn read_mount_points() -> HashSet<PathBuf> {
+    let mountinfo = std::fs::read("/proc/self/mountinfo").unwrap_or_default();
+    mountinfo
+        .split(|x| *x == b'\n')
+        .filter_map(|line| line.split(|x| *x == b' ').nth(4))
+        .map(unescape_mount_point)
+        .collect()
+}
+
+/// Replace octal escapes (e.g. `\040` for a space) in a mount point from `mountinfo`
+#[cfg(target_os = "linux")]
+fn unescape_mount_point(field: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+
+    let mut bytes = vec![];
+    let mut index = 0;
+    while index < field.len() {
+        let escaped = field.get(index + 1..index + 4).and_then(|x| {
+            let digits = std::str::from_utf8(x).ok()?;
+            u8::from_str_radix(digits, 8).ok()
+        });
+        match escaped {
+            Some(byte) if field[index] == b'\\' => {
+                bytes.push(byte);
+                index += 4;
+            }
+            _ => {
+                bytes.push(field[index]);
+                index += 1;
+            }
+        }
+    }
+    std::ffi::OsString::from_vec(bytes).into()
+}
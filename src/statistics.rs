@@ -0,0 +1,352 @@
+//! Aggregate statistics of duplicate groups
+//!
+//! This module provides functions summarising the duplicate groups returned by DuDe.
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{DuplicateObject, NodeKind, TreeNode};
+
+/// Lower bounds of the size bands of [`size_bands`] in bytes
+const BAND_BOUNDS: [u64; 4] = [0, 1_000, 1_000_000, 1_000_000_000];
+
+/// Amount of duplicated data in one input root
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RootStatistics {
+    /// Bytes that could be freed by removing the copies that have another copy in the same root
+    pub within_root: u64,
+    /// Bytes in the root that have a copy only in other roots
+    pub across_roots: u64,
+}
+
+/// Get the amount of duplicated data in each input root
+///
+/// For each group and each root the group has members in, one member is counted as duplicated
+/// across roots (if the group has members in other roots) and the rest of the members are counted
+/// as duplicated within the root. The sum of both values is the space that can be freed in the
+/// root if all of the duplicates were kept in other roots.
+///
+/// Returns a map from the index of the input root to its statistics. Roots are taken from the
+/// [`roots`](crate::DuplicateObject::roots) field of the duplicate objects.
+///
+/// # Arguments
+/// * `duplicates` - duplicate groups returned by DuDe
+pub fn root_statistics(duplicates: &[DuplicateObject]) -> BTreeMap<usize, RootStatistics> {
+    let mut statistics: BTreeMap<usize, RootStatistics> = BTreeMap::new();
+
+    for group in duplicates {
+        let mut members_per_root: HashMap<usize, u64> = HashMap::new();
+        for root in group.roots.values() {
+            *members_per_root.entry(*root).or_insert(0) += 1;
+        }
+
+        let spans_roots = members_per_root.len() > 1;
+        for (root, members) in members_per_root {
+            let root_stats = statistics.entry(root).or_default();
+            root_stats.within_root += group.size * (members - 1);
+            if spans_roots {
+                root_stats.across_roots += group.size;
+            }
+        }
+    }
+
+    statistics
+}
+
+/// Duplicate groups with elements in a range of sizes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SizeBand {
+    /// Smallest size of an element in the band
+    pub min: u64,
+    /// Size of elements above which they belong to the next band (None for the last band)
+    pub max: Option<u64>,
+    /// Number of groups in the band
+    pub groups: usize,
+    /// Bytes that could be freed by keeping only one member of each group in the band
+    pub savings: u64,
+}
+
+/// Get the histogram of duplicate groups by the size of their elements
+///
+/// The bands are below 1 kB, from 1 kB to 1 MB, from 1 MB to 1 GB and above 1 GB. All of the bands
+/// are returned, even the ones with no groups.
+///
+/// # Arguments
+/// * `duplicates` - duplicate groups returned by DuDe
+pub fn size_bands(duplicates: &[DuplicateObject]) -> Vec<SizeBand> {
+    let mut bands: Vec<_> = BAND_BOUNDS
+        .iter()
+        .enumerate()
+        .map(|(index, min)| SizeBand {
+            min: *min,
+            max: BAND_BOUNDS.get(index + 1).copied(),
+            ..Default::default()
+        })
+        .collect();
+
+    for group in duplicates {
+        let band = bands
+            .iter_mut()
+            .rev()
+            .find(|band| group.size >= band.min)
+            .expect("The first band starts at zero.");
+        band.groups += 1;
+        band.savings += group.savings();
+    }
+
+    bands
+}
+
+/// Duplicate groups with the same number of members
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CopyCount {
+    /// Number of members of each group
+    pub copies: usize,
+    /// Number of groups
+    pub groups: usize,
+    /// Bytes that could be freed by keeping only one member of each group
+    pub savings: u64,
+}
+
+/// Duplicated data in a top-level directory
+///
+/// A top-level directory is a child of a scanned directory (or the scanned directory itself for
+/// the groups it is a member of). Files directly in a scanned directory are top-level entries of
+/// their own.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DirectoryStatistics {
+    #[serde(serialize_with = "crate::path_serde::serialize")]
+    pub path: PathBuf,
+    /// Size of the directory (None if it could not be determined)
+    pub size: Option<u64>,
+    /// Bytes in the directory that could be freed by keeping only one member of each group
+    pub wasted: u64,
+    /// Fraction of the size of the directory that is wasted (None if the size is unknown or zero)
+    pub ratio: Option<f64>,
+}
+
+/// Aggregate analytics of duplicate groups
+///
+/// See [`Scanner::analytics`](crate::Scanner::analytics).
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Analytics {
+    /// Histogram of groups by their number of members (fewest members first)
+    pub copy_counts: Vec<CopyCount>,
+    /// Top-level directories with wasted bytes (most wasted bytes first)
+    pub directories: Vec<DirectoryStatistics>,
+}
+
+/// Get analytics of `duplicates` found in scanned directories `roots`
+///
+/// In each group, the [canonical](crate::DuplicateObject::canonical) member (or the first one)
+/// is kept and the other members are wasted in their top-level directories. Nothing is wasted in
+/// groups with [`shared_storage`](crate::DuplicateObject::shared_storage).
+///
+/// # Arguments
+/// * `duplicates` - duplicate groups returned by DuDe
+/// * `roots` - scanned directories
+/// * `sizes` - sizes of the scanned directories and of their children
+pub(crate) fn analytics(
+    duplicates: &[DuplicateObject],
+    roots: &[PathBuf],
+    sizes: &HashMap<PathBuf, Option<u64>>,
+) -> Analytics {
+    let mut copy_counts: BTreeMap<usize, CopyCount> = BTreeMap::new();
+    let mut wasted: HashMap<PathBuf, u64> = HashMap::new();
+
+    for group in duplicates {
+        let count = copy_counts
+            .entry(group.len())
+            .or_insert(CopyCount { copies: group.len(), ..Default::default() });
+        count.groups += 1;
+        count.savings += group.savings();
+
+        if group.savings() == 0 {
+            continue;
+        }
+        let kept = group.canonical.as_ref().or_else(|| group.duplicates.iter().next());
+        for path in group.duplicates.iter().filter(|x| Some(*x) != kept) {
+            *wasted.entry(top_level_entry(path, roots)).or_insert(0) += group.size;
+        }
+    }
+
+    let mut directories: Vec<_> = wasted
+        .into_iter()
+        .map(|(path, wasted)| {
+            let size = sizes.get(&path).copied().flatten();
+            let ratio = size.filter(|x| *x > 0).map(|x| wasted as f64 / x as f64);
+            DirectoryStatistics { path, size, wasted, ratio }
+        })
+        .collect();
+    directories.sort_by(|a, b| (Reverse(a.wasted), &a.path).cmp(&(Reverse(b.wasted), &b.path)));
+
+    Analytics { copy_counts: copy_counts.into_values().collect(), directories }
+}
+
+/// Share of the content of a directory that has copies outside of it
+///
+/// See [`Scanner::directory_duplication`](crate::Scanner::directory_duplication).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DirectoryDuplication {
+    #[serde(serialize_with = "crate::path_serde::serialize")]
+    pub path: PathBuf,
+    /// Bytes in the files in the directory and its subdirectories
+    pub size: u64,
+    /// Bytes in the files in the directory that have a copy outside of it
+    pub duplicated: u64,
+    /// Fraction of the bytes of the files that have a copy outside of the directory
+    pub ratio: f64,
+}
+
+/// Rank the directories under `roots` by the fraction of their bytes that have a copy elsewhere
+///
+/// A file counts as duplicated in each of its ancestors that does not contain all of its copies,
+/// so a directory can have a high ratio even if it is not a duplicate as a whole. Only
+/// directories with some duplicated bytes are returned, the highest ratio first (and the most
+/// duplicated bytes first among the same ratios).
+///
+/// # Arguments
+/// * `roots` - topmost nodes of the scanned tree
+pub(crate) fn directory_duplication(roots: &[TreeNode]) -> Vec<DirectoryDuplication> {
+    let mut ranking = vec![];
+    for root in roots {
+        add_directory_duplication(root, &mut ranking);
+    }
+    ranking.sort_by(|a, b| {
+        b.ratio
+            .total_cmp(&a.ratio)
+            .then(b.duplicated.cmp(&a.duplicated))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    ranking
+}
+
+/// Add the duplication of directory `node` and of its subdirectories to `ranking`
+///
+/// The subdirectories are walked with an explicit stack, so that deep trees do not overflow the
+/// stack. Each directory is finished after all of its subdirectories, with the size of the files in
+/// it and the size of each file with a copy outside of it together with the common ancestor of its
+/// copies.
+fn add_directory_duplication(node: &TreeNode, ranking: &mut Vec<DirectoryDuplication>) {
+    // Directories entered and not finished yet
+    let mut open: Vec<OpenDirectory> = vec![];
+    // `None` finishes the last directory entered
+    let mut stack = vec![Some(node.clone())];
+    while let Some(item) = stack.pop() {
+        let node = match item {
+            Some(node) => node,
+            None => {
+                let OpenDirectory { path, size, mut copied } =
+                    open.pop().expect("Directory was entered.");
+                // Files whose copies are all in this directory are not duplicated in its
+                // ancestors either
+                copied.retain(|(_, ancestor)| !ancestor.starts_with(&path));
+                let duplicated = copied.iter().map(|(file_size, _)| file_size).sum();
+                if duplicated > 0 {
+                    let ratio = duplicated as f64 / size as f64;
+                    ranking.push(DirectoryDuplication { path, size, duplicated, ratio });
+                }
+                if let Some(parent) = open.last_mut() {
+                    parent.size += size;
+                    parent.copied.extend(copied);
+                }
+                continue;
+            }
+        };
+
+        let mut size = 0;
+        let mut copied = vec![];
+        stack.push(None);
+        for child in node.children() {
+            match child.kind() {
+                NodeKind::File => {
+                    let file_size = child.size().unwrap_or(0);
+                    size += file_size;
+                    let copies: Vec<_> = child.duplicates().iter().map(|x| x.path()).collect();
+                    if let Some(ancestor) = common_ancestor(&copies) {
+                        copied.push((file_size, ancestor));
+                    }
+                }
+                NodeKind::Dir => stack.push(Some(child)),
+                NodeKind::Symlink | NodeKind::Inaccessible => {}
+            }
+        }
+        open.push(OpenDirectory { path: node.path(), size, copied });
+    }
+}
+
+/// Directory walked by [`add_directory_duplication`] whose subdirectories are not finished yet
+struct OpenDirectory {
+    path: PathBuf,
+    /// Size of the files found in the directory so far
+    size: u64,
+    /// Size of each file found so far with a copy outside of the directory together with the
+    /// common ancestor of its copies
+    copied: Vec<(u64, PathBuf)>,
+}
+
+/// Duplicate groups whose members have the same nearest common ancestor
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AncestorCluster {
+    /// Deepest directory containing all members of each of the groups
+    #[serde(serialize_with = "crate::path_serde::serialize")]
+    pub path: PathBuf,
+    /// Number of groups
+    pub groups: usize,
+    /// Bytes that could be freed by keeping only one member of each group
+    pub savings: u64,
+}
+
+/// Cluster duplicate groups by the nearest common ancestor of their members
+///
+/// Cleanups are often planned directory by directory, so the clusters show the directories in
+/// which the groups can be handled without looking elsewhere. The clusters with the most savings
+/// are returned first.
+///
+/// # Arguments
+/// * `duplicates` - duplicate groups returned by DuDe
+pub fn ancestor_clusters(duplicates: &[DuplicateObject]) -> Vec<AncestorCluster> {
+    let mut clusters: HashMap<PathBuf, AncestorCluster> = HashMap::new();
+    for group in duplicates {
+        let paths: Vec<_> = group.duplicates.iter().cloned().collect();
+        let path = match common_ancestor(&paths) {
+            Some(path) => path,
+            None => continue,
+        };
+        let cluster =
+            clusters.entry(path.clone()).or_insert(AncestorCluster { path, groups: 0, savings: 0 });
+        cluster.groups += 1;
+        cluster.savings += group.savings();
+    }
+
+    let mut clusters: Vec<_> = clusters.into_values().collect();
+    clusters.sort_by(|a, b| (Reverse(a.savings), &a.path).cmp(&(Reverse(b.savings), &b.path)));
+    clusters
+}
+
+/// Get the deepest path containing all of `paths` (None if `paths` is empty)
+fn common_ancestor(paths: &[PathBuf]) -> Option<PathBuf> {
+    let (first, rest) = paths.split_first()?;
+    let mut ancestor = first.clone();
+    for path in rest {
+        while !path.starts_with(&ancestor) {
+            if !ancestor.pop() {
+                break;
+            }
+        }
+    }
+    Some(ancestor)
+}
+
+/// Get the top-level entry containing `path`: the child of the (innermost) root that contains it
+///
+/// Returns the path itself if it is a root or if it is in none of the roots.
+fn top_level_entry(path: &Path, roots: &[PathBuf]) -> PathBuf {
+    let root = roots.iter().filter(|x| path.starts_with(x)).max_by_key(|x| x.components().count());
+    match root.and_then(|root| Some((root, path.strip_prefix(root).ok()?.components().next()?))) {
+        Some((root, child)) => root.join(child),
+        None => path.to_path_buf(),
+    }
+}
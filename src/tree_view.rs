@@ -0,0 +1,98 @@
+//! Read-only view of the scanned directory tree
+//!
+//! This module provides the TreeNode structure that allows browsing the directory tree scanned by
+//! [`Scanner`](crate::Scanner) together with the duplicates of each of its nodes. It is meant for
+//! frontends that want to show the duplicates in the context of the directory structure.
+use std::path::PathBuf;
+
+use id_tree::NodeId;
+
+use crate::checksum::Checksum;
+use crate::dir_tree::DirTree;
+
+/// Kind of a node in the scanned directory tree
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum NodeKind {
+    File,
+    Dir,
+    Symlink,
+    /// File or directory that could not be read
+    Inaccessible,
+}
+
+/// Immutable handle to a node of the scanned directory tree
+///
+/// The handle borrows the [`Scanner`](crate::Scanner) it was obtained from.
+#[derive(Clone)]
+pub struct TreeNode<'a> {
+    tree: &'a DirTree,
+    node_id: NodeId,
+}
+
+impl<'a> TreeNode<'a> {
+    pub(crate) fn new(tree: &'a DirTree, node_id: NodeId) -> Self {
+        TreeNode { tree, node_id }
+    }
+
+    /// Path of the file or directory
+    pub fn path(&self) -> PathBuf {
+        self.tree.get_node_path(&self.node_id).into()
+    }
+
+    /// Size of the file or directory (None if the size could not be determined)
+    pub fn size(&self) -> Option<u64> {
+        self.tree.get_node_size(&self.node_id)
+    }
+
+    /// Digest of the content of the file or directory
+    ///
+    /// For files this is the checksum of the whole file. For directories it combines the digests
+    /// of all children regardless of their names, so two directories have the same digest exactly
+    /// when they are duplicates. The digest is stable between scans with the same
+    /// [`HashAlgorithm`](crate::HashAlgorithm), so it can be used to identify a subtree.
+    ///
+    /// None for files that were not hashed as a whole (files with no possible duplicates), for
+    /// directories containing such files, and for empty directories, symlinks and inaccessible
+    /// nodes.
+    pub fn digest(&self) -> Option<Checksum> {
+        self.tree.get_node_digest(&self.node_id)
+    }
+
+    /// Kind of the node
+    pub fn kind(&self) -> NodeKind {
+        self.tree.get_node_kind(&self.node_id)
+    }
+
+    /// Nodes directly contained in this node (empty for anything but directories)
+    pub fn children(&self) -> Vec<TreeNode<'a>> {
+        self.tree
+            .get_children_ids(&self.node_id)
+            .into_iter()
+            .map(|id| TreeNode::new(self.tree, id))
+            .collect()
+    }
+
+    /// Nodes elsewhere in the tree that are duplicates of this node
+    pub fn duplicates(&self) -> Vec<TreeNode<'a>> {
+        self.tree
+            .get_node_duplicates(&self.node_id)
+            .into_iter()
+            .map(|id| TreeNode::new(self.tree, id))
+            .collect()
+    }
+
+    /// Returns true if there is at least one duplicate of this node
+    pub fn is_duplicated(&self) -> bool {
+        !self.tree.get_node_duplicates(&self.node_id).is_empty()
+    }
+}
+
+impl std::fmt::Debug for TreeNode<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TreeNode")
+            .field("path", &self.path())
+            .field("kind", &self.kind())
+            .field("size", &self.size())
+            .finish()
+    }
+}
@@ -0,0 +1,517 @@
+//! Location of the elementary streams in video files
+//!
+//! Remuxing a video to another container (e.g. from MP4 to Matroska) copies the encoded frames
+//! unchanged, but stores them between different headers and metadata. The functions of this
+//! module find the frames of the video and audio tracks of a file, so that the streams can be
+//! compared regardless of the container:
+//! * MP4 and QuickTime (`mp4`, `m4v`, `mov`) - the samples listed in the sample tables of the
+//!   tracks (fragmented files are not supported)
+//! * Matroska and WebM (`mkv`, `webm`) - the frames of the blocks in the clusters (tracks with
+//!   compressed or stripped frames are not supported)
+//!
+//! The frames of the video tracks are read first, then the frames of the audio tracks, each in
+//! the order of the tracks in the file. Subtitles and other tracks are ignored. The format is
+//! chosen by the extension of the file (ignoring its case).
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::content::read_at;
+
+/// Extensions (in lowercase) of the video files whose streams can be found
+const VIDEO_EXTENSIONS: [&str; 5] = ["mp4", "m4v", "mov", "mkv", "webm"];
+
+/// Maximum size of the headers (`moov` box or `Tracks` element) that are read into memory
+const MAX_HEADER_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Maximum number of bytes at the start of a Matroska block read to find its frames
+const BLOCK_HEADER_SIZE: u64 = 1024;
+
+const EBML_ID: u64 = 0x1A45_DFA3;
+const SEGMENT_ID: u64 = 0x1853_8067;
+const TRACKS_ID: u64 = 0x1654_AE6B;
+const TRACK_ENTRY_ID: u64 = 0xAE;
+const TRACK_NUMBER_ID: u64 = 0xD7;
+const TRACK_TYPE_ID: u64 = 0x83;
+const CONTENT_ENCODINGS_ID: u64 = 0x6D80;
+const CLUSTER_ID: u64 = 0x1F43_B675;
+const SIMPLE_BLOCK_ID: u64 = 0xA3;
+const BLOCK_GROUP_ID: u64 = 0xA0;
+const BLOCK_ID: u64 = 0xA1;
+
+/// Returns true if the streams of the file at `path` can be found (by its extension)
+pub(crate) fn is_video(path: &Path) -> bool {
+    match path.extension().and_then(|x| x.to_str()) {
+        Some(extension) => VIDEO_EXTENSIONS.iter().any(|x| x.eq_ignore_ascii_case(extension)),
+        None => false,
+    }
+}
+
+/// Get the ranges of bytes of the file at `path` holding the frames of its video and audio tracks
+///
+/// Returns None if the file is not a supported video file or if its structure is not recognised.
+///
+/// # Arguments
+/// * `path` - path to the file (only its extension is used)
+/// * `file` - the opened file
+pub(crate) fn video_ranges(path: &Path, file: &mut File) -> io::Result<Option<Vec<Range<u64>>>> {
+    let extension = match path.extension().and_then(|x| x.to_str()) {
+        Some(extension) => extension.to_ascii_lowercase(),
+        None => return Ok(None),
+    };
+    let len = file.metadata()?.len();
+    let tracks = match extension.as_str() {
+        "mp4" | "m4v" | "mov" => mp4_tracks(file, len)?,
+        "mkv" | "webm" => matroska_tracks(file, len)?,
+        _ => None,
+    };
+    Ok(tracks.and_then(|x| into_ranges(x, len)))
+}
+
+/// Kind of a track
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum TrackKind {
+    Video,
+    Audio,
+    Other,
+}
+
+/// Frames of a track in the order they are stored in
+#[derive(Debug)]
+struct Track {
+    kind: TrackKind,
+    ranges: Vec<Range<u64>>,
+}
+
+impl Track {
+    fn new(kind: TrackKind) -> Self {
+        Track { kind, ranges: vec![] }
+    }
+
+    /// Add the next frame (merged with the previous one if they are adjacent)
+    fn push(&mut self, frame: Range<u64>) {
+        if let Some(last) = self.ranges.last_mut() {
+            if last.end == frame.start {
+                last.end = frame.end;
+                return;
+            }
+        }
+        self.ranges.push(frame);
+    }
+}
+
+/// Join the frames of the video tracks and of the audio tracks
+///
+/// Returns None if there are no frames or if any of them is outside of the file.
+fn into_ranges(mut tracks: Vec<Track>, len: u64) -> Option<Vec<Range<u64>>> {
+    tracks.retain(|x| x.kind != TrackKind::Other);
+    tracks.sort_by_key(|x| x.kind);
+    let ranges: Vec<_> = tracks.into_iter().flat_map(|x| x.ranges).collect();
+    if ranges.is_empty() || ranges.iter().any(|x| x.start > x.end || x.end > len) {
+        None
+    } else {
+        Some(ranges)
+    }
+}
+
+/*************************/
+/*    MP4 (ISO BMFF)     */
+/*************************/
+
+/// Read the header of the MP4 box at `position`: its type, the length of the header and the size
+/// of the whole box
+///
+/// Returns None if the header is not valid or the box does not fit in the file.
+pub(crate) fn read_box_header(
+    file: &mut File,
+    position: u64,
+    len: u64,
+) -> io::Result<Option<([u8; 4], u64, u64)>> {
+    let mut header = [0u8; 16];
+    if !read_at(file, position, &mut header[..8])? {
+        return Ok(None);
+    }
+    let kind = [header[4], header[5], header[6], header[7]];
+    let (header_len, size) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]])
+    {
+        // The box extends to the end of the file
+        0 => (8, len - position),
+        // The size is stored as a 64-bit number after the type
+        1 => {
+            if !read_at(file, position + 8, &mut header[8..])? {
+                return Ok(None);
+            }
+            (16, be_u64(&header, 8).unwrap_or(0))
+        }
+        size => (8, u64::from(size)),
+    };
+    if size < header_len || position.checked_add(size).map_or(true, |end| end > len) {
+        return Ok(None);
+    }
+    Ok(Some((kind, header_len, size)))
+}
+
+/// Find the tracks of an MP4 file in its `moov` box
+fn mp4_tracks(file: &mut File, len: u64) -> io::Result<Option<Vec<Track>>> {
+    let mut moov = None;
+    let mut position = 0;
+    while position + 8 <= len {
+        let (kind, header_len, size) = match read_box_header(file, position, len)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        match &kind {
+            b"moov" if size - header_len <= MAX_HEADER_SIZE => {
+                let mut content = vec![0u8; (size - header_len) as usize];
+                if !read_at(file, position + header_len, &mut content)? {
+                    return Ok(None);
+                }
+                moov = Some(content);
+            }
+            // Samples of fragmented files are listed in the fragments
+            b"moov" | b"moof" => return Ok(None),
+            _ => {}
+        }
+        position += size;
+    }
+
+    let tracks = moov.and_then(|moov| {
+        child_boxes(&moov)?
+            .into_iter()
+            .filter(|(kind, _)| kind == b"trak")
+            .map(|(_, trak)| mp4_track(trak, len))
+            .collect()
+    });
+    Ok(tracks)
+}
+
+/// Get the kind and the samples of the track in `trak` box (None if the box is not valid)
+fn mp4_track(trak: &[u8], len: u64) -> Option<Track> {
+    let mdia = find_box(trak, b"mdia")?;
+    let kind = match find_box(mdia, b"hdlr")?.get(8..12)? {
+        b"vide" => TrackKind::Video,
+        b"soun" => TrackKind::Audio,
+        _ => return Some(Track::new(TrackKind::Other)),
+    };
+    let stbl = find_box(find_box(mdia, b"minf")?, b"stbl")?;
+
+    let sizes = sample_sizes(find_box(stbl, b"stsz")?, len)?;
+    let offsets: Vec<u64> = match find_box(stbl, b"stco") {
+        Some(stco) => table_entries(stco, 4)?.map(|x| u64::from(be_u32(x, 0).unwrap())).collect(),
+        None => {
+            table_entries(find_box(stbl, b"co64")?, 8)?.map(|x| be_u64(x, 0).unwrap()).collect()
+        }
+    };
+    // Number of samples in the chunks starting with each first chunk
+    let chunk_runs: Vec<(u32, u32)> = table_entries(find_box(stbl, b"stsc")?, 12)?
+        .map(|x| (be_u32(x, 0).unwrap(), be_u32(x, 4).unwrap()))
+        .collect();
+
+    let mut track = Track::new(kind);
+    let mut sizes = sizes.into_iter();
+    let mut run = 0;
+    for (index, offset) in offsets.into_iter().enumerate() {
+        let chunk = index as u64 + 1;
+        while run + 1 < chunk_runs.len() && u64::from(chunk_runs[run + 1].0) <= chunk {
+            run += 1;
+        }
+        let mut position = offset;
+        for _ in 0..chunk_runs.get(run)?.1 {
+            let end = position.checked_add(sizes.next()?)?;
+            track.push(position..end);
+            position = end;
+        }
+    }
+    Some(track)
+}
+
+/// Get the size of each sample from `stsz` box
+fn sample_sizes(stsz: &[u8], len: u64) -> Option<Vec<u64>> {
+    let size = u64::from(be_u32(stsz, 4)?);
+    let count = be_u32(stsz, 8)? as usize;
+    if size != 0 {
+        // All samples have the same size, they can't take more than the whole file
+        return if size.checked_mul(count as u64)? <= len { Some(vec![size; count]) } else { None };
+    }
+    let entries = stsz.get(12..12 + count.checked_mul(4)?)?;
+    Some(entries.chunks_exact(4).map(|x| u64::from(be_u32(x, 0).unwrap())).collect())
+}
+
+/// Get the entries of a table box with version, flags and number of entries followed by the
+/// entries of `entry_len` bytes
+fn table_entries(data: &[u8], entry_len: usize) -> Option<std::slice::ChunksExact<'_, u8>> {
+    let count = be_u32(data, 4)? as usize;
+    let entries = data.get(8..8 + count.checked_mul(entry_len)?)?;
+    Some(entries.chunks_exact(entry_len))
+}
+
+/// Split `data` into the types and the contents of the boxes it contains
+fn child_boxes(data: &[u8]) -> Option<Vec<([u8; 4], &[u8])>> {
+    let mut boxes = vec![];
+    let mut rest = data;
+    while rest.len() >= 8 {
+        let kind = [rest[4], rest[5], rest[6], rest[7]];
+        let (header_len, size) = match be_u32(rest, 0)? {
+            0 => (8, rest.len() as u64),
+            1 => (16, be_u64(rest, 8)?),
+            size => (8, u64::from(size)),
+        };
+        if size < header_len || size > rest.len() as u64 {
+            return None;
+        }
+        boxes.push((kind, &rest[header_len as usize..size as usize]));
+        rest = &rest[size as usize..];
+    }
+    Some(boxes)
+}
+
+/// Get the content of the first box of `kind` in `data`
+fn find_box<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    child_boxes(data)?.into_iter().find(|(x, _)| x == kind).map(|(_, content)| content)
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn be_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+/*************************/
+/*  Matroska and WebM    */
+/*************************/
+
+/// Find the tracks of a Matroska file and the frames of its blocks
+fn matroska_tracks(file: &mut File, len: u64) -> io::Result<Option<Vec<Track>>> {
+    let mut position = match read_element_header(file, 0)? {
+        Some((EBML_ID, header_len, Some(size))) => header_len + size,
+        _ => return Ok(None),
+    };
+    let segment_end = match read_element_header(file, position)? {
+        Some((SEGMENT_ID, header_len, size)) => {
+            position += header_len;
+            size.map_or(len, |x| position + x).min(len)
+        }
+        _ => return Ok(None),
+    };
+
+    let mut entries = None;
+    let mut frames: HashMap<u64, Track> = HashMap::new();
+    while position < segment_end {
+        let (id, header_len, size) = match read_element_header(file, position)? {
+            Some((id, header_len, Some(size))) => (id, header_len, size),
+            _ => return Ok(None),
+        };
+        let content = position + header_len;
+        let valid = match id {
+            TRACKS_ID if size <= MAX_HEADER_SIZE => {
+                let mut data = vec![0u8; size as usize];
+                entries =
+                    if read_at(file, content, &mut data)? { track_entries(&data) } else { None };
+                entries.is_some()
+            }
+            TRACKS_ID => false,
+            CLUSTER_ID => add_frames(file, content..content + size, &mut frames)?,
+            _ => true,
+        };
+        if !valid {
+            return Ok(None);
+        }
+        position = content + size;
+    }
+
+    let tracks = entries.map(|entries| {
+        entries
+            .into_iter()
+            .map(|(number, kind)| Track {
+                kind,
+                ranges: frames.remove(&number).map(|x| x.ranges).unwrap_or_default(),
+            })
+            .collect()
+    });
+    Ok(tracks)
+}
+
+/// Get the number and the kind of each track in the content of `Tracks` element
+///
+/// Returns None if the element is not valid or if the frames of a video or audio track are
+/// compressed (or have their headers stripped), so that they differ from the frames in other
+/// containers.
+fn track_entries(tracks: &[u8]) -> Option<Vec<(u64, TrackKind)>> {
+    let mut entries = vec![];
+    for (id, entry) in child_elements(tracks)? {
+        if id != TRACK_ENTRY_ID {
+            continue;
+        }
+        let mut number = None;
+        let mut kind = TrackKind::Other;
+        let mut encoded = false;
+        for (id, content) in child_elements(entry)? {
+            match id {
+                TRACK_NUMBER_ID => number = Some(read_uint(content)),
+                TRACK_TYPE_ID => {
+                    kind = match read_uint(content) {
+                        1 => TrackKind::Video,
+                        2 => TrackKind::Audio,
+                        _ => TrackKind::Other,
+                    }
+                }
+                CONTENT_ENCODINGS_ID => encoded = true,
+                _ => {}
+            }
+        }
+        if encoded && kind != TrackKind::Other {
+            return None;
+        }
+        entries.push((number?, kind));
+    }
+    Some(entries)
+}
+
+/// Add the frames of the blocks among the elements spanning `elements` (the content of a cluster
+/// or of a block group) to the tracks in `frames`
+///
+/// Returns false if the elements are not valid.
+fn add_frames(
+    file: &mut File,
+    elements: Range<u64>,
+    frames: &mut HashMap<u64, Track>,
+) -> io::Result<bool> {
+    let mut position = elements.start;
+    while position < elements.end {
+        let (id, header_len, size) = match read_element_header(file, position)? {
+            Some((id, header_len, Some(size))) => (id, header_len, size),
+            _ => return Ok(false),
+        };
+        let content = position + header_len..position + header_len + size;
+        let valid = match id {
+            SIMPLE_BLOCK_ID | BLOCK_ID => add_block_frames(file, content.clone(), frames)?,
+            BLOCK_GROUP_ID => add_frames(file, content.clone(), frames)?,
+            _ => true,
+        };
+        if !valid {
+            return Ok(false);
+        }
+        position = content.end;
+    }
+    Ok(true)
+}
+
+/// Add the frames of the block spanning `block` to its track in `frames`
+///
+/// The laced frames of a block are stored one after another following the lacing header, so they
+/// are added as a single range. Returns false if the block is not valid.
+fn add_block_frames(
+    file: &mut File,
+    block: Range<u64>,
+    frames: &mut HashMap<u64, Track>,
+) -> io::Result<bool> {
+    let mut header = [0u8; BLOCK_HEADER_SIZE as usize];
+    let header_len = (block.end - block.start).min(BLOCK_HEADER_SIZE) as usize;
+    if !read_at(file, block.start, &mut header[..header_len])? {
+        return Ok(false);
+    }
+    let start = match block_frames_start(&header[..header_len]) {
+        Some(start) if start <= block.end - block.start => block.start + start,
+        _ => return Ok(false),
+    };
+    let (track, _, _) = read_vint(&header).expect("The block header was already parsed.");
+    frames.entry(track).or_insert_with(|| Track::new(TrackKind::Other)).push(start..block.end);
+    Ok(true)
+}
+
+/// Get the offset of the first frame of a block with `header` at its start
+fn block_frames_start(header: &[u8]) -> Option<u64> {
+    // Track number, timecode and flags
+    let (_, _, track_len) = read_vint(header)?;
+    let flags = *header.get(track_len + 2)?;
+    let mut offset = track_len + 3;
+    match (flags >> 1) & 3 {
+        // No lacing
+        0 => {}
+        // Xiph lacing: number of frames - 1 and sizes of the frames except for the last one
+        1 => {
+            let laced = *header.get(offset)?;
+            offset += 1;
+            for _ in 0..laced {
+                while *header.get(offset)? == 255 {
+                    offset += 1;
+                }
+                offset += 1;
+            }
+        }
+        // Fixed-size lacing: number of frames - 1
+        2 => offset += 1,
+        // EBML lacing: number of frames - 1, size of the first frame and differences of the sizes
+        _ => {
+            let laced = *header.get(offset)?;
+            offset += 1;
+            for _ in 0..laced {
+                offset += read_vint(header.get(offset..)?)?.2;
+            }
+        }
+    }
+    Some(offset as u64)
+}
+
+/// Read the header of the EBML element at `position`: its ID, the length of the header and the
+/// size of its content (None if the size is unknown)
+fn read_element_header(
+    file: &mut File,
+    position: u64,
+) -> io::Result<Option<(u64, u64, Option<u64>)>> {
+    let mut buffer = [0u8; 12];
+    file.seek(SeekFrom::Start(position))?;
+    let mut count = 0;
+    while count < buffer.len() {
+        match file.read(&mut buffer[count..])? {
+            0 => break,
+            read => count += read,
+        }
+    }
+    Ok(parse_element_header(&buffer[..count]))
+}
+
+/// Parse the ID and the size of an EBML element at the start of `data`
+fn parse_element_header(data: &[u8]) -> Option<(u64, u64, Option<u64>)> {
+    let (_, id, id_len) = read_vint(data)?;
+    let (size, _, size_len) = read_vint(data.get(id_len..)?)?;
+    // The size with all bits set is unknown
+    let size = if size == (1 << (7 * size_len as u64)) - 1 { None } else { Some(size) };
+    Some((id, (id_len + size_len) as u64, size))
+}
+
+/// Read the EBML variable-size integer at the start of `data`
+///
+/// Returns the value of the integer, the value including the length marker (used for element
+/// IDs) and the length of the integer.
+fn read_vint(data: &[u8]) -> Option<(u64, u64, usize)> {
+    let len = data.first()?.leading_zeros() as usize + 1;
+    if len > 8 {
+        return None;
+    }
+    let raw = data.get(..len)?.iter().fold(0u64, |value, x| value << 8 | u64::from(*x));
+    let value = raw & ((1 << (7 * len as u64)) - 1);
+    Some((value, raw, len))
+}
+
+/// Read an EBML unsigned integer
+fn read_uint(data: &[u8]) -> u64 {
+    data.iter().fold(0, |value, x| value << 8 | u64::from(*x))
+}
+
+/// Split `data` into the IDs and the contents of the EBML elements it contains
+fn child_elements(data: &[u8]) -> Option<Vec<(u64, &[u8])>> {
+    let mut elements = vec![];
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (id, header_len, size) = parse_element_header(rest)?;
+        let end = (header_len as usize).checked_add(size? as usize)?;
+        elements.push((id, rest.get(header_len as usize..end)?));
+        rest = &rest[end..];
+    }
+    Some(elements)
+}
@@ -1,13 +1,18 @@
+use std::cell::RefCell;
 use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
 use std::fs::{DirBuilder, File};
 use std::io::{self, Write};
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 
 use tempdir::TempDir;
 
-use duplicate_destroyer;
-use duplicate_destroyer::DuplicateObject;
+use duplicate_destroyer::{
+    DuplicateObject, NodeKind, ProgressHandle, ProgressMultiline, ScanErrorKind,
+};
 
 fn write_file(path: &Path, contents: &str) -> io::Result<()> {
     let mut file = File::create(path).expect("Could not create a file.");
@@ -88,7 +93,7 @@ fn duplicate_dirs_test() -> io::Result<()> {
 /// where a.txt is duplicated and diff.txt is not.
 ///
 /// Check that we got a duplicate object with the files tempdir/A/a.txt and tempdir/B/a.txt .
-fn duplicate_files_test() -> io::Result<()>{
+fn duplicate_files_test() -> io::Result<()> {
     // Create a temporary directory
     let tmp_dir = TempDir::new("add_directories_success_test").expect("Failed creating temp dir.");
     let tmp_dir_str = tmp_dir.path().to_owned().into_os_string();
@@ -206,3 +211,2694 @@ fn contained_duplicate_test() -> io::Result<()> {
     tmp_dir.close()?;
     Ok(())
 }
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   └── a.txt
+/// └── B
+///     └── a.txt
+/// where a.txt is duplicated.
+///
+/// Check that adding tempdir together with its subdirectory tempdir/A (or with tempdir itself, or
+/// with a symlink to it) yields the same result as adding only tempdir.
+fn overlapping_roots_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("overlapping_roots_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B"] {
+        DirBuilder::new().create(tmp_dir_path.join(topdir))?;
+        write_file(&tmp_dir_path.join(topdir).join("a.txt"), "test_text_a")?;
+    }
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+
+    let expected_duplicate = DuplicateObject::new(
+        8204,
+        HashSet::from([
+            tmp_dir_path.join("A").into_os_string(),
+            tmp_dir_path.join("B").into_os_string(),
+        ]),
+    );
+
+    // Nested root
+    let paths =
+        vec![tmp_dir_path.join("A").into_os_string(), tmp_dir_path.to_owned().into_os_string()];
+    let duplicates = duplicate_destroyer::get_duplicates(paths, &options);
+    assert_eq!(Ok(vec![expected_duplicate.clone()]), duplicates);
+
+    // Equal roots
+    let paths =
+        vec![tmp_dir_path.to_owned().into_os_string(), tmp_dir_path.join("A/..").into_os_string()];
+    let duplicates = duplicate_destroyer::get_duplicates(paths, &options);
+    assert_eq!(Ok(vec![expected_duplicate.clone()]), duplicates);
+
+    // Root reached through a symlink
+    let alias_dir = TempDir::new("overlapping_roots_alias").expect("Failed creating temp dir.");
+    let alias = alias_dir.path().join("alias");
+    std::os::unix::fs::symlink(tmp_dir_path, &alias)?;
+    let paths = vec![tmp_dir_path.to_owned().into_os_string(), alias.into_os_string()];
+    let duplicates = duplicate_destroyer::get_duplicates(paths, &options);
+    assert_eq!(Ok(vec![expected_duplicate]), duplicates);
+    alias_dir.close()?;
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   ├── a.txt
+/// │   └── b
+/// │       └── alpha.txt
+/// ├── B
+/// │   ├── a.txt
+/// │   └── b
+/// │       └── alpha.txt
+/// └── unique.txt
+/// where a.txt and alpha.txt are duplicated.
+///
+/// Check that Scanner answers multiple queries about a single scan.
+fn scanner_queries_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("scanner_queries_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B"] {
+        DirBuilder::new().create(tmp_dir_path.join(topdir))?;
+        write_file(&tmp_dir_path.join(topdir).join("a.txt"), "test_text_a")?;
+        DirBuilder::new().create(tmp_dir_path.join(topdir).join("b"))?;
+        write_file(&tmp_dir_path.join(topdir).join("b/alpha.txt"), "test_text_alpha")?;
+    }
+    write_file(&tmp_dir_path.join("unique.txt"), "test_text_unique")?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+
+    let mut scanner = duplicate_destroyer::Scanner::new(paths, &options).unwrap();
+
+    // Topmost duplicates can be queried repeatedly
+    let topmost = scanner.topmost_duplicates(0);
+    assert_eq!(topmost.len(), 1);
+    assert_eq!(topmost, scanner.topmost_duplicates(0));
+    assert!(scanner.topmost_duplicates(100_000).is_empty());
+
+    // Groups A, B; A/a.txt, B/a.txt; A/b, B/b; A/b/alpha.txt, B/b/alpha.txt
+    assert_eq!(scanner.all_groups().len(), 4);
+
+    let group = scanner.duplicates_of(tmp_dir_path.join("A/b")).unwrap();
+    assert!(group.contains(tmp_dir_path.join("B/b")));
+    assert_eq!(scanner.duplicates_of(tmp_dir_path.join("unique.txt")), None);
+
+    assert_eq!(scanner.unique_files(), vec![tmp_dir_path.join("unique.txt").into_os_string()]);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// └── project
+///     ├── a.txt
+///     ├── src
+///     │   └── b.txt
+///     └── backup
+///         ├── c.txt
+///         └── project
+///             ├── a.txt
+///             └── src
+///                 └── b.txt
+/// where a.txt and b.txt are duplicated.
+///
+/// Check that the project and its copy in backup are reported as one nested group instead of the
+/// groups of their contents, also by repeated queries with different minimum sizes.
+fn nested_duplicates_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("nested_duplicates_test").expect("Failed creating temp dir.");
+    let project = tmp_dir.path().join("project");
+    let copy = project.join("backup/project");
+
+    // Create files and folders
+    for dir in [&project, &copy] {
+        DirBuilder::new().recursive(true).create(dir.join("src"))?;
+        write_file(&dir.join("a.txt"), "test_text_a")?;
+        write_file(&dir.join("src/b.txt"), "test_text_b")?;
+    }
+    write_file(&project.join("backup/c.txt"), "test_text_c")?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let paths = vec![tmp_dir.path().to_owned().into_os_string()];
+    let mut scanner = duplicate_destroyer::Scanner::new(paths, &options).unwrap();
+    let topmost = scanner.topmost_duplicates(0);
+
+    assert_eq!(topmost.len(), 1);
+    let group = &topmost[0];
+    assert!(group.nested);
+    assert!(group.contains(&project) && group.contains(&copy));
+    let copy_node = scanner.roots()[0].children()[0]
+        .children()
+        .into_iter()
+        .find(|x| x.path() == project.join("backup").into_os_string())
+        .unwrap()
+        .children()
+        .into_iter()
+        .find(|x| x.path() == copy.clone().into_os_string())
+        .unwrap();
+    assert_eq!(group.size, copy_node.size().unwrap());
+    assert_eq!(group.digest, copy_node.digest());
+
+    let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(group).unwrap())?;
+    assert_eq!(json["nested"], true);
+
+    // The nested group is left out if the copy is smaller than the minimum size
+    let larger = scanner.topmost_duplicates(group.size + 1);
+    assert!(larger.iter().all(|x| !x.nested));
+    assert_eq!(scanner.topmost_duplicates(0), topmost);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   ├── a.txt
+/// │   └── b.txt
+/// ├── B
+/// │   ├── a.txt
+/// │   └── b.txt
+/// └── C
+///     └── a.txt
+/// where a.txt and b.txt are duplicated.
+///
+/// Check that duplicate directories share a digest that is reported in their group and that the
+/// digest differs for a directory with different content.
+fn directory_digest_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("directory_digest_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B", "C"] {
+        DirBuilder::new().create(tmp_dir_path.join(topdir))?;
+        write_file(&tmp_dir_path.join(topdir).join("a.txt"), "test_text_a")?;
+    }
+    for topdir in ["A", "B"] {
+        write_file(&tmp_dir_path.join(topdir).join("b.txt"), "test_text_b")?;
+    }
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+    let mut scanner = duplicate_destroyer::Scanner::new(paths, &options).unwrap();
+    let topmost = scanner.topmost_duplicates(0);
+
+    let digest_of = |name: &str| {
+        scanner.roots()[0]
+            .children()
+            .into_iter()
+            .find(|x| x.path() == tmp_dir_path.join(name).into_os_string())
+            .and_then(|x| x.digest())
+    };
+    assert!(digest_of("A").is_some());
+    assert_eq!(digest_of("A"), digest_of("B"));
+    assert!(digest_of("C").is_some());
+    assert_ne!(digest_of("A"), digest_of("C"));
+    // The tempdir contains a unique combination of directories
+    assert!(scanner.roots()[0].digest().is_some());
+
+    let group = topmost.iter().find(|x| x.contains(tmp_dir_path.join("A"))).unwrap();
+    assert_eq!(group.digest, digest_of("A"));
+
+    // Files are identified by their checksums
+    let file_group = scanner.duplicates_of(tmp_dir_path.join("C/a.txt")).unwrap();
+    assert!(scanner.file_clusters().contains_key(file_group.digest.as_ref().unwrap()));
+
+    let json = serde_json::to_string(group).unwrap();
+    assert!(json.contains(&format!("\"digest\":\"{}\"", group.digest.as_ref().unwrap())));
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   └── a.txt
+/// └── B
+///     └── b.txt
+/// where no file is duplicated.
+///
+/// Check that unique files are hashed only with hash_all_files, so that all nodes have a digest.
+fn hash_all_files_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("hash_all_files_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for (topdir, name) in [("A", "a.txt"), ("B", "b.txt")] {
+        DirBuilder::new().create(tmp_dir_path.join(topdir))?;
+        write_file(&tmp_dir_path.join(topdir).join(name), name)?;
+    }
+
+    for hash_all_files in [false, true] {
+        let mut options: duplicate_destroyer::Config = Default::default();
+        options.set_hash_all_files(hash_all_files);
+        let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+        let scanner = duplicate_destroyer::Scanner::new(paths, &options).unwrap();
+
+        let root = &scanner.roots()[0];
+        let files: Vec<_> = root.children().iter().flat_map(|x| x.children()).collect();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|x| x.digest().is_some() == hash_all_files));
+        assert_eq!(root.digest().is_some(), hash_all_files);
+        assert!(scanner.all_groups().is_empty());
+    }
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Check that the tree of tests/fixtures can be browsed and that the duplicate status of nodes is
+/// reported correctly.
+fn tree_view_test() {
+    let options: duplicate_destroyer::Config = Default::default();
+    let scanner = duplicate_destroyer::Scanner::new(["tests/fixtures"], &options).unwrap();
+
+    let roots = scanner.roots();
+    assert_eq!(roots.len(), 1);
+    assert_eq!(roots[0].kind(), NodeKind::Dir);
+
+    let children = roots[0].children();
+    assert_eq!(children.len(), 3);
+
+    let node_a = children.iter().find(|x| x.path() == Path::new("tests/fixtures/A")).unwrap();
+    assert!(node_a.is_duplicated());
+    assert_eq!(node_a.duplicates()[0].path(), Path::new("tests/fixtures/B/A"));
+    assert_eq!(node_a.size(), Some(8235));
+
+    let node_c = children.iter().find(|x| x.path() == Path::new("tests/fixtures/C")).unwrap();
+    assert!(!node_c.is_duplicated());
+    let diff =
+        node_c.children().into_iter().find(|x| x.path() == Path::new("tests/fixtures/C/diff.txt"));
+    assert_eq!(diff.unwrap().kind(), NodeKind::File);
+}
+
+#[test]
+/// Check that all three duplicate files in tests/fixtures/A, tests/fixtures/B/A and
+/// tests/fixtures/C are reported as clusters of files with the same content.
+fn file_clusters_test() {
+    let options: duplicate_destroyer::Config = Default::default();
+    let clusters = duplicate_destroyer::get_file_clusters(["tests/fixtures"], &options).unwrap();
+
+    assert_eq!(clusters.len(), 3);
+    for paths in clusters.values() {
+        assert_eq!(paths.len(), 3);
+    }
+    let a_cluster = clusters
+        .values()
+        .find(|paths| paths.contains(&PathBuf::from("tests/fixtures/A/a.txt")))
+        .unwrap();
+    assert!(a_cluster.contains(&PathBuf::from("tests/fixtures/B/A/a.txt")));
+    assert!(a_cluster.contains(&PathBuf::from("tests/fixtures/C/a.txt")));
+}
+
+#[test]
+/// In tests/fixtures the directory A is duplicated twice, while its subdirectory b and file a.txt
+/// are present three times (in A, B/A and C).
+///
+/// Check that when at least three copies are required, the groups of b and a.txt are reported
+/// instead of the group of A.
+fn min_copies_test() {
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_min_copies(3);
+    let duplicates = duplicate_destroyer::get_duplicates(["tests/fixtures"], &options).unwrap();
+
+    assert_eq!(duplicates.len(), 2);
+    for group in duplicates.iter() {
+        assert_eq!(group.duplicates.len(), 3);
+    }
+    assert!(duplicates[0].contains("tests/fixtures/C/b"));
+    assert!(duplicates[1].contains("tests/fixtures/C/a.txt"));
+
+    options.set_min_copies(4);
+    let duplicates = duplicate_destroyer::get_duplicates(["tests/fixtures"], &options).unwrap();
+    assert!(duplicates.is_empty());
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── Downloads
+/// │   ├── a.jpg
+/// │   ├── a (1).jpg
+/// │   ├── album
+/// │   │   └── c.jpg
+/// │   ├── album (1)
+/// │   │   └── c.jpg
+/// │   └── b.jpg
+/// └── other
+///     └── b.jpg
+/// where the files with the same name (and a.jpg with a (1).jpg) are duplicated.
+///
+/// Check that with same_directory_only only the groups of a.jpg and of album are reported, since
+/// the copies of b.jpg are in different directories, and that with cross_directory_only only the
+/// group of b.jpg is reported (not the c.jpg files inside the sibling albums).
+fn directory_placement_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("directory_placement_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for dir in ["Downloads/album", "Downloads/album (1)", "other"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(dir))?;
+    }
+    write_file(&tmp_dir_path.join("Downloads/a.jpg"), "test_text_a")?;
+    write_file(&tmp_dir_path.join("Downloads/a (1).jpg"), "test_text_a")?;
+    write_file(&tmp_dir_path.join("Downloads/album/c.jpg"), "test_text_c")?;
+    write_file(&tmp_dir_path.join("Downloads/album (1)/c.jpg"), "test_text_c")?;
+    write_file(&tmp_dir_path.join("Downloads/b.jpg"), "test_text_b")?;
+    write_file(&tmp_dir_path.join("other/b.jpg"), "test_text_b")?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    assert_eq!(duplicates.len(), 3);
+
+    options.set_same_directory_only(true);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    let expected = [
+        duplicate_destroyer::DuplicateObject::new(
+            0,
+            [tmp_dir_path.join("Downloads/a.jpg"), tmp_dir_path.join("Downloads/a (1).jpg")],
+        ),
+        duplicate_destroyer::DuplicateObject::new(
+            0,
+            [tmp_dir_path.join("Downloads/album"), tmp_dir_path.join("Downloads/album (1)")],
+        ),
+    ];
+    assert_eq!(duplicates.len(), 2);
+    for group in expected.iter() {
+        assert!(duplicates.contains(group));
+    }
+
+    options.set_same_directory_only(false);
+    options.set_cross_directory_only(true);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    let expected = duplicate_destroyer::DuplicateObject::new(
+        0,
+        [tmp_dir_path.join("Downloads/b.jpg"), tmp_dir_path.join("other/b.jpg")],
+    );
+    assert_eq!(duplicates, vec![expected]);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   └── d
+/// │       └── ... (DEPTH levels of d)
+/// │           └── a.txt
+/// └── B (the same as A)
+///
+/// Check that the deep trees are walked, resolved and ranked by duplication without overflowing
+/// the stack of the test thread and that with a maximum depth the deepest directories are not
+/// read, so that A and B are not reported as duplicates.
+fn deep_tree_test() -> io::Result<()> {
+    const DEPTH: usize = 1500;
+
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("deep_tree_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    let deep: PathBuf = std::iter::repeat("d").take(DEPTH).collect();
+    for dir in ["A", "B"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(dir).join(&deep))?;
+        write_file(&tmp_dir_path.join(dir).join(&deep).join("a.txt"), "test_text_a")?;
+    }
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_max_depth(DEPTH + 1);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    let expected = duplicate_destroyer::DuplicateObject::new(
+        0,
+        [tmp_dir_path.join("A"), tmp_dir_path.join("B")],
+    );
+    assert_eq!(duplicates, vec![expected]);
+
+    // Each directory of both chains has a copy of a.txt outside of it
+    let scanner = duplicate_destroyer::Scanner::new([tmp_dir_path], &options).unwrap();
+    assert_eq!(scanner.directory_duplication().len(), 2 * (DEPTH + 1));
+
+    options.set_max_depth(DEPTH);
+    let mut scanner = duplicate_destroyer::Scanner::new([tmp_dir_path], &options).unwrap();
+    assert!(scanner.topmost_duplicates(0).is_empty());
+    let errors = scanner.scan_errors();
+    assert_eq!(errors.len(), 2);
+    assert!(errors.iter().all(|x| x.kind == duplicate_destroyer::ScanErrorKind::Inaccessible));
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   └── d
+/// │       └── a.txt
+/// └── B (the same as A)
+///
+/// Check that with a maximum depth of 1 the multiline progress is told about each of the skipped
+/// directories d, the same paths that are listed by the scan errors.
+fn skipped_paths_progress_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("skipped_paths_progress_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for dir in ["A", "B"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(dir).join("d"))?;
+        write_file(&tmp_dir_path.join(dir).join("d").join("a.txt"), "test_text_a")?;
+    }
+
+    let skipped = Arc::new(AtomicU64::new(0));
+    let progress = CountingMultiline {
+        processed: Default::default(),
+        finished: Default::default(),
+        bytes: Default::default(),
+        current_files: Default::default(),
+        skipped: skipped.clone(),
+    };
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_max_depth(1);
+    options.set_multiline_progress(Rc::new(RefCell::new(progress)));
+    let mut scanner = duplicate_destroyer::Scanner::new([tmp_dir_path], &options).unwrap();
+    scanner.topmost_duplicates(0);
+
+    assert_eq!(scanner.scan_errors().len(), 2);
+    assert_eq!(skipped.load(Ordering::SeqCst), 2);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Scan tests/fixtures/C and tests/fixtures/B together with the overlapping tests/fixtures/B/A.
+///
+/// Check that group members are annotated with the index of the input path they come from and
+/// that the per-root statistics count the duplicated a.txt and b dir as duplicated across roots.
+fn root_annotation_test() {
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let paths = vec![
+        OsString::from("tests/fixtures/C"),
+        OsString::from("tests/fixtures/B/A"),
+        OsString::from("tests/fixtures/B"),
+    ];
+    let duplicates = duplicate_destroyer::get_duplicates(paths, &options).unwrap();
+
+    assert_eq!(duplicates.len(), 2);
+    let b_group = &duplicates[0];
+    assert_eq!(b_group.roots[Path::new("tests/fixtures/C/b")], 0);
+    assert_eq!(b_group.roots[Path::new("tests/fixtures/B/A/b")], 2);
+
+    let stats = duplicate_destroyer::root_statistics(&duplicates);
+    let b_size = b_group.size;
+    let a_size = duplicates[1].size;
+    assert_eq!(stats[&0].across_roots, b_size + a_size);
+    assert_eq!(stats[&0].within_root, 0);
+    assert_eq!(stats[&2].across_roots, b_size + a_size);
+    assert!(!stats.contains_key(&1));
+}
+
+#[test]
+/// Check that the duplicate in the preferred path is marked as canonical in tests/fixtures.
+fn preferred_roots_test() {
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_preferred_roots(vec![PathBuf::from("tests/fixtures/B")]);
+    let duplicates = duplicate_destroyer::get_duplicates(["tests/fixtures"], &options).unwrap();
+    assert_eq!(duplicates[0].canonical, Some(PathBuf::from("tests/fixtures/B/A")));
+
+    options.set_preferred_roots(vec![PathBuf::from("tests/fixtures/C")]);
+    let duplicates = duplicate_destroyer::get_duplicates(["tests/fixtures"], &options).unwrap();
+    assert_eq!(duplicates[0].canonical, None);
+}
+
+#[cfg(feature = "async")]
+#[test]
+/// Check that the stream returned by get_duplicates_async yields the same groups as
+/// get_duplicates for tests/fixtures.
+fn async_stream_test() {
+    use futures_core::Stream;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake};
+    use std::thread::{self, Thread};
+
+    /// Waker unparking the test thread
+    struct ThreadWaker(Thread);
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let expected = duplicate_destroyer::get_duplicates(["tests/fixtures"], &options).unwrap();
+
+    let mut stream = duplicate_destroyer::get_duplicates_async(["tests/fixtures"], &options);
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut streamed = vec![];
+    loop {
+        match Pin::new(&mut stream).poll_next(&mut cx) {
+            Poll::Ready(Some(group)) => streamed.push(group),
+            Poll::Ready(None) => break,
+            Poll::Pending => thread::park(),
+        }
+    }
+
+    assert_eq!(expected, streamed);
+}
+
+#[test]
+/// Check that hashing the files in per-device queues finds the same duplicates in tests/fixtures as
+/// hashing them in one threadpool.
+fn per_device_queues_test() {
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_num_threads(2);
+    let expected = duplicate_destroyer::get_duplicates(["tests/fixtures"], &options).unwrap();
+
+    options.set_per_device_queues(true);
+    let duplicates = duplicate_destroyer::get_duplicates(["tests/fixtures"], &options).unwrap();
+    assert!(!duplicates.is_empty());
+    assert_eq!(duplicates, expected);
+}
+
+#[cfg(feature = "events")]
+#[test]
+/// Check that get_duplicates_with_events reports the progress of hashing and sends the same groups
+/// as get_duplicates for tests/fixtures.
+fn scan_events_test() {
+    use duplicate_destroyer::ScanEvent;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_num_threads(2);
+    let expected = duplicate_destroyer::get_duplicates(["tests/fixtures"], &options).unwrap();
+
+    let (events, handle) =
+        duplicate_destroyer::get_duplicates_with_events(["tests/fixtures"], &options);
+
+    // The channel is disconnected once the scan finishes
+    let mut total_files = None;
+    let mut processed = 0;
+    let mut groups = vec![];
+    for event in events.iter() {
+        match event {
+            ScanEvent::HashingStarted { total_files: total, .. } => total_files = Some(total),
+            ScanEvent::FilesProcessed(files) => processed += files,
+            ScanEvent::GroupFound(group) => groups.push(group),
+            _ => {}
+        }
+    }
+
+    assert_eq!(total_files, Some(processed));
+    assert_eq!(groups, expected);
+    assert_eq!(handle.join().unwrap().unwrap(), expected);
+}
+
+#[cfg(feature = "events")]
+#[test]
+/// Check that get_duplicates_with_events announces all phases of the search in order for
+/// tests/fixtures, and that the walking phase has the total of the files processed.
+fn phase_events_test() {
+    use duplicate_destroyer::{Phase, ScanEvent};
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_num_threads(2);
+
+    let (events, handle) =
+        duplicate_destroyer::get_duplicates_with_events(["tests/fixtures"], &options);
+
+    let mut phases = vec![];
+    let mut walking_total = None;
+    let mut total_files = None;
+    for event in events.iter() {
+        match event {
+            ScanEvent::PhaseChanged { phase, total } => {
+                if phase == Phase::Walking {
+                    walking_total = Some(total);
+                }
+                phases.push(phase);
+            }
+            ScanEvent::HashingStarted { total_files: total, .. } => total_files = Some(total),
+            _ => {}
+        }
+    }
+    handle.join().unwrap().unwrap();
+
+    let expected =
+        [Phase::Counting, Phase::Walking, Phase::Hashing, Phase::ResolvingDirs, Phase::Curating];
+    assert_eq!(phases, expected);
+    assert_eq!(phases.iter().map(|x| x.number()).collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+    assert_eq!(walking_total, total_files);
+    assert_eq!(Phase::Hashing.to_string(), "Phase 3/5: Calculating hashes");
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── a.txt
+/// ├── link -> a.txt
+/// └── socket
+/// where socket is a unix socket.
+///
+/// Check that the socket is reported as a skipped special file and the other items are counted.
+fn scan_report_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("scan_report_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files
+    write_file(&tmp_dir_path.join("a.txt"), "test_text_a")?;
+    std::os::unix::fs::symlink(tmp_dir_path.join("a.txt"), tmp_dir_path.join("link"))?;
+    let _socket = std::os::unix::net::UnixListener::bind(tmp_dir_path.join("socket"))?;
+
+    let options: duplicate_destroyer::Config = Default::default();
+    let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+    let scanner = duplicate_destroyer::Scanner::new(paths, &options).unwrap();
+
+    let errors = scanner.scan_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, tmp_dir_path.join("socket").into_os_string());
+    assert_eq!(errors[0].kind, ScanErrorKind::SpecialFile);
+
+    let stats = scanner.scan_stats();
+    assert_eq!((stats.files, stats.dirs, stats.symlinks, stats.errors), (1, 1, 1, 1));
+    assert_eq!(stats.total_size, "test_text_a\n".len() as u64);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── a.txt
+/// ├── b.txt
+/// └── c.txt
+/// where a.txt and b.txt are duplicated.
+///
+/// Check that the timings count the bytes of the duplicated files hashed, both when hashing in
+/// the main thread and in a threadpool.
+fn scan_timings_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("scan_timings_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files
+    write_file(&tmp_dir_path.join("a.txt"), "test_text_a")?;
+    write_file(&tmp_dir_path.join("b.txt"), "test_text_a")?;
+    write_file(&tmp_dir_path.join("c.txt"), "test_text_c")?;
+
+    for num_threads in [0, 2] {
+        let mut options: duplicate_destroyer::Config = Default::default();
+        options.set_num_threads(num_threads);
+        let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+        let mut scanner = duplicate_destroyer::Scanner::new(paths, &options).unwrap();
+        scanner.topmost_duplicates(0);
+
+        let timings = scanner.timings();
+        assert_eq!(timings.bytes_hashed, 2 * "test_text_a\n".len() as u64);
+    }
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+/// Multiline progress counting the files processed by its handles
+struct CountingMultiline {
+    processed: Arc<AtomicU64>,
+    finished: Arc<AtomicU64>,
+    bytes: Arc<AtomicU64>,
+    current_files: Arc<AtomicU64>,
+    skipped: Arc<AtomicU64>,
+}
+
+struct CountingHandle {
+    processed: Arc<AtomicU64>,
+    finished: Arc<AtomicU64>,
+    bytes: Arc<AtomicU64>,
+    current_files: Arc<AtomicU64>,
+}
+
+impl ProgressHandle for CountingHandle {
+    fn inc(&self, delta: u64) {
+        self.processed.fetch_add(delta, Ordering::SeqCst);
+    }
+    fn inc_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::SeqCst);
+    }
+    fn set_current_file(&self, _path: &std::ffi::OsStr) {
+        self.current_files.fetch_add(1, Ordering::SeqCst);
+    }
+    fn set_len(&self, _len: u64) {}
+    fn finish(&self) {
+        self.finished.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl ProgressMultiline for CountingMultiline {
+    fn create(&mut self, _message: String, _total_files: u64) -> Box<dyn ProgressHandle> {
+        Box::new(CountingHandle {
+            processed: self.processed.clone(),
+            finished: self.finished.clone(),
+            bytes: self.bytes.clone(),
+            current_files: self.current_files.clone(),
+        })
+    }
+    fn update_dir(&self, _new_dir: OsString) {}
+    fn skip_path(&self, _path: &OsStr) {
+        self.skipped.fetch_add(1, Ordering::SeqCst);
+    }
+    fn finalise(&self) {}
+    fn debug_string(&self) -> String {
+        "Counting progress".to_string()
+    }
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── a.txt
+/// ├── b.txt
+/// ├── c.txt
+/// └── d.txt
+/// where a.txt and b.txt are duplicated.
+///
+/// Check that the progress handle is advanced once for each file, told about each of the files
+/// hashed as a whole (a.txt and b.txt) and finished, both when hashing in the main thread and in a
+/// threadpool.
+fn progress_handle_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("progress_handle_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files
+    write_file(&tmp_dir_path.join("a.txt"), "test_text_a")?;
+    write_file(&tmp_dir_path.join("b.txt"), "test_text_a")?;
+    write_file(&tmp_dir_path.join("c.txt"), "test_text_c")?;
+    write_file(&tmp_dir_path.join("d.txt"), "test_text_d")?;
+
+    for num_threads in [0, 2] {
+        let processed = Arc::new(AtomicU64::new(0));
+        let finished = Arc::new(AtomicU64::new(0));
+        let current_files = Arc::new(AtomicU64::new(0));
+        let progress = CountingMultiline {
+            processed: processed.clone(),
+            finished: finished.clone(),
+            bytes: Default::default(),
+            current_files: current_files.clone(),
+            skipped: Default::default(),
+        };
+
+        let mut options: duplicate_destroyer::Config = Default::default();
+        options.set_num_threads(num_threads);
+        options.set_multiline_progress(Rc::new(RefCell::new(progress)));
+        let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+        duplicate_destroyer::get_duplicates(paths, &options).unwrap();
+
+        assert_eq!(processed.load(Ordering::SeqCst), 4);
+        assert_eq!(current_files.load(Ordering::SeqCst), 2);
+        assert_eq!(finished.load(Ordering::SeqCst), 1);
+    }
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+/// Multiline progress whose handles remove the read permission of `path` before it is hashed
+struct UnreadableMultiline {
+    path: PathBuf,
+}
+
+struct UnreadableHandle {
+    path: PathBuf,
+}
+
+impl ProgressHandle for UnreadableHandle {
+    fn inc(&self, _delta: u64) {}
+    fn set_current_file(&self, path: &std::ffi::OsStr) {
+        if Path::new(path) == self.path {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o000)).unwrap();
+        }
+    }
+    fn set_len(&self, _len: u64) {}
+    fn finish(&self) {}
+}
+
+impl ProgressMultiline for UnreadableMultiline {
+    fn create(&mut self, _message: String, _total_files: u64) -> Box<dyn ProgressHandle> {
+        Box::new(UnreadableHandle { path: self.path.clone() })
+    }
+    fn update_dir(&self, _new_dir: OsString) {}
+    fn finalise(&self) {}
+    fn debug_string(&self) -> String {
+        "Unreadable progress".to_string()
+    }
+}
+
+#[test]
+#[cfg(unix)]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   └── a.txt
+/// ├── B
+/// │   └── a.txt
+/// └── C
+///     └── a.txt
+/// where a.txt is duplicated and B/a.txt becomes unreadable after the directories are walked.
+///
+/// Check that the scan finishes, that B/a.txt is reported as inaccessible and that A and C are
+/// still duplicates, both when hashing in the main thread and in a threadpool.
+fn unreadable_while_hashing_test() -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Root can read the file regardless of its permissions
+    if unsafe { libc::geteuid() } == 0 {
+        return Ok(());
+    }
+
+    for num_threads in [0, 2] {
+        // Create a temporary directory
+        let tmp_dir =
+            TempDir::new("unreadable_while_hashing_test").expect("Failed creating temp dir.");
+        let tmp_dir_path = tmp_dir.path();
+
+        // Create files and folders
+        for topdir in ["A", "B", "C"] {
+            DirBuilder::new().recursive(true).create(tmp_dir_path.join(topdir))?;
+            write_file(&tmp_dir_path.join(topdir).join("a.txt"), "test_text_a")?;
+        }
+        let unreadable = tmp_dir_path.join("B").join("a.txt");
+
+        let mut options: duplicate_destroyer::Config = Default::default();
+        options.set_minimum_size(0);
+        options.set_num_threads(num_threads);
+        let progress = UnreadableMultiline { path: unreadable.clone() };
+        options.set_multiline_progress(Rc::new(RefCell::new(progress)));
+        let mut scanner = duplicate_destroyer::Scanner::new([tmp_dir_path], &options).unwrap();
+
+        let errors = scanner.scan_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, unreadable);
+        assert_eq!(errors[0].kind, ScanErrorKind::Inaccessible);
+        assert_eq!(scanner.scan_stats().errors, 1);
+
+        let duplicates = scanner.topmost_duplicates(0);
+        let expected = DuplicateObject::new(
+            4096 + 12,
+            HashSet::from([
+                tmp_dir_path.join("A").into_os_string(),
+                tmp_dir_path.join("C").into_os_string(),
+            ]),
+        );
+        assert_eq!(duplicates, vec![expected]);
+
+        std::fs::set_permissions(&unreadable, std::fs::Permissions::from_mode(0o644))?;
+        // Prevent removing of tmp_dir until all tests are done
+        tmp_dir.close()?;
+    }
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── a.txt
+/// ├── b.txt
+/// ├── c.txt
+/// └── d.txt
+/// where b.txt and c.txt are hardlinks of a.txt and d.txt is its copy.
+///
+/// Check that all four files are grouped together, but the content of the hardlinked inode is
+/// read only once for each checksum.
+fn hardlinks_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("hardlinks_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files (write_file appends a newline)
+    let content = "test_text_a";
+    write_file(&tmp_dir_path.join("a.txt"), content)?;
+    std::fs::hard_link(tmp_dir_path.join("a.txt"), tmp_dir_path.join("b.txt"))?;
+    std::fs::hard_link(tmp_dir_path.join("a.txt"), tmp_dir_path.join("c.txt"))?;
+    write_file(&tmp_dir_path.join("d.txt"), content)?;
+
+    for num_threads in [0, 2] {
+        let processed = Arc::new(AtomicU64::new(0));
+        let bytes = Arc::new(AtomicU64::new(0));
+        let progress = CountingMultiline {
+            processed: processed.clone(),
+            finished: Default::default(),
+            bytes: bytes.clone(),
+            current_files: Default::default(),
+            skipped: Default::default(),
+        };
+
+        let mut options: duplicate_destroyer::Config = Default::default();
+        options.set_num_threads(num_threads);
+        options.set_minimum_size(0);
+        options.set_multiline_progress(Rc::new(RefCell::new(progress)));
+        let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+        let duplicates = duplicate_destroyer::get_duplicates(paths, &options).unwrap();
+
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].duplicates.len(), 4);
+        assert_eq!(processed.load(Ordering::SeqCst), 4);
+        // Partial and full checksums of a.txt and d.txt
+        assert_eq!(bytes.load(Ordering::SeqCst), 4 * (content.len() as u64 + 1));
+    }
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── a.txt
+/// └── b.txt
+/// where a.txt and b.txt are duplicated.
+///
+/// Check that with an expired deadline no files are hashed, so no duplicates are confirmed and the
+/// scan is reported as truncated.
+fn deadline_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("deadline_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files
+    write_file(&tmp_dir_path.join("a.txt"), "test_text_a")?;
+    write_file(&tmp_dir_path.join("b.txt"), "test_text_a")?;
+
+    for num_threads in [0, 2] {
+        let mut options: duplicate_destroyer::Config = Default::default();
+        options.set_minimum_size(0);
+        options.set_num_threads(num_threads);
+        let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+
+        let mut scanner = duplicate_destroyer::Scanner::new(paths.clone(), &options).unwrap();
+        assert_eq!(scanner.topmost_duplicates(0).len(), 1);
+        assert!(!scanner.is_truncated());
+
+        options.set_deadline(std::time::Duration::ZERO);
+        let mut scanner = duplicate_destroyer::Scanner::new(paths, &options).unwrap();
+        assert!(scanner.topmost_duplicates(0).is_empty());
+        assert!(scanner.is_truncated());
+    }
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   └── b
+/// │       ├── alpha.txt
+/// │       └── beta.txt
+/// └── B
+///     └── b
+///         ├── alpha.txt
+///         └── beta.txt
+/// where alpha.txt and beta.txt are duplicated.
+///
+/// Check that the duplicates are found with only one file open at a time, also when the files
+/// are hashed in chunks in parallel.
+fn max_open_files_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("max_open_files_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(topdir).join("b"))?;
+        write_file(&tmp_dir_path.join(topdir).join("b/alpha.txt"), "test_text_alpha")?;
+        write_file(&tmp_dir_path.join(topdir).join("b/beta.txt"), "test_text_beta")?;
+    }
+
+    for (num_threads, chunk_size) in [(0, None), (3, None), (0, Some(4)), (3, Some(4))] {
+        let mut options: duplicate_destroyer::Config = Default::default();
+        options.set_minimum_size(0);
+        options.set_num_threads(num_threads);
+        options.set_max_open_files(1);
+        if let Some(chunk_size) = chunk_size {
+            options.set_parallel_hash_threshold(chunk_size);
+        }
+        let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+        let duplicates = duplicate_destroyer::get_duplicates(paths, &options).unwrap();
+
+        let expected = DuplicateObject::new(
+            2 * 4096 + 30,
+            HashSet::from([
+                tmp_dir_path.join("A").into_os_string(),
+                tmp_dir_path.join("B").into_os_string(),
+            ]),
+        );
+        assert_eq!(duplicates, vec![expected]);
+    }
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── d.txt
+/// ├── b.txt
+/// ├── e.txt
+/// ├── a.txt
+/// └── c.txt
+/// where all the files are duplicated.
+///
+/// Check that the members of the group are always in sorted order.
+fn member_order_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("member_order_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files
+    for name in ["d.txt", "b.txt", "e.txt", "a.txt", "c.txt"] {
+        write_file(&tmp_dir_path.join(name), "test_text")?;
+    }
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+    let duplicates = duplicate_destroyer::get_duplicates(paths, &options).unwrap();
+    assert_eq!(duplicates.len(), 1);
+
+    let expected: Vec<_> = ["a.txt", "b.txt", "c.txt", "d.txt", "e.txt"]
+        .iter()
+        .map(|name| tmp_dir_path.join(name).into_os_string())
+        .collect();
+    let members: Vec<_> = duplicates[0].duplicates.iter().cloned().collect();
+    assert_eq!(members, expected);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Check the convenience methods and the ordering of duplicate groups.
+fn duplicate_object_test() {
+    let group_a = DuplicateObject::new(100, [OsString::from("x/a"), OsString::from("y/a")]);
+    let group_b = DuplicateObject::new(
+        10,
+        [OsString::from("x/b"), OsString::from("y/b"), OsString::from("z/b")],
+    );
+
+    assert_eq!(group_a.len(), 2);
+    assert!(group_b.contains("z/b"));
+    assert!(group_b.contains(Path::new("x/b")));
+    assert!(!group_b.contains("x/a"));
+    assert_eq!(group_a.savings(), 100);
+    assert_eq!(group_b.savings(), 20);
+
+    // Equal groups are deduplicated regardless of their size
+    let same_as_a = DuplicateObject::new(0, [OsString::from("y/a"), OsString::from("x/a")]);
+    let groups: HashSet<_> = [group_a.clone(), group_b.clone(), same_as_a].into_iter().collect();
+    assert_eq!(groups.len(), 2);
+
+    let mut sorted = vec![group_b.clone(), group_a.clone()];
+    sorted.sort();
+    assert_eq!(sorted, vec![group_a, group_b]);
+
+    // Groups survive the round trip through json
+    let json = serde_json::to_string(&sorted).unwrap();
+    let loaded: Vec<DuplicateObject> = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded, sorted);
+    assert_eq!(loaded[1].size, 10);
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   ├── dir1
+/// │   │   └── a
+/// │   └── dir2
+/// │       ├── b
+/// │       └── c
+/// └── B
+///     └── dir3
+///         └── d
+/// where a, b, c and d are empty files, which makes A and B duplicates.
+///
+/// Check the expansion of the group to files.
+fn expand_group_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("expand_group_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for dir in ["A/dir1", "A/dir2", "B/dir3"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(dir))?;
+    }
+    for file in ["A/dir1/a", "A/dir2/b", "A/dir2/c", "B/dir3/d"] {
+        File::create(tmp_dir_path.join(file))?;
+    }
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+    let mut scanner = duplicate_destroyer::Scanner::new(paths, &options).unwrap();
+    let topmost = scanner.topmost_duplicates(0);
+    assert_eq!(topmost.len(), 1);
+
+    let expansion = scanner.expand_group(&topmost[0]).unwrap();
+    let expected_paths: Vec<_> = ["A/dir1/a", "A/dir2/b", "A/dir2/c", "B/dir3/d"]
+        .iter()
+        .map(|x| tmp_dir_path.join(x).into_os_string())
+        .collect();
+    assert_eq!(expansion.matches.len(), 1);
+    assert_eq!(expansion.matches[0].paths, expected_paths);
+    assert!(expansion.extras.is_empty());
+
+    // The expansion of the fixtures shows each pair of files
+    let paths = vec![OsString::from("tests/fixtures")];
+    let mut scanner = duplicate_destroyer::Scanner::new(paths, &options).unwrap();
+    let group = scanner.duplicates_of("tests/fixtures/A").unwrap();
+    let expansion = scanner.expand_group(&group).unwrap();
+    assert_eq!(expansion.matches.len(), 3);
+    assert_eq!(
+        expansion.matches[0].paths,
+        vec![OsString::from("tests/fixtures/A/a.txt"), OsString::from("tests/fixtures/B/A/a.txt")]
+    );
+    let checksum = duplicate_destroyer::checksum::hash_file(
+        "tests/fixtures/A/a.txt",
+        options.get_hash_algorithm(),
+    )?;
+    assert_eq!(expansion.matches[0].digest, Some(checksum));
+    assert!(expansion.extras.is_empty());
+    assert!(scanner.topmost_duplicates(0).iter().all(|x| scanner.expand_group(x).is_some()));
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Check that groups larger than the maximum size are replaced by their descendants and that the
+/// size bands add up.
+fn max_size_test() {
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_max_size(4096);
+    let paths = vec![OsString::from("tests/fixtures")];
+    let duplicates = duplicate_destroyer::get_duplicates(paths, &options).unwrap();
+
+    assert!(!duplicates.is_empty());
+    assert!(duplicates.iter().all(|x| x.size <= 4096));
+    assert!(duplicates.iter().any(|x| x.contains("tests/fixtures/A/a.txt")));
+
+    let bands = duplicate_destroyer::size_bands(&duplicates);
+    assert_eq!(bands.len(), 4);
+    assert_eq!(bands[0].groups, duplicates.len());
+    assert_eq!(bands[0].max, Some(1000));
+    assert_eq!(bands[3].max, None);
+    let savings: u64 = duplicates.iter().map(|x| x.savings()).sum();
+    assert_eq!(bands.iter().map(|x| x.savings).sum::<u64>(), savings);
+}
+
+#[test]
+/// Check that the metadata of group members is serialized and that the root indices and the device
+/// flag are restored when the groups are deserialized.
+fn member_metadata_test() {
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let paths = vec![OsString::from("tests/fixtures/A"), OsString::from("tests/fixtures/B")];
+    let duplicates = duplicate_destroyer::get_duplicates(paths, &options).unwrap();
+    let group = duplicates.iter().find(|x| x.contains("tests/fixtures/A")).unwrap();
+
+    let members = group.members();
+    assert_eq!(members.len(), 2);
+    assert_eq!(members[0].path, OsString::from("tests/fixtures/A"));
+    assert_eq!(members[0].root, Some(0));
+    assert_eq!(members[1].root, Some(1));
+    assert!(members.iter().all(|x| x.modified.is_some() && x.disk_size.is_some()));
+    assert_ne!(members[0].inode, members[1].inode);
+    assert_eq!(group.same_device, Some(true));
+
+    let json = serde_json::to_value(group).unwrap();
+    assert_eq!(json["duplicates"][1]["path"], "tests/fixtures/B/A");
+    assert_eq!(json["sameDevice"], true);
+    let loaded: DuplicateObject = serde_json::from_value(json).unwrap();
+    assert_eq!(loaded, *group);
+    assert_eq!(loaded.roots, group.roots);
+    assert_eq!(loaded.same_device, Some(true));
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   └── image
+/// └── B
+///     └── image
+/// where image is a sparse file of 1 MB with no data written.
+///
+/// Check that the images are reported as sparse and that they save no allocated space.
+fn sparse_files_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("sparse_files_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(topdir))?;
+        File::create(tmp_dir_path.join(topdir).join("image"))?.set_len(1_000_000)?;
+    }
+
+    let options: duplicate_destroyer::Config = Default::default();
+    let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+    let duplicates = duplicate_destroyer::get_duplicates(paths, &options).unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert!(duplicates[0].savings() >= 1_000_000);
+    assert!(duplicates[0].members().iter().all(|x| x.sparse == Some(true)));
+    assert!(duplicates[0].allocated_savings() < 1_000_000);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Check that each of the groups returned for tests/fixtures is passed to the group observer.
+fn group_observer_test() {
+    let found = Arc::new(std::sync::Mutex::new(vec![]));
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let observer_found = Arc::clone(&found);
+    options.on_group_found(move |group| observer_found.lock().unwrap().push(group.clone()));
+    let duplicates = duplicate_destroyer::get_duplicates(["tests/fixtures"], &options).unwrap();
+
+    let found = found.lock().unwrap();
+    assert!(!duplicates.is_empty());
+    assert!(duplicates.iter().all(|x| found.contains(x)));
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   ├── alpha.txt
+/// │   └── beta.txt
+/// └── B
+///     ├── gamma.txt
+///     └── delta.txt
+/// where alpha.txt and beta.txt are duplicates and so are gamma.txt and delta.txt.
+///
+/// Check that the group observer is not passed groups within one directory if only
+/// cross-directory groups are requested.
+fn group_observer_cross_directory_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir =
+        TempDir::new("group_observer_cross_directory_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    DirBuilder::new().recursive(true).create(tmp_dir_path.join("A"))?;
+    DirBuilder::new().recursive(true).create(tmp_dir_path.join("B"))?;
+    write_file(&tmp_dir_path.join("A/alpha.txt"), "test_text_alpha")?;
+    write_file(&tmp_dir_path.join("A/beta.txt"), "test_text_alpha")?;
+    write_file(&tmp_dir_path.join("B/gamma.txt"), "test_text_gamma")?;
+    write_file(&tmp_dir_path.join("B/delta.txt"), "test_text_gamma")?;
+
+    let found = Arc::new(std::sync::Mutex::new(vec![]));
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_cross_directory_only(true);
+    let observer_found = Arc::clone(&found);
+    options.on_group_found(move |group| observer_found.lock().unwrap().push(group.clone()));
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+
+    assert!(duplicates.is_empty());
+    assert!(found.lock().unwrap().is_empty());
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   ├── .cache
+/// │   │   └── alpha.txt
+/// │   └── .beta.txt
+/// └── B
+///     ├── .cache
+///     │   └── alpha.txt
+///     ├── .beta.txt
+///     └── gamma.txt
+/// where alpha.txt and .beta.txt are duplicated.
+///
+/// Check that no hidden files are scanned if they are skipped.
+fn skip_hidden_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("skip_hidden_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(topdir).join(".cache"))?;
+        write_file(&tmp_dir_path.join(topdir).join(".cache/alpha.txt"), "test_text_alpha")?;
+        write_file(&tmp_dir_path.join(topdir).join(".beta.txt"), "test_text_beta")?;
+    }
+    write_file(&tmp_dir_path.join("B/gamma.txt"), "test_text_gamma")?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+    assert!(!duplicate_destroyer::get_duplicates(paths.clone(), &options).unwrap().is_empty());
+
+    options.set_skip_hidden(true);
+    let mut scanner = duplicate_destroyer::Scanner::new(paths, &options).unwrap();
+    assert!(scanner.topmost_duplicates(0).is_empty());
+    assert_eq!(scanner.unique_files(), vec![tmp_dir_path.join("B/gamma.txt").into_os_string()]);
+    assert_eq!(scanner.scan_stats().files, 1);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   ├── alpha.txt
+/// │   ├── empty1
+/// │   └── empty2
+/// └── B
+///     └── alpha.txt
+/// where alpha.txt is duplicated and empty1 and empty2 are empty files.
+///
+/// Check that the empty files form a group unless they are skipped, in which case A and B are
+/// duplicates.
+fn skip_empty_files_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("skip_empty_files_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(topdir))?;
+        write_file(&tmp_dir_path.join(topdir).join("alpha.txt"), "test_text_alpha")?;
+    }
+    File::create(tmp_dir_path.join("A/empty1"))?;
+    File::create(tmp_dir_path.join("A/empty2"))?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+    let scanner = duplicate_destroyer::Scanner::new(paths.clone(), &options).unwrap();
+    let duplicates = scanner.all_groups();
+    assert!(duplicates.iter().any(|x| x.contains(tmp_dir_path.join("A/empty1"))));
+    assert!(!duplicates.iter().any(|x| x.contains(tmp_dir_path.join("A"))));
+
+    options.set_skip_empty_files(true);
+    let scanner = duplicate_destroyer::Scanner::new(paths, &options).unwrap();
+    let duplicates = scanner.all_groups();
+    assert!(duplicates.iter().any(|x| x.contains(tmp_dir_path.join("A"))));
+    assert!(!duplicates.iter().any(|x| x.contains(tmp_dir_path.join("A/empty1"))));
+    assert_eq!(scanner.scan_stats().files, 2);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   └── alpha.txt
+/// └── B
+///     └── alpha.txt
+/// where alpha.txt is duplicated.
+///
+/// Check that the files are skipped if they are owned by another user than the one set.
+fn owner_uid_test() -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("owner_uid_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(topdir))?;
+        write_file(&tmp_dir_path.join(topdir).join("alpha.txt"), "test_text_alpha")?;
+    }
+    let uid = tmp_dir_path.join("A/alpha.txt").metadata()?.uid();
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_owner_uid(uid);
+    let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+    let scanner = duplicate_destroyer::Scanner::new(paths.clone(), &options).unwrap();
+    assert!(scanner.all_groups().iter().any(|x| x.contains(tmp_dir_path.join("A"))));
+
+    options.set_owner_uid(uid.wrapping_add(1));
+    let scanner = duplicate_destroyer::Scanner::new(paths, &options).unwrap();
+    assert!(scanner.all_groups().is_empty());
+    assert_eq!(scanner.scan_stats().files, 0);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   ├── .git
+/// │   │   └── HEAD
+/// │   └── node_modules
+/// │       └── alpha.txt
+/// └── B
+///     ├── .git
+///     │   └── HEAD
+///     ├── node_modules
+///     │   └── alpha.txt
+///     └── Thumbs.db
+/// where HEAD and alpha.txt are duplicated.
+///
+/// Check that the files and directories in enabled presets are skipped.
+fn preset_test() -> io::Result<()> {
+    use duplicate_destroyer::Preset;
+
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("preset_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(topdir).join(".git"))?;
+        DirBuilder::new().create(tmp_dir_path.join(topdir).join("node_modules"))?;
+        write_file(&tmp_dir_path.join(topdir).join(".git/HEAD"), "test_text_head")?;
+        write_file(&tmp_dir_path.join(topdir).join("node_modules/alpha.txt"), "test_text_alpha")?;
+    }
+    write_file(&tmp_dir_path.join("B/Thumbs.db"), "test_text_thumbs")?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.enable_preset(Preset::Vcs);
+    options.enable_preset(Preset::OsMetadata);
+    let paths = vec![tmp_dir_path.to_owned().into_os_string()];
+    let mut scanner = duplicate_destroyer::Scanner::new(paths.clone(), &options).unwrap();
+    let duplicates = scanner.topmost_duplicates(0);
+    // Without .git and Thumbs.db the whole directories are duplicates
+    assert_eq!(duplicates.len(), 1);
+    assert!(duplicates[0].contains(tmp_dir_path.join("A")));
+    assert!(duplicates[0].contains(tmp_dir_path.join("B")));
+    assert!(scanner.unique_files().is_empty());
+
+    options.enable_preset(Preset::Developer);
+    assert!(duplicate_destroyer::get_duplicates(paths, &options).unwrap().is_empty());
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Check that the excluded files are not scanned: without tests/fixtures/C/diff.txt the directory
+/// C is a duplicate of A. Without the b directories as well, A, B/A and C contain only a.txt.
+fn exclude_test() {
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.add_exclude("diff.txt");
+    let duplicates = duplicate_destroyer::get_duplicates(["tests/fixtures"], &options).unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].len(), 3);
+    assert!(duplicates[0].contains("tests/fixtures/C"));
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.add_exclude("fixtures/*/b/");
+    options.add_exclude("fixtures/B/A/b");
+    options.add_exclude("C/diff.txt");
+    let duplicates = duplicate_destroyer::get_duplicates(["tests/fixtures"], &options).unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].len(), 3);
+    assert!(duplicates[0].contains("tests/fixtures/A"));
+    assert!(duplicates[0].contains("tests/fixtures/C"));
+}
+
+#[test]
+/// Check that scanning a listing of tests/fixtures finds the same duplicates as walking it and
+/// that the listed roots missing from the listing are reported.
+fn listing_test() {
+    use duplicate_destroyer::{ListedEntry, Scanner};
+
+    let mut entries = vec![];
+    for entry in walkdir::WalkDir::new("tests/fixtures").into_iter().map(|x| x.unwrap()) {
+        let kind = if entry.file_type().is_dir() { NodeKind::Dir } else { NodeKind::File };
+        let size = entry.metadata().unwrap().len();
+        entries.push(ListedEntry::new(entry.path().as_os_str(), size, kind));
+    }
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let expected = duplicate_destroyer::get_duplicates(["tests/fixtures"], &options).unwrap();
+
+    let roots = vec![OsString::from("tests/fixtures"), OsString::from("tests/not_listed")];
+    let mut scanner = Scanner::from_listing(roots, entries, &options).unwrap();
+    assert_eq!(scanner.topmost_duplicates(0), expected);
+    assert_eq!(scanner.scan_stats().errors, 1);
+    assert_eq!(scanner.scan_errors()[0].path, Path::new("tests/not_listed"));
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   └── alpha.txt
+/// ├── B
+/// │   └── alpha.txt
+/// └── journal
+/// where alpha.txt is duplicated and the journal was left behind by an interrupted scan.
+///
+/// Check that the checksum recorded for A/alpha.txt in the journal is used instead of calculating
+/// it (so the files are no longer duplicates) and that the journal is removed after the scan.
+fn journal_test() -> io::Result<()> {
+    use std::time::UNIX_EPOCH;
+
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("journal_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(topdir))?;
+        write_file(&tmp_dir_path.join(topdir).join("alpha.txt"), "test_text_alpha")?;
+    }
+    let paths: Vec<OsString> =
+        ["A", "B"].iter().map(|x| tmp_dir_path.join(x).into_os_string()).collect();
+    let journal_path = tmp_dir_path.join("journal");
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_journal(journal_path.clone());
+    let duplicates = duplicate_destroyer::get_duplicates(paths.clone(), &options).unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert!(!journal_path.exists());
+
+    // Record a different checksum for A/alpha.txt
+    let alpha = tmp_dir_path.join("A/alpha.txt");
+    let metadata = alpha.metadata()?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    let mut journal = File::create(&journal_path)?;
+    writeln!(journal, "{}\t{}\trecorded\t{}", metadata.len(), modified, alpha.display())?;
+    writeln!(journal, "incomplete line")?;
+    drop(journal);
+
+    let duplicates = duplicate_destroyer::get_duplicates(paths, &options).unwrap();
+    assert!(duplicates.is_empty());
+    assert!(!journal_path.exists());
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+/// Multiline progress whose handles append to the file at `path` once it was read for hashing
+struct ModifyingMultiline {
+    path: PathBuf,
+}
+
+struct ModifyingHandle {
+    path: PathBuf,
+    /// The file at `path` is being hashed
+    hashing: AtomicBool,
+}
+
+impl ProgressHandle for ModifyingHandle {
+    fn inc(&self, _delta: u64) {}
+    fn inc_bytes(&self, _bytes: u64) {
+        if self.hashing.swap(false, Ordering::Relaxed) {
+            let mut file = std::fs::OpenOptions::new().append(true).open(&self.path).unwrap();
+            file.write_all(b"appended").unwrap();
+        }
+    }
+    fn set_current_file(&self, path: &std::ffi::OsStr) {
+        self.hashing.store(Path::new(path) == self.path, Ordering::Relaxed);
+    }
+    fn set_len(&self, _len: u64) {}
+    fn finish(&self) {}
+}
+
+impl ProgressMultiline for ModifyingMultiline {
+    fn create(&mut self, _message: String, _total_files: u64) -> Box<dyn ProgressHandle> {
+        Box::new(ModifyingHandle { path: self.path.clone(), hashing: AtomicBool::new(false) })
+    }
+    fn update_dir(&self, _new_dir: OsString) {}
+    fn finalise(&self) {}
+    fn debug_string(&self) -> String {
+        "Modifying progress".to_string()
+    }
+}
+
+#[cfg(unix)]
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   └── alpha.txt
+/// ├── B
+/// │   └── alpha.txt
+/// ├── journal
+/// └── journal_link
+/// where alpha.txt is duplicated, A/alpha.txt is appended to while it is hashed and journal_link
+/// is a hardlink of the journal (so that it can be read after the scan removes the journal).
+///
+/// Check that the checksum of A/alpha.txt is recorded with the size read before it was hashed, so
+/// that the next scan does not reuse the checksum of the content before the change.
+fn journal_modified_while_hashing_test() -> io::Result<()> {
+    use std::fs::Permissions;
+    use std::os::unix::fs::PermissionsExt;
+
+    // Create a temporary directory
+    let tmp_dir =
+        TempDir::new("journal_modified_while_hashing_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(topdir))?;
+        write_file(&tmp_dir_path.join(topdir).join("alpha.txt"), "test_text_alpha")?;
+    }
+    let paths: Vec<OsString> =
+        ["A", "B"].iter().map(|x| tmp_dir_path.join(x).into_os_string()).collect();
+    let alpha = tmp_dir_path.join("A/alpha.txt");
+    let original_size = alpha.metadata()?.len();
+    let journal_path = tmp_dir_path.join("journal");
+    let journal_link = tmp_dir_path.join("journal_link");
+    File::create(&journal_path)?;
+    std::fs::set_permissions(&journal_path, Permissions::from_mode(0o600))?;
+    std::fs::hard_link(&journal_path, &journal_link)?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_journal(journal_path.clone());
+    let progress = ModifyingMultiline { path: alpha.clone() };
+    options.set_multiline_progress(Rc::new(RefCell::new(progress)));
+    duplicate_destroyer::get_duplicates(paths, &options).unwrap();
+    assert!(alpha.metadata()?.len() > original_size);
+
+    let journal = std::fs::read_to_string(&journal_link)?;
+    let line = journal.lines().find(|x| x.ends_with(alpha.to_str().unwrap())).unwrap();
+    assert_eq!(line.split('\t').next(), Some(original_size.to_string().as_str()));
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   └── alpha.txt
+/// ├── B
+/// │   └── alpha.txt
+/// ├── journal
+/// └── victim
+/// where alpha.txt is duplicated and the journal recording a different checksum for A/alpha.txt
+/// was planted by someone else.
+///
+/// Check that the journal is not used (so the files are still duplicates) and left untouched if
+/// it is writable by other users, if it is a symlink (and the file it leads to is not written to)
+/// and if it is locked by another scan.
+fn planted_journal_test() -> io::Result<()> {
+    use std::fs::Permissions;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    use std::os::unix::io::AsRawFd;
+    use std::time::UNIX_EPOCH;
+
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("planted_journal_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(topdir))?;
+        write_file(&tmp_dir_path.join(topdir).join("alpha.txt"), "test_text_alpha")?;
+    }
+    let paths: Vec<OsString> =
+        ["A", "B"].iter().map(|x| tmp_dir_path.join(x).into_os_string()).collect();
+    let journal_path = tmp_dir_path.join("journal");
+    let alpha = tmp_dir_path.join("A/alpha.txt");
+    let metadata = alpha.metadata()?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    let forged = format!("{}\t{}\tforged\t{}\n", metadata.len(), modified, alpha.display());
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_journal(journal_path.clone());
+
+    // Journal writable by other users
+    std::fs::write(&journal_path, &forged)?;
+    std::fs::set_permissions(&journal_path, Permissions::from_mode(0o666))?;
+    let duplicates = duplicate_destroyer::get_duplicates(paths.clone(), &options).unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(std::fs::read_to_string(&journal_path)?, forged);
+    std::fs::remove_file(&journal_path)?;
+
+    // Journal leading to another file
+    let victim = tmp_dir_path.join("victim");
+    std::fs::write(&victim, &forged)?;
+    std::fs::set_permissions(&victim, Permissions::from_mode(0o600))?;
+    symlink(&victim, &journal_path)?;
+    let duplicates = duplicate_destroyer::get_duplicates(paths.clone(), &options).unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(std::fs::read_to_string(&victim)?, forged);
+    assert!(journal_path.symlink_metadata()?.file_type().is_symlink());
+    std::fs::remove_file(&journal_path)?;
+
+    // Journal locked by another scan
+    std::fs::write(&journal_path, &forged)?;
+    std::fs::set_permissions(&journal_path, Permissions::from_mode(0o600))?;
+    let locked = File::open(&journal_path)?;
+    assert_eq!(unsafe { libc::flock(locked.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) }, 0);
+    let duplicates = duplicate_destroyer::get_duplicates(paths, &options).unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(std::fs::read_to_string(&journal_path)?, forged);
+    drop(locked);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── caf\xe9.txt
+/// └── copy\xe9.txt
+/// where the file names are not valid UTF-8 and the files are duplicated.
+///
+/// Check that the paths are serialized as bytes and that the group survives the round trip through
+/// json unchanged.
+fn non_utf8_path_test() -> io::Result<()> {
+    use std::os::unix::ffi::OsStringExt;
+
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("non_utf8_path_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    let name = OsString::from_vec(b"caf\xe9.txt".to_vec());
+    write_file(&tmp_dir_path.join(&name), "test_text_alpha")?;
+    write_file(
+        &tmp_dir_path.join(OsString::from_vec(b"copy\xe9.txt".to_vec())),
+        "test_text_alpha",
+    )?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    assert_eq!(duplicates.len(), 1);
+    let group = &duplicates[0];
+    assert!(group.contains(tmp_dir_path.join(&name)));
+
+    let json = serde_json::to_value(group).unwrap();
+    let path = &json["duplicates"][0]["path"];
+    assert!(path.is_array());
+    assert_eq!(path.as_array().unwrap().last(), Some(&serde_json::json!(b't')));
+
+    let loaded: DuplicateObject = serde_json::from_value(json).unwrap();
+    assert_eq!(&loaded, group);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   ├── photo.JPG
+/// │   └── notes.txt
+/// └── B
+///     ├── photo.JPG
+///     └── notes.txt
+/// where photo.JPG and notes.txt are duplicated.
+///
+/// Check that only the photos are searched for duplicates if the scan is restricted to images and
+/// that the notes are found as well with documents added.
+fn categories_test() -> io::Result<()> {
+    use duplicate_destroyer::Category;
+
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("categories_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(topdir))?;
+        write_file(&tmp_dir_path.join(topdir).join("photo.JPG"), "test_text_photo")?;
+        write_file(&tmp_dir_path.join(topdir).join("notes.txt"), "test_text_notes")?;
+    }
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.add_category(Category::Images);
+    let scanner = duplicate_destroyer::Scanner::new([tmp_dir_path], &options).unwrap();
+    assert_eq!(scanner.scan_stats().files, 2);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert!(duplicates[0].contains(tmp_dir_path.join("A")));
+
+    options.add_category(Category::Documents);
+    let scanner = duplicate_destroyer::Scanner::new([tmp_dir_path], &options).unwrap();
+    assert_eq!(scanner.scan_stats().files, 4);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── a.mp3
+/// ├── b.mp3
+/// ├── a.flac
+/// ├── b.flac
+/// ├── a.m4a
+/// └── b.m4a
+/// where the files of each format have the same audio stream but different tags.
+///
+/// Check that the files are not duplicates when compared as a whole and that each pair is a group
+/// when the tags are ignored.
+fn ignore_audio_tags_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("ignore_audio_tags_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // ID3v2 tag with `len` bytes of frames
+    let id3v2 =
+        |len: u8| [b"ID3".as_slice(), &[4, 0, 0, 0, 0, 0, len], &vec![b'x'; len as usize]].concat();
+    let mut id3v1 = b"TAG".to_vec();
+    id3v1.resize(128, b' ');
+    // FLAC marker with a STREAMINFO block and a Vorbis comment of `len` bytes
+    let flac = |len: u8| {
+        [b"fLaC".as_slice(), &[0, 0, 0, 2, 1, 2], &[0x84, 0, 0, len], &vec![b'c'; len as usize]]
+            .concat()
+    };
+    // MP4 box of `kind` with `content`
+    let mp4_box = |kind: &[u8], content: &[u8]| {
+        [&(content.len() as u32 + 8).to_be_bytes(), kind, content].concat()
+    };
+
+    let files: [(&str, Vec<u8>); 6] = [
+        ("a.mp3", [id3v2(10), b"mp3_frames".to_vec()].concat()),
+        ("b.mp3", [id3v2(30), b"mp3_frames".to_vec(), id3v1].concat()),
+        ("a.flac", [flac(5), b"flac_frames".to_vec()].concat()),
+        ("b.flac", [flac(50), b"flac_frames".to_vec()].concat()),
+        (
+            "a.m4a",
+            [mp4_box(b"ftyp", b"M4A "), mp4_box(b"moov", b"one"), mp4_box(b"mdat", b"samples")]
+                .concat(),
+        ),
+        (
+            "b.m4a",
+            [mp4_box(b"ftyp", b"M4A "), mp4_box(b"mdat", b"samples"), mp4_box(b"moov", b"two")]
+                .concat(),
+        ),
+    ];
+    for (name, content) in files.iter() {
+        File::create(tmp_dir_path.join(name))?.write_all(content)?;
+    }
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    assert!(duplicates.is_empty());
+
+    options.set_ignore_audio_tags(true);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    assert_eq!(duplicates.len(), 3);
+    for extension in ["mp3", "flac", "m4a"] {
+        let group = duplicates
+            .iter()
+            .find(|x| x.contains(tmp_dir_path.join(format!("a.{extension}"))))
+            .expect("Audio files with different tags are not duplicates.");
+        assert!(group.contains(tmp_dir_path.join(format!("b.{extension}"))));
+    }
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── movie.mp4
+/// ├── movie.mkv
+/// ├── tagged.mp4
+/// └── other.mkv
+/// where movie.mkv is a remux of movie.mp4, tagged.mp4 is movie.mp4 with a title and other.mkv
+/// has a different video stream.
+///
+/// Check that the files are not duplicates when compared as a whole and that the three videos
+/// with the same streams are a group in a different container when compared by their streams.
+fn video_streams_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("video_streams_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    let video = [b"video_frame_1".to_vec(), b"video_frame_2".to_vec()];
+    let audio = [b"audio_1".to_vec(), b"audio_2".to_vec()];
+
+    // MP4 box of `kind` with `content`
+    let mp4_box = |kind: &[u8], content: &[u8]| {
+        [&(content.len() as u32 + 8).to_be_bytes(), kind, content].concat()
+    };
+    // Track of `handler` type with all `samples` in one chunk at `offset`
+    let trak = |handler: &[u8], samples: &[Vec<u8>], offset: u32| {
+        let count = (samples.len() as u32).to_be_bytes();
+        let sizes: Vec<u8> = samples.iter().flat_map(|x| (x.len() as u32).to_be_bytes()).collect();
+        let stbl = [
+            mp4_box(b"stsz", &[&[0; 8], &count[..], &sizes].concat()),
+            mp4_box(
+                b"stsc",
+                &[&[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1], &count[..], &[0, 0, 0, 1]].concat(),
+            ),
+            mp4_box(b"stco", &[&[0, 0, 0, 0, 0, 0, 0, 1], &offset.to_be_bytes()[..]].concat()),
+        ]
+        .concat();
+        let hdlr = mp4_box(b"hdlr", &[&[0; 8], handler, &[0; 12]].concat());
+        let minf = mp4_box(b"minf", &mp4_box(b"stbl", &stbl));
+        mp4_box(b"trak", &mp4_box(b"mdia", &[hdlr, minf].concat()))
+    };
+    let mp4 = |metadata: &[u8]| {
+        let ftyp = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        let video_offset = ftyp.len() as u32 + 8;
+        let audio_offset = video_offset + video.concat().len() as u32;
+        let mdat = mp4_box(b"mdat", &[video.concat(), audio.concat()].concat());
+        let moov = [
+            trak(b"vide", &video, video_offset),
+            trak(b"soun", &audio, audio_offset),
+            mp4_box(b"udta", metadata),
+        ]
+        .concat();
+        [ftyp, mdat, mp4_box(b"moov", &moov)].concat()
+    };
+
+    // EBML element with `id` and `content` (with 8-byte size)
+    let element = |id: &[u8], content: &[u8]| {
+        [id, &[1], &(content.len() as u64).to_be_bytes()[1..], content].concat()
+    };
+    let track_entry = |number: u8, kind: u8| {
+        element(&[0xAE], &[element(&[0xD7], &[number]), element(&[0x83], &[kind])].concat())
+    };
+    let mkv = |video: &[Vec<u8>]| {
+        let tracks =
+            element(&[0x16, 0x54, 0xAE, 0x6B], &[track_entry(1, 1), track_entry(2, 2)].concat());
+        let cluster = [
+            element(&[0xE7], &[0]),
+            element(&[0xA3], &[&[0x81, 0, 0, 0x80], &video[0][..]].concat()),
+            element(&[0xA0], &element(&[0xA1], &[&[0x81, 0, 1, 0], &video[1][..]].concat())),
+            // Both audio frames in a block with Xiph lacing
+            element(
+                &[0xA3],
+                &[&[0x82, 0, 0, 0x82, 1, audio[0].len() as u8], &audio.concat()[..]].concat(),
+            ),
+        ]
+        .concat();
+        let segment = [tracks, element(&[0x1F, 0x43, 0xB6, 0x75], &cluster)].concat();
+        [
+            element(&[0x1A, 0x45, 0xDF, 0xA3], &element(&[0x42, 0x86], &[1])),
+            element(&[0x18, 0x53, 0x80, 0x67], &segment),
+        ]
+        .concat()
+    };
+
+    let files = [
+        ("movie.mp4", mp4(b"")),
+        ("movie.mkv", mkv(&video)),
+        ("tagged.mp4", mp4(b"title")),
+        ("other.mkv", mkv(&[b"video_frame_3".to_vec(), b"video_frame_4".to_vec()])),
+    ];
+    for (name, content) in files.iter() {
+        File::create(tmp_dir_path.join(name))?.write_all(content)?;
+    }
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    assert!(duplicates.is_empty());
+
+    options.set_video_streams(true);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    assert_eq!(duplicates.len(), 1);
+    let group = &duplicates[0];
+    assert_eq!(group.len(), 3);
+    assert!(!group.contains(tmp_dir_path.join("other.mkv")));
+    assert!(group.different_container);
+
+    let json = serde_json::to_value(group).unwrap();
+    assert_eq!(json["differentContainer"], true);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── a.m4a
+/// ├── b.m4a
+/// ├── a.mp4
+/// └── b.mp4
+/// where each file has an `ftyp` box followed by a box whose 64-bit size overflows when added to
+/// its position.
+///
+/// Check that the scan with ignored audio tags and with video streams finishes and that the
+/// files of each format, which can't be parsed, are compared as a whole.
+fn mp4_box_size_overflow_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("mp4_box_size_overflow_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    let ftyp = |brand: &[u8]| [&16u32.to_be_bytes()[..], b"ftyp", brand, &[0; 4]].concat();
+    let free = [&1u32.to_be_bytes()[..], b"free", &(u64::MAX - 15).to_be_bytes()].concat();
+    for extension in ["m4a", "mp4"] {
+        let content = [ftyp(b"M4A "), free.clone(), extension.as_bytes().to_vec()].concat();
+        for name in ["a", "b"] {
+            File::create(tmp_dir_path.join(format!("{name}.{extension}")))?.write_all(&content)?;
+        }
+    }
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_ignore_audio_tags(true);
+    options.set_video_streams(true);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    assert_eq!(duplicates.len(), 2);
+    for extension in ["m4a", "mp4"] {
+        let group = duplicates
+            .iter()
+            .find(|x| x.contains(tmp_dir_path.join(format!("a.{extension}"))))
+            .expect("Files with invalid boxes are not duplicates.");
+        assert!(group.contains(tmp_dir_path.join(format!("b.{extension}"))));
+    }
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── file.txt
+/// └── .zfs
+///     └── snapshot
+///         └── daily
+///             └── file.txt
+/// where daily is a ZFS snapshot of tempdir.
+///
+/// Check that the group of tempdir and its snapshot is marked as sharing its storage without any
+/// savings and that the snapshot is not searched if snapshots are skipped.
+fn snapshots_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("snapshots_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    let snapshot = tmp_dir_path.join(".zfs").join("snapshot").join("daily");
+    DirBuilder::new().recursive(true).create(&snapshot)?;
+    write_file(&tmp_dir_path.join("file.txt"), "test_text_alpha")?;
+    write_file(&snapshot.join("file.txt"), "test_text_alpha")?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert!(duplicates[0].contains(&snapshot));
+    assert!(duplicates[0].shared_storage);
+    assert_eq!(duplicates[0].savings(), 0);
+
+    options.set_skip_snapshots(true);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    assert!(duplicates.is_empty());
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── a.img
+/// ├── b.img
+/// ├── c.img
+/// ├── a.mp3
+/// └── b.mp3
+/// where a.img and b.img are the same, c.img differs from them in its last byte and the mp3 files
+/// have the same audio stream but different tags.
+///
+/// Check that files hashed in chunks in parallel are grouped the same way as files hashed as a
+/// whole (also when only a part of their content is selected), but get a different digest.
+fn parallel_hash_threshold_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("parallel_hash_threshold_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    let image = "disk image ".repeat(20);
+    write_file(&tmp_dir_path.join("a.img"), &image)?;
+    write_file(&tmp_dir_path.join("b.img"), &image)?;
+    write_file(&tmp_dir_path.join("c.img"), &format!("{}!", &image[..image.len() - 1]))?;
+    let frames = b"mp3_frames".repeat(10);
+    let id3v2 =
+        |len: u8| [b"ID3".as_slice(), &[4, 0, 0, 0, 0, 0, len], &vec![b'x'; len as usize]].concat();
+    File::create(tmp_dir_path.join("a.mp3"))?.write_all(&[id3v2(10), frames.clone()].concat())?;
+    File::create(tmp_dir_path.join("b.mp3"))?.write_all(&[id3v2(30), frames].concat())?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_ignore_audio_tags(true);
+    let whole = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+
+    options.set_parallel_hash_threshold(16);
+    let chunked = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    for duplicates in [&whole, &chunked] {
+        assert_eq!(duplicates.len(), 2);
+        let images = duplicates
+            .iter()
+            .find(|x| x.contains(tmp_dir_path.join("a.img")))
+            .expect("Same images are not duplicates.");
+        assert_eq!(images.len(), 2);
+        assert!(images.contains(tmp_dir_path.join("b.img")));
+        let songs = duplicates
+            .iter()
+            .find(|x| x.contains(tmp_dir_path.join("a.mp3")))
+            .expect("Songs with the same audio stream are not duplicates.");
+        assert!(songs.contains(tmp_dir_path.join("b.mp3")));
+    }
+
+    let digest = |duplicates: &[duplicate_destroyer::DuplicateObject]| {
+        duplicates.iter().find(|x| x.contains(tmp_dir_path.join("a.img"))).unwrap().digest.clone()
+    };
+    assert!(digest(&chunked).is_some());
+    assert_ne!(digest(&whole), digest(&chunked));
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── a.bin
+/// ├── b.bin
+/// ├── c.bin
+/// └── journal
+/// where a.bin and b.bin are the same, c.bin starts with the same bytes but is longer, and the
+/// journal records the same checksum for all of them.
+///
+/// Check that the files are not grouped because the checksum contradicts their sizes, and that
+/// each of them is reported as a collision.
+fn collision_detected_test() -> io::Result<()> {
+    use std::time::UNIX_EPOCH;
+
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("collision_detected_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    let content = "x".repeat(2000);
+    write_file(&tmp_dir_path.join("a.bin"), &content)?;
+    write_file(&tmp_dir_path.join("b.bin"), &content)?;
+    write_file(&tmp_dir_path.join("c.bin"), &format!("{}tail", content))?;
+
+    // Record the same checksum for all files
+    let journal_path = tmp_dir_path.join("journal");
+    let mut journal = File::create(&journal_path)?;
+    for name in ["a.bin", "b.bin", "c.bin"] {
+        let path = tmp_dir_path.join(name);
+        let metadata = path.metadata()?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        writeln!(journal, "{}\t{}\tcollided\t{}", metadata.len(), modified, path.display())?;
+    }
+    drop(journal);
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_journal(journal_path);
+    let mut scanner = duplicate_destroyer::Scanner::new([tmp_dir_path], &options).unwrap();
+    assert!(scanner.topmost_duplicates(0).is_empty());
+    assert!(scanner.file_clusters().is_empty());
+
+    let collisions: HashSet<_> = scanner
+        .scan_errors()
+        .into_iter()
+        .filter(|x| x.kind == ScanErrorKind::CollisionDetected)
+        .map(|x| x.path)
+        .collect();
+    let expected: HashSet<_> =
+        ["a.bin", "b.bin", "c.bin"].iter().map(|x| tmp_dir_path.join(x)).collect();
+    assert_eq!(collisions, expected);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   ├── alpha.txt
+/// │   └── beta.txt
+/// ├── B
+/// │   └── alpha.txt
+/// └── C
+///     ├── gamma.txt
+///     └── gamma_copy.txt
+/// where alpha.txt is duplicated in A and B and gamma.txt in C.
+///
+/// Check that the analytics count both groups as groups of two copies and that the copies of
+/// B/alpha.txt and C/gamma_copy.txt are wasted in their top-level directories (C first, since its
+/// waste is larger).
+fn analytics_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("analytics_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B", "C"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(topdir))?;
+    }
+    let alpha = "alpha".repeat(10);
+    let gamma = "gamma".repeat(100);
+    write_file(&tmp_dir_path.join("A/alpha.txt"), &alpha)?;
+    write_file(&tmp_dir_path.join("A/beta.txt"), "test_text_beta")?;
+    write_file(&tmp_dir_path.join("B/alpha.txt"), &alpha)?;
+    write_file(&tmp_dir_path.join("C/gamma.txt"), &gamma)?;
+    write_file(&tmp_dir_path.join("C/gamma_copy.txt"), &gamma)?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let mut scanner = duplicate_destroyer::Scanner::new([tmp_dir_path], &options).unwrap();
+    let duplicates = scanner.topmost_duplicates(0);
+    let analytics = scanner.analytics(&duplicates);
+
+    assert_eq!(analytics.copy_counts.len(), 1);
+    assert_eq!(analytics.copy_counts[0].copies, 2);
+    assert_eq!(analytics.copy_counts[0].groups, 2);
+    assert_eq!(analytics.copy_counts[0].savings, 51 + 501);
+
+    let directories: Vec<_> =
+        analytics.directories.iter().map(|x| (x.path.clone(), x.wasted)).collect();
+    assert_eq!(directories, vec![(tmp_dir_path.join("C"), 501), (tmp_dir_path.join("B"), 51)]);
+    for directory in analytics.directories.iter() {
+        let size = directory.size.expect("Size of a top-level directory is unknown.");
+        assert!(size >= directory.wasted);
+        assert_eq!(directory.ratio, Some(directory.wasted as f64 / size as f64));
+    }
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── a.txt
+/// └── b.txt
+/// where a.txt is duplicated.
+///
+/// Check that the public checksum functions calculate the same checksums as the scan: the whole
+/// file checksum is the digest of the group, and the prefix checksum covering the whole file is the
+/// whole file checksum.
+fn checksum_api_test() -> io::Result<()> {
+    use duplicate_destroyer::checksum::{hash_file, hash_prefix, PREFIX_LENGTH};
+    use duplicate_destroyer::HashAlgorithm;
+
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("checksum_api_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    write_file(&tmp_dir_path.join("a.txt"), &"test_text_alpha".repeat(100))?;
+    write_file(&tmp_dir_path.join("b.txt"), &"test_text_alpha".repeat(100))?;
+
+    let algorithms = [
+        HashAlgorithm::Blake2,
+        HashAlgorithm::SHA3_256,
+        HashAlgorithm::SHA3_512,
+        HashAlgorithm::SHA2_256,
+        HashAlgorithm::SHA2_512,
+    ];
+    for algorithm in algorithms {
+        let mut options: duplicate_destroyer::Config = Default::default();
+        options.set_minimum_size(0);
+        options.set_hash_algorithm(algorithm);
+        let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+        assert_eq!(duplicates.len(), 1);
+
+        let path = tmp_dir_path.join("a.txt");
+        let checksum = hash_file(&path, algorithm)?;
+        assert_eq!(duplicates[0].digest.as_ref(), Some(&checksum));
+        assert_eq!(hash_prefix(&path, 2000, algorithm)?, checksum);
+        assert_ne!(hash_prefix(&path, PREFIX_LENGTH, algorithm)?, checksum);
+    }
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── a.txt
+/// ├── b.txt
+/// └── c.txt
+/// where a.txt is duplicated and c.txt has the same beginning and size as a.txt.
+///
+/// Check that files are compared with the prefix hash algorithm first and confirmed with the
+/// whole file hash algorithm, whose checksum is the digest of the group.
+fn prefix_hash_algorithm_test() -> io::Result<()> {
+    use duplicate_destroyer::checksum::hash_file;
+    use duplicate_destroyer::HashAlgorithm;
+
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("prefix_hash_algorithm_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    write_file(&tmp_dir_path.join("a.txt"), &"test_text_alpha".repeat(100))?;
+    write_file(&tmp_dir_path.join("b.txt"), &"test_text_alpha".repeat(100))?;
+    write_file(&tmp_dir_path.join("c.txt"), &("test_text_alpha".repeat(99) + "test_text_omega"))?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_prefix_hash_algorithm(HashAlgorithm::Blake2);
+    options.set_hash_algorithm(HashAlgorithm::SHA2_512);
+    assert_eq!(options.get_prefix_hash_algorithm(), HashAlgorithm::Blake2);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].duplicates.len(), 2);
+    let checksum = hash_file(tmp_dir_path.join("a.txt"), HashAlgorithm::SHA2_512)?;
+    assert_eq!(duplicates[0].digest.as_ref(), Some(&checksum));
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   ├── a.txt
+/// │   ├── b.txt
+/// │   └── .hidden.txt
+/// ├── c.txt
+/// └── d.txt
+/// where a.txt, .hidden.txt and c.txt are duplicated, b.txt has the same size and a different
+/// content and d.txt has no copy.
+///
+/// Check that find_copies reports the copies of c.txt and d.txt in tempdir (but not c.txt itself)
+/// and that hidden files are skipped if set in the configuration.
+fn find_copies_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("find_copies_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    DirBuilder::new().create(tmp_dir_path.join("A"))?;
+    write_file(&tmp_dir_path.join("A/a.txt"), "test_text_a")?;
+    write_file(&tmp_dir_path.join("A/b.txt"), "test_text_b")?;
+    write_file(&tmp_dir_path.join("A/.hidden.txt"), "test_text_a")?;
+    write_file(&tmp_dir_path.join("c.txt"), "test_text_a")?;
+    write_file(&tmp_dir_path.join("d.txt"), "test_text_d")?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    let files = [tmp_dir_path.join("c.txt"), tmp_dir_path.join("d.txt")];
+    let copies = duplicate_destroyer::find_copies(files.clone(), [tmp_dir_path], &options)?;
+    assert_eq!(copies.len(), 2);
+    assert_eq!(copies[0].path, files[0]);
+    assert_eq!(
+        copies[0].copies,
+        vec![tmp_dir_path.join("A/.hidden.txt"), tmp_dir_path.join("A/a.txt")]
+    );
+    assert_eq!(copies[1].path, files[1]);
+    assert!(copies[1].copies.is_empty());
+
+    options.set_skip_hidden(true);
+    let copies = duplicate_destroyer::find_copies(files, [tmp_dir_path], &options)?;
+    assert_eq!(copies[0].copies, vec![tmp_dir_path.join("A/a.txt")]);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Estimate duplicates in directory structure:
+/// tempdir
+/// ├── A
+/// │   └── a.txt
+/// ├── B
+/// │   ├── a.txt
+/// │   └── b.txt
+/// └── C
+///     └── a.txt
+/// where A/a.txt, B/a.txt and B/b.txt are duplicated and C/a.txt has the same size and a different
+/// content.
+///
+/// Check that the files with the same size and name are grouped regardless of their content and
+/// that the copy with another name is missed.
+fn estimate_duplicates_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("estimate_duplicates_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for dir in ["A", "B", "C"] {
+        DirBuilder::new().create(tmp_dir_path.join(dir))?;
+    }
+    write_file(&tmp_dir_path.join("A/a.txt"), "test_text_a")?;
+    write_file(&tmp_dir_path.join("B/a.txt"), "test_text_a")?;
+    write_file(&tmp_dir_path.join("B/b.txt"), "test_text_a")?;
+    write_file(&tmp_dir_path.join("C/a.txt"), "test_text_c")?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let estimate = duplicate_destroyer::estimate_duplicates([tmp_dir_path], &options);
+    let expected = duplicate_destroyer::DuplicationEstimate {
+        files: 4,
+        total_size: 48,
+        groups: 1,
+        grouped_files: 3,
+        max_saved_space: 24,
+    };
+    assert_eq!(estimate, expected);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+/// Scan tests/fixtures (see the schema in the documentation of the library).
+///
+/// Check that the directories are ranked by the fraction of their bytes that have a copy outside
+/// of them: C (with the unique diff.txt) comes after the fully copied directories and
+/// tests/fixtures is not ranked, since all copies are inside it.
+fn directory_duplication_test() {
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let scanner = duplicate_destroyer::Scanner::new(["tests/fixtures"], &options).unwrap();
+    let ranking = scanner.directory_duplication();
+
+    let paths: Vec<_> = ranking.iter().map(|x| x.path.clone()).collect();
+    let expected: Vec<PathBuf> = ["A", "B", "B/A", "A/b", "B/A/b", "C/b", "C"]
+        .iter()
+        .map(|x| Path::new("tests/fixtures").join(x))
+        .collect();
+    assert_eq!(paths, expected);
+
+    let c_dir = ranking.last().unwrap();
+    let diff_size = std::fs::metadata("tests/fixtures/C/diff.txt").unwrap().len();
+    assert_eq!(c_dir.size, c_dir.duplicated + diff_size);
+    assert_eq!(c_dir.ratio, c_dir.duplicated as f64 / c_dir.size as f64);
+    assert!(ranking[..6].iter().all(|x| x.ratio == 1.0));
+}
+
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   ├── B
+/// │   │   ├── a.txt
+/// │   │   └── b.txt
+/// │   ├── C
+/// │   │   ├── a.txt
+/// │   │   └── b.txt
+/// │   └── d.txt
+/// └── e.txt
+/// where a.txt, b.txt (with a different content than a.txt) and d.txt are duplicated, which makes
+/// B and C duplicates, and e.txt has the same content as d.txt.
+///
+/// Check that the groups are clustered by the nearest common ancestor of their members: B and C
+/// under A, the copies of d.txt under tempdir.
+fn ancestor_clusters_test() -> io::Result<()> {
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("ancestor_clusters_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for dir in ["A/B", "A/C"] {
+        DirBuilder::new().recursive(true).create(tmp_dir_path.join(dir))?;
+        write_file(&tmp_dir_path.join(dir).join("a.txt"), "test_text_a")?;
+        write_file(&tmp_dir_path.join(dir).join("b.txt"), "test_text_b")?;
+    }
+    write_file(&tmp_dir_path.join("A/d.txt"), "test_text_d")?;
+    write_file(&tmp_dir_path.join("e.txt"), "test_text_d")?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    let duplicates = duplicate_destroyer::get_duplicates([tmp_dir_path], &options).unwrap();
+    assert_eq!(duplicates.len(), 2);
+
+    let clusters = duplicate_destroyer::ancestor_clusters(&duplicates);
+    assert_eq!(clusters.len(), 2);
+    assert_eq!(clusters[0].path, tmp_dir_path.join("A"));
+    assert_eq!(clusters[0].groups, 1);
+    assert_eq!(clusters[1].path, tmp_dir_path);
+    assert_eq!(clusters[1].savings, 12);
+    // The savings of B and C include the size of the directory itself
+    let savings: u64 = duplicates.iter().map(|x| x.savings()).sum();
+    assert_eq!(clusters[0].savings, savings - 12);
+
+    // Prevent removing of tmp_dir until all tests are done
+    tmp_dir.close()?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+/// Create a directory structure with the schema
+/// tempdir
+/// ├── A
+/// │   └── a.txt
+/// ├── B
+/// │   └── a.txt
+/// └── c.txt
+/// where a.txt and c.txt are duplicated and B/a.txt is kept open for writing.
+///
+/// Check that with skip_open_for_writing B/a.txt is reported as skipped and neither it nor B is in
+/// any group, while A/a.txt and c.txt still are.
+fn skip_open_for_writing_test() -> io::Result<()> {
+    use duplicate_destroyer::ScanErrorKind;
+
+    // Create a temporary directory
+    let tmp_dir = TempDir::new("skip_open_for_writing_test").expect("Failed creating temp dir.");
+    let tmp_dir_path = tmp_dir.path();
+
+    // Create files and folders
+    for topdir in ["A", "B"] {
+        DirBuilder::new().create(tmp_dir_path.join(topdir))?;
+        write_file(&tmp_dir_path.join(topdir).join("a.txt"), "test_text_a")?;
+    }
+    write_file(&tmp_dir_path.join("c.txt"), "test_text_a")?;
+    let open_file = std::fs::OpenOptions::new().append(true).open(tmp_dir_path.join("B/a.txt"))?;
+
+    let mut options: duplicate_destroyer::Config = Default::default();
+    options.set_minimum_size(0);
+    options.set_skip_open_for_writing(true);
+    let scanner = duplicate_destroyer::Scanner::new([tmp_dir_path], &options).unwrap();
+    let duplicates = scanner.all_groups();
+
+    let expected_paths = std::collections::BTreeSet::from([
+        tmp_dir_path.join("A/a.txt"),
+        tmp_dir_path.join("c.txt"),
+    ]);
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].duplicates, expected_paths);
+
+    let errors = scanner.scan_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, tmp_dir_path.join("B/a.txt"));
+    assert_eq!(errors[0].kind, ScanErrorKind::OpenForWriting);
+
+    // Prevent removing of tmp_dir until all tests are done
+    drop(open_file);
+    tmp_dir.close()?;
+    Ok(())
+}